@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::io;
 
 use crate::boxes::GlueSetRatio;
 use crate::boxes::TeXBox;
@@ -6,7 +7,11 @@ use crate::dimension::Dimen;
 use crate::dvi::{DVICommand, DVIFile};
 use crate::font::Font;
 use crate::font_metrics::FontMetrics;
-use crate::list::{HorizontalListElem, VerticalListElem};
+use crate::glue::Glue;
+use crate::list::{
+    char_runs, with_unbroken_discretionaries, HorizontalListElem,
+    VerticalListElem,
+};
 
 pub struct DVIFileWriter {
     commands: Vec<DVICommand>,
@@ -20,6 +25,194 @@ pub struct DVIFileWriter {
     num_pages: u16,
     max_stack_depth: u16,
     curr_stack_depth: u16,
+    // The largest extents of any page, including material that sticks out of
+    // its box (like shifted boxes). These are reported in the postamble.
+    max_page_height: Dimen,
+    max_page_width: Dimen,
+    // Metrics for the fonts we've seen, used for finding the extents of
+    // characters on the page.
+    font_metrics: HashMap<Font, FontMetrics>,
+    // The recent horizontal and vertical movements on the current page, with
+    // the most recent last, which we look through to find movements that can
+    // reuse the w, x, y, and z registers.
+    right_movements: Vec<Movement>,
+    down_movements: Vec<Movement>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Direction {
+    Right,
+    Down,
+}
+
+// Which command we use for a movement: a plain right or down, or one which
+// sets the first (w or y) or second (x or z) register.
+#[derive(Clone, Copy, PartialEq)]
+enum MovementKind {
+    Plain,
+    First,
+    Second,
+}
+
+// What we know about a movement that's already been written, following TeX's
+// names (where y and z stand for w and x in horizontal movements):
+//  * YHere/ZHere: the movement sets the y/z register
+//  * YzOk: the movement is plain, but could be changed to set y or z
+//  * YOk/ZOk: the movement is plain, but could only be changed to set y/z
+//  * DFixed: the movement is plain and must stay that way
+#[derive(Clone, Copy, PartialEq)]
+enum MovementInfo {
+    YHere,
+    ZHere,
+    YzOk,
+    YOk,
+    ZOk,
+    DFixed,
+}
+
+struct Movement {
+    amount: i32,
+    // The index of the movement's command in the list of commands.
+    location: usize,
+    info: MovementInfo,
+}
+
+// While looking back through movements, which register we've passed a
+// command setting.
+#[derive(Clone, Copy, PartialEq)]
+enum RegisterSeen {
+    Neither,
+    Y,
+    Z,
+}
+
+// How far some material extends to the right of, above, and below its
+// reference point.
+struct Extent {
+    right: Dimen,
+    up: Dimen,
+    down: Dimen,
+}
+
+impl Extent {
+    fn include(&mut self, right: Dimen, up: Dimen, down: Dimen) {
+        if right > self.right {
+            self.right = right;
+        }
+        if up > self.up {
+            self.up = up;
+        }
+        if down > self.down {
+            self.down = down;
+        }
+    }
+}
+
+// Finds how much space some glue takes up in a list with the given glue set
+// ratio.
+fn get_set_glue_amount(
+    glue: &Glue,
+    glue_set_ratio: &Option<GlueSetRatio>,
+) -> Dimen {
+    match glue_set_ratio {
+        Some(set_ratio) => set_ratio.apply_to_glue(glue),
+        None => glue.space,
+    }
+}
+
+// Like TeX, we use the variant of a movement command with the fewest bytes
+// that can hold the (signed) amount being moved.
+fn movement_command_size(amount: i32) -> usize {
+    if (-0x80..0x80).contains(&amount) {
+        1
+    } else if (-0x8000..0x8000).contains(&amount) {
+        2
+    } else if (-0x80_0000..0x80_0000).contains(&amount) {
+        3
+    } else {
+        4
+    }
+}
+
+fn right_command(amount: i32) -> DVICommand {
+    match movement_command_size(amount) {
+        1 => DVICommand::Right1(amount as i8),
+        2 => DVICommand::Right2(amount as i16),
+        3 => DVICommand::Right3(amount),
+        _ => DVICommand::Right4(amount),
+    }
+}
+
+fn down_command(amount: i32) -> DVICommand {
+    match movement_command_size(amount) {
+        1 => DVICommand::Down1(amount as i8),
+        2 => DVICommand::Down2(amount as i16),
+        3 => DVICommand::Down3(amount),
+        _ => DVICommand::Down4(amount),
+    }
+}
+
+// Returns the command which moves by `amount`, setting a register if the kind
+// isn't plain.
+fn movement_command(
+    direction: Direction,
+    kind: MovementKind,
+    amount: i32,
+) -> DVICommand {
+    let size = movement_command_size(amount);
+    match (direction, kind) {
+        (Direction::Right, MovementKind::Plain) => right_command(amount),
+        (Direction::Down, MovementKind::Plain) => down_command(amount),
+        (Direction::Right, MovementKind::First) => match size {
+            1 => DVICommand::W1(amount as i8),
+            2 => DVICommand::W2(amount as i16),
+            3 => DVICommand::W3(amount),
+            _ => DVICommand::W4(amount),
+        },
+        (Direction::Right, MovementKind::Second) => match size {
+            1 => DVICommand::X1(amount as i8),
+            2 => DVICommand::X2(amount as i16),
+            3 => DVICommand::X3(amount),
+            _ => DVICommand::X4(amount),
+        },
+        (Direction::Down, MovementKind::First) => match size {
+            1 => DVICommand::Y1(amount as i8),
+            2 => DVICommand::Y2(amount as i16),
+            3 => DVICommand::Y3(amount),
+            _ => DVICommand::Y4(amount),
+        },
+        (Direction::Down, MovementKind::Second) => match size {
+            1 => DVICommand::Z1(amount as i8),
+            2 => DVICommand::Z2(amount as i16),
+            3 => DVICommand::Z3(amount),
+            _ => DVICommand::Z4(amount),
+        },
+    }
+}
+
+// Returns the command which typesets a character and moves right by its width.
+fn char_command(chr: char) -> DVICommand {
+    if (chr as u8) < 128 {
+        DVICommand::SetCharN(chr as u8)
+    } else {
+        DVICommand::Set1(chr as u8)
+    }
+}
+
+// Returns the command which moves by the amount already in a register.
+fn register_movement_command(
+    direction: Direction,
+    kind: MovementKind,
+) -> DVICommand {
+    match (direction, kind) {
+        (Direction::Right, MovementKind::First) => DVICommand::W0,
+        (Direction::Right, MovementKind::Second) => DVICommand::X0,
+        (Direction::Down, MovementKind::First) => DVICommand::Y0,
+        (Direction::Down, MovementKind::Second) => DVICommand::Z0,
+        (_, MovementKind::Plain) => {
+            panic!("Plain movements don't use a register")
+        }
+    }
 }
 
 impl DVIFileWriter {
@@ -36,7 +229,327 @@ impl DVIFileWriter {
             num_pages: 0,
             max_stack_depth: 0,
             curr_stack_depth: 0,
+            max_page_height: Dimen::zero(),
+            max_page_width: Dimen::zero(),
+            font_metrics: HashMap::new(),
+            right_movements: Vec::new(),
+            down_movements: Vec::new(),
+        }
+    }
+
+    // Adds a movement to the page. Like TeX, when we move by the same amount
+    // as a recent movement, we change the earlier movement to set a register
+    // (if it didn't already) and reuse the register here. This follows
+    // sections 607-615 of TeX: The Program.
+    fn add_movement(&mut self, direction: Direction, amount: i32) {
+        // Moving by nothing doesn't do anything.
+        if amount == 0 {
+            return;
+        }
+
+        let location = self.commands.len();
+        let commands = &mut self.commands;
+        let movements = match direction {
+            Direction::Right => &mut self.right_movements,
+            Direction::Down => &mut self.down_movements,
+        };
+
+        let mut register_seen = RegisterSeen::Neither;
+        let mut found = None;
+        for (i, movement) in movements.iter_mut().enumerate().rev() {
+            if movement.amount == amount {
+                match (register_seen, movement.info) {
+                    (RegisterSeen::Neither, MovementInfo::YzOk)
+                    | (RegisterSeen::Neither, MovementInfo::YOk)
+                    | (RegisterSeen::Z, MovementInfo::YzOk)
+                    | (RegisterSeen::Z, MovementInfo::YOk) => {
+                        commands[movement.location] = movement_command(
+                            direction,
+                            MovementKind::First,
+                            amount,
+                        );
+                        movement.info = MovementInfo::YHere;
+                        found = Some(i);
+                        break;
+                    }
+                    (RegisterSeen::Neither, MovementInfo::ZOk)
+                    | (RegisterSeen::Y, MovementInfo::YzOk)
+                    | (RegisterSeen::Y, MovementInfo::ZOk) => {
+                        commands[movement.location] = movement_command(
+                            direction,
+                            MovementKind::Second,
+                            amount,
+                        );
+                        movement.info = MovementInfo::ZHere;
+                        found = Some(i);
+                        break;
+                    }
+                    (RegisterSeen::Neither, MovementInfo::YHere)
+                    | (RegisterSeen::Neither, MovementInfo::ZHere)
+                    | (RegisterSeen::Y, MovementInfo::ZHere)
+                    | (RegisterSeen::Z, MovementInfo::YHere) => {
+                        found = Some(i);
+                        break;
+                    }
+                    _ => {}
+                }
+            } else {
+                match (register_seen, movement.info) {
+                    (RegisterSeen::Neither, MovementInfo::YHere) => {
+                        register_seen = RegisterSeen::Y;
+                    }
+                    (RegisterSeen::Neither, MovementInfo::ZHere) => {
+                        register_seen = RegisterSeen::Z;
+                    }
+                    (RegisterSeen::Y, MovementInfo::ZHere)
+                    | (RegisterSeen::Z, MovementInfo::YHere) => {
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let info = match found {
+            Some(i) => {
+                let info = movements[i].info;
+                let kind = if info == MovementInfo::YHere {
+                    MovementKind::First
+                } else {
+                    MovementKind::Second
+                };
+
+                // The movements in between can't be changed to set the
+                // register we're reusing anymore, since that would change the
+                // amount we move here.
+                for movement in &mut movements[i + 1..] {
+                    movement.info = match (kind, movement.info) {
+                        (MovementKind::First, MovementInfo::YzOk) => {
+                            MovementInfo::ZOk
+                        }
+                        (MovementKind::First, MovementInfo::YOk) => {
+                            MovementInfo::DFixed
+                        }
+                        (MovementKind::Second, MovementInfo::YzOk) => {
+                            MovementInfo::YOk
+                        }
+                        (MovementKind::Second, MovementInfo::ZOk) => {
+                            MovementInfo::DFixed
+                        }
+                        (_, info) => info,
+                    };
+                }
+
+                commands.push(register_movement_command(direction, kind));
+                info
+            }
+            None => {
+                commands.push(movement_command(
+                    direction,
+                    MovementKind::Plain,
+                    amount,
+                ));
+                MovementInfo::YzOk
+            }
+        };
+
+        movements.push(Movement {
+            amount,
+            location,
+            info,
+        });
+    }
+
+    // Forgets the movements from `location` on, which happens when we pop
+    // the DVI stack since the registers go back to how they were before the
+    // corresponding push.
+    fn prune_movements(&mut self, location: usize) {
+        self.right_movements
+            .retain(|movement| movement.location < location);
+        self.down_movements
+            .retain(|movement| movement.location < location);
+    }
+
+    fn get_font_metrics(&mut self, font: &Font) -> &FontMetrics {
+        // We only clone the font the first time we see it, since this is
+        // called for every run of characters on the page.
+        if !self.font_metrics.contains_key(font) {
+            let metrics = FontMetrics::from_font(font).unwrap_or_else(|| {
+                panic!("Error loading font metrics for {}", font.font_name)
+            });
+            self.font_metrics.insert(font.clone(), metrics);
+        }
+
+        &self.font_metrics[font]
+    }
+
+    // Finds how far the contents of a box actually reach, which can be
+    // further than the box's own dimensions if it contains shifted boxes or
+    // had its dimensions changed.
+    fn get_box_extent(&mut self, tex_box: &TeXBox) -> Extent {
+        let mut extent = Extent {
+            right: *tex_box.width(),
+            up: *tex_box.height(),
+            down: *tex_box.depth(),
+        };
+
+        match tex_box {
+            TeXBox::HorizontalBox(hbox) => {
+                let mut h = Dimen::zero();
+                let list = with_unbroken_discretionaries(&hbox.list);
+                for piece in char_runs(&list) {
+                    match &piece[0] {
+                        HorizontalListElem::Char { font, .. } => {
+                            let metrics = self.get_font_metrics(font);
+                            for elem in piece {
+                                if let HorizontalListElem::Char {
+                                    chr, ..
+                                } = elem
+                                {
+                                    let width = metrics.get_width(*chr);
+                                    extent.include(
+                                        h + width,
+                                        metrics.get_height(*chr),
+                                        metrics.get_depth(*chr),
+                                    );
+                                    h = h + width;
+                                }
+                            }
+                        }
+                        HorizontalListElem::CharRun { chars, font } => {
+                            let metrics = self.get_font_metrics(font);
+                            for chr in chars {
+                                let width = metrics.get_width(*chr);
+                                extent.include(
+                                    h + width,
+                                    metrics.get_height(*chr),
+                                    metrics.get_depth(*chr),
+                                );
+                                h = h + width;
+                            }
+                        }
+                        HorizontalListElem::HSkip(glue) => {
+                            h = h + get_set_glue_amount(
+                                glue,
+                                &hbox.glue_set_ratio,
+                            );
+                        }
+                        HorizontalListElem::Kern(width) => {
+                            h = h + *width;
+                        }
+                        HorizontalListElem::Penalty(_)
+                        | HorizontalListElem::Discretionary { .. } => {}
+                        HorizontalListElem::Rule {
+                            height,
+                            depth,
+                            width,
+                        } => {
+                            // Running dimensions match the box's, which are
+                            // already included.
+                            extent.include(
+                                h + *width,
+                                height.unwrap_or_else(Dimen::zero),
+                                depth.unwrap_or_else(Dimen::zero),
+                            );
+                            h = h + *width;
+                        }
+                        HorizontalListElem::RuleLeaders { glue, rule } => {
+                            let width =
+                                get_set_glue_amount(glue, &hbox.glue_set_ratio);
+                            extent.include(
+                                h + width,
+                                rule.height.unwrap_or_else(Dimen::zero),
+                                rule.depth.unwrap_or_else(Dimen::zero),
+                            );
+                            h = h + width;
+                        }
+                        HorizontalListElem::Box { tex_box, shift } => {
+                            let box_extent = self.get_box_extent(tex_box);
+                            extent.include(
+                                h + box_extent.right,
+                                box_extent.up + *shift,
+                                box_extent.down - *shift,
+                            );
+                            h = h + *tex_box.width();
+                        }
+                    }
+                }
+            }
+            TeXBox::VerticalBox(vbox) => {
+                let (right, down) = self
+                    .get_vertical_list_extent(&vbox.list, &vbox.glue_set_ratio);
+                extent.include(right, Dimen::zero(), down - vbox.height);
+            }
+        }
+
+        extent
+    }
+
+    // Finds how far right and down the contents of a vertical list reach,
+    // starting from the top of the list.
+    fn get_vertical_list_extent(
+        &mut self,
+        elems: &[VerticalListElem],
+        glue_set_ratio: &Option<GlueSetRatio>,
+    ) -> (Dimen, Dimen) {
+        let mut right = Dimen::zero();
+        let mut down = Dimen::zero();
+
+        let mut v = Dimen::zero();
+        for elem in elems {
+            match elem {
+                VerticalListElem::VSkip(glue) => {
+                    v = v + get_set_glue_amount(glue, glue_set_ratio);
+                }
+                VerticalListElem::Kern(height) => {
+                    v = v + *height;
+                }
+                VerticalListElem::Box { tex_box, shift } => {
+                    v = v + *tex_box.height();
+
+                    let box_extent = self.get_box_extent(tex_box);
+                    if box_extent.right + *shift > right {
+                        right = box_extent.right + *shift;
+                    }
+                    if v + box_extent.down > down {
+                        down = v + box_extent.down;
+                    }
+
+                    v = v + *tex_box.depth();
+                }
+                VerticalListElem::Rule {
+                    height,
+                    depth,
+                    width,
+                } => {
+                    if let Some(width) = width {
+                        if *width > right {
+                            right = *width;
+                        }
+                    }
+
+                    v = v + *height + *depth;
+                    if v > down {
+                        down = v;
+                    }
+                }
+                VerticalListElem::RuleLeaders { glue, rule } => {
+                    if let Some(width) = rule.width {
+                        if width > right {
+                            right = width;
+                        }
+                    }
+
+                    v = v + get_set_glue_amount(glue, glue_set_ratio);
+                    if v > down {
+                        down = v;
+                    }
+                }
+                VerticalListElem::Penalty(_) => {}
+            }
         }
+
+        (right, down)
     }
 
     fn add_font_def_with_metrics(
@@ -74,6 +587,10 @@ impl DVIFileWriter {
         font_num
     }
 
+    // Selects the given font unless it's already selected. The current font
+    // isn't part of what push and pop save and restore in DVI files, so a
+    // font selected inside of a box is still selected after the box ends and
+    // curr_font_num stays correct across boxes.
     fn switch_to_font(&mut self, font: &Font) {
         let font_num = if let Some(font_num) = self.font_nums.get(font) {
             *font_num
@@ -88,6 +605,7 @@ impl DVIFileWriter {
     }
 
     fn add_box(&mut self, tex_box: &TeXBox) {
+        let push_location = self.commands.len();
         self.commands.push(DVICommand::Push);
         self.curr_stack_depth += 1;
         if self.curr_stack_depth > self.max_stack_depth {
@@ -96,95 +614,252 @@ impl DVIFileWriter {
 
         match tex_box {
             TeXBox::HorizontalBox(hbox) => {
-                for elem in &hbox.list {
-                    self.add_horizontal_list_elem(&elem, &hbox.glue_set_ratio);
+                // Lines are never broken inside of boxes, so discretionaries
+                // are always typeset with their no-break material.
+                let list = with_unbroken_discretionaries(&hbox.list);
+                for piece in char_runs(&list) {
+                    if piece.len() > 1 {
+                        self.add_char_run(piece);
+                    } else {
+                        self.add_horizontal_list_elem(
+                            &piece[0],
+                            &hbox.glue_set_ratio,
+                            &hbox.height,
+                            &hbox.depth,
+                        );
+                    }
                 }
             }
             TeXBox::VerticalBox(vbox) => {
-                self.commands
-                    .push(DVICommand::Down4(-vbox.height.as_scaled_points()));
+                self.add_movement(
+                    Direction::Down,
+                    -vbox.height.as_scaled_points(),
+                );
 
-                for elem in &vbox.list {
-                    self.add_vertical_list_elem(&elem, &vbox.glue_set_ratio);
+                for elem in vbox.list.iter() {
+                    self.add_vertical_list_elem(
+                        elem,
+                        &vbox.glue_set_ratio,
+                        &vbox.width,
+                    );
                 }
             }
         }
 
         self.commands.push(DVICommand::Pop);
+        self.prune_movements(push_location);
         self.curr_stack_depth -= 1;
     }
 
+    // Adds an element of a vertical list to the page. `list_width` is the
+    // width of the list, which rules with running widths span.
     fn add_vertical_list_elem(
         &mut self,
         elem: &VerticalListElem,
         glue_set_ratio: &Option<GlueSetRatio>,
+        list_width: &Dimen,
     ) {
         match elem {
             VerticalListElem::VSkip(glue) => {
-                let move_amount = if let Some(set_ratio) = glue_set_ratio {
-                    set_ratio.apply_to_glue(glue)
-                } else {
-                    glue.space
-                };
+                let move_amount = get_set_glue_amount(glue, glue_set_ratio);
 
-                self.commands
-                    .push(DVICommand::Down4(move_amount.as_scaled_points()));
+                self.add_movement(
+                    Direction::Down,
+                    move_amount.as_scaled_points(),
+                );
+            }
+
+            // Kerns never stretch or shrink, so the glue set ratio doesn't
+            // affect them.
+            VerticalListElem::Kern(height) => {
+                self.add_movement(Direction::Down, height.as_scaled_points());
             }
 
-            VerticalListElem::Box(tex_box) => {
-                self.commands.push(DVICommand::Down4(
+            VerticalListElem::Box { tex_box, shift } => {
+                self.add_movement(
+                    Direction::Down,
                     tex_box.height().as_scaled_points(),
-                ));
-                self.add_box(tex_box);
-                self.commands.push(DVICommand::Down4(
+                );
+                if shift != &Dimen::zero() {
+                    let push_location = self.commands.len();
+                    self.commands.push(DVICommand::Push);
+                    self.add_movement(
+                        Direction::Right,
+                        shift.as_scaled_points(),
+                    );
+                    self.add_box(tex_box);
+                    self.commands.push(DVICommand::Pop);
+                    self.prune_movements(push_location);
+                } else {
+                    self.add_box(tex_box);
+                }
+                self.add_movement(
+                    Direction::Down,
                     tex_box.depth().as_scaled_points(),
-                ));
+                );
+            }
+
+            VerticalListElem::Rule {
+                height,
+                depth,
+                width,
+            } => {
+                // Rules are drawn up and to the right of the current position,
+                // so we move to the bottom of the rule first.
+                let total_height = *height + *depth;
+                self.add_movement(
+                    Direction::Down,
+                    total_height.as_scaled_points(),
+                );
+                self.commands.push(DVICommand::PutRule {
+                    height: total_height.as_scaled_points(),
+                    width: width.unwrap_or(*list_width).as_scaled_points(),
+                });
+            }
+
+            VerticalListElem::RuleLeaders { glue, rule } => {
+                // Instead of repeating the rule, we draw a single rule which
+                // fills all of the space of the glue.
+                let height = get_set_glue_amount(glue, glue_set_ratio);
+                let width = rule.width.unwrap_or(*list_width);
+
+                self.add_movement(Direction::Down, height.as_scaled_points());
+                if height > Dimen::zero() && width > Dimen::zero() {
+                    self.commands.push(DVICommand::PutRule {
+                        height: height.as_scaled_points(),
+                        width: width.as_scaled_points(),
+                    });
+                }
+            }
+
+            // Penalties only affect where breaks happen, so there's nothing
+            // to draw for them.
+            VerticalListElem::Penalty(_) => {}
+        }
+    }
+
+    // Adds a run of characters which are all in the same font to the page,
+    // only switching fonts once for the whole run.
+    fn add_char_run(&mut self, run: &[HorizontalListElem]) {
+        if let HorizontalListElem::Char { font, .. } = &run[0] {
+            self.switch_to_font(font);
+        }
+
+        self.commands.reserve(run.len());
+        for elem in run {
+            if let HorizontalListElem::Char { chr, .. } = elem {
+                self.commands.push(char_command(*chr));
             }
         }
     }
 
+    // Adds an element of a horizontal list to the page. `list_height` and
+    // `list_depth` are the height and depth of the list, which rules with
+    // running heights and depths span.
     fn add_horizontal_list_elem(
         &mut self,
         elem: &HorizontalListElem,
         glue_set_ratio: &Option<GlueSetRatio>,
+        list_height: &Dimen,
+        list_depth: &Dimen,
     ) {
         match elem {
             HorizontalListElem::Char { chr, font } => {
-                let command = if (*chr as u8) < 128 {
-                    DVICommand::SetCharN(*chr as u8)
-                } else {
-                    DVICommand::Set1(*chr as u8)
-                };
+                self.switch_to_font(font);
+                self.commands.push(char_command(*chr));
+            }
 
+            HorizontalListElem::CharRun { chars, font } => {
                 self.switch_to_font(&font);
-                self.commands.push(command);
+                self.commands.reserve(chars.len());
+                for chr in chars {
+                    self.commands.push(char_command(*chr));
+                }
             }
 
             HorizontalListElem::HSkip(glue) => {
-                let move_amount = if let Some(set_ratio) = glue_set_ratio {
-                    set_ratio.apply_to_glue(glue)
-                } else {
-                    glue.space
-                };
+                let move_amount = get_set_glue_amount(glue, glue_set_ratio);
+
+                self.add_movement(
+                    Direction::Right,
+                    move_amount.as_scaled_points(),
+                );
+            }
+
+            HorizontalListElem::Kern(width) => {
+                self.add_movement(Direction::Right, width.as_scaled_points());
+            }
+
+            // Discretionaries are replaced with their no-break material
+            // before the list is added.
+            HorizontalListElem::Penalty(_)
+            | HorizontalListElem::Discretionary { .. } => {}
+
+            HorizontalListElem::Rule {
+                height,
+                depth,
+                width,
+            } => {
+                // Rules are drawn up from the current position, so we move to
+                // the bottom of the rule first and then back to the baseline.
+                let depth = depth.unwrap_or(*list_depth);
+                let total_height = height.unwrap_or(*list_height) + depth;
+
+                self.add_movement(Direction::Down, depth.as_scaled_points());
+                self.commands.push(DVICommand::SetRule {
+                    height: total_height.as_scaled_points(),
+                    width: width.as_scaled_points(),
+                });
+                self.add_movement(Direction::Down, -depth.as_scaled_points());
+            }
 
-                self.commands
-                    .push(DVICommand::Right4(move_amount.as_scaled_points()));
+            HorizontalListElem::RuleLeaders { glue, rule } => {
+                // Instead of repeating the rule, we draw a single rule which
+                // fills all of the space of the glue.
+                let width = get_set_glue_amount(glue, glue_set_ratio);
+                let depth = rule.depth.unwrap_or(*list_depth);
+                let total_height = rule.height.unwrap_or(*list_height) + depth;
+
+                if total_height > Dimen::zero() && width > Dimen::zero() {
+                    self.add_movement(
+                        Direction::Down,
+                        depth.as_scaled_points(),
+                    );
+                    self.commands.push(DVICommand::SetRule {
+                        height: total_height.as_scaled_points(),
+                        width: width.as_scaled_points(),
+                    });
+                    self.add_movement(
+                        Direction::Down,
+                        -depth.as_scaled_points(),
+                    );
+                } else {
+                    self.add_movement(
+                        Direction::Right,
+                        width.as_scaled_points(),
+                    );
+                }
             }
 
             HorizontalListElem::Box { tex_box, shift } => {
                 if shift != &Dimen::zero() {
+                    let push_location = self.commands.len();
                     self.commands.push(DVICommand::Push);
-                    self.commands
-                        .push(DVICommand::Down4(-shift.as_scaled_points()));
+                    self.add_movement(
+                        Direction::Down,
+                        -shift.as_scaled_points(),
+                    );
                     self.add_box(tex_box);
                     self.commands.push(DVICommand::Pop);
+                    self.prune_movements(push_location);
                 } else {
                     self.add_box(tex_box);
                 }
 
-                self.commands.push(DVICommand::Right4(
+                self.add_movement(
+                    Direction::Right,
                     tex_box.width().as_scaled_points(),
-                ));
+                );
             }
         }
     }
@@ -212,8 +887,21 @@ impl DVIFileWriter {
         });
 
         self.curr_font_num = -1;
+        // The registers are reset at the start of each page.
+        self.right_movements.clear();
+        self.down_movements.clear();
+
+        let (page_width, page_height) =
+            self.get_vertical_list_extent(elems, glue_set_ratio);
         for elem in elems {
-            self.add_vertical_list_elem(elem, glue_set_ratio);
+            self.add_vertical_list_elem(elem, glue_set_ratio, &page_width);
+        }
+
+        if page_width > self.max_page_width {
+            self.max_page_width = page_width;
+        }
+        if page_height > self.max_page_height {
+            self.max_page_height = page_height;
         }
 
         self.commands.push(DVICommand::Eop);
@@ -243,13 +931,19 @@ impl DVIFileWriter {
             num: self.num,
             den: self.den,
             mag: self.mag,
-            max_page_height: 43725786, // TODO(fixme)
-            max_page_width: 30785863,  // TODO(fixme)
+            max_page_height: self.max_page_height.as_scaled_points() as u32,
+            max_page_width: self.max_page_width.as_scaled_points() as u32,
             max_stack_depth: self.max_stack_depth,
             num_pages: self.num_pages,
         });
 
-        for (font, font_num) in std::mem::take(&mut self.font_nums) {
+        // Every font used in the body is defined again in the postamble, with
+        // the same number it was given when it was first used. We write them
+        // in that order so the postamble doesn't depend on hash map ordering.
+        let mut fonts: Vec<(Font, i32)> =
+            std::mem::take(&mut self.font_nums).into_iter().collect();
+        fonts.sort_by_key(|(_, font_num)| *font_num);
+        for (font, font_num) in fonts {
             let metrics = FontMetrics::from_font(&font).unwrap_or_else(|| {
                 panic!("Error loading font metrics for {}", font.font_name)
             });
@@ -266,20 +960,70 @@ impl DVIFileWriter {
         });
     }
 
+    /// Finishes a file whose pages were added without calling start(). This
+    /// puts a preamble with TeX's standard units and the given magnification
+    /// in front of the pages, and then writes the postamble like end().
+    pub fn finish(&mut self, mag: u32) {
+        if let Some(DVICommand::Pre { .. }) = self.commands.first() {
+            panic!("DVI preamble was already written with start()");
+        }
+
+        let mut pages = std::mem::take(&mut self.commands);
+        self.start((25400000, 473628672), mag, b"Made by XymosTeX".to_vec());
+
+        // Every pointer into the pages moves down by the size of the
+        // preamble. The first bop's pointer stays -1.
+        let preamble_size = self.total_byte_size() as i32;
+        for command in &mut pages {
+            if let DVICommand::Bop { pointer, .. } = command {
+                if *pointer != -1 {
+                    *pointer += preamble_size;
+                }
+            }
+        }
+        if self.last_page_start != -1 {
+            self.last_page_start += preamble_size;
+        }
+        self.commands.append(&mut pages);
+
+        // The recorded movements refer to command indices, which are now
+        // out of date.
+        self.right_movements.clear();
+        self.down_movements.clear();
+
+        self.end();
+    }
+
+    #[cfg(test)]
     pub fn to_file(&self) -> DVIFile {
         DVIFile {
             commands: self.commands.clone(),
         }
     }
+
+    /// Serializes the commands written so far to the given output, returning
+    /// the number of bytes written.
+    pub fn write_to<W: io::Write>(&self, output: &mut W) -> io::Result<usize> {
+        DVIFile::write_commands(&self.commands, output)?;
+
+        Ok(self
+            .commands
+            .iter()
+            .map(|command| command.byte_size())
+            .sum())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use std::rc::Rc;
+
     use crate::boxes::{GlueSetRatioKind, HorizontalBox, VerticalBox};
     use crate::dimension::{Dimen, FilDimen, FilKind, SpringDimen, Unit};
     use crate::glue::Glue;
+    use crate::list::unpack_char_runs;
 
     lazy_static! {
         static ref CMR10: Font = Font {
@@ -288,6 +1032,15 @@ mod tests {
         };
     }
 
+    // Paragraph lines are as wide as \hsize, so a line with a single
+    // character moves right past the rest of the line after the character.
+    fn line_end_command(metrics: &FontMetrics, chr: char) -> DVICommand {
+        DVICommand::Right4(
+            (Dimen::from_unit(6.5, Unit::Inch) - metrics.get_width(chr))
+                .as_scaled_points(),
+        )
+    }
+
     #[test]
     fn it_generates_commands_for_chars() {
         let mut writer = DVIFileWriter::new();
@@ -297,6 +1050,8 @@ mod tests {
                 font: CMR10.clone(),
             },
             &None,
+            &Dimen::zero(),
+            &Dimen::zero(),
         );
         writer.add_horizontal_list_elem(
             &HorizontalListElem::Char {
@@ -304,6 +1059,8 @@ mod tests {
                 font: CMR10.clone(),
             },
             &None,
+            &Dimen::zero(),
+            &Dimen::zero(),
         );
 
         // The commands start with a fnt_def4 and fnt4 command, then come the
@@ -342,6 +1099,8 @@ mod tests {
                 font: CMR10.clone(),
             },
             &None,
+            &Dimen::zero(),
+            &Dimen::zero(),
         );
         writer.add_horizontal_list_elem(
             &HorizontalListElem::Char {
@@ -349,6 +1108,8 @@ mod tests {
                 font: CMR10.clone(),
             },
             &None,
+            &Dimen::zero(),
+            &Dimen::zero(),
         );
         writer.add_horizontal_list_elem(
             &HorizontalListElem::Char {
@@ -356,6 +1117,8 @@ mod tests {
                 font: cmr7.clone(),
             },
             &None,
+            &Dimen::zero(),
+            &Dimen::zero(),
         );
         writer.add_horizontal_list_elem(
             &HorizontalListElem::Char {
@@ -363,6 +1126,8 @@ mod tests {
                 font: cmr7.clone(),
             },
             &None,
+            &Dimen::zero(),
+            &Dimen::zero(),
         );
         writer.add_horizontal_list_elem(
             &HorizontalListElem::Char {
@@ -370,6 +1135,8 @@ mod tests {
                 font: CMR10.clone(),
             },
             &None,
+            &Dimen::zero(),
+            &Dimen::zero(),
         );
         writer.add_horizontal_list_elem(
             &HorizontalListElem::Char {
@@ -377,6 +1144,8 @@ mod tests {
                 font: big_cmr10.clone(),
             },
             &None,
+            &Dimen::zero(),
+            &Dimen::zero(),
         );
         writer.add_horizontal_list_elem(
             &HorizontalListElem::Char {
@@ -384,6 +1153,8 @@ mod tests {
                 font: small_cmr10.clone(),
             },
             &None,
+            &Dimen::zero(),
+            &Dimen::zero(),
         );
         writer.add_horizontal_list_elem(
             &HorizontalListElem::Char {
@@ -391,6 +1162,8 @@ mod tests {
                 font: big_cmr10,
             },
             &None,
+            &Dimen::zero(),
+            &Dimen::zero(),
         );
         writer.add_horizontal_list_elem(
             &HorizontalListElem::Char {
@@ -398,6 +1171,8 @@ mod tests {
                 font: small_cmr10,
             },
             &None,
+            &Dimen::zero(),
+            &Dimen::zero(),
         );
         writer.add_horizontal_list_elem(
             &HorizontalListElem::Char {
@@ -405,6 +1180,8 @@ mod tests {
                 font: cmtt10.clone(),
             },
             &None,
+            &Dimen::zero(),
+            &Dimen::zero(),
         );
         writer.add_horizontal_list_elem(
             &HorizontalListElem::Char {
@@ -412,6 +1189,8 @@ mod tests {
                 font: cmr7.clone(),
             },
             &None,
+            &Dimen::zero(),
+            &Dimen::zero(),
         );
         writer.add_horizontal_list_elem(
             &HorizontalListElem::Char {
@@ -419,6 +1198,8 @@ mod tests {
                 font: CMR10.clone(),
             },
             &None,
+            &Dimen::zero(),
+            &Dimen::zero(),
         );
 
         let cmr10_metrics = FontMetrics::from_font(&CMR10).unwrap();
@@ -499,6 +1280,63 @@ mod tests {
         );
     }
 
+    // Finds how far each movement command moves, including the ones which
+    // reuse the amount in a register.
+    fn get_movement_amounts(commands: &[DVICommand]) -> Vec<i32> {
+        let (mut w, mut x, mut y, mut z) = (0, 0, 0, 0);
+        commands
+            .iter()
+            .filter_map(|command| match *command {
+                DVICommand::Right1(b) => Some(i32::from(b)),
+                DVICommand::Right2(b) => Some(i32::from(b)),
+                DVICommand::Right3(b) | DVICommand::Right4(b) => Some(b),
+                DVICommand::W0 => Some(w),
+                DVICommand::W3(b) => {
+                    w = b;
+                    Some(b)
+                }
+                DVICommand::X0 => Some(x),
+                DVICommand::X3(b) => {
+                    x = b;
+                    Some(b)
+                }
+                DVICommand::Down1(a) => Some(i32::from(a)),
+                DVICommand::Down2(a) => Some(i32::from(a)),
+                DVICommand::Down3(a) | DVICommand::Down4(a) => Some(a),
+                DVICommand::Y0 => Some(y),
+                DVICommand::Y3(a) => {
+                    y = a;
+                    Some(a)
+                }
+                DVICommand::Z0 => Some(z),
+                DVICommand::Z3(a) => {
+                    z = a;
+                    Some(a)
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn it_ignores_penalties() {
+        let mut writer = DVIFileWriter::new();
+
+        writer.add_horizontal_list_elem(
+            &HorizontalListElem::Penalty(100),
+            &None,
+            &Dimen::zero(),
+            &Dimen::zero(),
+        );
+        writer.add_vertical_list_elem(
+            &VerticalListElem::Penalty(-100),
+            &None,
+            &Dimen::zero(),
+        );
+
+        assert_eq!(writer.commands, vec![]);
+    }
+
     #[test]
     fn it_adds_hskips() {
         let mut writer = DVIFileWriter::new();
@@ -510,6 +1348,8 @@ mod tests {
                 Unit::Point,
             ))),
             &None,
+            &Dimen::zero(),
+            &Dimen::zero(),
         );
         writer.add_horizontal_list_elem(
             &HorizontalListElem::HSkip(Glue::from_dimen(Dimen::from_unit(
@@ -517,6 +1357,8 @@ mod tests {
                 Unit::Point,
             ))),
             &Some(GlueSetRatio::from(GlueSetRatioKind::Finite, 2.0)),
+            &Dimen::zero(),
+            &Dimen::zero(),
         );
 
         // Finite stretch
@@ -527,6 +1369,8 @@ mod tests {
                 shrink: SpringDimen::Dimen(Dimen::zero()),
             }),
             &None,
+            &Dimen::zero(),
+            &Dimen::zero(),
         );
         writer.add_horizontal_list_elem(
             &HorizontalListElem::HSkip(Glue {
@@ -535,6 +1379,8 @@ mod tests {
                 shrink: SpringDimen::Dimen(Dimen::zero()),
             }),
             &Some(GlueSetRatio::from(GlueSetRatioKind::Finite, 1.5)),
+            &Dimen::zero(),
+            &Dimen::zero(),
         );
         writer.add_horizontal_list_elem(
             &HorizontalListElem::HSkip(Glue {
@@ -543,6 +1389,8 @@ mod tests {
                 shrink: SpringDimen::Dimen(Dimen::zero()),
             }),
             &Some(GlueSetRatio::from(GlueSetRatioKind::Fil, 2.0)),
+            &Dimen::zero(),
+            &Dimen::zero(),
         );
         writer.add_horizontal_list_elem(
             &HorizontalListElem::HSkip(Glue {
@@ -551,6 +1399,8 @@ mod tests {
                 shrink: SpringDimen::Dimen(Dimen::zero()),
             }),
             &Some(GlueSetRatio::from(GlueSetRatioKind::Finite, -1.5)),
+            &Dimen::zero(),
+            &Dimen::zero(),
         );
 
         // Finite shrink
@@ -561,6 +1411,8 @@ mod tests {
                 shrink: SpringDimen::Dimen(Dimen::from_unit(2.0, Unit::Point)),
             }),
             &None,
+            &Dimen::zero(),
+            &Dimen::zero(),
         );
         writer.add_horizontal_list_elem(
             &HorizontalListElem::HSkip(Glue {
@@ -569,6 +1421,8 @@ mod tests {
                 shrink: SpringDimen::Dimen(Dimen::from_unit(2.0, Unit::Point)),
             }),
             &Some(GlueSetRatio::from(GlueSetRatioKind::Finite, -0.5)),
+            &Dimen::zero(),
+            &Dimen::zero(),
         );
         writer.add_horizontal_list_elem(
             &HorizontalListElem::HSkip(Glue {
@@ -577,6 +1431,8 @@ mod tests {
                 shrink: SpringDimen::Dimen(Dimen::from_unit(2.0, Unit::Point)),
             }),
             &Some(GlueSetRatio::from(GlueSetRatioKind::Fil, -1.5)),
+            &Dimen::zero(),
+            &Dimen::zero(),
         );
         writer.add_horizontal_list_elem(
             &HorizontalListElem::HSkip(Glue {
@@ -585,6 +1441,8 @@ mod tests {
                 shrink: SpringDimen::Dimen(Dimen::from_unit(2.0, Unit::Point)),
             }),
             &Some(GlueSetRatio::from(GlueSetRatioKind::Finite, 1.5)),
+            &Dimen::zero(),
+            &Dimen::zero(),
         );
 
         // Infinite stretch
@@ -598,6 +1456,8 @@ mod tests {
                 shrink: SpringDimen::Dimen(Dimen::zero()),
             }),
             &None,
+            &Dimen::zero(),
+            &Dimen::zero(),
         );
         writer.add_horizontal_list_elem(
             &HorizontalListElem::HSkip(Glue {
@@ -609,6 +1469,8 @@ mod tests {
                 shrink: SpringDimen::Dimen(Dimen::zero()),
             }),
             &Some(GlueSetRatio::from(GlueSetRatioKind::Fil, 1.5)),
+            &Dimen::zero(),
+            &Dimen::zero(),
         );
         writer.add_horizontal_list_elem(
             &HorizontalListElem::HSkip(Glue {
@@ -620,6 +1482,8 @@ mod tests {
                 shrink: SpringDimen::Dimen(Dimen::zero()),
             }),
             &Some(GlueSetRatio::from(GlueSetRatioKind::Finite, 1.5)),
+            &Dimen::zero(),
+            &Dimen::zero(),
         );
         writer.add_horizontal_list_elem(
             &HorizontalListElem::HSkip(Glue {
@@ -631,6 +1495,8 @@ mod tests {
                 shrink: SpringDimen::Dimen(Dimen::zero()),
             }),
             &Some(GlueSetRatio::from(GlueSetRatioKind::Fill, 1.5)),
+            &Dimen::zero(),
+            &Dimen::zero(),
         );
         writer.add_horizontal_list_elem(
             &HorizontalListElem::HSkip(Glue {
@@ -642,6 +1508,8 @@ mod tests {
                 shrink: SpringDimen::Dimen(Dimen::zero()),
             }),
             &Some(GlueSetRatio::from(GlueSetRatioKind::Fil, -0.5)),
+            &Dimen::zero(),
+            &Dimen::zero(),
         );
 
         // Infinite shrink
@@ -652,6 +1520,8 @@ mod tests {
                 shrink: SpringDimen::FilDimen(FilDimen::new(FilKind::Fil, 2.0)),
             }),
             &None,
+            &Dimen::zero(),
+            &Dimen::zero(),
         );
         writer.add_horizontal_list_elem(
             &HorizontalListElem::HSkip(Glue {
@@ -660,6 +1530,8 @@ mod tests {
                 shrink: SpringDimen::FilDimen(FilDimen::new(FilKind::Fil, 2.0)),
             }),
             &Some(GlueSetRatio::from(GlueSetRatioKind::Fil, -1.5)),
+            &Dimen::zero(),
+            &Dimen::zero(),
         );
         writer.add_horizontal_list_elem(
             &HorizontalListElem::HSkip(Glue {
@@ -668,6 +1540,8 @@ mod tests {
                 shrink: SpringDimen::FilDimen(FilDimen::new(FilKind::Fil, 2.0)),
             }),
             &Some(GlueSetRatio::from(GlueSetRatioKind::Finite, -0.5)),
+            &Dimen::zero(),
+            &Dimen::zero(),
         );
         writer.add_horizontal_list_elem(
             &HorizontalListElem::HSkip(Glue {
@@ -676,6 +1550,8 @@ mod tests {
                 shrink: SpringDimen::FilDimen(FilDimen::new(FilKind::Fil, 2.0)),
             }),
             &Some(GlueSetRatio::from(GlueSetRatioKind::Fill, -1.5)),
+            &Dimen::zero(),
+            &Dimen::zero(),
         );
         writer.add_horizontal_list_elem(
             &HorizontalListElem::HSkip(Glue {
@@ -684,31 +1560,33 @@ mod tests {
                 shrink: SpringDimen::FilDimen(FilDimen::new(FilKind::Fil, 2.0)),
             }),
             &Some(GlueSetRatio::from(GlueSetRatioKind::Fil, 1.5)),
+            &Dimen::zero(),
+            &Dimen::zero(),
         );
 
         assert_eq!(
-            &writer.commands,
-            &[
-                DVICommand::Right4(2 * 65536),
-                DVICommand::Right4(2 * 65536),
-                DVICommand::Right4(2 * 65536),
-                DVICommand::Right4(2 * 65536 + 3 * 3 * 65536 / 2),
-                DVICommand::Right4(2 * 65536),
-                DVICommand::Right4(2 * 65536),
-                DVICommand::Right4(4 * 65536),
-                DVICommand::Right4(4 * 65536 - 2 * 65536 / 2),
-                DVICommand::Right4(4 * 65536),
-                DVICommand::Right4(4 * 65536),
-                DVICommand::Right4(2 * 65536),
-                DVICommand::Right4(2 * 65536 + 3 * 3 * 65536 / 2),
-                DVICommand::Right4(2 * 65536),
-                DVICommand::Right4(2 * 65536),
-                DVICommand::Right4(2 * 65536),
-                DVICommand::Right4(6 * 65536),
-                DVICommand::Right4(6 * 65536 - 3 * 65536),
-                DVICommand::Right4(6 * 65536),
-                DVICommand::Right4(6 * 65536),
-                DVICommand::Right4(6 * 65536),
+            get_movement_amounts(&writer.commands),
+            vec![
+                2 * 65536,
+                2 * 65536,
+                2 * 65536,
+                2 * 65536 + 3 * 3 * 65536 / 2,
+                2 * 65536,
+                2 * 65536,
+                4 * 65536,
+                4 * 65536 - 2 * 65536 / 2,
+                4 * 65536,
+                4 * 65536,
+                2 * 65536,
+                2 * 65536 + 3 * 3 * 65536 / 2,
+                2 * 65536,
+                2 * 65536,
+                2 * 65536,
+                6 * 65536,
+                6 * 65536 - 3 * 65536,
+                6 * 65536,
+                6 * 65536,
+                6 * 65536,
             ]
         );
     }
@@ -746,6 +1624,47 @@ mod tests {
         }
     }
 
+    #[test]
+    fn it_adds_the_no_break_material_of_discretionaries() {
+        let mut writer = DVIFileWriter::new();
+
+        let metrics = FontMetrics::from_font(&CMR10).unwrap();
+        let char_elem = |chr| HorizontalListElem::Char {
+            chr,
+            font: CMR10.clone(),
+        };
+
+        let tex_box = TeXBox::HorizontalBox(HorizontalBox {
+            height: metrics.get_height('b'),
+            depth: Dimen::zero(),
+            width: metrics.get_width('a') + metrics.get_width('b'),
+
+            list: Rc::new(vec![
+                char_elem('a'),
+                HorizontalListElem::Discretionary {
+                    pre_break: vec![char_elem('-')],
+                    post_break: vec![char_elem('c')],
+                    no_break: vec![char_elem('b')],
+                },
+            ]),
+            glue_set_ratio: None,
+        });
+
+        writer.add_box(&tex_box);
+
+        assert_matches(
+            &writer.commands,
+            &[
+                MaybeEquals::Equals(DVICommand::Push),
+                MaybeEquals::Anything,
+                MaybeEquals::Anything,
+                MaybeEquals::Equals(DVICommand::SetCharN(97)),
+                MaybeEquals::Equals(DVICommand::SetCharN(98)),
+                MaybeEquals::Equals(DVICommand::Pop),
+            ],
+        );
+    }
+
     #[test]
     fn it_adds_basic_horizontal_boxes() {
         let mut writer = DVIFileWriter::new();
@@ -757,10 +1676,10 @@ mod tests {
             depth: metrics.get_depth('a'),
             width: metrics.get_width('a'),
 
-            list: vec![HorizontalListElem::Char {
+            list: Rc::new(vec![HorizontalListElem::Char {
                 chr: 'a',
                 font: CMR10.clone(),
-            }],
+            }]),
             glue_set_ratio: None,
         });
 
@@ -771,6 +1690,8 @@ mod tests {
                 shift: Dimen::zero(),
             },
             &None,
+            &Dimen::zero(),
+            &Dimen::zero(),
         );
 
         assert_matches(
@@ -784,7 +1705,7 @@ mod tests {
                 MaybeEquals::Equals(DVICommand::Push),
                 MaybeEquals::Equals(DVICommand::SetCharN(97)),
                 MaybeEquals::Equals(DVICommand::Pop),
-                MaybeEquals::Equals(DVICommand::Right4(
+                MaybeEquals::Equals(DVICommand::Right3(
                     metrics.get_width('a').as_scaled_points(),
                 )),
             ],
@@ -792,23 +1713,123 @@ mod tests {
     }
 
     #[test]
-    fn it_adds_vskips() {
+    fn it_doesnt_stretch_kerns_in_horizontal_boxes() {
         let mut writer = DVIFileWriter::new();
 
-        // No stretch/shrink
-        writer.add_vertical_list_elem(
-            &VerticalListElem::VSkip(Glue::from_dimen(Dimen::from_unit(
-                2.0,
-                Unit::Point,
-            ))),
-            &None,
+        let tex_box = TeXBox::HorizontalBox(HorizontalBox {
+            height: Dimen::zero(),
+            depth: Dimen::zero(),
+            width: Dimen::from_unit(20.0, Unit::Point),
+
+            list: Rc::new(vec![
+                HorizontalListElem::Kern(Dimen::from_unit(3.0, Unit::Point)),
+                HorizontalListElem::HSkip(Glue {
+                    space: Dimen::zero(),
+                    stretch: SpringDimen::FilDimen(FilDimen::new(
+                        FilKind::Fil,
+                        1.0,
+                    )),
+                    shrink: SpringDimen::Dimen(Dimen::zero()),
+                }),
+                HorizontalListElem::Kern(Dimen::from_unit(2.0, Unit::Point)),
+            ]),
+            glue_set_ratio: Some(GlueSetRatio::from(
+                GlueSetRatioKind::Fil,
+                15.0,
+            )),
+        });
+
+        writer.add_box(&tex_box);
+
+        assert_eq!(
+            writer.commands,
+            vec![
+                DVICommand::Push,
+                DVICommand::Right3(
+                    Dimen::from_unit(3.0, Unit::Point).as_scaled_points()
+                ),
+                DVICommand::Right3(
+                    Dimen::from_unit(15.0, Unit::Point).as_scaled_points()
+                ),
+                DVICommand::Right3(
+                    Dimen::from_unit(2.0, Unit::Point).as_scaled_points()
+                ),
+                DVICommand::Pop,
+            ]
         );
-        writer.add_vertical_list_elem(
+    }
+
+    #[test]
+    fn it_doesnt_stretch_kerns_in_vertical_boxes() {
+        let mut writer = DVIFileWriter::new();
+
+        let tex_box = TeXBox::VerticalBox(VerticalBox {
+            height: Dimen::from_unit(20.0, Unit::Point),
+            depth: Dimen::zero(),
+            width: Dimen::zero(),
+
+            list: Rc::new(vec![
+                VerticalListElem::Kern(Dimen::from_unit(3.0, Unit::Point)),
+                VerticalListElem::VSkip(Glue {
+                    space: Dimen::zero(),
+                    stretch: SpringDimen::FilDimen(FilDimen::new(
+                        FilKind::Fil,
+                        1.0,
+                    )),
+                    shrink: SpringDimen::Dimen(Dimen::zero()),
+                }),
+                VerticalListElem::Kern(Dimen::from_unit(2.0, Unit::Point)),
+            ]),
+            glue_set_ratio: Some(GlueSetRatio::from(
+                GlueSetRatioKind::Fil,
+                15.0,
+            )),
+        });
+
+        writer.add_box(&tex_box);
+
+        assert_eq!(
+            writer.commands,
+            vec![
+                DVICommand::Push,
+                // Vertical boxes start at their top.
+                DVICommand::Down3(
+                    Dimen::from_unit(-20.0, Unit::Point).as_scaled_points()
+                ),
+                DVICommand::Down3(
+                    Dimen::from_unit(3.0, Unit::Point).as_scaled_points()
+                ),
+                DVICommand::Down3(
+                    Dimen::from_unit(15.0, Unit::Point).as_scaled_points()
+                ),
+                DVICommand::Down3(
+                    Dimen::from_unit(2.0, Unit::Point).as_scaled_points()
+                ),
+                DVICommand::Pop,
+            ]
+        );
+    }
+
+    #[test]
+    fn it_adds_vskips() {
+        let mut writer = DVIFileWriter::new();
+
+        // No stretch/shrink
+        writer.add_vertical_list_elem(
+            &VerticalListElem::VSkip(Glue::from_dimen(Dimen::from_unit(
+                2.0,
+                Unit::Point,
+            ))),
+            &None,
+            &Dimen::zero(),
+        );
+        writer.add_vertical_list_elem(
             &VerticalListElem::VSkip(Glue::from_dimen(Dimen::from_unit(
                 2.0,
                 Unit::Point,
             ))),
             &Some(GlueSetRatio::from(GlueSetRatioKind::Finite, 2.0)),
+            &Dimen::zero(),
         );
 
         // Finite stretch
@@ -819,6 +1840,7 @@ mod tests {
                 shrink: SpringDimen::Dimen(Dimen::zero()),
             }),
             &None,
+            &Dimen::zero(),
         );
         writer.add_vertical_list_elem(
             &VerticalListElem::VSkip(Glue {
@@ -827,6 +1849,7 @@ mod tests {
                 shrink: SpringDimen::Dimen(Dimen::zero()),
             }),
             &Some(GlueSetRatio::from(GlueSetRatioKind::Finite, 1.5)),
+            &Dimen::zero(),
         );
         writer.add_vertical_list_elem(
             &VerticalListElem::VSkip(Glue {
@@ -835,6 +1858,7 @@ mod tests {
                 shrink: SpringDimen::Dimen(Dimen::zero()),
             }),
             &Some(GlueSetRatio::from(GlueSetRatioKind::Fil, 2.0)),
+            &Dimen::zero(),
         );
         writer.add_vertical_list_elem(
             &VerticalListElem::VSkip(Glue {
@@ -843,6 +1867,7 @@ mod tests {
                 shrink: SpringDimen::Dimen(Dimen::zero()),
             }),
             &Some(GlueSetRatio::from(GlueSetRatioKind::Finite, -1.5)),
+            &Dimen::zero(),
         );
 
         // Finite shrink
@@ -853,6 +1878,7 @@ mod tests {
                 shrink: SpringDimen::Dimen(Dimen::from_unit(2.0, Unit::Point)),
             }),
             &None,
+            &Dimen::zero(),
         );
         writer.add_vertical_list_elem(
             &VerticalListElem::VSkip(Glue {
@@ -861,6 +1887,7 @@ mod tests {
                 shrink: SpringDimen::Dimen(Dimen::from_unit(2.0, Unit::Point)),
             }),
             &Some(GlueSetRatio::from(GlueSetRatioKind::Finite, -0.5)),
+            &Dimen::zero(),
         );
         writer.add_vertical_list_elem(
             &VerticalListElem::VSkip(Glue {
@@ -869,6 +1896,7 @@ mod tests {
                 shrink: SpringDimen::Dimen(Dimen::from_unit(2.0, Unit::Point)),
             }),
             &Some(GlueSetRatio::from(GlueSetRatioKind::Fil, -1.5)),
+            &Dimen::zero(),
         );
         writer.add_vertical_list_elem(
             &VerticalListElem::VSkip(Glue {
@@ -877,6 +1905,7 @@ mod tests {
                 shrink: SpringDimen::Dimen(Dimen::from_unit(2.0, Unit::Point)),
             }),
             &Some(GlueSetRatio::from(GlueSetRatioKind::Finite, 1.5)),
+            &Dimen::zero(),
         );
 
         // Infinite stretch
@@ -890,6 +1919,7 @@ mod tests {
                 shrink: SpringDimen::Dimen(Dimen::zero()),
             }),
             &None,
+            &Dimen::zero(),
         );
         writer.add_vertical_list_elem(
             &VerticalListElem::VSkip(Glue {
@@ -901,6 +1931,7 @@ mod tests {
                 shrink: SpringDimen::Dimen(Dimen::zero()),
             }),
             &Some(GlueSetRatio::from(GlueSetRatioKind::Fil, 1.5)),
+            &Dimen::zero(),
         );
         writer.add_vertical_list_elem(
             &VerticalListElem::VSkip(Glue {
@@ -912,6 +1943,7 @@ mod tests {
                 shrink: SpringDimen::Dimen(Dimen::zero()),
             }),
             &Some(GlueSetRatio::from(GlueSetRatioKind::Finite, 1.5)),
+            &Dimen::zero(),
         );
         writer.add_vertical_list_elem(
             &VerticalListElem::VSkip(Glue {
@@ -923,6 +1955,7 @@ mod tests {
                 shrink: SpringDimen::Dimen(Dimen::zero()),
             }),
             &Some(GlueSetRatio::from(GlueSetRatioKind::Fill, 1.5)),
+            &Dimen::zero(),
         );
         writer.add_vertical_list_elem(
             &VerticalListElem::VSkip(Glue {
@@ -934,6 +1967,7 @@ mod tests {
                 shrink: SpringDimen::Dimen(Dimen::zero()),
             }),
             &Some(GlueSetRatio::from(GlueSetRatioKind::Fil, -0.5)),
+            &Dimen::zero(),
         );
 
         // Infinite shrink
@@ -944,6 +1978,7 @@ mod tests {
                 shrink: SpringDimen::FilDimen(FilDimen::new(FilKind::Fil, 2.0)),
             }),
             &None,
+            &Dimen::zero(),
         );
         writer.add_vertical_list_elem(
             &VerticalListElem::VSkip(Glue {
@@ -952,6 +1987,7 @@ mod tests {
                 shrink: SpringDimen::FilDimen(FilDimen::new(FilKind::Fil, 2.0)),
             }),
             &Some(GlueSetRatio::from(GlueSetRatioKind::Fil, -1.5)),
+            &Dimen::zero(),
         );
         writer.add_vertical_list_elem(
             &VerticalListElem::VSkip(Glue {
@@ -960,6 +1996,7 @@ mod tests {
                 shrink: SpringDimen::FilDimen(FilDimen::new(FilKind::Fil, 2.0)),
             }),
             &Some(GlueSetRatio::from(GlueSetRatioKind::Finite, -0.5)),
+            &Dimen::zero(),
         );
         writer.add_vertical_list_elem(
             &VerticalListElem::VSkip(Glue {
@@ -968,6 +2005,7 @@ mod tests {
                 shrink: SpringDimen::FilDimen(FilDimen::new(FilKind::Fil, 2.0)),
             }),
             &Some(GlueSetRatio::from(GlueSetRatioKind::Fill, -1.5)),
+            &Dimen::zero(),
         );
         writer.add_vertical_list_elem(
             &VerticalListElem::VSkip(Glue {
@@ -976,31 +2014,32 @@ mod tests {
                 shrink: SpringDimen::FilDimen(FilDimen::new(FilKind::Fil, 2.0)),
             }),
             &Some(GlueSetRatio::from(GlueSetRatioKind::Fil, 1.5)),
+            &Dimen::zero(),
         );
 
         assert_eq!(
-            &writer.commands,
-            &[
-                DVICommand::Down4(2 * 65536),
-                DVICommand::Down4(2 * 65536),
-                DVICommand::Down4(2 * 65536),
-                DVICommand::Down4(2 * 65536 + 3 * 3 * 65536 / 2),
-                DVICommand::Down4(2 * 65536),
-                DVICommand::Down4(2 * 65536),
-                DVICommand::Down4(4 * 65536),
-                DVICommand::Down4(4 * 65536 - 2 * 65536 / 2),
-                DVICommand::Down4(4 * 65536),
-                DVICommand::Down4(4 * 65536),
-                DVICommand::Down4(2 * 65536),
-                DVICommand::Down4(2 * 65536 + 3 * 3 * 65536 / 2),
-                DVICommand::Down4(2 * 65536),
-                DVICommand::Down4(2 * 65536),
-                DVICommand::Down4(2 * 65536),
-                DVICommand::Down4(6 * 65536),
-                DVICommand::Down4(6 * 65536 - 3 * 65536),
-                DVICommand::Down4(6 * 65536),
-                DVICommand::Down4(6 * 65536),
-                DVICommand::Down4(6 * 65536),
+            get_movement_amounts(&writer.commands),
+            vec![
+                2 * 65536,
+                2 * 65536,
+                2 * 65536,
+                2 * 65536 + 3 * 3 * 65536 / 2,
+                2 * 65536,
+                2 * 65536,
+                4 * 65536,
+                4 * 65536 - 2 * 65536 / 2,
+                4 * 65536,
+                4 * 65536,
+                2 * 65536,
+                2 * 65536 + 3 * 3 * 65536 / 2,
+                2 * 65536,
+                2 * 65536,
+                2 * 65536,
+                6 * 65536,
+                6 * 65536 - 3 * 65536,
+                6 * 65536,
+                6 * 65536,
+                6 * 65536,
             ]
         );
     }
@@ -1016,10 +2055,10 @@ mod tests {
             depth: metrics.get_depth('g'),
             width: metrics.get_width('g'),
 
-            list: vec![HorizontalListElem::Char {
+            list: Rc::new(vec![HorizontalListElem::Char {
                 chr: 'g',
                 font: CMR10.clone(),
-            }],
+            }]),
             glue_set_ratio: None,
         });
 
@@ -1028,28 +2067,38 @@ mod tests {
             depth: *hbox.depth() + Dimen::from_unit(2.0, Unit::Point),
             width: *hbox.width(),
 
-            list: vec![
-                VerticalListElem::Box(hbox.clone()),
+            list: Rc::new(vec![
+                VerticalListElem::Box {
+                    tex_box: hbox.clone(),
+                    shift: Dimen::zero(),
+                },
                 VerticalListElem::VSkip(Glue {
                     space: Dimen::from_unit(2.0, Unit::Point),
                     stretch: SpringDimen::Dimen(Dimen::zero()),
                     shrink: SpringDimen::Dimen(Dimen::zero()),
                 }),
-            ],
+            ]),
             glue_set_ratio: None,
         });
 
         writer.add_box(&vbox);
-        writer.add_vertical_list_elem(&VerticalListElem::Box(vbox), &None);
+        writer.add_vertical_list_elem(
+            &VerticalListElem::Box {
+                tex_box: vbox,
+                shift: Dimen::zero(),
+            },
+            &None,
+            &Dimen::zero(),
+        );
 
         assert_matches(
             &writer.commands,
             &[
                 MaybeEquals::Equals(DVICommand::Push),
-                MaybeEquals::Equals(DVICommand::Down4(
+                MaybeEquals::Equals(DVICommand::Down3(
                     -hbox.height().as_scaled_points(),
                 )),
-                MaybeEquals::Equals(DVICommand::Down4(
+                MaybeEquals::Equals(DVICommand::Down3(
                     hbox.height().as_scaled_points(),
                 )),
                 MaybeEquals::Equals(DVICommand::Push),
@@ -1057,38 +2106,219 @@ mod tests {
                 MaybeEquals::Anything,
                 MaybeEquals::Equals(DVICommand::SetCharN(103)),
                 MaybeEquals::Equals(DVICommand::Pop),
-                MaybeEquals::Equals(DVICommand::Down4(
+                MaybeEquals::Equals(DVICommand::Down3(
                     hbox.depth().as_scaled_points(),
                 )),
-                MaybeEquals::Equals(DVICommand::Down4(131072)),
+                MaybeEquals::Equals(DVICommand::Down3(131072)),
                 MaybeEquals::Equals(DVICommand::Pop),
-                MaybeEquals::Equals(DVICommand::Down4(
+                MaybeEquals::Equals(DVICommand::Y3(
                     hbox.height().as_scaled_points(),
                 )),
                 MaybeEquals::Equals(DVICommand::Push),
-                MaybeEquals::Equals(DVICommand::Down4(
+                MaybeEquals::Equals(DVICommand::Down3(
                     -hbox.height().as_scaled_points(),
                 )),
-                MaybeEquals::Equals(DVICommand::Down4(
-                    hbox.height().as_scaled_points(),
-                )),
+                MaybeEquals::Equals(DVICommand::Y0),
                 MaybeEquals::Equals(DVICommand::Push),
                 MaybeEquals::Equals(DVICommand::SetCharN(103)),
                 MaybeEquals::Equals(DVICommand::Pop),
-                MaybeEquals::Equals(DVICommand::Down4(
+                MaybeEquals::Equals(DVICommand::Down3(
                     hbox.depth().as_scaled_points(),
                 )),
-                MaybeEquals::Equals(DVICommand::Down4(131072)),
+                MaybeEquals::Equals(DVICommand::Down3(131072)),
                 MaybeEquals::Equals(DVICommand::Pop),
-                MaybeEquals::Equals(DVICommand::Down4(
+                MaybeEquals::Equals(DVICommand::Down3(
                     hbox.depth().as_scaled_points() + 131072,
                 )),
             ],
         );
     }
 
+    #[test]
+    fn it_adds_rules_in_vertical_boxes() {
+        let mut writer = DVIFileWriter::new();
+
+        let vbox = TeXBox::VerticalBox(VerticalBox {
+            height: Dimen::from_unit(3.0, Unit::Point),
+            depth: Dimen::zero(),
+            width: Dimen::from_unit(5.0, Unit::Point),
+
+            list: Rc::new(vec![
+                VerticalListElem::Rule {
+                    height: Dimen::from_unit(1.0, Unit::Point),
+                    depth: Dimen::zero(),
+                    width: None,
+                },
+                VerticalListElem::Rule {
+                    height: Dimen::from_unit(1.5, Unit::Point),
+                    depth: Dimen::from_unit(0.5, Unit::Point),
+                    width: Some(Dimen::from_unit(2.0, Unit::Point)),
+                },
+            ]),
+            glue_set_ratio: None,
+        });
+
+        writer.add_box(&vbox);
+
+        // Rules with running widths span the width of the box.
+        assert_eq!(
+            &writer.commands,
+            &[
+                DVICommand::Push,
+                DVICommand::Down3(-3 * 65536),
+                DVICommand::Down3(65536),
+                DVICommand::PutRule {
+                    height: 65536,
+                    width: 5 * 65536,
+                },
+                DVICommand::Down3(2 * 65536),
+                DVICommand::PutRule {
+                    height: 2 * 65536,
+                    width: 2 * 65536,
+                },
+                DVICommand::Pop,
+            ]
+        );
+    }
+
+    #[test]
+    fn it_adds_rules_in_horizontal_boxes() {
+        let mut writer = DVIFileWriter::new();
+
+        let hbox = TeXBox::HorizontalBox(HorizontalBox {
+            height: Dimen::from_unit(3.0, Unit::Point),
+            depth: Dimen::from_unit(1.0, Unit::Point),
+            width: Dimen::from_unit(5.0, Unit::Point),
+
+            list: Rc::new(vec![
+                HorizontalListElem::Rule {
+                    height: None,
+                    depth: None,
+                    width: Dimen::from_unit(1.0, Unit::Point),
+                },
+                HorizontalListElem::Rule {
+                    height: Some(Dimen::from_unit(2.0, Unit::Point)),
+                    depth: Some(Dimen::zero()),
+                    width: Dimen::from_unit(4.0, Unit::Point),
+                },
+            ]),
+            glue_set_ratio: None,
+        });
+
+        writer.add_box(&hbox);
+
+        // Rules with running heights and depths span the height and depth of
+        // the box.
+        assert_eq!(
+            &writer.commands,
+            &[
+                DVICommand::Push,
+                DVICommand::Down3(65536),
+                DVICommand::SetRule {
+                    height: 4 * 65536,
+                    width: 65536,
+                },
+                DVICommand::Down3(-65536),
+                DVICommand::SetRule {
+                    height: 2 * 65536,
+                    width: 4 * 65536,
+                },
+                DVICommand::Pop,
+            ]
+        );
+    }
+
+    use crate::state::TeXState;
     use crate::testing::with_parser;
 
+    #[test]
+    fn it_spans_boxes_with_running_rule_dimensions() {
+        with_parser(
+            &[
+                r"\vbox{\hbox to 10pt{}\hrule}%",
+                r"\hbox{\vrule\lower3pt\hbox{\vrule height 5pt}}%",
+            ],
+            |parser| {
+                let mut writer = DVIFileWriter::new();
+                writer.add_box(&parser.parse_box().unwrap());
+
+                // \hrule spans the width of the vbox it's in.
+                assert!(writer.commands.contains(&DVICommand::PutRule {
+                    height: 26214,
+                    width: 10 * 65536,
+                }));
+
+                let mut writer = DVIFileWriter::new();
+                writer.add_box(&parser.parse_box().unwrap());
+
+                // \vrule spans the height and depth of the hbox it's in. (The
+                // move down sets y because the lowered box moves down the same
+                // amount.)
+                assert_eq!(
+                    &writer.commands[..4],
+                    &[
+                        DVICommand::Push,
+                        DVICommand::Y3(3 * 65536),
+                        DVICommand::SetRule {
+                            height: 5 * 65536,
+                            width: 26214,
+                        },
+                        DVICommand::Down3(-3 * 65536),
+                    ]
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn it_draws_rule_leaders_as_a_single_rule() {
+        with_parser(
+            &[
+                r"\def\hfill{\hskip 0pt plus 1fill}%",
+                r"\hbox to20pt{\leaders\hrule\hfill}%",
+                r"\vbox to10pt{\hbox to5pt{}\leaders\hrule\vskip 0pt plus 1fil}%",
+            ],
+            |parser| {
+                parser.parse_assignment();
+
+                let mut writer = DVIFileWriter::new();
+                writer.add_box(&parser.parse_box().unwrap());
+
+                assert_eq!(
+                    writer.commands,
+                    vec![
+                        DVICommand::Push,
+                        DVICommand::SetRule {
+                            height: 26214,
+                            width: 20 * 65536,
+                        },
+                        DVICommand::Pop,
+                    ]
+                );
+
+                let mut writer = DVIFileWriter::new();
+                writer.add_box(&parser.parse_box().unwrap());
+
+                // The rule fills the stretched glue, and has a running width
+                // which spans the vbox.
+                let rules: Vec<&DVICommand> = writer
+                    .commands
+                    .iter()
+                    .filter(|command| {
+                        matches!(command, DVICommand::PutRule { .. })
+                    })
+                    .collect();
+                assert_eq!(
+                    rules,
+                    vec![&DVICommand::PutRule {
+                        height: 10 * 65536,
+                        width: 5 * 65536,
+                    }]
+                );
+            },
+        );
+    }
+
     #[test]
     fn it_adds_pages() {
         let mut writer = DVIFileWriter::new();
@@ -1145,67 +2375,320 @@ mod tests {
                     cs: [1, 0, 0, 0, 0, 0, 0, 0, 0, 0],
                     pointer: -1,
                 }),
-                MaybeEquals::Equals(DVICommand::Down4(
+                MaybeEquals::Equals(DVICommand::Y3(
                     metrics.get_height('g').as_scaled_points(),
                 )),
                 MaybeEquals::Equals(DVICommand::Push),
                 MaybeEquals::Anything,
                 MaybeEquals::Anything,
                 MaybeEquals::Equals(DVICommand::SetCharN(b'g')),
+                MaybeEquals::Equals(line_end_command(&metrics, 'g')),
                 MaybeEquals::Equals(DVICommand::Pop),
-                MaybeEquals::Equals(DVICommand::Down4(
+                MaybeEquals::Equals(DVICommand::Down3(
                     metrics.get_depth('g').as_scaled_points(),
                 )),
-                MaybeEquals::Equals(DVICommand::Down4(0)),
-                MaybeEquals::Equals(DVICommand::Down4(
+                MaybeEquals::Equals(DVICommand::Down3(
                     Dimen::from_unit(12.0, Unit::Point).as_scaled_points()
                         - metrics.get_depth('g').as_scaled_points()
                         - metrics.get_height('a').as_scaled_points(),
                 )),
-                MaybeEquals::Equals(DVICommand::Down4(
-                    metrics.get_height('a').as_scaled_points(),
-                )),
+                MaybeEquals::Equals(DVICommand::Y0),
                 MaybeEquals::Equals(DVICommand::Push),
                 MaybeEquals::Equals(DVICommand::SetCharN(b'a')),
+                MaybeEquals::Equals(line_end_command(&metrics, 'a')),
                 MaybeEquals::Equals(DVICommand::Pop),
-                MaybeEquals::Equals(DVICommand::Down4(
-                    metrics.get_depth('a').as_scaled_points(),
-                )),
                 MaybeEquals::Equals(DVICommand::Eop),
                 MaybeEquals::Equals(DVICommand::Bop {
                     cs: [2, 0, 0, 0, 0, 0, 0, 0, 0, 0],
                     pointer: 0,
                 }),
-                MaybeEquals::Equals(DVICommand::Down4(
+                MaybeEquals::Equals(DVICommand::Down3(
                     metrics.get_height('q').as_scaled_points(),
                 )),
                 MaybeEquals::Equals(DVICommand::Push),
                 MaybeEquals::Anything,
                 MaybeEquals::Equals(DVICommand::SetCharN(b'q')),
+                MaybeEquals::Equals(line_end_command(&metrics, 'q')),
                 MaybeEquals::Equals(DVICommand::Pop),
-                MaybeEquals::Equals(DVICommand::Down4(
+                MaybeEquals::Equals(DVICommand::Down3(
                     metrics.get_depth('q').as_scaled_points(),
                 )),
                 MaybeEquals::Equals(DVICommand::Eop),
                 MaybeEquals::Equals(DVICommand::Bop {
                     cs: [3, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-                    pointer: 111,
+                    pointer: 104,
                 }),
-                MaybeEquals::Equals(DVICommand::Down4(
+                MaybeEquals::Equals(DVICommand::Down3(
                     metrics.get_height('a').as_scaled_points(),
                 )),
                 MaybeEquals::Equals(DVICommand::Push),
                 MaybeEquals::Anything,
                 MaybeEquals::Equals(DVICommand::SetCharN(b'a')),
+                MaybeEquals::Equals(line_end_command(&metrics, 'a')),
                 MaybeEquals::Equals(DVICommand::Pop),
-                MaybeEquals::Equals(DVICommand::Down4(
-                    metrics.get_depth('a').as_scaled_points(),
-                )),
                 MaybeEquals::Equals(DVICommand::Eop),
             ],
         );
     }
 
+    #[test]
+    fn it_defines_fonts_once_across_pages() {
+        let mut writer = DVIFileWriter::new();
+        writer.start((25400000, 473628672), 1000, b"hello".to_vec());
+
+        with_parser(
+            &[
+                r"\font\big=cmr10 scaled 2000%",
+                r"\vbox{\noindent a}%",
+                r"\vbox{\noindent\big a}%",
+                r"\vbox{\noindent a\big a}%",
+            ],
+            |parser| {
+                parser.parse_assignment();
+                for page in 1..=3 {
+                    match parser.parse_box().unwrap() {
+                        TeXBox::VerticalBox(vbox) => writer.add_page(
+                            &vbox.list,
+                            &vbox.glue_set_ratio,
+                            [page, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                        ),
+                        tex_box => panic!("Expected a vbox: {:?}", tex_box),
+                    }
+                }
+            },
+        );
+        writer.end();
+
+        let post_index = writer
+            .commands
+            .iter()
+            .position(|command| matches!(command, DVICommand::Post { .. }))
+            .unwrap();
+        let get_font_def_nums = |commands: &[DVICommand]| {
+            commands
+                .iter()
+                .filter_map(|command| match command {
+                    DVICommand::FntDef4 { font_num, .. } => Some(*font_num),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // Each font is defined once in the body, the first time it's used,
+        // and once in the postamble with the same number.
+        assert_eq!(get_font_def_nums(&writer.commands[..post_index]), [0, 1]);
+        assert_eq!(get_font_def_nums(&writer.commands[post_index..]), [0, 1]);
+
+        // Fonts are selected again on each page, since the current font
+        // isn't kept between pages, but they keep their numbers.
+        let font_switches: Vec<&DVICommand> = writer
+            .commands
+            .iter()
+            .filter(|command| matches!(command, DVICommand::Fnt4(_)))
+            .collect();
+        assert_eq!(
+            font_switches,
+            [
+                &DVICommand::Fnt4(0),
+                &DVICommand::Fnt4(1),
+                &DVICommand::Fnt4(0),
+                &DVICommand::Fnt4(1),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_reselects_fonts_after_boxes_that_switch_fonts() {
+        let mut writer = DVIFileWriter::new();
+
+        with_parser(
+            &[
+                r"\font\big=cmr10 scaled 2000%",
+                r"\hbox{a\hbox{\big a}a\hbox{a}a}%",
+            ],
+            |parser| {
+                parser.parse_assignment();
+                let tex_box = parser.parse_box().unwrap();
+                writer.add_box(&tex_box);
+            },
+        );
+
+        let font_switches: Vec<&DVICommand> = writer
+            .commands
+            .iter()
+            .filter(|command| matches!(command, DVICommand::Fnt4(_)))
+            .collect();
+
+        // The font selected inside the first inner box is still selected
+        // after its pop, so the outer font has to be selected again. The
+        // second inner box uses the same font as the outer box, so no more
+        // switches are needed.
+        assert_eq!(
+            font_switches,
+            [
+                &DVICommand::Fnt4(0),
+                &DVICommand::Fnt4(1),
+                &DVICommand::Fnt4(0),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_uses_the_smallest_movement_commands() {
+        assert_eq!(right_command(0), DVICommand::Right1(0));
+        assert_eq!(right_command(127), DVICommand::Right1(127));
+        assert_eq!(right_command(-128), DVICommand::Right1(-128));
+        assert_eq!(right_command(128), DVICommand::Right2(128));
+        assert_eq!(right_command(-129), DVICommand::Right2(-129));
+        assert_eq!(right_command(32767), DVICommand::Right2(32767));
+        assert_eq!(right_command(-32768), DVICommand::Right2(-32768));
+        assert_eq!(right_command(32768), DVICommand::Right3(32768));
+        assert_eq!(right_command(-32769), DVICommand::Right3(-32769));
+        assert_eq!(right_command(8388607), DVICommand::Right3(8388607));
+        assert_eq!(right_command(-8388608), DVICommand::Right3(-8388608));
+        assert_eq!(right_command(8388608), DVICommand::Right4(8388608));
+        assert_eq!(right_command(-8388609), DVICommand::Right4(-8388609));
+
+        assert_eq!(down_command(127), DVICommand::Down1(127));
+        assert_eq!(down_command(-128), DVICommand::Down1(-128));
+        assert_eq!(down_command(128), DVICommand::Down2(128));
+        assert_eq!(down_command(-129), DVICommand::Down2(-129));
+        assert_eq!(down_command(32767), DVICommand::Down2(32767));
+        assert_eq!(down_command(-32768), DVICommand::Down2(-32768));
+        assert_eq!(down_command(32768), DVICommand::Down3(32768));
+        assert_eq!(down_command(-32769), DVICommand::Down3(-32769));
+        assert_eq!(down_command(8388608), DVICommand::Down4(8388608));
+        assert_eq!(down_command(-8388609), DVICommand::Down4(-8388609));
+    }
+
+    #[test]
+    fn it_writes_the_smallest_movement_commands() {
+        let mut writer = DVIFileWriter::new();
+
+        writer.add_horizontal_list_elem(
+            &HorizontalListElem::Kern(Dimen::from_unit(
+                -128.0,
+                Unit::ScaledPoint,
+            )),
+            &None,
+            &Dimen::zero(),
+            &Dimen::zero(),
+        );
+        writer.add_horizontal_list_elem(
+            &HorizontalListElem::Kern(Dimen::from_unit(
+                32768.0,
+                Unit::ScaledPoint,
+            )),
+            &None,
+            &Dimen::zero(),
+            &Dimen::zero(),
+        );
+        writer.add_vertical_list_elem(
+            &VerticalListElem::VSkip(Glue::from_dimen(Dimen::from_unit(
+                -32768.0,
+                Unit::ScaledPoint,
+            ))),
+            &None,
+            &Dimen::zero(),
+        );
+
+        assert_eq!(
+            writer.commands,
+            vec![
+                DVICommand::Right1(-128),
+                DVICommand::Right3(32768),
+                DVICommand::Down2(-32768),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_reuses_registers_for_repeated_movements() {
+        let mut writer = DVIFileWriter::new();
+
+        let skip = HorizontalListElem::HSkip(Glue::from_dimen(
+            Dimen::from_unit(2.0, Unit::Point),
+        ));
+        for _ in 0..3 {
+            writer.add_horizontal_list_elem(
+                &skip,
+                &None,
+                &Dimen::zero(),
+                &Dimen::zero(),
+            );
+        }
+
+        // The first skip is changed to set w once it's repeated.
+        assert_eq!(
+            writer.commands,
+            vec![DVICommand::W3(2 * 65536), DVICommand::W0, DVICommand::W0]
+        );
+    }
+
+    #[test]
+    fn it_uses_both_registers_for_alternating_movements() {
+        let mut writer = DVIFileWriter::new();
+
+        for amount in &[1000, 2000, 3000, 1000, 2000, 1000] {
+            writer.add_vertical_list_elem(
+                &VerticalListElem::VSkip(Glue::from_dimen(
+                    Dimen::from_scaled_points(*amount),
+                )),
+                &None,
+                &Dimen::zero(),
+            );
+        }
+
+        assert_eq!(
+            writer.commands,
+            vec![
+                DVICommand::Y2(1000),
+                DVICommand::Z2(2000),
+                DVICommand::Down2(3000),
+                DVICommand::Y0,
+                DVICommand::Z0,
+                DVICommand::Y0,
+            ]
+        );
+    }
+
+    #[test]
+    fn it_doesnt_reuse_registers_set_inside_popped_boxes() {
+        let mut writer = DVIFileWriter::new();
+
+        let skip = HorizontalListElem::HSkip(Glue::from_dimen(
+            Dimen::from_scaled_points(1000),
+        ));
+        let hbox = TeXBox::HorizontalBox(HorizontalBox {
+            height: Dimen::zero(),
+            depth: Dimen::zero(),
+            width: Dimen::zero(),
+            list: Rc::new(vec![skip.clone(), skip.clone()]),
+            glue_set_ratio: None,
+        });
+
+        writer.add_box(&hbox);
+        writer.add_horizontal_list_elem(
+            &skip,
+            &None,
+            &Dimen::zero(),
+            &Dimen::zero(),
+        );
+
+        // w goes back to what it was before the push after the pop, so the
+        // last skip can't use it.
+        assert_eq!(
+            writer.commands,
+            vec![
+                DVICommand::Push,
+                DVICommand::W2(1000),
+                DVICommand::W0,
+                DVICommand::Pop,
+                DVICommand::Right2(1000),
+            ]
+        );
+    }
+
     #[test]
     fn it_adds_basic_pre_and_post() {
         let mut writer = DVIFileWriter::new();
@@ -1246,33 +2729,36 @@ mod tests {
                     cs: [1, 0, 0, 0, 0, 0, 0, 0, 0, 0],
                     pointer: -1,
                 }),
-                MaybeEquals::Equals(DVICommand::Down4(
+                MaybeEquals::Equals(DVICommand::Down3(
                     metrics.get_height('a').as_scaled_points(),
                 )),
                 MaybeEquals::Equals(DVICommand::Push),
                 MaybeEquals::Anything,
                 MaybeEquals::Anything,
                 MaybeEquals::Equals(DVICommand::SetCharN(b'a')),
+                MaybeEquals::Equals(line_end_command(&metrics, 'a')),
                 MaybeEquals::Equals(DVICommand::Pop),
-                MaybeEquals::Equals(DVICommand::Down4(
-                    metrics.get_depth('a').as_scaled_points(),
-                )),
                 MaybeEquals::Equals(DVICommand::Eop),
                 MaybeEquals::Equals(DVICommand::Post {
                     pointer: 28,
                     num: 25400000,
                     den: 473628672,
                     mag: 1000,
-                    max_page_height: 43725786,
-                    max_page_width: 30785863,
+                    max_page_height: (metrics.get_height('a')
+                        + metrics.get_depth('a'))
+                    .as_scaled_points()
+                        as u32,
+                    max_page_width: Dimen::from_unit(6.5, Unit::Inch)
+                        .as_scaled_points()
+                        as u32,
                     max_stack_depth: 1,
                     num_pages: 1,
                 }),
                 MaybeEquals::Anything,
                 MaybeEquals::Equals(DVICommand::PostPost {
-                    post_pointer: 116,
+                    post_pointer: 115,
                     format: 2,
-                    tail: 5,
+                    tail: 6,
                 }),
             ],
         );
@@ -1287,6 +2773,69 @@ mod tests {
         assert_eq!(first_font_def, last_font_def);
     }
 
+    #[test]
+    fn it_includes_shifted_boxes_in_the_max_page_height() {
+        let mut writer = DVIFileWriter::new();
+
+        let metrics = FontMetrics::from_font(&CMR10).unwrap();
+
+        writer.start((25400000, 473628672), 1000, vec![]);
+
+        with_parser(
+            &[
+                r"\setbox0=\hbox{\lower 5pt\hbox{a}}%",
+                r"\dp0=0pt%",
+                r"\box0%",
+            ],
+            |parser| {
+                parser.parse_assignment();
+                parser.parse_assignment();
+
+                // The box says it has no depth, but the lowered box inside of
+                // it still sticks out below it.
+                let tex_box = parser.parse_box().unwrap();
+                assert_eq!(*tex_box.height(), Dimen::zero());
+                assert_eq!(*tex_box.depth(), Dimen::zero());
+
+                writer.add_page(
+                    &[VerticalListElem::Box {
+                        tex_box,
+                        shift: Dimen::zero(),
+                    }],
+                    &None,
+                    [1, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                );
+            },
+        );
+
+        writer.end();
+
+        let (max_page_height, max_page_width) = writer
+            .commands
+            .iter()
+            .find_map(|command| match command {
+                DVICommand::Post {
+                    max_page_height,
+                    max_page_width,
+                    ..
+                } => Some((*max_page_height, *max_page_width)),
+                _ => None,
+            })
+            .unwrap();
+
+        // The a is less than 5pt tall, so the outer box has no height and the
+        // page reaches down to the bottom of the lowered a.
+        assert_eq!(
+            max_page_height,
+            (metrics.get_depth('a') + Dimen::from_unit(5.0, Unit::Point))
+                .as_scaled_points() as u32
+        );
+        assert_eq!(
+            max_page_width,
+            metrics.get_width('a').as_scaled_points() as u32
+        );
+    }
+
     #[test]
     fn it_calculates_num_pages_correctly() {
         let mut writer = DVIFileWriter::new();
@@ -1329,8 +2878,8 @@ mod tests {
                     num: 25400000,
                     den: 473628672,
                     mag: 1000,
-                    max_page_height: 43725786,
-                    max_page_width: 30785863,
+                    max_page_height: 0,
+                    max_page_width: 0,
                     max_stack_depth: 0,
                     num_pages: 3,
                 }),
@@ -1375,8 +2924,8 @@ mod tests {
                     num: 25400000,
                     den: 473628672,
                     mag: 1000,
-                    max_page_height: 43725786,
-                    max_page_width: 30785863,
+                    max_page_height: 0,
+                    max_page_width: 0,
                     max_stack_depth: 0,
                     num_pages: 1,
                 }),
@@ -1407,13 +2956,13 @@ mod tests {
             &writer.commands,
             &[
                 MaybeEquals::Equals(DVICommand::Push),
-                MaybeEquals::Equals(DVICommand::Down4(
+                MaybeEquals::Equals(DVICommand::Y3(
                     -metrics.get_height('b').as_scaled_points()
                         - Dimen::from_unit(12.0, Unit::Point)
                             .as_scaled_points()
                         - metrics.get_depth('c').as_scaled_points(),
                 )),
-                MaybeEquals::Equals(DVICommand::Down4(
+                MaybeEquals::Equals(DVICommand::Down3(
                     metrics.get_height('b').as_scaled_points()
                         + Dimen::from_unit(12.0, Unit::Point)
                             .as_scaled_points()
@@ -1424,41 +2973,31 @@ mod tests {
                 MaybeEquals::Anything,
                 MaybeEquals::Equals(DVICommand::SetCharN(b'g')),
                 MaybeEquals::Equals(DVICommand::Push),
-                MaybeEquals::Equals(DVICommand::Down4(
-                    -metrics.get_height('b').as_scaled_points()
-                        - Dimen::from_unit(12.0, Unit::Point)
-                            .as_scaled_points()
-                        - metrics.get_depth('c').as_scaled_points(),
-                )),
-                MaybeEquals::Equals(DVICommand::Down4(
+                MaybeEquals::Equals(DVICommand::Y0),
+                MaybeEquals::Equals(DVICommand::Down3(
                     metrics.get_height('b').as_scaled_points(),
                 )),
                 MaybeEquals::Equals(DVICommand::Push),
                 MaybeEquals::Equals(DVICommand::SetCharN(b'b')),
+                MaybeEquals::Equals(line_end_command(&metrics, 'b')),
                 MaybeEquals::Equals(DVICommand::Pop),
-                MaybeEquals::Equals(DVICommand::Down4(
-                    metrics.get_depth('b').as_scaled_points(),
-                )),
-                MaybeEquals::Equals(DVICommand::Down4(0)),
-                MaybeEquals::Equals(DVICommand::Down4(
+                MaybeEquals::Equals(DVICommand::Down3(
                     Dimen::from_unit(12.0, Unit::Point).as_scaled_points()
                         - metrics.get_height('c').as_scaled_points(),
                 )),
-                MaybeEquals::Equals(DVICommand::Down4(
+                MaybeEquals::Equals(DVICommand::Down3(
                     metrics.get_height('c').as_scaled_points(),
                 )),
                 MaybeEquals::Equals(DVICommand::Push),
                 MaybeEquals::Equals(DVICommand::SetCharN(b'c')),
+                MaybeEquals::Equals(line_end_command(&metrics, 'c')),
                 MaybeEquals::Equals(DVICommand::Pop),
-                MaybeEquals::Equals(DVICommand::Down4(
-                    metrics.get_depth('c').as_scaled_points(),
-                )),
                 MaybeEquals::Equals(DVICommand::Pop),
                 MaybeEquals::Equals(DVICommand::Right4(
-                    metrics.get_width('b').as_scaled_points(),
+                    Dimen::from_unit(6.5, Unit::Inch).as_scaled_points(),
                 )),
                 MaybeEquals::Equals(DVICommand::Pop),
-                MaybeEquals::Equals(DVICommand::Down4(
+                MaybeEquals::Equals(DVICommand::Down3(
                     metrics.get_depth('g').as_scaled_points(),
                 )),
                 MaybeEquals::Equals(DVICommand::Pop),
@@ -1466,6 +3005,227 @@ mod tests {
         );
     }
 
+    #[test]
+    fn it_adds_long_paragraphs_the_same_as_one_char_at_a_time() {
+        // A paragraph with 100,000 characters in it, which is broken into
+        // lines before being added to the page.
+        let line = "pack my box with five dozen liquor jugs ".repeat(33);
+        let lines = vec![line.as_str(); 100];
+
+        let state = TeXState::new();
+        let mut writer = DVIFileWriter::new();
+        let mut naive_writer = DVIFileWriter::new();
+
+        with_parser(&lines, |parser| {
+            let list = parser.parse_horizontal_list(false, false);
+            let num_chars = list
+                .iter()
+                .filter(|elem| matches!(elem, HorizontalListElem::Char { .. }))
+                .count();
+            assert!(num_chars >= 100_000);
+
+            let lines = parser.break_paragraph_into_lines(list);
+            assert!(lines.len() > 1000);
+
+            // The characters in the lines are stored in runs, so there are
+            // far fewer copies of the font than there are characters. Runs
+            // end at spaces and kerns, so this is about one copy for every
+            // two or three characters.
+            let num_fonts = lines
+                .iter()
+                .flat_map(|hbox| hbox.list.iter())
+                .filter(|elem| {
+                    matches!(
+                        elem,
+                        HorizontalListElem::Char { .. }
+                            | HorizontalListElem::CharRun { .. }
+                    )
+                })
+                .count();
+            assert!(num_fonts < num_chars / 2);
+
+            for hbox in lines {
+                // The line's height and depth should match the largest of
+                // the heights and depths of each element individually.
+                let mut height = Dimen::zero();
+                let mut depth = Dimen::zero();
+                for elem in hbox.list.iter() {
+                    let (elem_height, elem_depth, _) = elem.get_size(&state);
+                    if elem_height > height {
+                        height = elem_height;
+                    }
+                    if elem_depth > depth {
+                        depth = elem_depth;
+                    }
+                }
+                assert_eq!((hbox.height, hbox.depth), (height, depth));
+
+                let tex_box = TeXBox::HorizontalBox(hbox.clone());
+                writer.add_box(&tex_box);
+
+                let push_location = naive_writer.commands.len();
+                naive_writer.commands.push(DVICommand::Push);
+                for elem in unpack_char_runs(hbox.list.to_vec()).iter() {
+                    naive_writer.add_horizontal_list_elem(
+                        elem,
+                        &hbox.glue_set_ratio,
+                        &hbox.height,
+                        &hbox.depth,
+                    );
+                }
+                naive_writer.commands.push(DVICommand::Pop);
+                naive_writer.prune_movements(push_location);
+            }
+        });
+
+        assert!(writer.commands.len() > 100_000);
+        assert_eq!(writer.commands, naive_writer.commands);
+    }
+
+    #[test]
+    fn it_finishes_files_without_a_preamble() {
+        let add_pages = |writer: &mut DVIFileWriter| {
+            with_parser(
+                &[r"\vbox{\noindent a}\vbox{\noindent b}%"],
+                |parser| {
+                    for page_num in 1..=2 {
+                        match parser.parse_box().unwrap() {
+                            TeXBox::VerticalBox(vbox) => writer.add_page(
+                                &vbox.list,
+                                &vbox.glue_set_ratio,
+                                [page_num, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                            ),
+                            page => {
+                                panic!("page isn't a vertical box: {:?}", page)
+                            }
+                        }
+                    }
+                },
+            );
+        };
+
+        let mut started_writer = DVIFileWriter::new();
+        started_writer.start(
+            (25400000, 473628672),
+            2000,
+            b"Made by XymosTeX".to_vec(),
+        );
+        add_pages(&mut started_writer);
+        started_writer.end();
+
+        let mut finished_writer = DVIFileWriter::new();
+        add_pages(&mut finished_writer);
+        finished_writer.finish(2000);
+
+        assert_eq!(finished_writer.commands, started_writer.commands);
+    }
+
+    #[test]
+    #[should_panic(expected = "DVI preamble was already written")]
+    fn it_fails_to_finish_files_with_a_preamble() {
+        let mut writer = DVIFileWriter::new();
+        writer.start((25400000, 473628672), 1000, vec![]);
+        writer.finish(1000);
+    }
+
+    #[test]
+    fn it_writes_files_with_consistent_pointers() {
+        let mut writer = DVIFileWriter::new();
+        writer.start((25400000, 473628672), 1000, b"pointers".to_vec());
+
+        with_parser(
+            &[r"\vbox{\noindent a}\vbox{\noindent b}\vbox{\noindent c}%"],
+            |parser| {
+                for page_num in 1..=3 {
+                    match parser.parse_box().unwrap() {
+                        TeXBox::VerticalBox(vbox) => writer.add_page(
+                            &vbox.list,
+                            &vbox.glue_set_ratio,
+                            [page_num, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                        ),
+                        page => {
+                            panic!("page isn't a vertical box: {:?}", page)
+                        }
+                    }
+                }
+            },
+        );
+
+        writer.end();
+
+        let mut bytes: Vec<u8> = Vec::new();
+        writer.write_to(&mut bytes).unwrap();
+
+        // The file is padded out to a multiple of 4 bytes with at least four
+        // 223 bytes.
+        assert_eq!(bytes.len() % 4, 0);
+        assert_eq!(&bytes[bytes.len() - 4..], &[223, 223, 223, 223]);
+
+        let file = DVIFile::new(&bytes[..]).unwrap();
+        assert_eq!(file.commands, writer.commands);
+
+        // Each bop points at the previous bop, the post points at the last
+        // bop, and the post_post points at the post.
+        let mut last_bop_pointer = -1;
+        let mut post_pointer = None;
+        let mut offset = 0;
+        for command in &file.commands {
+            match command {
+                DVICommand::Bop { pointer, .. } => {
+                    assert_eq!(*pointer, last_bop_pointer);
+                    last_bop_pointer = offset as i32;
+                }
+                DVICommand::Post {
+                    pointer, num_pages, ..
+                } => {
+                    assert_eq!(*pointer, last_bop_pointer as u32);
+                    assert_eq!(*num_pages, 3);
+                    post_pointer = Some(offset as u32);
+                }
+                DVICommand::PostPost {
+                    post_pointer: pointer,
+                    ..
+                } => {
+                    assert_eq!(Some(*pointer), post_pointer);
+                }
+                _ => {}
+            }
+
+            offset += command.byte_size();
+        }
+        assert_eq!(offset, bytes.len());
+    }
+
+    #[test]
+    fn it_writes_commands_to_an_output() {
+        let mut writer = DVIFileWriter::new();
+        writer.start((25400000, 473628672), 1000, b"output".to_vec());
+
+        with_parser(&[r"\vbox{\noindent ab}%"], |parser| {
+            match parser.parse_box().unwrap() {
+                TeXBox::VerticalBox(vbox) => writer.add_page(
+                    &vbox.list,
+                    &vbox.glue_set_ratio,
+                    [1, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                ),
+                page => panic!("page isn't a vertical box: {:?}", page),
+            }
+        });
+
+        writer.end();
+
+        let mut bytes: Vec<u8> = Vec::new();
+        let written = writer.write_to(&mut bytes).unwrap();
+
+        let total_size: usize =
+            writer.commands.iter().map(|c| c.byte_size()).sum();
+        assert_eq!(written, total_size);
+        assert_eq!(bytes.len(), total_size);
+
+        let file = DVIFile::new(&bytes[..]).unwrap();
+        assert_eq!(file.commands, writer.commands);
+    }
+
     #[test]
     fn it_calculates_post_post_correctly() {
         let mut writer = DVIFileWriter::new();
@@ -1553,6 +3313,8 @@ mod tests {
                     shift: Dimen::from_unit(2.0, Unit::Point),
                 },
                 &None,
+                &Dimen::zero(),
+                &Dimen::zero(),
             );
             writer.add_horizontal_list_elem(
                 &HorizontalListElem::Box {
@@ -1560,6 +3322,8 @@ mod tests {
                     shift: Dimen::from_unit(-2.0, Unit::Point),
                 },
                 &None,
+                &Dimen::zero(),
+                &Dimen::zero(),
             );
             writer.add_horizontal_list_elem(
                 &HorizontalListElem::Box {
@@ -1567,6 +3331,8 @@ mod tests {
                     shift: Dimen::zero(),
                 },
                 &None,
+                &Dimen::zero(),
+                &Dimen::zero(),
             );
         });
 
@@ -1574,7 +3340,7 @@ mod tests {
             &writer.commands,
             &[
                 MaybeEquals::Equals(DVICommand::Push),
-                MaybeEquals::Equals(DVICommand::Down4(
+                MaybeEquals::Equals(DVICommand::Down3(
                     -Dimen::from_unit(2.0, Unit::Point).as_scaled_points(),
                 )),
                 MaybeEquals::Equals(DVICommand::Push),
@@ -1583,26 +3349,111 @@ mod tests {
                 MaybeEquals::Equals(DVICommand::SetCharN(97)),
                 MaybeEquals::Equals(DVICommand::Pop),
                 MaybeEquals::Equals(DVICommand::Pop),
-                MaybeEquals::Equals(DVICommand::Right4(
+                MaybeEquals::Equals(DVICommand::W3(
                     metrics.get_width('a').as_scaled_points(),
                 )),
                 MaybeEquals::Equals(DVICommand::Push),
-                MaybeEquals::Equals(DVICommand::Down4(
+                MaybeEquals::Equals(DVICommand::Down3(
                     Dimen::from_unit(2.0, Unit::Point).as_scaled_points(),
                 )),
                 MaybeEquals::Equals(DVICommand::Push),
                 MaybeEquals::Equals(DVICommand::SetCharN(97)),
                 MaybeEquals::Equals(DVICommand::Pop),
                 MaybeEquals::Equals(DVICommand::Pop),
-                MaybeEquals::Equals(DVICommand::Right4(
-                    metrics.get_width('a').as_scaled_points(),
+                MaybeEquals::Equals(DVICommand::W0),
+                MaybeEquals::Equals(DVICommand::Push),
+                MaybeEquals::Equals(DVICommand::SetCharN(97)),
+                MaybeEquals::Equals(DVICommand::Pop),
+                MaybeEquals::Equals(DVICommand::W0),
+            ],
+        );
+    }
+
+    #[test]
+    fn it_moves_boxes_in_vertical_lists_right_and_left() {
+        let mut writer = DVIFileWriter::new();
+
+        let metrics = FontMetrics::from_font(&CMR10).unwrap();
+
+        with_parser(
+            &[r"\moveright 5pt\hbox{x}\moveleft 5pt\hbox{x}%"],
+            |parser| {
+                for elem in parser.parse_vertical_list(true) {
+                    writer.add_vertical_list_elem(&elem, &None, &Dimen::zero());
+                }
+            },
+        );
+
+        let five_points = Dimen::from_unit(5.0, Unit::Point);
+        let x_height = metrics.get_height('x').as_scaled_points();
+        assert_matches(
+            &writer.commands,
+            &[
+                MaybeEquals::Equals(DVICommand::Y3(x_height)),
+                MaybeEquals::Equals(DVICommand::Push),
+                MaybeEquals::Equals(DVICommand::Right3(
+                    five_points.as_scaled_points(),
+                )),
+                MaybeEquals::Equals(DVICommand::Push),
+                MaybeEquals::Anything,
+                MaybeEquals::Anything,
+                MaybeEquals::Equals(DVICommand::SetCharN(120)),
+                MaybeEquals::Equals(DVICommand::Pop),
+                MaybeEquals::Equals(DVICommand::Pop),
+                // Interline glue and the second box's height
+                MaybeEquals::Anything,
+                MaybeEquals::Anything,
+                MaybeEquals::Equals(DVICommand::Push),
+                MaybeEquals::Equals(DVICommand::Right3(
+                    -five_points.as_scaled_points(),
+                )),
+                MaybeEquals::Equals(DVICommand::Push),
+                MaybeEquals::Equals(DVICommand::SetCharN(120)),
+                MaybeEquals::Equals(DVICommand::Pop),
+                MaybeEquals::Equals(DVICommand::Pop),
+            ],
+        );
+    }
+
+    #[test]
+    fn it_moves_raised_and_lowered_boxes() {
+        let mut writer = DVIFileWriter::new();
+
+        with_parser(&[r"\raise 3pt\hbox{a}\lower 3pt\hbox{a}%"], |parser| {
+            for elem in parser.parse_horizontal_list(true, false) {
+                writer.add_horizontal_list_elem(
+                    &elem,
+                    &None,
+                    &Dimen::zero(),
+                    &Dimen::zero(),
+                );
+            }
+        });
+
+        let three_points = Dimen::from_unit(3.0, Unit::Point);
+        assert_matches(
+            &writer.commands,
+            &[
+                MaybeEquals::Equals(DVICommand::Push),
+                MaybeEquals::Equals(DVICommand::Down3(
+                    -three_points.as_scaled_points(),
                 )),
                 MaybeEquals::Equals(DVICommand::Push),
+                MaybeEquals::Anything,
+                MaybeEquals::Anything,
                 MaybeEquals::Equals(DVICommand::SetCharN(97)),
                 MaybeEquals::Equals(DVICommand::Pop),
-                MaybeEquals::Equals(DVICommand::Right4(
-                    metrics.get_width('a').as_scaled_points(),
+                MaybeEquals::Equals(DVICommand::Pop),
+                MaybeEquals::Anything,
+                MaybeEquals::Equals(DVICommand::Push),
+                MaybeEquals::Equals(DVICommand::Down3(
+                    three_points.as_scaled_points(),
                 )),
+                MaybeEquals::Equals(DVICommand::Push),
+                MaybeEquals::Equals(DVICommand::SetCharN(97)),
+                MaybeEquals::Equals(DVICommand::Pop),
+                MaybeEquals::Equals(DVICommand::Pop),
+                MaybeEquals::Anything,
             ],
         );
     }
@@ -1,6 +1,10 @@
+use std::rc::Rc;
+
 use crate::dimension::{Dimen, FilDimen, FilKind, SpringDimen};
 use crate::glue::Glue;
-use crate::list::{HorizontalListElem, VerticalListElem};
+use crate::list::{
+    with_unbroken_discretionaries, HorizontalListElem, VerticalListElem,
+};
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum GlueSetRatioKind {
@@ -22,55 +26,105 @@ impl GlueSetRatioKind {
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, Clone)]
 pub struct GlueSetRatio {
     // What kinds of glue should be stretching. For instance, if this is
     // GlueSetRatioKind::Fil then only glues with fil stretch/shrink components
     // will be affected.
     kind: GlueSetRatioKind,
-    // How much to stretch/shrink. Each increment of this value represents
-    // 1/65536 of a unit of stretch. Measured in units of pt/pt for finite
-    // stretching and pt/<fil/fill/filll> for infinite stretching.
-    stretch: i32,
+    // How much to stretch/shrink, stored as the exact fraction needed /
+    // available so that setting glue doesn't lose any precision. `needed` is
+    // the amount of space that the glue needs to fill in scaled points, which
+    // is negative for shrinking, and `available` is the total amount of
+    // stretch/shrink in scaled points (or scaled fils/fills/fillls), which is
+    // always positive.
+    needed: i32,
+    available: i32,
 }
 
 impl GlueSetRatio {
-    pub fn from(kind: GlueSetRatioKind, ratio: f64) -> GlueSetRatio {
+    pub fn from_scaled_points(
+        kind: GlueSetRatioKind,
+        needed: i32,
+        available: i32,
+    ) -> GlueSetRatio {
+        let (needed, available) = if available < 0 {
+            (-needed, -available)
+        } else {
+            (needed, available)
+        };
+
         GlueSetRatio {
             kind,
-            stretch: (ratio * 65536.0) as i32,
+            needed,
+            available,
         }
     }
 
+    // Makes a glue set ratio from a decimal ratio, which is only exact to
+    // 1/65536. This is mostly useful for writing out ratios in tests.
+    #[cfg(test)]
+    pub fn from(kind: GlueSetRatioKind, ratio: f64) -> GlueSetRatio {
+        Self::from_scaled_points(kind, (ratio * 65536.0) as i32, 65536)
+    }
+
+    // Returns the ratio as a decimal. Since this isn't exact, it shouldn't be
+    // used for actually setting glue.
+    pub fn as_f64(&self) -> f64 {
+        (self.needed as f64) / (self.available as f64)
+    }
+
+    // Multiplies a number of scaled points by the ratio, truncating the result
+    // towards zero. This is exact, like TeX's xn_over_d.
+    fn multiply_scaled_points(&self, value: i32) -> Dimen {
+        Dimen::from_scaled_points(
+            ((value as i64) * (self.needed as i64) / (self.available as i64))
+                as i32,
+        )
+    }
+
     fn multiply_spring_dimen(&self, spring_dimen: &SpringDimen) -> Dimen {
         match (&self.kind, spring_dimen) {
             (&GlueSetRatioKind::Finite, SpringDimen::Dimen(dimen)) => {
-                *dimen * (self.stretch, 65536)
+                self.multiply_scaled_points(dimen.as_scaled_points())
             }
             (
                 &GlueSetRatioKind::Fil,
                 SpringDimen::FilDimen(FilDimen(FilKind::Fil, fils)),
-            ) => Dimen::from_scaled_points(
-                ((*fils as i64) * (self.stretch as i64) / 65536) as i32,
-            ),
+            ) => self.multiply_scaled_points(*fils),
             (
                 &GlueSetRatioKind::Fill,
                 SpringDimen::FilDimen(FilDimen(FilKind::Fill, fills)),
-            ) => Dimen::from_scaled_points(
-                ((*fills as i64) * (self.stretch as i64) / 65536) as i32,
-            ),
+            ) => self.multiply_scaled_points(*fills),
             (
                 &GlueSetRatioKind::Filll,
                 SpringDimen::FilDimen(FilDimen(FilKind::Filll, fillls)),
-            ) => Dimen::from_scaled_points(
-                ((*fillls as i64) * (self.stretch as i64) / 65536) as i32,
-            ),
+            ) => self.multiply_scaled_points(*fillls),
             _ => Dimen::zero(),
         }
     }
 
+    // Prints the glue set ratio the way TeX does in box dumps, e.g. "0.5",
+    // "- 0.25" for shrinking, or "1.0fil" for infinite stretching.
+    pub fn to_tex_string(&self) -> String {
+        let sign = if self.needed < 0 { "- " } else { "" };
+        let order = match self.kind {
+            GlueSetRatioKind::Finite => "",
+            GlueSetRatioKind::Fil => "fil",
+            GlueSetRatioKind::Fill => "fill",
+            GlueSetRatioKind::Filll => "filll",
+        };
+        let scaled = (self.as_f64().abs() * 65536.0).round() as i32;
+        format!(
+            "{}{}{}",
+            sign,
+            Dimen::from_scaled_points(scaled).to_tex_number_string(),
+            order
+        )
+    }
+
     pub fn apply_to_glue(&self, glue: &Glue) -> Dimen {
-        if self.stretch < 0 {
+        if self.needed < 0 {
             glue.space + self.multiply_spring_dimen(&glue.shrink)
         } else {
             glue.space + self.multiply_spring_dimen(&glue.stretch)
@@ -78,13 +132,172 @@ impl GlueSetRatio {
     }
 }
 
+// Two ratios are equal if they set the same kind of glue by the same exact
+// amount, even if their fractions aren't reduced the same way.
+impl PartialEq for GlueSetRatio {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+            && (self.needed as i64) * (other.available as i64)
+                == (other.needed as i64) * (self.available as i64)
+    }
+}
+
+/// The badness that TeX considers to be infinitely bad.
+pub const INF_BAD: i32 = 10000;
+
+/// Computes the badness of stretching or shrinking glue by `amount` when
+/// `available` total stretch or shrink is present. This is approximately
+/// 100*(amount/available)^3, but is calculated exactly the way TeX does it so
+/// that we make the same decisions about where to break.
+pub fn badness(amount: &Dimen, available: &Dimen) -> i32 {
+    let t = amount.as_scaled_points();
+    let s = available.as_scaled_points();
+
+    if t == 0 {
+        0
+    } else if s <= 0 {
+        INF_BAD
+    } else {
+        // r is approximately 297*t/s, which is chosen so that r^3 is about
+        // 2^18*100*(t/s)^3. The different cases avoid overflow.
+        let r = if t <= 7_230_584 {
+            (t * 297) / s
+        } else if s >= 1_663_497 {
+            t / (s / 297)
+        } else {
+            t
+        };
+
+        if r > 1290 {
+            INF_BAD
+        } else {
+            (r * r * r + 0x20000) / 0x40000
+        }
+    }
+}
+
+/// How well the glue in a list could be set to reach a target size.
+#[derive(Debug, PartialEq)]
+pub enum BoxFit {
+    /// The glue was stretched (or left alone) with the given badness. A
+    /// badness of INF_BAD means the list is as underfull as it gets.
+    Stretched(i32),
+    /// The glue was shrunk with the given badness.
+    Shrunk(i32),
+    /// There wasn't enough shrink in the list, so it is too big by the given
+    /// amount even after shrinking all of its glue.
+    Overfull(Dimen),
+}
+
+/// Computes the badness of setting the glue in a list with the given natural
+/// size and total stretch and shrink to make the list have the target size,
+/// or how much too big the list is if it can't shrink enough. Infinite
+/// stretch and shrink can always be set with a badness of 0.
+pub fn compute_box_fit(
+    natural: &Dimen,
+    total_stretch: &SpringDimen,
+    total_shrink: &SpringDimen,
+    target: &Dimen,
+) -> BoxFit {
+    if target >= natural {
+        let needed = *target - *natural;
+        match total_stretch {
+            SpringDimen::Dimen(stretch) => {
+                BoxFit::Stretched(badness(&needed, stretch))
+            }
+            SpringDimen::FilDimen(FilDimen(_, fils)) if *fils != 0 => {
+                BoxFit::Stretched(0)
+            }
+            SpringDimen::FilDimen(_) => {
+                BoxFit::Stretched(badness(&needed, &Dimen::zero()))
+            }
+        }
+    } else {
+        let needed = *natural - *target;
+        match total_shrink {
+            SpringDimen::Dimen(shrink) => {
+                if needed > *shrink {
+                    BoxFit::Overfull(needed - *shrink)
+                } else {
+                    BoxFit::Shrunk(badness(&needed, shrink))
+                }
+            }
+            SpringDimen::FilDimen(FilDimen(_, fils)) if *fils != 0 => {
+                BoxFit::Shrunk(0)
+            }
+            SpringDimen::FilDimen(_) => BoxFit::Overfull(needed),
+        }
+    }
+}
+
+/// Computes how the glue in a list with the given natural size and total
+/// stretch and shrink needs to be set to make the list have the target size.
+/// The ratio sets the highest order of infinity of glue present, and is
+/// negative for shrinking. Returns None if no glue setting is needed, or if
+/// there is no stretch or shrink available to set.
+pub fn compute_glue_set(
+    natural: &Dimen,
+    total_stretch: &SpringDimen,
+    total_shrink: &SpringDimen,
+    target: &Dimen,
+) -> Option<GlueSetRatio> {
+    // If the natural dimension exactly equals the target dimension, then we
+    // don't need a glue set. This is probably very unlikely to happen except
+    // in unique cases, like when the dimension is 0.
+    if target == natural {
+        return None;
+    }
+
+    let needed = *target - *natural;
+    let available = if target > natural {
+        total_stretch
+    } else {
+        total_shrink
+    };
+
+    let (kind, available) = match available {
+        // If we have a finite amount of stretch/shrink available, then we set
+        // a finite glue ratio. Glue can stretch as far as needed, but it can
+        // never shrink by more than its total shrink, so overfull lists just
+        // shrink by a ratio of exactly -1.
+        SpringDimen::Dimen(dimen) => {
+            if needed.as_scaled_points() < -dimen.as_scaled_points() {
+                return Some(GlueSetRatio::from_scaled_points(
+                    GlueSetRatioKind::Finite,
+                    -dimen.as_scaled_points(),
+                    dimen.as_scaled_points(),
+                ));
+            }
+            (GlueSetRatioKind::Finite, dimen.as_scaled_points())
+        }
+
+        // If there's an infinite amount of stretch/shrink available, then we
+        // can stretch/shrink as much as is needed with no limits.
+        SpringDimen::FilDimen(FilDimen(fil_kind, fils)) => {
+            (GlueSetRatioKind::from_fil_kind(fil_kind), *fils)
+        }
+    };
+
+    if available == 0 {
+        return None;
+    }
+
+    Some(GlueSetRatio::from_scaled_points(
+        kind,
+        needed.as_scaled_points(),
+        available,
+    ))
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct HorizontalBox {
     pub height: Dimen,
     pub depth: Dimen,
     pub width: Dimen,
 
-    pub list: Vec<HorizontalListElem>,
+    // The list is shared between copies of the box, so copying a box (like
+    // with \copy) doesn't copy everything inside of it.
+    pub list: Rc<Vec<HorizontalListElem>>,
     // For each glue, this says how much the glue should stretch/shrink by.
     pub glue_set_ratio: Option<GlueSetRatio>,
 }
@@ -98,11 +311,17 @@ impl HorizontalBox {
             return vec![' '];
         }
 
-        self.list
+        with_unbroken_discretionaries(&self.list)
             .iter()
             .flat_map(|elem| match elem {
                 HorizontalListElem::Char { chr: ch, font: _ } => vec![*ch],
+                HorizontalListElem::CharRun { chars, font: _ } => chars.clone(),
                 HorizontalListElem::HSkip(_) => vec![' '],
+                HorizontalListElem::Kern(_) => vec![],
+                HorizontalListElem::Penalty(_) => vec![],
+                HorizontalListElem::Discretionary { .. } => vec![],
+                HorizontalListElem::Rule { .. } => vec![],
+                HorizontalListElem::RuleLeaders { .. } => vec![' '],
                 HorizontalListElem::Box { tex_box, shift: _ } => {
                     tex_box.to_chars()
                 }
@@ -116,10 +335,20 @@ impl HorizontalBox {
             height: Dimen::zero(),
             depth: Dimen::zero(),
             width: Dimen::zero(),
-            list: Vec::new(),
+            list: Rc::new(Vec::new()),
             glue_set_ratio: None,
         }
     }
+
+    /// Returns an empty box with no width but the given height and depth,
+    /// which can be used to make things at least as tall and deep as it.
+    pub fn strut(height: Dimen, depth: Dimen) -> Self {
+        HorizontalBox {
+            height,
+            depth,
+            ..HorizontalBox::empty()
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -128,7 +357,9 @@ pub struct VerticalBox {
     pub depth: Dimen,
     pub width: Dimen,
 
-    pub list: Vec<VerticalListElem>,
+    // The list is shared between copies of the box, so copying a box (like
+    // with \copy) doesn't copy everything inside of it.
+    pub list: Rc<Vec<VerticalListElem>>,
     // For each glue, this says how much the glue should stretch/shrink by.
     pub glue_set_ratio: Option<GlueSetRatio>,
 }
@@ -141,7 +372,11 @@ impl VerticalBox {
             // between each element here.
             .flat_map(|elem| match elem {
                 VerticalListElem::VSkip(_) => vec![],
-                VerticalListElem::Box(tex_box) => {
+                VerticalListElem::Kern(_) => vec![],
+                VerticalListElem::Penalty(_) => vec![],
+                VerticalListElem::Rule { .. } => vec![],
+                VerticalListElem::RuleLeaders { .. } => vec![],
+                VerticalListElem::Box { tex_box, .. } => {
                     let mut vec = tex_box.to_chars();
                     vec.push('\n');
                     vec
@@ -233,7 +468,7 @@ mod tests {
             height: Dimen::zero(),
             depth: Dimen::zero(),
 
-            list: vec![
+            list: Rc::new(vec![
                 HorizontalListElem::Char {
                     chr: 'a',
                     font: CMR10.clone(),
@@ -245,7 +480,7 @@ mod tests {
                         height: Dimen::zero(),
                         depth: Dimen::zero(),
 
-                        list: vec![
+                        list: Rc::new(vec![
                             HorizontalListElem::Char {
                                 chr: 'b',
                                 font: CMR10.clone(),
@@ -253,7 +488,7 @@ mod tests {
                             HorizontalListElem::HSkip(Glue::from_dimen(
                                 Dimen::zero(),
                             )),
-                        ],
+                        ]),
                         glue_set_ratio: None,
                     }),
                     shift: Dimen::zero(),
@@ -262,7 +497,7 @@ mod tests {
                     chr: 'c',
                     font: CMR10.clone(),
                 },
-            ],
+            ]),
             glue_set_ratio: None,
         });
 
@@ -271,11 +506,17 @@ mod tests {
             height: Dimen::zero(),
             depth: Dimen::zero(),
 
-            list: vec![
-                VerticalListElem::Box(inner_hbox.clone()),
+            list: Rc::new(vec![
+                VerticalListElem::Box {
+                    tex_box: inner_hbox.clone(),
+                    shift: Dimen::zero(),
+                },
                 VerticalListElem::VSkip(Glue::from_dimen(Dimen::zero())),
-                VerticalListElem::Box(inner_hbox),
-            ],
+                VerticalListElem::Box {
+                    tex_box: inner_hbox,
+                    shift: Dimen::zero(),
+                },
+            ]),
             glue_set_ratio: None,
         });
 
@@ -288,4 +529,75 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn it_computes_glue_set_ratios() {
+        let natural = Dimen::from_unit(10.0, Unit::Point);
+        let stretch = SpringDimen::Dimen(Dimen::from_unit(4.0, Unit::Point));
+        let shrink = SpringDimen::FilDimen(FilDimen::new(FilKind::Fill, 2.0));
+
+        assert_eq!(
+            compute_glue_set(&natural, &stretch, &shrink, &natural),
+            None
+        );
+        assert_eq!(
+            compute_glue_set(
+                &natural,
+                &stretch,
+                &shrink,
+                &Dimen::from_unit(12.0, Unit::Point)
+            ),
+            Some(GlueSetRatio::from(GlueSetRatioKind::Finite, 0.5))
+        );
+        assert_eq!(
+            compute_glue_set(
+                &natural,
+                &stretch,
+                &shrink,
+                &Dimen::from_unit(9.0, Unit::Point)
+            ),
+            Some(GlueSetRatio::from(GlueSetRatioKind::Fill, -0.5))
+        );
+    }
+
+    #[test]
+    fn it_sets_glue_exactly() {
+        let glue = Glue {
+            space: Dimen::zero(),
+            stretch: SpringDimen::Dimen(Dimen::from_unit(300.0, Unit::Point)),
+            shrink: SpringDimen::Dimen(Dimen::zero()),
+        };
+
+        // 1/3 can't be represented exactly in 1/65536ths, so a decimal ratio
+        // ends up a few scaled points short when multiplied by a lot of
+        // stretch.
+        let exact_ratio = GlueSetRatio::from_scaled_points(
+            GlueSetRatioKind::Finite,
+            Dimen::from_unit(1.0, Unit::Point).as_scaled_points(),
+            Dimen::from_unit(3.0, Unit::Point).as_scaled_points(),
+        );
+        let decimal_ratio =
+            GlueSetRatio::from(GlueSetRatioKind::Finite, 1.0 / 3.0);
+
+        assert_eq!(
+            exact_ratio.apply_to_glue(&glue),
+            Dimen::from_unit(100.0, Unit::Point)
+        );
+        assert!(
+            decimal_ratio.apply_to_glue(&glue)
+                < Dimen::from_unit(100.0, Unit::Point)
+        );
+    }
+
+    #[test]
+    fn it_calculates_badness() {
+        let pt = |n: f64| Dimen::from_unit(n, Unit::Point);
+
+        assert_eq!(badness(&Dimen::zero(), &Dimen::zero()), 0);
+        assert_eq!(badness(&pt(1.0), &Dimen::zero()), INF_BAD);
+        assert_eq!(badness(&pt(1.0), &pt(1.0)), 100);
+        assert_eq!(badness(&pt(1.0), &pt(2.0)), 12);
+        assert_eq!(badness(&pt(2.0), &pt(1.0)), 800);
+        assert_eq!(badness(&pt(10.0), &pt(1.0)), INF_BAD);
+    }
 }
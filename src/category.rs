@@ -18,3 +18,50 @@ pub enum Category {
     Comment,      // 14
     Invalid,      // 15
 }
+
+/// The different sets of categories that a Parser can start out with.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum CategoryPreset {
+    // The categories that INITEX starts with, where only \, %, space,
+    // newlines, letters and a couple of special characters have non-Other
+    // categories. Notably, since ^ isn't a superscript character, ^^ notation
+    // doesn't work.
+    #[allow(dead_code)]
+    IniTeX,
+    // The categories that plain.tex sets up on top of the INITEX ones, like
+    // { and } for grouping and $ for math.
+    Plain,
+}
+
+impl CategoryPreset {
+    /// Returns the categories that need to be assigned on top of TeX's
+    /// initial ones to get the categories for this preset.
+    pub fn get_categories(&self) -> Vec<(char, Category)> {
+        match self {
+            CategoryPreset::IniTeX => vec![
+                ('{', Category::Other),
+                ('}', Category::Other),
+                ('$', Category::Other),
+                ('&', Category::Other),
+                ('#', Category::Other),
+                ('^', Category::Other),
+                ('\u{000b}', Category::Other),
+                ('_', Category::Other),
+                ('\u{0001}', Category::Other),
+                ('\t', Category::Other),
+            ],
+            CategoryPreset::Plain => vec![
+                ('{', Category::BeginGroup),
+                ('}', Category::EndGroup),
+                ('$', Category::MathShift),
+                ('&', Category::AlignmentTab),
+                ('#', Category::Parameter),
+                ('^', Category::Superscript),
+                ('\u{000b}', Category::Superscript),
+                ('_', Category::Subscript),
+                ('\u{0001}', Category::Subscript),
+                ('\t', Category::Space),
+            ],
+        }
+    }
+}
@@ -40,21 +40,33 @@ pub struct Dimen(i32);
 
 impl Dimen {
     fn validate(self) -> Dimen {
+        Self::from_i64(self.0 as i64)
+    }
+
+    // Makes a Dimen from the result of some arithmetic, which is done in i64s
+    // so that going past TeX's limits produces an error instead of
+    // overflowing.
+    fn from_i64(value: i64) -> Dimen {
         assert!(
-            DIMEN_MIN <= self.0 && self.0 <= DIMEN_MAX,
+            DIMEN_MIN as i64 <= value && value <= DIMEN_MAX as i64,
             "Dimension too large"
         );
-        self
+        Dimen(value as i32)
     }
 
     pub fn zero() -> Dimen {
         Dimen(0)
     }
 
+    // The largest dimension that TeX allows, which is \maxdimen in plain.tex.
+    pub fn max_value() -> Dimen {
+        Dimen(DIMEN_MAX)
+    }
+
     // Given a number of a given unit, create a Dimen.
     pub fn from_unit(num: f64, from_unit: Unit) -> Dimen {
         let scale = get_scale(from_unit);
-        Dimen((num * scale.0 / scale.1) as i32).validate()
+        Self::from_i64((num * scale.0 / scale.1) as i64)
     }
 
     pub fn from_scaled_points(num: i32) -> Dimen {
@@ -73,6 +85,47 @@ impl Dimen {
     pub fn as_scaled_points(&self) -> i32 {
         self.0
     }
+
+    // Prints the Dimen in points the same way that TeX does, with the fewest
+    // decimal digits needed to exactly recover the number of scaled points,
+    // e.g. "10.0pt" or "-2.5pt".
+    pub fn to_tex_string(self) -> String {
+        format!("{}pt", self.to_tex_number_string())
+    }
+
+    // Prints the Dimen like `to_tex_string()` but without the unit, like TeX
+    // does in box dumps, e.g. "10.0" or "-2.5".
+    pub fn to_tex_number_string(self) -> String {
+        let unity = 65536;
+        let mut result = String::new();
+
+        let mut s = self.0;
+        if s < 0 {
+            result.push('-');
+            s = -s;
+        }
+
+        result.push_str(&(s / unity).to_string());
+        result.push('.');
+
+        s = 10 * (s % unity) + 5;
+        let mut delta = 10;
+        loop {
+            if delta > unity {
+                // Round the last digit
+                s += 0x8000 - 50000;
+            }
+            result.push(std::char::from_digit((s / unity) as u32, 10).unwrap());
+            s = 10 * (s % unity);
+            delta *= 10;
+
+            if s <= delta {
+                break;
+            }
+        }
+
+        result
+    }
 }
 
 impl PartialOrd for Dimen {
@@ -84,14 +137,14 @@ impl PartialOrd for Dimen {
 impl Add for Dimen {
     type Output = Dimen;
     fn add(self, other: Dimen) -> Dimen {
-        Dimen(self.0 + other.0).validate()
+        Self::from_i64(self.0 as i64 + other.0 as i64)
     }
 }
 
 impl Sub for Dimen {
     type Output = Dimen;
     fn sub(self, other: Dimen) -> Dimen {
-        Dimen(self.0 - other.0).validate()
+        Self::from_i64(self.0 as i64 - other.0 as i64)
     }
 }
 
@@ -99,7 +152,7 @@ impl Mul<i32> for Dimen {
     type Output = Dimen;
 
     fn mul(self, other: i32) -> Dimen {
-        Dimen(self.0 * other).validate()
+        Self::from_i64(self.0 as i64 * other as i64)
     }
 }
 
@@ -109,8 +162,7 @@ impl Mul<(i32, i32)> for Dimen {
     type Output = Dimen;
 
     fn mul(self, other: (i32, i32)) -> Dimen {
-        let value = (self.0 as i64) * (other.0 as i64) / (other.1 as i64);
-        Dimen(value as i32).validate()
+        Self::from_i64((self.0 as i64) * (other.0 as i64) / (other.1 as i64))
     }
 }
 
@@ -149,8 +201,10 @@ pub enum FilKind {
 pub struct FilDimen(pub FilKind, pub i32);
 
 impl FilDimen {
+    // Makes a FilDimen from a number of fils, which is rounded to the nearest
+    // 1/65536 fil like TeX does.
     pub fn new(kind: FilKind, value: f64) -> Self {
-        FilDimen(kind, (value * 65536.0) as i32)
+        FilDimen(kind, (value * 65536.0).round() as i32)
     }
 }
 
@@ -190,6 +244,14 @@ impl Mul<i32> for FilDimen {
     }
 }
 
+impl Div<i32> for FilDimen {
+    type Output = FilDimen;
+
+    fn div(self, other: i32) -> FilDimen {
+        FilDimen(self.0, self.1 / other)
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum SpringDimen {
     Dimen(Dimen),
@@ -229,6 +291,17 @@ impl Mul<i32> for SpringDimen {
     }
 }
 
+impl Div<i32> for SpringDimen {
+    type Output = SpringDimen;
+
+    fn div(self, other: i32) -> SpringDimen {
+        match self {
+            SpringDimen::FilDimen(fil) => SpringDimen::FilDimen(fil / other),
+            SpringDimen::Dimen(dimen) => SpringDimen::Dimen(dimen / other),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -303,6 +376,18 @@ mod tests {
         Dimen(1073741824).validate();
     }
 
+    #[test]
+    #[should_panic(expected = "Dimension too large")]
+    fn it_checks_for_overflow_when_adding() {
+        let _ = Dimen(DIMEN_MAX) + Dimen(DIMEN_MAX);
+    }
+
+    #[test]
+    #[should_panic(expected = "Dimension too large")]
+    fn it_checks_for_overflow_when_multiplying() {
+        let _ = Dimen::from_unit(10000.0, Unit::Point) * 1000;
+    }
+
     #[test]
     fn it_supports_negative_dimens() {
         assert_eq!(Dimen::from_unit(-123.0, Unit::Point), Dimen(-8060928));
@@ -379,4 +464,22 @@ mod tests {
             SpringDimen::FilDimen(FilDimen::new(FilKind::Filll, 3.4))
         );
     }
+
+    #[test]
+    fn it_prints_dimens_like_tex() {
+        assert_eq!(
+            Dimen::from_unit(10.0, Unit::Point).to_tex_string(),
+            "10.0pt"
+        );
+        assert_eq!(
+            Dimen::from_unit(-2.5, Unit::Point).to_tex_string(),
+            "-2.5pt"
+        );
+        assert_eq!(Dimen::from_scaled_points(1).to_tex_string(), "0.00002pt");
+        assert_eq!(
+            Dimen::from_unit(1.0, Unit::Inch).to_tex_string(),
+            "72.26999pt"
+        );
+        assert_eq!(Dimen::zero().to_tex_string(), "0.0pt");
+    }
 }
@@ -4,10 +4,20 @@ use std::io;
 use super::file::{DVICommand, DVIFile};
 
 impl DVIFile {
+    #[cfg(test)]
     pub fn write_to<T: io::Write>(&self, output: T) -> io::Result<()> {
+        Self::write_commands(&self.commands, output)
+    }
+
+    /// Writes the given commands to the output one at a time, so a list of
+    /// commands can be written out without building a DVIFile first.
+    pub fn write_commands<T: io::Write>(
+        commands: &[DVICommand],
+        output: T,
+    ) -> io::Result<()> {
         let mut writer = DVIFileWriter::new(output);
 
-        for command in &self.commands {
+        for command in commands {
             writer.write(command)?;
         }
 
@@ -71,6 +81,16 @@ impl DVICommand {
     ) -> io::Result<()> {
         match self {
             DVICommand::SetCharN(n) => writer.write_1_byte_unsigned(*n),
+            DVICommand::SetRule { height, width } => {
+                writer.write_1_byte_unsigned(132)?;
+                writer.write_4_bytes_signed(*height)?;
+                writer.write_4_bytes_signed(*width)
+            }
+            DVICommand::PutRule { height, width } => {
+                writer.write_1_byte_unsigned(137)?;
+                writer.write_4_bytes_signed(*height)?;
+                writer.write_4_bytes_signed(*width)
+            }
             DVICommand::Bop { cs, pointer } => {
                 writer.write_1_byte_unsigned(139)?;
                 for c in cs {
@@ -81,6 +101,10 @@ impl DVICommand {
             DVICommand::Eop => writer.write_1_byte_unsigned(140),
             DVICommand::Push => writer.write_1_byte_unsigned(141),
             DVICommand::Pop => writer.write_1_byte_unsigned(142),
+            DVICommand::Right1(b) => {
+                writer.write_1_byte_unsigned(143)?;
+                writer.write_1_byte_signed(*b)
+            }
             DVICommand::Right2(b) => {
                 writer.write_1_byte_unsigned(144)?;
                 writer.write_2_bytes_signed(*b)
@@ -94,6 +118,10 @@ impl DVICommand {
                 writer.write_4_bytes_signed(*b)
             }
             DVICommand::W0 => writer.write_1_byte_unsigned(147),
+            DVICommand::W1(b) => {
+                writer.write_1_byte_unsigned(148)?;
+                writer.write_1_byte_signed(*b)
+            }
             DVICommand::W2(b) => {
                 writer.write_1_byte_unsigned(149)?;
                 writer.write_2_bytes_signed(*b)
@@ -102,6 +130,31 @@ impl DVICommand {
                 writer.write_1_byte_unsigned(150)?;
                 writer.write_3_bytes_signed(*b)
             }
+            DVICommand::W4(b) => {
+                writer.write_1_byte_unsigned(151)?;
+                writer.write_4_bytes_signed(*b)
+            }
+            DVICommand::X0 => writer.write_1_byte_unsigned(152),
+            DVICommand::X1(b) => {
+                writer.write_1_byte_unsigned(153)?;
+                writer.write_1_byte_signed(*b)
+            }
+            DVICommand::X2(b) => {
+                writer.write_1_byte_unsigned(154)?;
+                writer.write_2_bytes_signed(*b)
+            }
+            DVICommand::X3(b) => {
+                writer.write_1_byte_unsigned(155)?;
+                writer.write_3_bytes_signed(*b)
+            }
+            DVICommand::X4(b) => {
+                writer.write_1_byte_unsigned(156)?;
+                writer.write_4_bytes_signed(*b)
+            }
+            DVICommand::Down1(a) => {
+                writer.write_1_byte_unsigned(157)?;
+                writer.write_1_byte_signed(*a)
+            }
             DVICommand::Down2(a) => {
                 writer.write_1_byte_unsigned(158)?;
                 writer.write_2_bytes_signed(*a)
@@ -115,10 +168,39 @@ impl DVICommand {
                 writer.write_4_bytes_signed(*a)
             }
             DVICommand::Y0 => writer.write_1_byte_unsigned(161),
+            DVICommand::Y1(a) => {
+                writer.write_1_byte_unsigned(162)?;
+                writer.write_1_byte_signed(*a)
+            }
+            DVICommand::Y2(a) => {
+                writer.write_1_byte_unsigned(163)?;
+                writer.write_2_bytes_signed(*a)
+            }
             DVICommand::Y3(a) => {
                 writer.write_1_byte_unsigned(164)?;
                 writer.write_3_bytes_signed(*a)
             }
+            DVICommand::Y4(a) => {
+                writer.write_1_byte_unsigned(165)?;
+                writer.write_4_bytes_signed(*a)
+            }
+            DVICommand::Z0 => writer.write_1_byte_unsigned(166),
+            DVICommand::Z1(a) => {
+                writer.write_1_byte_unsigned(167)?;
+                writer.write_1_byte_signed(*a)
+            }
+            DVICommand::Z2(a) => {
+                writer.write_1_byte_unsigned(168)?;
+                writer.write_2_bytes_signed(*a)
+            }
+            DVICommand::Z3(a) => {
+                writer.write_1_byte_unsigned(169)?;
+                writer.write_3_bytes_signed(*a)
+            }
+            DVICommand::Z4(a) => {
+                writer.write_1_byte_unsigned(170)?;
+                writer.write_4_bytes_signed(*a)
+            }
             DVICommand::FntNumN(n) => writer.write_1_byte_unsigned(n + 171),
             DVICommand::Fnt4(n) => {
                 writer.write_1_byte_unsigned(238)?;
@@ -346,6 +428,41 @@ mod tests {
         );
     }
 
+    #[test]
+    fn it_writes_rules() {
+        let mut output: Vec<u8> = Vec::new();
+        let mut writer = DVIFileWriter::new(&mut output);
+
+        writer
+            .write(&DVICommand::SetRule {
+                height: 65536,
+                width: 131072,
+            })
+            .unwrap();
+        writer
+            .write(&DVICommand::PutRule {
+                height: -1,
+                width: 3,
+            })
+            .unwrap();
+
+        #[rustfmt::skip]
+        assert_eq!(
+            output,
+            vec![
+                // set_rule
+                132,
+                0, 1, 0, 0,
+                0, 2, 0, 0,
+
+                // put_rule
+                137,
+                255, 255, 255, 255,
+                0, 0, 0, 3,
+            ]
+        );
+    }
+
     #[test]
     fn it_writes_commands() {
         // TODO(xymostech): Move this test case into a shared file because it's
@@ -378,12 +495,14 @@ mod tests {
                 DVICommand::FntNumN(0),
                 DVICommand::SetCharN(0),
                 DVICommand::SetCharN(127),
+                DVICommand::Right1(-100),
                 DVICommand::Right2(-30000),
                 DVICommand::Right3(-60000),
                 DVICommand::Right4(-123456789),
                 DVICommand::W0,
                 DVICommand::W2(-30000),
                 DVICommand::W3(-60000),
+                DVICommand::Down1(-100),
                 DVICommand::Down2(-30000),
                 DVICommand::Down3(-60000),
                 DVICommand::Down4(-123456789),
@@ -410,7 +529,7 @@ mod tests {
                     font_name: "cmr10".to_string(),
                 },
                 DVICommand::PostPost {
-                    post_pointer: 128,
+                    post_pointer: 132,
                     format: 2,
                     tail: 6,
                 },
@@ -474,6 +593,9 @@ mod tests {
                 // set_char 127
                 127,
 
+                // right1
+                143, 156,
+
                 // right2
                 144, 138, 208,
 
@@ -492,6 +614,9 @@ mod tests {
                 // w3
                 150, 255, 21, 160,
 
+                // down1
+                157, 156,
+
                 // down2
                 158, 138, 208,
 
@@ -533,7 +658,7 @@ mod tests {
 
                 // post_post
                 249,
-                0, 0, 0, 128,
+                0, 0, 0, 132,
                 2,
                 223, 223, 223, 223, 223, 223,
             ]);
@@ -9,6 +9,8 @@ use crate::font_metrics::FontMetrics;
 #[derive(Debug, Hash, PartialEq, Eq)]
 pub enum DVIOutputElement {
     Character { char: i32, font: String },
+    // A rule whose bottom left corner is at the element's position.
+    Rule { height: i32, width: i32 },
 }
 
 pub type DVIPageOutput = HashMap<(i32, i32), HashSet<DVIOutputElement>>;
@@ -121,31 +123,105 @@ where
             DVICommand::Eop => break,
             DVICommand::Push => state.push_stack(),
             DVICommand::Pop => state.pop_stack(),
+            DVICommand::Right1(b) => {
+                state.curr_stack().h += i32::from(*b);
+            }
+            DVICommand::Right2(b) => {
+                state.curr_stack().h += i32::from(*b);
+            }
             DVICommand::Right3(b) => {
-                state.curr_stack().h += b;
+                state.curr_stack().h += *b;
             }
             DVICommand::Right4(b) => {
-                state.curr_stack().h += b;
+                state.curr_stack().h += *b;
             }
             DVICommand::W0 => {
                 state.curr_stack().h += state.curr_stack().w;
             }
+            DVICommand::W1(b) => {
+                state.curr_stack().w = i32::from(*b);
+                state.curr_stack().h += i32::from(*b);
+            }
+            DVICommand::W2(b) => {
+                state.curr_stack().w = i32::from(*b);
+                state.curr_stack().h += i32::from(*b);
+            }
             DVICommand::W3(b) => {
                 state.curr_stack().w = *b;
-                state.curr_stack().h += b;
+                state.curr_stack().h += *b;
+            }
+            DVICommand::W4(b) => {
+                state.curr_stack().w = *b;
+                state.curr_stack().h += *b;
+            }
+            DVICommand::X0 => {
+                state.curr_stack().h += state.curr_stack().x;
+            }
+            DVICommand::X1(b) => {
+                state.curr_stack().x = i32::from(*b);
+                state.curr_stack().h += i32::from(*b);
+            }
+            DVICommand::X2(b) => {
+                state.curr_stack().x = i32::from(*b);
+                state.curr_stack().h += i32::from(*b);
+            }
+            DVICommand::X3(b) => {
+                state.curr_stack().x = *b;
+                state.curr_stack().h += *b;
+            }
+            DVICommand::X4(b) => {
+                state.curr_stack().x = *b;
+                state.curr_stack().h += *b;
+            }
+            DVICommand::Down1(a) => {
+                state.curr_stack().v += i32::from(*a);
+            }
+            DVICommand::Down2(a) => {
+                state.curr_stack().v += i32::from(*a);
             }
             DVICommand::Down3(a) => {
-                state.curr_stack().v += a;
+                state.curr_stack().v += *a;
             }
             DVICommand::Down4(a) => {
-                state.curr_stack().v += a;
+                state.curr_stack().v += *a;
             }
             DVICommand::Y0 => {
                 state.curr_stack().v += state.curr_stack().y;
             }
+            DVICommand::Y1(a) => {
+                state.curr_stack().y = i32::from(*a);
+                state.curr_stack().v += i32::from(*a);
+            }
+            DVICommand::Y2(a) => {
+                state.curr_stack().y = i32::from(*a);
+                state.curr_stack().v += i32::from(*a);
+            }
             DVICommand::Y3(a) => {
                 state.curr_stack().y = *a;
-                state.curr_stack().v += a;
+                state.curr_stack().v += *a;
+            }
+            DVICommand::Y4(a) => {
+                state.curr_stack().y = *a;
+                state.curr_stack().v += *a;
+            }
+            DVICommand::Z0 => {
+                state.curr_stack().v += state.curr_stack().z;
+            }
+            DVICommand::Z1(a) => {
+                state.curr_stack().z = i32::from(*a);
+                state.curr_stack().v += i32::from(*a);
+            }
+            DVICommand::Z2(a) => {
+                state.curr_stack().z = i32::from(*a);
+                state.curr_stack().v += i32::from(*a);
+            }
+            DVICommand::Z3(a) => {
+                state.curr_stack().z = *a;
+                state.curr_stack().v += *a;
+            }
+            DVICommand::Z4(a) => {
+                state.curr_stack().z = *a;
+                state.curr_stack().v += *a;
             }
             DVICommand::FntDef1 {
                 font_num,
@@ -205,6 +281,26 @@ where
                 };
                 state.curr_stack().h += shift_width.as_scaled_points();
             }
+            command @ DVICommand::SetRule { height, width }
+            | command @ DVICommand::PutRule { height, width } => {
+                // Rules with a non-positive height or width aren't drawn at
+                // all.
+                if *height > 0 && *width > 0 {
+                    let stack = state.curr_stack();
+                    add_to_page(
+                        &mut page,
+                        (stack.h, stack.v),
+                        DVIOutputElement::Rule {
+                            height: *height,
+                            width: *width,
+                        },
+                    );
+                }
+
+                if let DVICommand::SetRule { .. } = command {
+                    state.curr_stack().h += width;
+                }
+            }
             other => panic!("unknown command: {:?}", other),
         }
     }
@@ -485,6 +581,14 @@ mod tests {
             },
             DVICommand::FntNumN(0),
             DVICommand::Push,
+            DVICommand::Right1(100),
+            DVICommand::SetCharN(67),
+            DVICommand::Pop,
+            DVICommand::Push,
+            DVICommand::Right2(500),
+            DVICommand::SetCharN(67),
+            DVICommand::Pop,
+            DVICommand::Push,
             DVICommand::Right3(1000),
             DVICommand::SetCharN(67),
             DVICommand::Pop,
@@ -498,6 +602,14 @@ mod tests {
             DVICommand::SetCharN(67),
             DVICommand::Pop,
             DVICommand::Push,
+            DVICommand::Down1(100),
+            DVICommand::SetCharN(67),
+            DVICommand::Pop,
+            DVICommand::Push,
+            DVICommand::Down2(500),
+            DVICommand::SetCharN(67),
+            DVICommand::Pop,
+            DVICommand::Push,
             DVICommand::Down3(1000),
             DVICommand::SetCharN(67),
             DVICommand::Pop,
@@ -518,14 +630,68 @@ mod tests {
             font: "cmr10".to_string(),
         }];
 
+        assert_eq!(page.get(&(100, 0)), Some(&char_vec));
+        assert_eq!(page.get(&(500, 0)), Some(&char_vec));
         assert_eq!(page.get(&(1000, 0)), Some(&char_vec));
         assert_eq!(page.get(&(2000, 0)), Some(&char_vec));
         assert_eq!(page.get(&(3000, 0)), Some(&char_vec));
+        assert_eq!(page.get(&(0, 100)), Some(&char_vec));
+        assert_eq!(page.get(&(0, 500)), Some(&char_vec));
         assert_eq!(page.get(&(0, 1000)), Some(&char_vec));
         assert_eq!(page.get(&(0, 2000)), Some(&char_vec));
         assert_eq!(page.get(&(0, 3000)), Some(&char_vec));
     }
 
+    #[test]
+    fn it_handles_rules() {
+        let page = interpret_page_from_commands(vec![
+            DVICommand::Bop {
+                cs: [1, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                pointer: -1,
+            },
+            DVICommand::PutRule {
+                height: 100,
+                width: 200,
+            },
+            DVICommand::SetRule {
+                height: 300,
+                width: 400,
+            },
+            DVICommand::SetRule {
+                height: 0,
+                width: 500,
+            },
+            DVICommand::PutRule {
+                height: 600,
+                width: 700,
+            },
+            DVICommand::Eop,
+        ]);
+
+        assert_eq!(
+            page.get(&(0, 0)),
+            Some(&set![
+                DVIOutputElement::Rule {
+                    height: 100,
+                    width: 200,
+                },
+                DVIOutputElement::Rule {
+                    height: 300,
+                    width: 400,
+                }
+            ])
+        );
+        // Empty rules still move, but aren't drawn.
+        assert_eq!(page.get(&(400, 0)), None);
+        assert_eq!(
+            page.get(&(900, 0)),
+            Some(&set![DVIOutputElement::Rule {
+                height: 600,
+                width: 700,
+            }])
+        );
+    }
+
     #[test]
     fn it_handles_multiple_characters_in_the_same_location() {
         let page = interpret_page_from_commands(vec![
@@ -28,6 +28,24 @@ impl DVICommand {
         match opcode {
             // set_char_n
             n if n <= 127 => Ok(Some(DVICommand::SetCharN(n))),
+            // set_rule
+            132 => {
+                let a = reader.read_4_bytes_signed()?;
+                let b = reader.read_4_bytes_signed()?;
+                Ok(Some(DVICommand::SetRule {
+                    height: a,
+                    width: b,
+                }))
+            }
+            // put_rule
+            137 => {
+                let a = reader.read_4_bytes_signed()?;
+                let b = reader.read_4_bytes_signed()?;
+                Ok(Some(DVICommand::PutRule {
+                    height: a,
+                    width: b,
+                }))
+            }
             // bop
             139 => {
                 let mut cs = [0; 10];
@@ -44,6 +62,11 @@ impl DVICommand {
             141 => Ok(Some(DVICommand::Push)),
             // pop
             142 => Ok(Some(DVICommand::Pop)),
+            // right1
+            143 => {
+                let b = reader.read_1_byte_signed()?;
+                Ok(Some(DVICommand::Right1(b)))
+            }
             // right2
             144 => {
                 let b = reader.read_2_bytes_signed()?;
@@ -61,6 +84,11 @@ impl DVICommand {
             }
             // w0
             147 => Ok(Some(DVICommand::W0)),
+            // w1
+            148 => {
+                let b = reader.read_1_byte_signed()?;
+                Ok(Some(DVICommand::W1(b)))
+            }
             // w2
             149 => {
                 let b = reader.read_2_bytes_signed()?;
@@ -71,8 +99,18 @@ impl DVICommand {
                 let b = reader.read_3_bytes_signed()?;
                 Ok(Some(DVICommand::W3(b)))
             }
+            // w4
+            151 => {
+                let b = reader.read_4_bytes_signed()?;
+                Ok(Some(DVICommand::W4(b)))
+            }
             // x0
             152 => Ok(Some(DVICommand::X0)),
+            // x1
+            153 => {
+                let b = reader.read_1_byte_signed()?;
+                Ok(Some(DVICommand::X1(b)))
+            }
             // x2
             154 => {
                 let b = reader.read_2_bytes_signed()?;
@@ -83,6 +121,16 @@ impl DVICommand {
                 let b = reader.read_3_bytes_signed()?;
                 Ok(Some(DVICommand::X3(b)))
             }
+            // x4
+            156 => {
+                let b = reader.read_4_bytes_signed()?;
+                Ok(Some(DVICommand::X4(b)))
+            }
+            // down1
+            157 => {
+                let a = reader.read_1_byte_signed()?;
+                Ok(Some(DVICommand::Down1(a)))
+            }
             // down2
             158 => {
                 let a = reader.read_2_bytes_signed()?;
@@ -100,11 +148,48 @@ impl DVICommand {
             }
             // y0
             161 => Ok(Some(DVICommand::Y0)),
+            // y1
+            162 => {
+                let a = reader.read_1_byte_signed()?;
+                Ok(Some(DVICommand::Y1(a)))
+            }
+            // y2
+            163 => {
+                let a = reader.read_2_bytes_signed()?;
+                Ok(Some(DVICommand::Y2(a)))
+            }
             // y3
             164 => {
                 let a = reader.read_3_bytes_signed()?;
                 Ok(Some(DVICommand::Y3(a)))
             }
+            // y4
+            165 => {
+                let a = reader.read_4_bytes_signed()?;
+                Ok(Some(DVICommand::Y4(a)))
+            }
+            // z0
+            166 => Ok(Some(DVICommand::Z0)),
+            // z1
+            167 => {
+                let a = reader.read_1_byte_signed()?;
+                Ok(Some(DVICommand::Z1(a)))
+            }
+            // z2
+            168 => {
+                let a = reader.read_2_bytes_signed()?;
+                Ok(Some(DVICommand::Z2(a)))
+            }
+            // z3
+            169 => {
+                let a = reader.read_3_bytes_signed()?;
+                Ok(Some(DVICommand::Z3(a)))
+            }
+            // z4
+            170 => {
+                let a = reader.read_4_bytes_signed()?;
+                Ok(Some(DVICommand::Z4(a)))
+            }
             // fnt_num_n
             n if n >= 171 && n <= 234 => Ok(Some(DVICommand::FntNumN(n - 171))),
             // fnt4
@@ -286,6 +371,9 @@ mod tests {
                 // set_char 127
                 127,
 
+                // right1
+                143, 156,
+
                 // right2
                 144, 138, 208,
 
@@ -304,6 +392,9 @@ mod tests {
                 // w3
                 150, 255, 21, 160,
 
+                // down1
+                157, 156,
+
                 // down2
                 158, 138, 208,
 
@@ -345,7 +436,7 @@ mod tests {
 
                 // post_post
                 249,
-                0, 0, 0, 128,
+                0, 0, 0, 132,
                 2,
                 223, 223, 223, 223, 223, 223,
             ][..],
@@ -382,12 +473,14 @@ mod tests {
                     DVICommand::FntNumN(0),
                     DVICommand::SetCharN(0),
                     DVICommand::SetCharN(127),
+                    DVICommand::Right1(-100),
                     DVICommand::Right2(-30000),
                     DVICommand::Right3(-60000),
                     DVICommand::Right4(-123456789),
                     DVICommand::W0,
                     DVICommand::W2(-30000),
                     DVICommand::W3(-60000),
+                    DVICommand::Down1(-100),
                     DVICommand::Down2(-30000),
                     DVICommand::Down3(-60000),
                     DVICommand::Down4(-123456789),
@@ -414,7 +507,7 @@ mod tests {
                         font_name: "cmr10".to_string(),
                     },
                     DVICommand::PostPost {
-                        post_pointer: 128,
+                        post_pointer: 132,
                         format: 2,
                         tail: 6,
                     },
@@ -423,6 +516,39 @@ mod tests {
         )
     }
 
+    #[test]
+    fn it_parses_rules() {
+        #[rustfmt::skip]
+        let file = DVIFile::new(
+            &[
+                // set_rule
+                132,
+                0, 1, 0, 0,
+                0, 2, 0, 0,
+
+                // put_rule
+                137,
+                255, 255, 255, 255,
+                0, 0, 0, 3,
+            ][..],
+        )
+        .unwrap();
+
+        assert_eq!(
+            file.commands,
+            vec![
+                DVICommand::SetRule {
+                    height: 65536,
+                    width: 131072,
+                },
+                DVICommand::PutRule {
+                    height: -1,
+                    width: 3,
+                },
+            ]
+        );
+    }
+
     #[test]
     fn it_parses_test_file() {
         let file = DVIFile::new(TEST_DVI).unwrap();
@@ -1,13 +1,20 @@
+use std::collections::HashMap;
+
 use crate::dimension::{Dimen, Unit};
 use crate::font::Font;
 use crate::paths::get_path_to_font;
-use crate::tfm::TFMFile;
+use crate::tfm::{ExtensibleRecipe, TFMFile};
 
 #[derive(Debug)]
 pub struct FontMetrics {
     tfm_file: TFMFile,
     design_size: Dimen,
     scale: Dimen,
+    // The number of font parameters, which can be larger than the number in
+    // the TFM file if a higher \fontdimen was assigned.
+    num_font_parameters: usize,
+    // The font parameters that have been assigned with \fontdimen.
+    set_font_parameters: HashMap<usize, Dimen>,
 }
 
 impl FontMetrics {
@@ -23,11 +30,14 @@ impl FontMetrics {
         }?;
 
         let design_size = file.get_design_size();
+        let num_font_parameters = file.get_num_font_parameters();
 
         Some(FontMetrics {
             tfm_file: file,
             design_size: Dimen::from_unit(design_size, Unit::Point),
             scale: font.scale,
+            num_font_parameters,
+            set_font_parameters: HashMap::new(),
         })
     }
 
@@ -48,6 +58,10 @@ impl FontMetrics {
         )
     }
 
+    pub fn exists(&self, chr: char) -> bool {
+        self.tfm_file.exists(chr)
+    }
+
     pub fn get_width(&self, chr: char) -> Dimen {
         self.scale_dimen(self.tfm_file.get_width(chr))
     }
@@ -59,6 +73,71 @@ impl FontMetrics {
     pub fn get_depth(&self, chr: char) -> Dimen {
         self.scale_dimen(self.tfm_file.get_depth(chr))
     }
+
+    pub fn get_italic_correction(&self, chr: char) -> Dimen {
+        self.scale_dimen(self.tfm_file.get_italic_correction(chr))
+    }
+
+    pub fn get_kern(&self, left: char, right: char) -> Option<Dimen> {
+        self.tfm_file
+            .get_kern(left, right)
+            .map(|kern| self.scale_dimen(kern))
+    }
+
+    pub fn get_ligature(&self, left: char, right: char) -> Option<char> {
+        self.tfm_file.get_ligature(left, right)
+    }
+
+    pub fn get_next_larger_char(&self, chr: char) -> Option<char> {
+        self.tfm_file.get_next_larger_char(chr)
+    }
+
+    pub fn get_extensible_recipe(&self, chr: char) -> Option<ExtensibleRecipe> {
+        self.tfm_file.get_extensible_recipe(chr)
+    }
+
+    pub fn get_num_font_parameters(&self) -> usize {
+        self.num_font_parameters
+    }
+
+    pub fn get_font_parameter(&self, index: usize) -> Dimen {
+        assert!(
+            1 <= index && index <= self.num_font_parameters,
+            "Font has only {} fontdimen parameters",
+            self.num_font_parameters
+        );
+
+        if let Some(value) = self.set_font_parameters.get(&index) {
+            return *value;
+        }
+
+        // Parameters past the ones in the TFM file start out as zero.
+        if index > self.tfm_file.get_num_font_parameters() {
+            return Dimen::zero();
+        }
+
+        let value = self.tfm_file.get_font_parameter(index);
+
+        // The slant doesn't change when the font is scaled
+        if index == 1 {
+            value
+        } else {
+            self.scale_dimen(value)
+        }
+    }
+
+    // Sets a font parameter, like assigning to \fontdimen does. Setting a
+    // parameter past the end of the existing ones adds more parameters, which
+    // is how macro packages give math fonts the full set of parameters that
+    // math typesetting needs.
+    pub fn set_font_parameter(&mut self, index: usize, value: Dimen) {
+        assert!(index >= 1, "Invalid \\fontdimen index: {}", index);
+
+        if index > self.num_font_parameters {
+            self.num_font_parameters = index;
+        }
+        self.set_font_parameters.insert(index, value);
+    }
 }
 
 #[cfg(test)]
@@ -76,6 +155,66 @@ mod tests {
         assert_eq!(metrics.get_width('a'), Dimen::from_scaled_points(327681));
     }
 
+    #[test]
+    fn it_finds_chains_of_larger_characters() {
+        let metrics = FontMetrics::from_font(&Font {
+            font_name: "cmex10".to_string(),
+            scale: Dimen::from_unit(10.0, Unit::Point),
+        })
+        .unwrap();
+
+        // The radical signs get larger and larger.
+        assert_eq!(metrics.get_next_larger_char('\x70'), Some('\x71'));
+        assert_eq!(metrics.get_next_larger_char('\x71'), Some('\x72'));
+        assert!(
+            metrics.get_height('\x71') + metrics.get_depth('\x71')
+                > metrics.get_height('\x70') + metrics.get_depth('\x70')
+        );
+
+        let metrics = FontMetrics::from_font(&Font {
+            font_name: "cmr10".to_string(),
+            scale: Dimen::from_unit(10.0, Unit::Point),
+        })
+        .unwrap();
+        assert_eq!(metrics.get_next_larger_char('a'), None);
+    }
+
+    #[test]
+    fn it_extends_font_parameters_when_they_are_set() {
+        let mut metrics = FontMetrics::from_font(&Font {
+            font_name: "cmr10".to_string(),
+            scale: Dimen::from_unit(10.0, Unit::Point),
+        })
+        .unwrap();
+
+        let quad = metrics.get_font_parameter(6);
+        metrics.set_font_parameter(6, Dimen::from_unit(3.0, Unit::Point));
+        metrics.set_font_parameter(10, Dimen::from_unit(2.0, Unit::Point));
+
+        assert_ne!(quad, Dimen::from_unit(3.0, Unit::Point));
+        assert_eq!(
+            metrics.get_font_parameter(6),
+            Dimen::from_unit(3.0, Unit::Point)
+        );
+        assert_eq!(metrics.get_font_parameter(8), Dimen::zero());
+        assert_eq!(
+            metrics.get_font_parameter(10),
+            Dimen::from_unit(2.0, Unit::Point)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Font has only 7 fontdimen parameters")]
+    fn it_fails_on_font_parameters_that_were_never_set() {
+        let metrics = FontMetrics::from_font(&Font {
+            font_name: "cmr10".to_string(),
+            scale: Dimen::from_unit(10.0, Unit::Point),
+        })
+        .unwrap();
+
+        metrics.get_font_parameter(8);
+    }
+
     #[test]
     fn it_loads_scaled_fonts() {
         let tenpt_metrics = FontMetrics::from_font(&Font {
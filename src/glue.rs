@@ -1,6 +1,6 @@
-use std::ops::{Add, Sub};
+use std::ops::{Add, Div, Mul, Sub};
 
-use crate::dimension::{Dimen, SpringDimen};
+use crate::dimension::{Dimen, FilDimen, FilKind, SpringDimen};
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Glue {
@@ -14,6 +14,32 @@ impl Glue {
         Self::from_dimen(Dimen::zero())
     }
 
+    // Prints the glue the way TeX does for \the, e.g.
+    // "12.0pt plus 1.0fil minus 2.0pt". Zero stretch and shrink components are
+    // left out.
+    pub fn to_tex_string(&self) -> String {
+        self.to_tex_string_with_unit("pt")
+    }
+
+    // Prints the glue like `to_tex_string()` but without units on the finite
+    // components, the way TeX does in box dumps, e.g. "3.0 plus 1.0fil".
+    pub fn to_tex_number_string(&self) -> String {
+        self.to_tex_string_with_unit("")
+    }
+
+    pub fn to_tex_string_with_unit(&self, unit: &str) -> String {
+        let mut result = self.space.to_tex_number_string() + unit;
+        if !is_zero_spring_dimen(&self.stretch) {
+            result.push_str(" plus ");
+            result.push_str(&spring_dimen_to_tex_string(&self.stretch, unit));
+        }
+        if !is_zero_spring_dimen(&self.shrink) {
+            result.push_str(" minus ");
+            result.push_str(&spring_dimen_to_tex_string(&self.shrink, unit));
+        }
+        result
+    }
+
     pub fn from_dimen(dimen: Dimen) -> Glue {
         Glue {
             space: dimen,
@@ -23,6 +49,30 @@ impl Glue {
     }
 }
 
+fn is_zero_spring_dimen(spring: &SpringDimen) -> bool {
+    match spring {
+        SpringDimen::Dimen(dimen) => *dimen == Dimen::zero(),
+        SpringDimen::FilDimen(FilDimen(_, value)) => *value == 0,
+    }
+}
+
+// Prints the stretch or shrink component of a glue, using the given unit for
+// finite amounts, e.g. "1.66498pt" or "1.0fil".
+fn spring_dimen_to_tex_string(spring: &SpringDimen, unit: &str) -> String {
+    match spring {
+        SpringDimen::Dimen(dimen) => dimen.to_tex_number_string() + unit,
+        SpringDimen::FilDimen(FilDimen(kind, value)) => format!(
+            "{}{}",
+            Dimen::from_scaled_points(*value).to_tex_number_string(),
+            match kind {
+                FilKind::Fil => "fil",
+                FilKind::Fill => "fill",
+                FilKind::Filll => "filll",
+            }
+        ),
+    }
+}
+
 impl Add for Glue {
     type Output = Glue;
 
@@ -47,3 +97,27 @@ impl Sub for Glue {
         self + other
     }
 }
+
+impl Mul<i32> for Glue {
+    type Output = Glue;
+
+    fn mul(self, other: i32) -> Glue {
+        Glue {
+            space: self.space * other,
+            stretch: self.stretch * other,
+            shrink: self.shrink * other,
+        }
+    }
+}
+
+impl Div<i32> for Glue {
+    type Output = Glue;
+
+    fn div(self, other: i32) -> Glue {
+        Glue {
+            space: self.space / other,
+            stretch: self.stretch / other,
+            shrink: self.shrink / other,
+        }
+    }
+}
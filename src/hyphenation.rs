@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+
+// The minimum number of letters that must come before and after a hyphen.
+// TODO(xymostech): These should be \lefthyphenmin and \righthyphenmin.
+const LEFT_HYPHEN_MIN: usize = 2;
+const RIGHT_HYPHEN_MIN: usize = 3;
+
+// A node in the trie of patterns. The path of letters from the root to a node
+// spells out a pattern, and the node stores the values for that pattern if
+// there is one.
+#[derive(Debug, Default)]
+struct TrieNode {
+    children: HashMap<char, usize>,
+    values: Option<Vec<u8>>,
+}
+
+/// The hyphenation patterns loaded with \patterns. Each pattern is a sequence
+/// of letters (where . represents the edge of a word) along with the values
+/// that go between each of the letters, as described in Appendix H of the
+/// TeXbook. The patterns are stored in a trie so that finding all of the
+/// patterns that match starting at a given letter only takes as many steps as
+/// the longest matching pattern.
+#[derive(Debug)]
+pub struct HyphenationPatterns {
+    // The nodes of the trie, with the root at index 0. Children are stored as
+    // indices into this list.
+    nodes: Vec<TrieNode>,
+}
+
+impl Default for HyphenationPatterns {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HyphenationPatterns {
+    pub fn new() -> Self {
+        HyphenationPatterns {
+            nodes: vec![TrieNode::default()],
+        }
+    }
+
+    /// Adds a pattern like "hen5at", where the digits represent the values
+    /// between letters. Missing digits are treated as 0.
+    pub fn add_pattern(&mut self, pattern: &str) {
+        let mut node = 0;
+        let mut values = vec![0];
+
+        for ch in pattern.chars() {
+            if let Some(digit) = ch.to_digit(10) {
+                *values.last_mut().unwrap() = digit as u8;
+            } else {
+                node = match self.nodes[node].children.get(&ch) {
+                    Some(&child) => child,
+                    None => {
+                        let child = self.nodes.len();
+                        self.nodes.push(TrieNode::default());
+                        self.nodes[node].children.insert(ch, child);
+                        child
+                    }
+                };
+                values.push(0);
+            }
+        }
+
+        if node == 0 {
+            panic!("Bad \\patterns: {}", pattern);
+        }
+
+        self.nodes[node].values = Some(values);
+    }
+
+    /// Finds the places where a word can be hyphenated. Each returned index
+    /// is the number of letters that come before the hyphen.
+    pub fn hyphenate(&self, word: &[char]) -> Vec<usize> {
+        if word.len() < LEFT_HYPHEN_MIN + RIGHT_HYPHEN_MIN {
+            return Vec::new();
+        }
+
+        // Patterns match against the lowercase word with . on either end.
+        let mut letters = vec!['.'];
+        letters.extend(word.iter().flat_map(|ch| ch.to_lowercase()));
+        letters.push('.');
+
+        let (values, _) = self.get_values(&letters);
+
+        // Odd values are places where we can hyphenate. Since there's a . at
+        // the beginning of the letters, values[i + 1] is the value after i
+        // letters of the word.
+        (LEFT_HYPHEN_MIN..=(word.len() - RIGHT_HYPHEN_MIN))
+            .filter(|i| values[i + 1] % 2 == 1)
+            .collect()
+    }
+
+    /// Finds the values between each of the letters, where values[i] is the
+    /// largest value of any matching pattern between letters[i - 1] and
+    /// letters[i]. This also returns the number of trie nodes that were
+    /// visited, so we can check how much work it took.
+    fn get_values(&self, letters: &[char]) -> (Vec<u8>, usize) {
+        let mut values = vec![0; letters.len() + 1];
+        let mut visited_nodes = 0;
+        for start in 0..letters.len() {
+            // Walk down the trie to find every pattern that starts here.
+            let mut node = 0;
+            for ch in &letters[start..] {
+                node = match self.nodes[node].children.get(ch) {
+                    Some(&child) => child,
+                    None => break,
+                };
+                visited_nodes += 1;
+
+                if let Some(pattern_values) = &self.nodes[node].values {
+                    for (i, value) in pattern_values.iter().enumerate() {
+                        if *value > values[start + i] {
+                            values[start + i] = *value;
+                        }
+                    }
+                }
+            }
+        }
+
+        (values, visited_nodes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The patterns from Appendix H of the TeXbook that are used to hyphenate
+    // "hyphenation".
+    fn get_texbook_patterns() -> HyphenationPatterns {
+        let mut patterns = HyphenationPatterns::new();
+        for pattern in &[
+            "hy3ph", "he2n", "hena4", "hen5at", "1na", "n2at", "1tio", "2io",
+            "o2n",
+        ] {
+            patterns.add_pattern(pattern);
+        }
+        patterns
+    }
+
+    #[test]
+    fn it_hyphenates_words_using_patterns() {
+        let patterns = get_texbook_patterns();
+        let word: Vec<char> = "hyphenation".chars().collect();
+
+        assert_eq!(patterns.hyphenate(&word), vec![2, 6]);
+    }
+
+    #[test]
+    fn it_doesnt_hyphenate_too_close_to_the_edges() {
+        let mut patterns = HyphenationPatterns::new();
+        patterns.add_pattern("1a");
+        let word: Vec<char> = "aaaaaa".chars().collect();
+
+        assert_eq!(patterns.hyphenate(&word), vec![2, 3]);
+    }
+
+    #[test]
+    fn it_visits_a_bounded_number_of_trie_nodes_per_letter() {
+        // Make a large set of patterns with every one, two, and three letter
+        // combination.
+        let alphabet: Vec<char> = ('a'..='z').collect();
+        let mut patterns = HyphenationPatterns::new();
+        for a in &alphabet {
+            patterns.add_pattern(&format!("{}1", a));
+            for b in &alphabet {
+                patterns.add_pattern(&format!("{}2{}", a, b));
+                for c in &alphabet {
+                    patterns.add_pattern(&format!("{}{}3{}", a, b, c));
+                }
+            }
+        }
+
+        let word: Vec<char> =
+            alphabet.iter().cycle().take(10000).cloned().collect();
+        assert!(!patterns.hyphenate(&word).is_empty());
+
+        // None of the patterns are longer than 3 letters, so we should only
+        // walk at most 3 nodes down the trie from each letter. Checking every
+        // pattern against every position would visit far more.
+        let (_, visited_nodes) = patterns.get_values(&word);
+        assert!(
+            visited_nodes <= 3 * word.len(),
+            "Visited {} trie nodes for a {} letter word",
+            visited_nodes,
+            word.len()
+        );
+    }
+}
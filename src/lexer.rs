@@ -20,6 +20,12 @@ fn is_hex_char(ch: char) -> bool {
     ('0' <= ch && ch <= '9') || ('a' <= ch && ch <= 'f')
 }
 
+// Decodes the character in a ^^X sequence, which is the character whose code
+// differs by 64 from the code of X.
+fn caret_char_value(ch: char) -> char {
+    ((ch as u8) ^ 0x40) as char
+}
+
 fn hex_value(ch: char) -> u8 {
     if '0' <= ch && ch <= '9' {
         (ch as u8) - (b'0')
@@ -63,6 +69,12 @@ impl<'a> Lexer<'a> {
         }
     }
 
+    /// Returns the (1-indexed) number of the line that is currently being
+    /// read.
+    pub fn get_line_number(&self) -> usize {
+        self.row + 1
+    }
+
     fn get_plain_char(&mut self) -> PlainLexResult {
         if self.row == self.source.len() {
             return PlainLexResult::Eof;
@@ -120,7 +132,8 @@ impl<'a> Lexer<'a> {
         }
 
         let third_char: char = match self.get_plain_char() {
-            PlainLexResult::Char(ch) => ch,
+            // Only 7-bit characters can follow ^^
+            PlainLexResult::Char(ch) if (ch as u32) < 128 => ch,
             rest => {
                 self.unget_plain_char(&rest);
                 self.unget_plain_char(&PlainLexResult::Char(second_char));
@@ -137,22 +150,13 @@ impl<'a> Lexer<'a> {
                         as char
                 } else {
                     self.unget_plain_char(&PlainLexResult::Char(fourth_char));
-                    if third_char <= '?' {
-                        ((third_char as u8) + 0x40) as char
-                    } else {
-                        ((third_char as u8) - 0x40) as char
-                    }
+                    caret_char_value(third_char)
                 };
                 self.handle_trigraphs(final_char)
             }
             rest => {
                 self.unget_plain_char(&rest);
-                let final_char = if third_char <= '?' {
-                    ((third_char as u8) + 0x40) as char
-                } else {
-                    ((third_char as u8) - 0x40) as char
-                };
-                self.handle_trigraphs(final_char)
+                self.handle_trigraphs(caret_char_value(third_char))
             }
         }
     }
@@ -329,6 +333,29 @@ mod tests {
         );
     }
 
+    #[test]
+    fn it_decodes_control_characters_and_hex_codes() {
+        assert_lexes_to(
+            &["^^M^^41%"],
+            &[
+                Token::Char('\r', Category::Other),
+                Token::Char('A', Category::Letter),
+            ],
+        );
+    }
+
+    #[test]
+    fn it_doesnt_decode_trigraphs_with_non_ascii_characters() {
+        assert_lexes_to(
+            &["^^\u{00e9}%"],
+            &[
+                Token::Char('^', Category::Superscript),
+                Token::Char('^', Category::Superscript),
+                Token::Char('\u{00e9}', Category::Other),
+            ],
+        );
+    }
+
     #[test]
     fn it_ignores_leading_spaces() {
         assert_lexes_to(&["  a%"], &[Token::Char('a', Category::Letter)]);
@@ -1,14 +1,62 @@
+use std::borrow::Cow;
+
 use crate::boxes::TeXBox;
 use crate::dimension::Dimen;
 use crate::font::Font;
 use crate::glue::Glue;
 use crate::state::TeXState;
 
+// The rule in \leaders. Any of its dimensions can be running (None), though
+// only some of them are used depending on which list the leaders end up in.
+#[derive(Debug, PartialEq, Clone)]
+pub struct LeaderRule {
+    pub height: Option<Dimen>,
+    pub depth: Option<Dimen>,
+    pub width: Option<Dimen>,
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum HorizontalListElem {
-    Char { chr: char, font: Font },
+    Char {
+        chr: char,
+        font: Font,
+    },
+    // Consecutive characters in the same font. Lists are built one Char at a
+    // time, but once they're packed into a box, runs of characters are
+    // stored like this so long lines don't keep a copy of the font for every
+    // character.
+    CharRun {
+        chars: Vec<char>,
+        font: Font,
+    },
     HSkip(Glue),
-    Box { tex_box: TeXBox, shift: Dimen },
+    Kern(Dimen),
+    Box {
+        tex_box: TeXBox,
+        shift: Dimen,
+    },
+    Penalty(i32),
+    // A place where the line can be broken. If the line is broken here,
+    // `pre_break` goes at the end of the line and `post_break` at the start
+    // of the next one, and otherwise `no_break` is typeset in its place.
+    Discretionary {
+        pre_break: Vec<HorizontalListElem>,
+        post_break: Vec<HorizontalListElem>,
+        no_break: Vec<HorizontalListElem>,
+    },
+    // A solid rule. A height or depth of None is "running", which makes the
+    // rule as tall or as deep as the box that it ends up in.
+    Rule {
+        height: Option<Dimen>,
+        depth: Option<Dimen>,
+        width: Dimen,
+    },
+    // Glue which is filled with a rule. The rule's width is ignored, and it
+    // spans however wide the glue ends up being instead.
+    RuleLeaders {
+        glue: Glue,
+        rule: LeaderRule,
+    },
 }
 
 impl HorizontalListElem {
@@ -24,10 +72,75 @@ impl HorizontalListElem {
                 (height, depth, Glue::from_dimen(width))
             }
 
+            HorizontalListElem::CharRun { chars, font } => {
+                let metrics = state.get_metrics_for_font(font).unwrap();
+
+                let mut height = Dimen::zero();
+                let mut depth = Dimen::zero();
+                let mut width = Dimen::zero();
+                for chr in chars {
+                    let char_height = metrics.get_height(*chr);
+                    let char_depth = metrics.get_depth(*chr);
+                    if char_height > height {
+                        height = char_height;
+                    }
+                    if char_depth > depth {
+                        depth = char_depth;
+                    }
+                    width = width + metrics.get_width(*chr);
+                }
+
+                (height, depth, Glue::from_dimen(width))
+            }
+
             HorizontalListElem::HSkip(glue) => {
                 (Dimen::zero(), Dimen::zero(), glue.clone())
             }
 
+            HorizontalListElem::Kern(width) => {
+                (Dimen::zero(), Dimen::zero(), Glue::from_dimen(*width))
+            }
+
+            HorizontalListElem::Penalty(_) => {
+                (Dimen::zero(), Dimen::zero(), Glue::zero())
+            }
+
+            // Discretionaries take up the space of their no-break material,
+            // since that's what is there unless the line is broken at them.
+            HorizontalListElem::Discretionary { no_break, .. } => {
+                let mut height = Dimen::zero();
+                let mut depth = Dimen::zero();
+                let mut width = Glue::zero();
+                for elem in no_break {
+                    let (elem_height, elem_depth, elem_width) =
+                        elem.get_size(state);
+                    if elem_height > height {
+                        height = elem_height;
+                    }
+                    if elem_depth > depth {
+                        depth = elem_depth;
+                    }
+                    width = width + elem_width;
+                }
+                (height, depth, width)
+            }
+
+            HorizontalListElem::Rule {
+                height,
+                depth,
+                width,
+            } => (
+                height.unwrap_or_else(Dimen::zero),
+                depth.unwrap_or_else(Dimen::zero),
+                Glue::from_dimen(*width),
+            ),
+
+            HorizontalListElem::RuleLeaders { glue, rule } => (
+                rule.height.unwrap_or_else(Dimen::zero),
+                rule.depth.unwrap_or_else(Dimen::zero),
+                glue.clone(),
+            ),
+
             HorizontalListElem::Box { tex_box, shift } => (
                 if *tex_box.height() + *shift < Dimen::zero() {
                     Dimen::zero()
@@ -45,24 +158,198 @@ impl HorizontalListElem {
     }
 }
 
+// An iterator over the pieces of a horizontal list, where each piece is
+// either a run of consecutive characters in the same font or a single other
+// element. Long horizontal lists are mostly made of these runs, so things
+// like switching fonts and looking up metrics can be done once per run
+// instead of once per character.
+pub struct CharRuns<'a> {
+    rest: &'a [HorizontalListElem],
+}
+
+impl<'a> Iterator for CharRuns<'a> {
+    type Item = &'a [HorizontalListElem];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let run_font = match self.rest.first()? {
+            HorizontalListElem::Char { font, .. } => font,
+            _ => {
+                let (piece, rest) = self.rest.split_at(1);
+                self.rest = rest;
+                return Some(piece);
+            }
+        };
+
+        let run_length = self
+            .rest
+            .iter()
+            .take_while(|elem| match elem {
+                HorizontalListElem::Char { font, .. } => font == run_font,
+                _ => false,
+            })
+            .count();
+
+        let (piece, rest) = self.rest.split_at(run_length);
+        self.rest = rest;
+        Some(piece)
+    }
+}
+
+pub fn char_runs(list: &[HorizontalListElem]) -> CharRuns<'_> {
+    CharRuns { rest: list }
+}
+
+/// Stores each run of two or more consecutive characters in the same font in
+/// a list as a single CharRun, for lists that are done being built.
+pub fn pack_char_runs(
+    list: Vec<HorizontalListElem>,
+) -> Vec<HorizontalListElem> {
+    let mut packed: Vec<HorizontalListElem> = Vec::with_capacity(list.len());
+    for elem in list {
+        let (chr, font) = match elem {
+            HorizontalListElem::Char { chr, font } => (chr, font),
+            elem => {
+                packed.push(elem);
+                continue;
+            }
+        };
+
+        match packed.last_mut() {
+            Some(HorizontalListElem::CharRun {
+                chars,
+                font: run_font,
+            }) if *run_font == font => {
+                chars.push(chr);
+                continue;
+            }
+            Some(HorizontalListElem::Char {
+                chr: last_chr,
+                font: last_font,
+            }) if *last_font == font => {
+                let last_chr = *last_chr;
+                *packed.last_mut().unwrap() = HorizontalListElem::CharRun {
+                    chars: vec![last_chr, chr],
+                    font,
+                };
+                continue;
+            }
+            _ => (),
+        }
+        packed.push(HorizontalListElem::Char { chr, font });
+    }
+    packed
+}
+
+/// Splits each CharRun in a list back into individual characters, for when
+/// the contents of a box are going to be built on again.
+pub fn unpack_char_runs(
+    list: Vec<HorizontalListElem>,
+) -> Vec<HorizontalListElem> {
+    let mut unpacked = Vec::with_capacity(list.len());
+    for elem in list {
+        match elem {
+            HorizontalListElem::CharRun { chars, font } => {
+                unpacked.extend(chars.into_iter().map(|chr| {
+                    HorizontalListElem::Char {
+                        chr,
+                        font: font.clone(),
+                    }
+                }))
+            }
+            elem => unpacked.push(elem),
+        }
+    }
+    unpacked
+}
+
+/// Replaces each discretionary in a list with its no-break material, which
+/// is what gets typeset for discretionaries that the line isn't broken at.
+/// The list is only copied if there is some no-break material to add.
+pub fn with_unbroken_discretionaries(
+    list: &[HorizontalListElem],
+) -> Cow<'_, [HorizontalListElem]> {
+    let is_discretionary = |elem: &HorizontalListElem| {
+        matches!(elem, HorizontalListElem::Discretionary { .. })
+    };
+    if !list.iter().any(is_discretionary) {
+        return Cow::Borrowed(list);
+    }
+
+    let mut unbroken = Vec::with_capacity(list.len());
+    for elem in list {
+        match elem {
+            HorizontalListElem::Discretionary { no_break, .. } => {
+                unbroken.extend(no_break.iter().cloned())
+            }
+            _ => unbroken.push(elem.clone()),
+        }
+    }
+    Cow::Owned(unbroken)
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum VerticalListElem {
-    Box(TeXBox),
+    // A box, moved right by `shift` (or left, if `shift` is negative).
+    Box {
+        tex_box: TeXBox,
+        shift: Dimen,
+    },
     VSkip(Glue),
+    Kern(Dimen),
+    Penalty(i32),
+    // A solid rule. A width of None is a "running" width, which makes the
+    // rule as wide as the box that it ends up in.
+    Rule {
+        height: Dimen,
+        depth: Dimen,
+        width: Option<Dimen>,
+    },
+    // Glue which is filled with a rule. The rule's height and depth are
+    // ignored, and it spans however tall the glue ends up being instead.
+    RuleLeaders {
+        glue: Glue,
+        rule: LeaderRule,
+    },
 }
 
 impl VerticalListElem {
     pub fn get_size(&self) -> (Glue, Dimen, Dimen) {
         match self {
-            VerticalListElem::Box(tex_box) => (
+            // Shifted boxes take up extra room to the right (or less, if
+            // they're moved left).
+            VerticalListElem::Box { tex_box, shift } => (
                 Glue::from_dimen(*tex_box.height()),
                 *tex_box.depth(),
-                *tex_box.width(),
+                *tex_box.width() + *shift,
             ),
 
             VerticalListElem::VSkip(glue) => {
                 (glue.clone(), Dimen::zero(), Dimen::zero())
             }
+
+            VerticalListElem::Kern(height) => {
+                (Glue::from_dimen(*height), Dimen::zero(), Dimen::zero())
+            }
+
+            VerticalListElem::Penalty(_) => {
+                (Glue::zero(), Dimen::zero(), Dimen::zero())
+            }
+
+            VerticalListElem::Rule {
+                height,
+                depth,
+                width,
+            } => (
+                Glue::from_dimen(*height),
+                *depth,
+                width.unwrap_or_else(Dimen::zero),
+            ),
+
+            VerticalListElem::RuleLeaders { glue, rule } => (
+                glue.clone(),
+                Dimen::zero(),
+                rule.width.unwrap_or_else(Dimen::zero),
+            ),
         }
     }
 }
@@ -75,6 +362,125 @@ mod tests {
     use crate::dimension::Unit;
     use crate::state::TeXState;
 
+    #[test]
+    fn it_splits_horizontal_lists_into_char_runs() {
+        let font = Font {
+            font_name: "cmr10".to_string(),
+            scale: Dimen::from_unit(10.0, Unit::Point),
+        };
+        let other_font = Font {
+            font_name: "cmr10".to_string(),
+            scale: Dimen::from_unit(12.0, Unit::Point),
+        };
+        let char_in = |chr: char, font: &Font| HorizontalListElem::Char {
+            chr,
+            font: font.clone(),
+        };
+
+        let list = vec![
+            char_in('a', &font),
+            char_in('b', &font),
+            char_in('c', &other_font),
+            HorizontalListElem::Kern(Dimen::from_unit(1.0, Unit::Point)),
+            HorizontalListElem::Penalty(0),
+            char_in('d', &font),
+            char_in('e', &font),
+            char_in('f', &font),
+        ];
+
+        assert_eq!(
+            char_runs(&list).collect::<Vec<_>>(),
+            vec![
+                &list[0..2],
+                &list[2..3],
+                &list[3..4],
+                &list[4..5],
+                &list[5..8],
+            ]
+        );
+        assert_eq!(char_runs(&[]).count(), 0);
+    }
+
+    #[test]
+    fn it_packs_and_unpacks_char_runs() {
+        let font = Font {
+            font_name: "cmr10".to_string(),
+            scale: Dimen::from_unit(10.0, Unit::Point),
+        };
+        let other_font = Font {
+            font_name: "cmr10".to_string(),
+            scale: Dimen::from_unit(12.0, Unit::Point),
+        };
+        let char_in = |chr: char, font: &Font| HorizontalListElem::Char {
+            chr,
+            font: font.clone(),
+        };
+
+        let list = vec![
+            char_in('a', &font),
+            char_in('b', &font),
+            char_in('c', &other_font),
+            HorizontalListElem::Kern(Dimen::from_unit(1.0, Unit::Point)),
+            char_in('d', &font),
+            char_in('e', &font),
+            char_in('f', &font),
+        ];
+
+        let packed = pack_char_runs(list.clone());
+        assert_eq!(
+            packed,
+            vec![
+                HorizontalListElem::CharRun {
+                    chars: vec!['a', 'b'],
+                    font: font.clone(),
+                },
+                char_in('c', &other_font),
+                HorizontalListElem::Kern(Dimen::from_unit(1.0, Unit::Point)),
+                HorizontalListElem::CharRun {
+                    chars: vec!['d', 'e', 'f'],
+                    font: font.clone(),
+                },
+            ]
+        );
+        assert_eq!(unpack_char_runs(packed), list);
+    }
+
+    #[test]
+    fn it_calculates_sizes_of_char_runs() {
+        let state = TeXState::new();
+        let font = Font {
+            font_name: "cmr10".to_string(),
+            scale: Dimen::from_unit(10.0, Unit::Point),
+        };
+        let list = vec![
+            HorizontalListElem::Char {
+                chr: 'g',
+                font: font.clone(),
+            },
+            HorizontalListElem::Char {
+                chr: 'h',
+                font: font.clone(),
+            },
+        ];
+
+        let mut height = Dimen::zero();
+        let mut depth = Dimen::zero();
+        let mut width = Glue::zero();
+        for elem in &list {
+            let (elem_height, elem_depth, elem_width) = elem.get_size(&state);
+            if elem_height > height {
+                height = elem_height;
+            }
+            if elem_depth > depth {
+                depth = elem_depth;
+            }
+            width = width + elem_width;
+        }
+
+        let run = &pack_char_runs(list)[0];
+        assert_eq!(run.get_size(&state), (height, depth, width));
+    }
+
     #[test]
     fn it_calculates_elem_sizes_with_shifts() {
         let mut hbox = HorizontalBox::empty();
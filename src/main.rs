@@ -11,11 +11,13 @@ mod dvi;
 mod font;
 mod font_metrics;
 mod glue;
+mod hyphenation;
 mod lexer;
 mod list;
 mod makro;
 mod math_code;
 mod math_list;
+mod mu_glue;
 mod parser;
 mod paths;
 mod state;
@@ -33,6 +35,10 @@ use std::io;
 use std::io::prelude::*;
 
 use crate::box_to_dvi::DVIFileWriter;
+use crate::boxes::TeXBox;
+use crate::category::CategoryPreset;
+use crate::dimension::Dimen;
+use crate::list::VerticalListElem;
 use crate::parser::Parser;
 use crate::state::TeXState;
 
@@ -47,22 +53,67 @@ fn main() -> io::Result<()> {
     }
 
     let state = TeXState::new();
-    let mut parser = Parser::new(&lines[..], &state);
 
-    let mut file_writer = DVIFileWriter::new();
-    file_writer.start(
-        (25400000, 473628672),
-        1000,
-        b"Made by XymosTeX".to_vec(),
+    // Let the non-ASCII letters that the Computer Modern fonts have glyphs
+    // for be typed directly.
+    for (ch, position) in &[
+        ('\u{0131}', 16),
+        ('\u{0237}', 17),
+        ('\u{00df}', 25),
+        ('\u{00e6}', 26),
+        ('\u{0153}', 27),
+        ('\u{00f8}', 28),
+        ('\u{00c6}', 29),
+        ('\u{0152}', 30),
+        ('\u{00d8}', 31),
+    ] {
+        state.set_font_position(true, *ch, *position);
+    }
+    let mut parser = Parser::new_with_category_preset(
+        &lines[..],
+        &state,
+        CategoryPreset::Plain,
     );
 
+    let mut file_writer = DVIFileWriter::new();
+
     let result = parser.parse_outer_vertical_box();
-    file_writer.add_page(&result.list, &None, [1, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
 
-    file_writer.end();
+    for message in parser.take_messages() {
+        println!("{}", message);
+    }
+
+    // Pages that were shipped out while parsing come first, followed by
+    // whatever was left on the main vertical list at the end.
+    let mut page_number = 1;
+    for tex_box in parser.take_shipped_out_boxes() {
+        match tex_box {
+            TeXBox::VerticalBox(vbox) => file_writer.add_page(
+                &vbox.list,
+                &vbox.glue_set_ratio,
+                [page_number, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+            ),
+            TeXBox::HorizontalBox(_) => file_writer.add_page(
+                &[VerticalListElem::Box {
+                    tex_box,
+                    shift: Dimen::zero(),
+                }],
+                &None,
+                [page_number, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+            ),
+        }
+        page_number += 1;
+    }
+    file_writer.add_page(
+        &result.list,
+        &None,
+        [page_number, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+    );
+
+    file_writer.finish(1000);
 
-    let file = file_writer.to_file();
+    let mut output = fs::File::create("texput.dvi")?;
+    file_writer.write_to(&mut output)?;
 
-    let output = fs::File::create("texput.dvi")?;
-    file.write_to(output)
+    Ok(())
 }
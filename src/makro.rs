@@ -3,6 +3,7 @@
 
 use std::collections::HashMap;
 
+use crate::category::Category;
 use crate::token::Token;
 
 #[derive(PartialEq, Eq, Debug)]
@@ -15,6 +16,12 @@ pub enum MacroListElem {
 pub struct Macro {
     pub parameter_list: Vec<MacroListElem>,
     replacement_list: Vec<MacroListElem>,
+    // Whether the macro was defined with \long, which means that its
+    // arguments can contain \par.
+    long: bool,
+    // Whether the macro was defined with \outer, which means that it can't
+    // show up in places like macro arguments and definitions.
+    outer: bool,
 }
 
 impl Macro {
@@ -25,6 +32,8 @@ impl Macro {
         let makro: Macro = Macro {
             parameter_list,
             replacement_list,
+            long: false,
+            outer: false,
         };
 
         makro.validate();
@@ -62,6 +71,74 @@ impl Macro {
         }
     }
 
+    pub fn with_long(mut self, long: bool) -> Macro {
+        self.long = long;
+        self
+    }
+
+    pub fn is_long(&self) -> bool {
+        self.long
+    }
+
+    pub fn with_outer(mut self, outer: bool) -> Macro {
+        self.outer = outer;
+        self
+    }
+
+    pub fn is_outer(&self) -> bool {
+        self.outer
+    }
+
+    // Returns the meaning of the macro like \meaning shows it, with its
+    // prefixes, its parameter text, and its replacement text.
+    pub fn meaning(&self) -> String {
+        let mut prefixes = String::new();
+        if self.long {
+            prefixes.push_str("\\long");
+        }
+        if self.outer {
+            prefixes.push_str("\\outer");
+        }
+
+        let mut meaning = if prefixes.is_empty() {
+            "macro:".to_string()
+        } else {
+            format!("{} macro:", prefixes)
+        };
+
+        for elem in &self.parameter_list {
+            match elem {
+                MacroListElem::Parameter(param_num) => {
+                    meaning.push_str(&format!("#{}", param_num))
+                }
+                MacroListElem::Token(tok) => {
+                    meaning.push_str(&tok.to_token_list_string())
+                }
+            }
+        }
+
+        meaning.push_str("->");
+
+        for elem in &self.replacement_list {
+            match elem {
+                MacroListElem::Parameter(param_num) => {
+                    meaning.push_str(&format!("#{}", param_num))
+                }
+                // Parameter characters in the replacement text are doubled
+                // so that they aren't confused with parameters.
+                MacroListElem::Token(Token::Char(chr, Category::Parameter)) => {
+                    meaning.push(*chr);
+                    meaning.push(*chr);
+                }
+                MacroListElem::Token(tok) => {
+                    meaning.push_str(&tok.to_token_list_string())
+                }
+            }
+        }
+
+        meaning
+    }
+
     pub fn get_replacement(
         &self,
         parameter_values: &HashMap<usize, Vec<Token>>,
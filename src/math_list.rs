@@ -1,6 +1,8 @@
 use crate::boxes::TeXBox;
 use crate::dimension::Dimen;
+use crate::glue::Glue;
 use crate::math_code::{MathClass, MathCode};
+use crate::mu_glue::MuGlue;
 
 #[derive(Debug, PartialEq, Clone, Hash, Eq, Copy)]
 pub enum AtomKind {
@@ -11,17 +13,13 @@ pub enum AtomKind {
     Open,  // 4
     Close, // 5
     Punct, // 6
-    #[allow(dead_code)]
     Inner,
     #[allow(dead_code)]
     Over,
     #[allow(dead_code)]
     Under,
-    #[allow(dead_code)]
     Acc,
-    #[allow(dead_code)]
     Rad,
-    #[allow(dead_code)]
     Vcent,
 }
 
@@ -66,12 +64,31 @@ pub enum MathField {
     MathList(MathList),
 }
 
+/// Where the scripts on an Op atom go, set with \limits, \nolimits, and
+/// \displaylimits.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum OpLimits {
+    // Limits go above and below the operator in display style, and next to
+    // it otherwise.
+    DisplayLimits,
+    // Limits always go above and below the operator.
+    Limits,
+    // Limits always go next to the operator, like normal scripts.
+    NoLimits,
+}
+
 #[derive(Debug, PartialEq)]
 pub struct MathAtom {
     pub kind: AtomKind,
     pub nucleus: Option<MathField>,
     pub superscript: Option<MathField>,
     pub subscript: Option<MathField>,
+    // This only matters for Op atoms.
+    pub limits: OpLimits,
+    // The accent character placed over the nucleus of Acc atoms.
+    pub accent: Option<MathSymbol>,
+    // The radical sign placed in front of the nucleus of Rad atoms.
+    pub delimiter: Option<MathDelimiter>,
 }
 
 impl MathAtom {
@@ -81,6 +98,9 @@ impl MathAtom {
             nucleus: None,
             superscript: None,
             subscript: None,
+            limits: OpLimits::DisplayLimits,
+            accent: None,
+            delimiter: None,
         }
     }
 
@@ -92,6 +112,9 @@ impl MathAtom {
             nucleus: Some(MathField::Symbol(symbol)),
             superscript: None,
             subscript: None,
+            limits: OpLimits::DisplayLimits,
+            accent: None,
+            delimiter: None,
         }
     }
 
@@ -101,6 +124,9 @@ impl MathAtom {
             nucleus: Some(MathField::MathList(math_list)),
             superscript: None,
             subscript: None,
+            limits: OpLimits::DisplayLimits,
+            accent: None,
+            delimiter: None,
         }
     }
 
@@ -137,6 +163,80 @@ pub enum MathStyle {
 }
 
 impl MathStyle {
+    /// Returns the style that superscripts are set in for this style.
+    pub fn superscript_style(&self) -> MathStyle {
+        match *self {
+            MathStyle::DisplayStyle => MathStyle::ScriptStyle,
+            MathStyle::DisplayStylePrime => MathStyle::ScriptStylePrime,
+            MathStyle::TextStyle => MathStyle::ScriptStyle,
+            MathStyle::TextStylePrime => MathStyle::ScriptStylePrime,
+            MathStyle::ScriptStyle => MathStyle::ScriptScriptStyle,
+            MathStyle::ScriptStylePrime => MathStyle::ScriptScriptStylePrime,
+            MathStyle::ScriptScriptStyle => MathStyle::ScriptScriptStyle,
+            MathStyle::ScriptScriptStylePrime => {
+                MathStyle::ScriptScriptStylePrime
+            }
+        }
+    }
+
+    /// Returns the style that subscripts are set in for this style. These
+    /// are always cramped.
+    pub fn subscript_style(&self) -> MathStyle {
+        match *self {
+            MathStyle::DisplayStyle
+            | MathStyle::DisplayStylePrime
+            | MathStyle::TextStyle
+            | MathStyle::TextStylePrime => MathStyle::ScriptStylePrime,
+            MathStyle::ScriptStyle
+            | MathStyle::ScriptStylePrime
+            | MathStyle::ScriptScriptStyle
+            | MathStyle::ScriptScriptStylePrime => {
+                MathStyle::ScriptScriptStylePrime
+            }
+        }
+    }
+
+    /// Returns the style that the numerator of a fraction is set in for this
+    /// style.
+    pub fn fraction_numerator_style(&self) -> MathStyle {
+        match *self {
+            MathStyle::DisplayStyle => MathStyle::TextStyle,
+            MathStyle::DisplayStylePrime => MathStyle::TextStylePrime,
+            _ => self.superscript_style(),
+        }
+    }
+
+    /// Returns the style that the denominator of a fraction is set in for
+    /// this style. These are always cramped.
+    pub fn fraction_denominator_style(&self) -> MathStyle {
+        self.fraction_numerator_style().cramped_style()
+    }
+
+    /// Returns the cramped version of this style.
+    pub fn cramped_style(&self) -> MathStyle {
+        match *self {
+            MathStyle::DisplayStyle => MathStyle::DisplayStylePrime,
+            MathStyle::TextStyle => MathStyle::TextStylePrime,
+            MathStyle::ScriptStyle => MathStyle::ScriptStylePrime,
+            MathStyle::ScriptScriptStyle => MathStyle::ScriptScriptStylePrime,
+            _ => self.clone(),
+        }
+    }
+
+    pub fn is_display(&self) -> bool {
+        matches!(self, MathStyle::DisplayStyle | MathStyle::DisplayStylePrime)
+    }
+
+    pub fn is_cramped(&self) -> bool {
+        matches!(
+            self,
+            MathStyle::DisplayStylePrime
+                | MathStyle::TextStylePrime
+                | MathStyle::ScriptStylePrime
+                | MathStyle::ScriptScriptStylePrime
+        )
+    }
+
     pub fn is_script(&self) -> bool {
         match *self {
             MathStyle::DisplayStyle => false,
@@ -151,24 +251,57 @@ impl MathStyle {
     }
 }
 
-#[allow(dead_code)]
 #[derive(Debug, PartialEq)]
 pub struct MathDelimiter {
-    small_font_family: u16,
-    small_position: u16,
-    large_font_family: u16,
-    large_position: u16,
+    pub small_font_family: u8,
+    pub small_position: u8,
+    pub large_font_family: u8,
+    pub large_position: u8,
+}
+
+impl MathDelimiter {
+    /// Splits up a 24-bit delimiter code like "uvvxyz into the family and
+    /// position of the small variant (u and vv) and the large variant (x and
+    /// yz) of the delimiter.
+    pub fn from_number(num: u32) -> MathDelimiter {
+        if num >= 0x1000000 {
+            panic!("Invalid value for delimiter: {}", num);
+        }
+
+        MathDelimiter {
+            small_font_family: ((num / 0x100000) % 0x10) as u8,
+            small_position: ((num / 0x1000) % 0x100) as u8,
+            large_font_family: ((num / 0x100) % 0x10) as u8,
+            large_position: (num % 0x100) as u8,
+        }
+    }
 }
 
-#[allow(dead_code)]
 #[derive(Debug, PartialEq)]
 pub struct GeneralizedFraction {
-    left_delim: Option<MathDelimiter>,
-    right_delim: Option<MathDelimiter>,
-    bar_height: Dimen,
+    pub numerator: MathList,
+    pub denominator: MathList,
+    pub left_delim: Option<MathDelimiter>,
+    pub right_delim: Option<MathDelimiter>,
+    // The thickness of the fraction rule. None means that the default rule
+    // thickness of the current font is used, like with \over.
+    pub bar_height: Option<Dimen>,
+}
+
+impl GeneralizedFraction {
+    /// Makes the fraction for \over, which has a default thickness rule and
+    /// no delimiters.
+    pub fn over(numerator: MathList, denominator: MathList) -> Self {
+        GeneralizedFraction {
+            numerator,
+            denominator,
+            left_delim: None,
+            right_delim: None,
+            bar_height: None,
+        }
+    }
 }
 
-#[allow(dead_code)]
 #[derive(Debug, PartialEq)]
 pub enum BoundaryKind {
     Left,
@@ -180,11 +313,18 @@ pub enum MathListElem {
     Atom(MathAtom),
     #[allow(dead_code)]
     StyleChange(MathStyle),
-    #[allow(dead_code)]
     GeneralizedFraction(GeneralizedFraction),
-    #[allow(dead_code)]
+    // The delimiters from \left and \right, at the beginning and end of the
+    // list inside of them. Null delimiters are None.
     Boundary(BoundaryKind, Option<MathDelimiter>),
-    #[allow(dead_code)]
+    // Glue from \hskip, which is already in regular units.
+    Glue(Glue),
+    // Glue from \mskip, which is measured in mu.
+    MuGlue(MuGlue),
+    // A \nonscript, which cancels glue right after it in script styles.
+    NonScript,
+    // A list for each style, only one of which ends up being used depending
+    // on the style that this elem is in.
     FourWayChoice {
         display: MathList,
         text: MathList,
@@ -0,0 +1,164 @@
+use std::ops::{Add, Div, Mul};
+
+use crate::dimension::{Dimen, SpringDimen};
+use crate::glue::Glue;
+
+// Glue that is measured in math units (mu) instead of points, like the glue in
+// \muskip registers and the math spacing parameters. The components are
+// stored in the same fixed-point format as a Glue's, so 1mu is stored the
+// same way as 1pt, but they only turn into real lengths once the size of the
+// math font they are used with is known.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct MuGlue {
+    pub space: Dimen,
+    pub stretch: SpringDimen,
+    pub shrink: SpringDimen,
+}
+
+impl MuGlue {
+    pub fn zero() -> MuGlue {
+        Self::from_dimen(Dimen::zero())
+    }
+
+    pub fn from_dimen(dimen: Dimen) -> MuGlue {
+        MuGlue {
+            space: dimen,
+            stretch: SpringDimen::Dimen(Dimen::zero()),
+            shrink: SpringDimen::Dimen(Dimen::zero()),
+        }
+    }
+
+    // Prints the glue the way TeX does for \the, e.g. "5.0mu plus 1.0fil".
+    pub fn to_tex_string(&self) -> String {
+        let glue = Glue {
+            space: self.space,
+            stretch: self.stretch.clone(),
+            shrink: self.shrink.clone(),
+        };
+        glue.to_tex_string_with_unit("mu")
+    }
+
+    // Converts the glue into regular glue for a math font whose quad is
+    // `quad`, where 1mu is 1/18 of the quad. Like TeX's math_glue, the finite
+    // components are multiplied exactly and infinite stretch and shrink are
+    // left as they are.
+    pub fn to_glue(&self, quad: Dimen) -> Glue {
+        let mu = quad.as_scaled_points() / 18;
+        let mut whole = mu / 65536;
+        let mut fraction = mu % 65536;
+        if fraction < 0 {
+            whole -= 1;
+            fraction += 65536;
+        }
+
+        let mu_mult = |dimen: Dimen| {
+            let value = dimen.as_scaled_points();
+            Dimen::from_scaled_points(
+                whole * value
+                    + ((value as i64) * (fraction as i64) / 65536) as i32,
+            )
+        };
+        let mu_mult_spring = |spring: &SpringDimen| match spring {
+            SpringDimen::Dimen(dimen) => SpringDimen::Dimen(mu_mult(*dimen)),
+            SpringDimen::FilDimen(_) => spring.clone(),
+        };
+
+        Glue {
+            space: mu_mult(self.space),
+            stretch: mu_mult_spring(&self.stretch),
+            shrink: mu_mult_spring(&self.shrink),
+        }
+    }
+}
+
+impl Add for MuGlue {
+    type Output = MuGlue;
+
+    fn add(mut self, other: MuGlue) -> MuGlue {
+        self.space = self.space + other.space;
+        self.stretch = self.stretch + other.stretch;
+        self.shrink = self.shrink + other.shrink;
+        self
+    }
+}
+
+impl Mul<i32> for MuGlue {
+    type Output = MuGlue;
+
+    fn mul(self, other: i32) -> MuGlue {
+        MuGlue {
+            space: self.space * other,
+            stretch: self.stretch * other,
+            shrink: self.shrink * other,
+        }
+    }
+}
+
+impl Div<i32> for MuGlue {
+    type Output = MuGlue;
+
+    fn div(self, other: i32) -> MuGlue {
+        MuGlue {
+            space: self.space / other,
+            stretch: self.stretch / other,
+            shrink: self.shrink / other,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::dimension::{FilDimen, FilKind, Unit};
+
+    #[test]
+    fn it_converts_mu_glue_to_glue_with_a_10pt_quad() {
+        let mu_glue = MuGlue {
+            space: Dimen::from_unit(18.0, Unit::Point),
+            stretch: SpringDimen::Dimen(Dimen::from_unit(9.0, Unit::Point)),
+            shrink: SpringDimen::FilDimen(FilDimen::new(FilKind::Fil, 1.0)),
+        };
+
+        // 18mu is exactly a quad, apart from the rounding of 1mu to a whole
+        // number of scaled points.
+        let quad = Dimen::from_unit(10.0, Unit::Point);
+        let mu = quad.as_scaled_points() / 18;
+        assert_eq!(
+            mu_glue.to_glue(quad),
+            Glue {
+                space: Dimen::from_scaled_points(mu * 18),
+                stretch: SpringDimen::Dimen(Dimen::from_scaled_points(mu * 9)),
+                shrink: SpringDimen::FilDimen(FilDimen::new(FilKind::Fil, 1.0)),
+            }
+        );
+    }
+
+    #[test]
+    fn it_converts_mu_glue_to_glue_with_a_7pt_quad() {
+        let quad = Dimen::from_unit(7.0, Unit::Point);
+        let mu = quad.as_scaled_points() / 18;
+        assert_eq!(
+            MuGlue::from_dimen(Dimen::from_unit(3.0, Unit::Point))
+                .to_glue(quad),
+            Glue::from_dimen(Dimen::from_scaled_points(mu * 3))
+        );
+
+        // Fractions of a mu are multiplied exactly.
+        assert_eq!(
+            MuGlue::from_dimen(Dimen::from_unit(0.5, Unit::Point))
+                .to_glue(quad),
+            Glue::from_dimen(Dimen::from_scaled_points(mu / 2))
+        );
+    }
+
+    #[test]
+    fn it_prints_mu_glue() {
+        let mu_glue = MuGlue {
+            space: Dimen::from_unit(5.0, Unit::Point),
+            stretch: SpringDimen::FilDimen(FilDimen::new(FilKind::Fil, 1.0)),
+            shrink: SpringDimen::Dimen(Dimen::from_unit(2.0, Unit::Point)),
+        };
+        assert_eq!(mu_glue.to_tex_string(), "5.0mu plus 1.0fil minus 2.0mu");
+    }
+}
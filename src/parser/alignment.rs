@@ -0,0 +1,328 @@
+use crate::boxes::{HorizontalBox, TeXBox};
+use crate::category::Category;
+use crate::dimension::Dimen;
+use crate::list::{HorizontalListElem, VerticalListElem};
+use crate::parser::boxes::BoxLayout;
+use crate::parser::Parser;
+use crate::token::Token;
+
+// The template for a single column of an alignment, which is split into the
+// tokens that come before the # (the u part) and after it (the v part).
+struct ColumnTemplate {
+    u: Vec<Token>,
+    v: Vec<Token>,
+}
+
+impl<'a> Parser<'a> {
+    pub fn is_halign_head(&mut self) -> bool {
+        self.is_next_expanded_token_in_set_of_primitives(&["halign"])
+    }
+
+    /// Lexes unexpanded tokens until a & or \cr that isn't inside of braces,
+    /// returning the tokens and whether the thing that ended them was a \cr.
+    fn parse_alignment_entry_tokens(&mut self) -> (Vec<Token>, bool) {
+        let mut tokens = Vec::new();
+        let mut brace_level = 0;
+
+        loop {
+            let token = match self.lex_unexpanded_token() {
+                Some(token) => token,
+                None => panic!(r"EOF found while scanning an \halign"),
+            };
+
+            match token {
+                Token::Char(_, Category::BeginGroup) => brace_level += 1,
+                Token::Char(_, Category::EndGroup) => {
+                    if brace_level == 0 {
+                        panic!(r"Missing \cr inserted");
+                    }
+                    brace_level -= 1;
+                }
+                Token::Char(_, Category::AlignmentTab) if brace_level == 0 => {
+                    return (tokens, false);
+                }
+                ref tok
+                    if brace_level == 0
+                        && self.state.is_token_equal_to_prim(tok, "cr") =>
+                {
+                    return (tokens, true);
+                }
+                _ => (),
+            }
+
+            tokens.push(token);
+        }
+    }
+
+    /// Parses the preamble of an alignment, which is a list of column
+    /// templates separated by &s and ending with a \cr. Each template must
+    /// contain exactly one #, which is where the entries of the column go.
+    fn parse_alignment_preamble(&mut self) -> Vec<ColumnTemplate> {
+        let mut templates = Vec::new();
+
+        loop {
+            let (tokens, is_cr) = self.parse_alignment_entry_tokens();
+
+            let mut u = Vec::new();
+            let mut v = None;
+            for token in tokens {
+                match (token, &mut v) {
+                    (Token::Char(_, Category::Parameter), None) => {
+                        v = Some(Vec::new());
+                    }
+                    (Token::Char(_, Category::Parameter), Some(_)) => {
+                        panic!("Only one # is allowed per tab");
+                    }
+                    // Spaces at the beginning of templates are ignored.
+                    (Token::Char(_, Category::Space), None) if u.is_empty() => {
+                    }
+                    (token, None) => u.push(token),
+                    (token, Some(v)) => v.push(token),
+                }
+            }
+
+            match v {
+                Some(v) => templates.push(ColumnTemplate { u, v }),
+                None => panic!("Missing # inserted in alignment preamble"),
+            }
+
+            if is_cr {
+                return templates;
+            }
+        }
+    }
+
+    /// Parses an alignment entry in restricted horizontal mode, surrounded by
+    /// the u and v parts of its column template. Each entry is in its own
+    /// group, like TeX does.
+    fn parse_alignment_entry(
+        &mut self,
+        template: &ColumnTemplate,
+        entry: Vec<Token>,
+    ) -> Vec<HorizontalListElem> {
+        let mut tokens = template.u.clone();
+        tokens.extend(entry);
+        tokens.extend(template.v.clone());
+        tokens.push(Token::Char('}', Category::EndGroup));
+        self.add_upcoming_tokens(tokens);
+
+        self.state.push_state();
+        let list = self.parse_horizontal_list(true, false);
+        self.state.pop_state();
+
+        match self.lex_expanded_token() {
+            Some(Token::Char(_, Category::EndGroup)) => (),
+            _ => panic!("Expected } after alignment entry"),
+        }
+
+        list
+    }
+
+    /// Parses an \halign{<preamble>\cr<rows>}. Each row is turned into a box
+    /// where every entry is as wide as the widest entry in its column, and the
+    /// rows are returned to be added to the current vertical list.
+    pub fn parse_halign(&mut self) -> Vec<VerticalListElem> {
+        let tok = self.lex_expanded_token().unwrap();
+        if !self.state.is_token_equal_to_prim(&tok, "halign") {
+            panic!("Invalid halign head: {:?}", tok);
+        }
+
+        // TODO(xymostech): Support `to` and `spread` box specifications here
+        // once \tabskip glue exists to stretch.
+        self.parse_filler_expanded();
+        match self.lex_expanded_token() {
+            Some(Token::Char(_, Category::BeginGroup)) => (),
+            _ => panic!(r"Missing { inserted after \halign"),
+        }
+
+        self.state.push_state();
+
+        let templates = self.parse_alignment_preamble();
+
+        let mut rows: Vec<Vec<HorizontalBox>> = Vec::new();
+        loop {
+            self.parse_optional_spaces_expanded();
+            if let Some(Token::Char(_, Category::EndGroup)) =
+                self.peek_expanded_token()
+            {
+                self.lex_expanded_token();
+                break;
+            }
+
+            let mut row = Vec::new();
+            loop {
+                let (entry, is_cr) = self.parse_alignment_entry_tokens();
+                if row.len() == templates.len() {
+                    panic!(r"Extra alignment tab has been changed to \cr");
+                }
+
+                let list =
+                    self.parse_alignment_entry(&templates[row.len()], entry);
+                let (hbox, _) = self.hpack(list, &BoxLayout::Natural);
+                row.push(hbox);
+
+                if is_cr {
+                    break;
+                }
+            }
+            rows.push(row);
+        }
+
+        self.state.pop_state();
+
+        let mut column_widths = vec![Dimen::zero(); templates.len()];
+        for row in &rows {
+            for (hbox, width) in row.iter().zip(column_widths.iter_mut()) {
+                if hbox.width > *width {
+                    *width = hbox.width;
+                }
+            }
+        }
+
+        // TODO(xymostech): Add \tabskip glue between the columns.
+        let mut result = Vec::new();
+        for row in rows {
+            let mut row_list = Vec::new();
+            let mut cells = row.into_iter();
+            for width in &column_widths {
+                // Entries are never wider than their column, so they can't
+                // end up overfull. Rows with fewer entries than there are
+                // columns are padded with empty entries.
+                let cell = match cells.next() {
+                    Some(hbox) => {
+                        let layout = BoxLayout::Fixed(*width);
+                        self.hpack((*hbox.list).clone(), &layout).0
+                    }
+                    None => {
+                        let mut hbox = HorizontalBox::empty();
+                        hbox.width = *width;
+                        hbox
+                    }
+                };
+
+                row_list.push(HorizontalListElem::Box {
+                    tex_box: TeXBox::HorizontalBox(cell),
+                    shift: Dimen::zero(),
+                });
+            }
+
+            let (row_box, _) = self.hpack(row_list, &BoxLayout::Natural);
+            result.push(VerticalListElem::Box {
+                tex_box: TeXBox::HorizontalBox(row_box),
+                shift: Dimen::zero(),
+            });
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::math_list::{MathAtom, MathField, MathListElem};
+    use crate::testing::with_parser;
+
+    fn get_row_cells(elem: &VerticalListElem) -> Vec<HorizontalBox> {
+        match elem {
+            VerticalListElem::Box {
+                tex_box: TeXBox::HorizontalBox(row),
+                ..
+            } => row
+                .list
+                .iter()
+                .map(|cell| match cell {
+                    HorizontalListElem::Box {
+                        tex_box: TeXBox::HorizontalBox(cell),
+                        ..
+                    } => cell.clone(),
+                    cell => panic!("Expected an alignment entry: {:?}", cell),
+                })
+                .collect(),
+            elem => panic!("Expected an alignment row: {:?}", elem),
+        }
+    }
+
+    #[test]
+    fn it_aligns_columns_to_their_widest_entry() {
+        with_parser(
+            &[
+                r"\halign{#\hfil&\hfil#\cr%",
+                r"a&bbb\cr%",
+                r"aaa&b\cr%",
+                r"}%",
+                r"\hbox{aaa}\hbox{bbb}%",
+            ],
+            |parser| {
+                assert!(parser.is_halign_head());
+                let rows = parser.parse_halign();
+                assert_eq!(rows.len(), 2);
+
+                let aaa_width = *parser.parse_box().unwrap().width();
+                let bbb_width = *parser.parse_box().unwrap().width();
+
+                for row in &rows {
+                    let cells = get_row_cells(row);
+                    assert_eq!(cells.len(), 2);
+                    assert_eq!(cells[0].width, aaa_width);
+                    assert_eq!(cells[1].width, bbb_width);
+                }
+            },
+        );
+    }
+
+    #[test]
+    fn it_pads_short_rows_with_empty_entries() {
+        with_parser(&[r"\halign{#&#\cr a&b\cr a\cr}%"], |parser| {
+            let rows = parser.parse_halign();
+            let first_row = get_row_cells(&rows[0]);
+            let second_row = get_row_cells(&rows[1]);
+
+            assert_eq!(second_row.len(), 2);
+            assert_eq!(second_row[1].width, first_row[1].width);
+            assert!(second_row[1].list.is_empty());
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = r"Extra alignment tab has been changed to \cr")]
+    fn it_fails_on_rows_with_too_many_entries() {
+        with_parser(&[r"\halign{#\cr a&b\cr}%"], |parser| {
+            parser.parse_halign();
+        });
+    }
+
+    #[test]
+    fn it_parses_alignments_inside_vcenter() {
+        with_parser(
+            &[
+                r"\hbox{$aa$}%",
+                r"\vcenter{\halign{$#$\hfil&$#$\cr a&b\cr aa&bb\cr}}%",
+            ],
+            |parser| {
+                let aa_width = *parser.parse_box().unwrap().width();
+
+                let list = parser.parse_math_list();
+                let vbox = match &list[0] {
+                    MathListElem::Atom(MathAtom {
+                        nucleus:
+                            Some(MathField::TeXBox(TeXBox::VerticalBox(vbox))),
+                        ..
+                    }) => vbox.clone(),
+                    elem => panic!("Expected a \\vcenter atom: {:?}", elem),
+                };
+                let rows = vbox
+                    .list
+                    .iter()
+                    .filter(|elem| matches!(elem, VerticalListElem::Box { .. }))
+                    .map(get_row_cells)
+                    .collect::<Vec<_>>();
+                assert_eq!(rows.len(), 2);
+                assert_eq!(rows[0][0].width, aa_width);
+                assert_eq!(rows[1][0].width, aa_width);
+                assert_eq!(rows[0][1].width, rows[1][1].width);
+            },
+        );
+    }
+}
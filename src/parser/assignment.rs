@@ -5,6 +5,7 @@ use crate::dimension::Dimen;
 use crate::font::Font;
 use crate::font_metrics::FontMetrics;
 use crate::math_code::MathCode;
+use crate::math_list::MathStyle;
 use crate::parser::Parser;
 use crate::token::Token;
 
@@ -16,11 +17,14 @@ enum AtClause {
 
 impl<'a> Parser<'a> {
     fn is_variable_assignment_head(&mut self) -> bool {
-        self.is_integer_variable_head() || self.is_dimen_variable_head()
+        self.is_integer_variable_head()
+            || self.is_dimen_variable_head()
+            || self.is_glue_variable_head()
+            || self.is_mu_glue_variable_head()
     }
 
     fn is_macro_assignment_head(&mut self) -> bool {
-        self.is_next_expanded_token_in_set_of_primitives(&["def"])
+        self.is_next_expanded_token_in_set_of_primitives(&["def", "edef"])
     }
 
     fn is_let_assignment_head(&mut self) -> bool {
@@ -34,17 +38,34 @@ impl<'a> Parser<'a> {
     }
 
     fn is_shorthand_definition_head(&mut self) -> bool {
-        self.is_next_expanded_token_in_set_of_primitives(&["mathchardef"])
+        self.is_next_expanded_token_in_set_of_primitives(&[
+            "mathchardef",
+            "muskipdef",
+        ])
     }
 
     fn is_code_assignment_head(&mut self) -> bool {
-        self.is_next_expanded_token_in_set_of_primitives(&["mathcode"])
+        self.is_next_expanded_token_in_set_of_primitives(&[
+            "mathcode", "delcode",
+        ])
     }
 
     fn is_font_assignment_head(&mut self) -> bool {
         self.is_next_expanded_token_in_set_of_primitives(&["font"])
     }
 
+    fn is_token_list_assignment_head(&mut self) -> bool {
+        self.is_next_expanded_token_in_set_of_primitives(&["output"])
+    }
+
+    fn is_family_assignment_head(&mut self) -> bool {
+        self.is_next_expanded_token_in_set_of_primitives(&[
+            "textfont",
+            "scriptfont",
+            "scriptscriptfont",
+        ])
+    }
+
     fn is_fontdef_assignment_head(&mut self) -> bool {
         match self.peek_expanded_token() {
             Some(tok) => self.state.get_fontdef(&tok).is_some(),
@@ -61,10 +82,15 @@ impl<'a> Parser<'a> {
             || self.is_code_assignment_head()
             || self.is_font_assignment_head()
             || self.is_fontdef_assignment_head()
+            || self.is_family_assignment_head()
+            || self.is_token_list_assignment_head()
+            || self.is_patterns_assignment_head()
     }
 
     fn is_assignment_prefix(&mut self) -> bool {
-        self.is_next_expanded_token_in_set_of_primitives(&["global"])
+        self.is_next_expanded_token_in_set_of_primitives(&[
+            "global", "long", "outer",
+        ])
     }
 
     pub fn is_assignment_head(&mut self) -> bool {
@@ -84,6 +110,16 @@ impl<'a> Parser<'a> {
             self.parse_equals_expanded();
             let value = self.parse_dimen();
             variable.set(self.state, global, value);
+        } else if self.is_glue_variable_head() {
+            let variable = self.parse_glue_variable();
+            self.parse_equals_expanded();
+            let value = self.parse_glue();
+            variable.set(self.state, global, value);
+        } else if self.is_mu_glue_variable_head() {
+            let variable = self.parse_mu_glue_variable();
+            self.parse_equals_expanded();
+            let value = self.parse_mu_glue();
+            variable.set(self.state, global, value);
         } else {
             panic!("unimplemented");
         }
@@ -122,6 +158,18 @@ impl<'a> Parser<'a> {
 
     fn parse_arithmetic(&mut self, global: bool) {
         let tok = self.lex_expanded_token().unwrap();
+
+        if self.is_dimen_variable_head() {
+            self.parse_dimen_arithmetic(&tok, global);
+            return;
+        } else if self.is_glue_variable_head() {
+            self.parse_glue_arithmetic(&tok, global);
+            return;
+        } else if self.is_mu_glue_variable_head() {
+            self.parse_mu_glue_arithmetic(&tok, global);
+            return;
+        }
+
         let variable = self.parse_integer_variable();
         self.parse_optional_keyword_expanded("by");
         self.parse_optional_spaces_expanded();
@@ -142,12 +190,81 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn parse_macro_assignment(&mut self, global: bool) {
+    fn parse_dimen_arithmetic(&mut self, tok: &Token, global: bool) {
+        let variable = self.parse_dimen_variable();
+        self.parse_optional_keyword_expanded("by");
+        self.parse_optional_spaces_expanded();
+
+        if self.state.is_token_equal_to_prim(tok, "advance") {
+            let dimen = self.parse_dimen();
+            variable.set(self.state, global, variable.get(self.state) + dimen);
+        } else if self.state.is_token_equal_to_prim(tok, "multiply") {
+            let number = self.parse_number();
+            variable.set(self.state, global, variable.get(self.state) * number);
+        } else if self.state.is_token_equal_to_prim(tok, "divide") {
+            let number = self.parse_number();
+            variable.set(self.state, global, variable.get(self.state) / number);
+        } else {
+            panic!("Invalid arithmetic head: {:?}", tok);
+        }
+    }
+
+    fn parse_glue_arithmetic(&mut self, tok: &Token, global: bool) {
+        let variable = self.parse_glue_variable();
+        self.parse_optional_keyword_expanded("by");
+        self.parse_optional_spaces_expanded();
+
+        if self.state.is_token_equal_to_prim(tok, "advance") {
+            // Adding glue keeps the highest order of infinity in each of the
+            // stretch and shrink components, so e.g. 3fil + 1fill = 1fill.
+            let glue = self.parse_glue();
+            variable.set(self.state, global, variable.get(self.state) + glue);
+        } else if self.state.is_token_equal_to_prim(tok, "multiply") {
+            let number = self.parse_number();
+            variable.set(self.state, global, variable.get(self.state) * number);
+        } else if self.state.is_token_equal_to_prim(tok, "divide") {
+            let number = self.parse_number();
+            variable.set(self.state, global, variable.get(self.state) / number);
+        } else {
+            panic!("Invalid arithmetic head: {:?}", tok);
+        }
+    }
+
+    fn parse_mu_glue_arithmetic(&mut self, tok: &Token, global: bool) {
+        let variable = self.parse_mu_glue_variable();
+        self.parse_optional_keyword_expanded("by");
+        self.parse_optional_spaces_expanded();
+
+        if self.state.is_token_equal_to_prim(tok, "advance") {
+            let glue = self.parse_mu_glue();
+            variable.set(self.state, global, variable.get(self.state) + glue);
+        } else if self.state.is_token_equal_to_prim(tok, "multiply") {
+            let number = self.parse_number();
+            variable.set(self.state, global, variable.get(self.state) * number);
+        } else if self.state.is_token_equal_to_prim(tok, "divide") {
+            let number = self.parse_number();
+            variable.set(self.state, global, variable.get(self.state) / number);
+        } else {
+            panic!("Invalid arithmetic head: {:?}", tok);
+        }
+    }
+
+    fn parse_macro_assignment(
+        &mut self,
+        global: bool,
+        long: bool,
+        outer: bool,
+    ) {
         let tok = self.lex_expanded_token().unwrap();
 
-        if self.state.is_token_equal_to_prim(&tok, "def") {
+        let is_def = self.state.is_token_equal_to_prim(&tok, "def");
+        let is_edef = self.state.is_token_equal_to_prim(&tok, "edef");
+        if is_def || is_edef {
             let control_sequence = self.parse_unexpanded_control_sequence();
-            let makro = self.parse_macro_definition();
+            let makro = self
+                .parse_macro_definition(is_edef)
+                .with_long(long)
+                .with_outer(outer);
 
             self.state
                 .set_macro(global, &control_sequence, &Rc::new(makro));
@@ -189,6 +306,13 @@ impl<'a> Parser<'a> {
                 &control_sequence,
                 &MathCode::from_number(code_value as u32),
             );
+        } else if self.state.is_token_equal_to_prim(&tok, "muskipdef") {
+            let control_sequence = self.parse_unexpanded_control_sequence();
+            self.parse_equals_expanded();
+            let register_index = self.parse_8bit_number();
+
+            self.state
+                .set_muskipdef(global, &control_sequence, register_index);
         } else {
             panic!("unimplemented!");
         }
@@ -207,6 +331,17 @@ impl<'a> Parser<'a> {
                 num as char,
                 &MathCode::from_number(code_value as u32),
             );
+        } else if self.state.is_token_equal_to_prim(&tok, "delcode") {
+            let num = self.parse_8bit_number();
+            self.parse_equals_expanded();
+            let code_value = self.parse_number();
+
+            if code_value >= 0x1000000 {
+                panic!("Invalid delimiter code: {}", code_value);
+            }
+
+            self.state
+                .set_delimiter_code(global, num as char, code_value);
         } else {
             panic!("unimplemented");
         }
@@ -288,6 +423,7 @@ impl<'a> Parser<'a> {
         };
 
         self.state.set_fontdef(global, &fontdef_name, &font);
+        self.state.set_font_identifier(&font, &fontdef_name);
     }
 
     fn parse_fontdef_assignment(&mut self, global: bool) {
@@ -296,6 +432,75 @@ impl<'a> Parser<'a> {
         self.state.set_current_font(global, &font);
     }
 
+    pub fn is_font_identifier_head(&mut self) -> bool {
+        match self.peek_expanded_token() {
+            Some(tok) => {
+                self.state.is_token_equal_to_prim(&tok, "font")
+                    || self.state.get_fontdef(&tok).is_some()
+                    || self.is_family_assignment_head()
+            }
+            None => false,
+        }
+    }
+
+    // Parses a <family member>, which is \textfont, \scriptfont, or
+    // \scriptscriptfont followed by a family number. Returns the style whose
+    // fonts it refers to and the family number.
+    fn parse_family_member(&mut self) -> (MathStyle, u8) {
+        let tok = self.lex_expanded_token().unwrap();
+
+        let style = if self.state.is_token_equal_to_prim(&tok, "textfont") {
+            MathStyle::TextStyle
+        } else if self.state.is_token_equal_to_prim(&tok, "scriptfont") {
+            MathStyle::ScriptStyle
+        } else if self.state.is_token_equal_to_prim(&tok, "scriptscriptfont") {
+            MathStyle::ScriptScriptStyle
+        } else {
+            panic!("Invalid family member: {:?}", tok);
+        };
+
+        let family = self.parse_4bit_number();
+        (style, family)
+    }
+
+    fn parse_family_assignment(&mut self, global: bool) {
+        let (style, family) = self.parse_family_member();
+        self.parse_equals_expanded();
+        let font = self.parse_font_identifier();
+        self.state.set_family_font(global, &style, family, &font);
+    }
+
+    // Parses a <font>, which is either a token defined with \font, \font
+    // itself, which refers to the current font, or a <family member>.
+    pub fn parse_font_identifier(&mut self) -> Font {
+        if self.is_family_assignment_head() {
+            let (style, family) = self.parse_family_member();
+            return self.get_math_font(&style, family);
+        }
+
+        let tok = self.lex_expanded_token().unwrap();
+
+        if self.state.is_token_equal_to_prim(&tok, "font") {
+            self.state.get_current_font()
+        } else if let Some(font) = self.state.get_fontdef(&tok) {
+            font
+        } else {
+            panic!("Missing font identifier, found {:?}", tok);
+        }
+    }
+
+    fn parse_token_list_assignment(&mut self, global: bool) {
+        let tok = self.lex_expanded_token().unwrap();
+
+        if !self.state.is_token_equal_to_prim(&tok, "output") {
+            panic!("Invalid token list assignment head: {:?}", tok);
+        }
+
+        self.parse_equals_expanded();
+        let tokens = self.parse_general_text();
+        self.state.set_output_routine(global, &tokens);
+    }
+
     fn parse_simple_assignment(&mut self, global: bool) {
         if self.is_variable_assignment_head() {
             self.parse_variable_assignment(global)
@@ -313,24 +518,53 @@ impl<'a> Parser<'a> {
             self.parse_font_assignment(global)
         } else if self.is_fontdef_assignment_head() {
             self.parse_fontdef_assignment(global)
+        } else if self.is_family_assignment_head() {
+            self.parse_family_assignment(global)
+        } else if self.is_token_list_assignment_head() {
+            self.parse_token_list_assignment(global)
+        } else if self.is_patterns_assignment_head() {
+            // Patterns are always global
+            self.parse_patterns_assignment()
         } else {
             panic!("unimplemented");
         }
     }
 
-    fn parse_assignment_global(&mut self, global: bool) {
+    // Parses an assignment after some prefixes. \long and \outer only apply
+    // to macro definitions, but \global applies to any assignment.
+    fn parse_assignment_with_prefixes(
+        &mut self,
+        global: bool,
+        long: bool,
+        outer: bool,
+    ) {
         if self.is_macro_assignment_head() {
-            self.parse_macro_assignment(global)
+            self.parse_macro_assignment(global, long, outer)
         } else if self.is_simple_assignment_head() {
+            if long || outer {
+                panic!("You can't use a prefix with a non-macro assignment");
+            }
             self.parse_simple_assignment(global)
         } else {
             let tok = self.lex_expanded_token().unwrap();
             if self.state.is_token_equal_to_prim(&tok, "global") {
                 if self.is_assignment_head() {
-                    self.parse_assignment_global(true);
+                    self.parse_assignment_with_prefixes(true, long, outer);
                 } else {
                     panic!("Non-assignment head found after \\global");
                 }
+            } else if self.state.is_token_equal_to_prim(&tok, "long") {
+                if self.is_assignment_head() {
+                    self.parse_assignment_with_prefixes(global, true, outer);
+                } else {
+                    panic!("Non-assignment head found after \\long");
+                }
+            } else if self.state.is_token_equal_to_prim(&tok, "outer") {
+                if self.is_assignment_head() {
+                    self.parse_assignment_with_prefixes(global, long, true);
+                } else {
+                    panic!("Non-assignment head found after \\outer");
+                }
             } else {
                 panic!("Invalid start found in parse_assignment");
             }
@@ -338,7 +572,7 @@ impl<'a> Parser<'a> {
     }
 
     pub fn parse_assignment(&mut self) {
-        self.parse_assignment_global(false);
+        self.parse_assignment_with_prefixes(false, false, false);
     }
 }
 
@@ -347,8 +581,10 @@ mod tests {
     use super::*;
 
     use crate::category::Category;
-    use crate::dimension::{Dimen, Unit};
+    use crate::dimension::{Dimen, FilDimen, FilKind, SpringDimen, Unit};
+    use crate::glue::Glue;
     use crate::makro::{Macro, MacroListElem};
+    use crate::mu_glue::MuGlue;
     use crate::testing::with_parser;
 
     #[test]
@@ -382,6 +618,84 @@ mod tests {
         });
     }
 
+    #[test]
+    fn it_expands_edefs() {
+        with_parser(
+            &[
+                r"\def\a{x}%",
+                r"\def\b#1{#1\a}%",
+                r"\edef\c#1{\b y#1\ifnum1<2 z\fi\number\count1}%",
+            ],
+            |parser| {
+                parser.parse_assignment();
+                parser.parse_assignment();
+                parser.parse_assignment();
+
+                assert_eq!(
+                    *parser
+                        .state
+                        .get_macro(&Token::ControlSequence("c".to_string()))
+                        .unwrap(),
+                    Macro::new(
+                        vec![MacroListElem::Parameter(1)],
+                        vec![
+                            MacroListElem::Token(Token::Char(
+                                'y',
+                                Category::Letter
+                            )),
+                            MacroListElem::Token(Token::Char(
+                                'x',
+                                Category::Letter
+                            )),
+                            MacroListElem::Parameter(1),
+                            MacroListElem::Token(Token::Char(
+                                'z',
+                                Category::Letter
+                            )),
+                            MacroListElem::Token(Token::Char(
+                                '0',
+                                Category::Other
+                            )),
+                        ]
+                    )
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn it_doesnt_expand_unexpanded_tokens_in_edefs() {
+        with_parser(
+            &[r"\def\b{x}%", r"\edef\a{\unexpanded{\b\c}\b}%"],
+            |parser| {
+                parser.parse_assignment();
+                parser.parse_assignment();
+
+                assert_eq!(
+                    *parser
+                        .state
+                        .get_macro(&Token::ControlSequence("a".to_string()))
+                        .unwrap(),
+                    Macro::new(
+                        vec![],
+                        vec![
+                            MacroListElem::Token(Token::ControlSequence(
+                                "b".to_string()
+                            )),
+                            MacroListElem::Token(Token::ControlSequence(
+                                "c".to_string()
+                            )),
+                            MacroListElem::Token(Token::Char(
+                                'x',
+                                Category::Letter
+                            )),
+                        ]
+                    )
+                );
+            },
+        );
+    }
+
     #[test]
     fn it_sets_global_defs() {
         with_parser(&["\\global\\def\\a{x}%"], |parser| {
@@ -407,6 +721,47 @@ mod tests {
         });
     }
 
+    #[test]
+    fn it_sets_outer_defs() {
+        with_parser(&[r"\outer\global\def\a{x}\def\b{y}%"], |parser| {
+            parser.state.push_state();
+            assert!(parser.is_assignment_head());
+            parser.parse_assignment();
+            parser.parse_assignment();
+            parser.state.pop_state();
+
+            let outer_macro = parser
+                .state
+                .get_macro(&Token::ControlSequence("a".to_string()))
+                .unwrap();
+            assert!(outer_macro.is_outer());
+            assert_eq!(
+                *outer_macro,
+                Macro::new(
+                    vec![],
+                    vec![MacroListElem::Token(Token::Char(
+                        'x',
+                        Category::Letter
+                    ))]
+                )
+                .with_outer(true)
+            );
+
+            assert!(parser
+                .state
+                .get_macro(&Token::ControlSequence("b".to_string()))
+                .is_none());
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "You can't use a prefix with")]
+    fn it_fails_on_outer_non_macro_assignments() {
+        with_parser(&[r"\outer\count1=2%"], |parser| {
+            parser.parse_assignment();
+        });
+    }
+
     #[test]
     fn it_assigns_lets_for_characters() {
         with_parser(&["\\let\\a=b%"], |parser| {
@@ -579,6 +934,220 @@ mod tests {
         );
     }
 
+    #[test]
+    fn it_assigns_and_prints_count_registers() {
+        with_parser(
+            &[
+                r"\count0='17 \the\count0%",
+                r"\count0=`A \the\count0%",
+                r"\count0=-2147483647 \number\count0%",
+            ],
+            |parser| {
+                let mut print_assignment = || {
+                    parser.parse_assignment();
+                    parser
+                        .expand_print()
+                        .iter()
+                        .map(|token| match token {
+                            Token::Char(chr, _) => *chr,
+                            _ => unreachable!(),
+                        })
+                        .collect::<String>()
+                };
+
+                assert_eq!(print_assignment(), "15");
+                assert_eq!(print_assignment(), "65");
+                assert_eq!(print_assignment(), "-2147483647");
+            },
+        );
+    }
+
+    #[test]
+    fn it_does_arithmetic_on_dimens() {
+        with_parser(
+            &[
+                r"\dimen0=2pt%",
+                r"\advance\dimen0 by 1.5pt%",
+                r"\multiply\dimen0 by 4%",
+                r"\divide\dimen0 by 7%",
+            ],
+            |parser| {
+                parser.parse_assignment();
+                parser.parse_assignment();
+                assert_eq!(
+                    parser.state.get_dimen_register(0),
+                    Dimen::from_unit(3.5, Unit::Point)
+                );
+
+                parser.parse_assignment();
+                assert_eq!(
+                    parser.state.get_dimen_register(0),
+                    Dimen::from_unit(14.0, Unit::Point)
+                );
+
+                parser.parse_assignment();
+                assert_eq!(
+                    parser.state.get_dimen_register(0),
+                    Dimen::from_unit(2.0, Unit::Point)
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn it_assigns_and_advances_skip_registers() {
+        with_parser(
+            &[
+                r"\skip0=2pt plus3fil \skip1=1pt plus1fill%",
+                r"\skip2=\skip0%",
+                r"\advance\skip0 by\skip1 \the\skip0%",
+            ],
+            |parser| {
+                parser.parse_assignment();
+                parser.parse_assignment();
+                parser.parse_assignment();
+                assert_eq!(
+                    parser.state.get_skip_register(2),
+                    Glue {
+                        space: Dimen::from_unit(2.0, Unit::Point),
+                        stretch: SpringDimen::FilDimen(FilDimen::new(
+                            FilKind::Fil,
+                            3.0
+                        )),
+                        shrink: SpringDimen::Dimen(Dimen::zero()),
+                    }
+                );
+
+                // The fill stretch in \skip1 dominates the fil stretch in
+                // \skip0.
+                parser.parse_assignment();
+                let printed: String = parser
+                    .expand_print()
+                    .iter()
+                    .map(|token| match token {
+                        Token::Char(chr, _) => *chr,
+                        _ => unreachable!(),
+                    })
+                    .collect();
+                assert_eq!(printed, "3.0pt plus 1.0fill");
+            },
+        );
+    }
+
+    #[test]
+    fn it_assigns_and_advances_muskip_registers() {
+        with_parser(
+            &[
+                r"\muskip0=3mu plus1mu%",
+                r"\advance\muskip0 by2mu\relax%",
+                r"\the\muskip0%",
+            ],
+            |parser| {
+                parser.parse_assignment();
+                parser.parse_assignment();
+                assert_eq!(
+                    parser.lex_unexpanded_token(),
+                    Some(Token::ControlSequence("relax".to_string()))
+                );
+
+                let printed: String = parser
+                    .expand_print()
+                    .iter()
+                    .map(|token| match token {
+                        Token::Char(chr, _) => *chr,
+                        _ => unreachable!(),
+                    })
+                    .collect();
+                assert_eq!(printed, "5.0mu plus 1.0mu");
+            },
+        );
+    }
+
+    #[test]
+    fn it_multiplies_and_divides_muskip_registers() {
+        with_parser(
+            &[
+                r"\muskip0=3mu plus2fil minus1mu%",
+                r"\multiply\muskip0 by4%",
+                r"\divide\muskip0 by3%",
+            ],
+            |parser| {
+                parser.parse_assignment();
+                parser.parse_assignment();
+                assert_eq!(
+                    parser.state.get_muskip_register(0),
+                    MuGlue {
+                        space: Dimen::from_unit(12.0, Unit::Point),
+                        stretch: SpringDimen::FilDimen(FilDimen::new(
+                            FilKind::Fil,
+                            8.0
+                        )),
+                        shrink: SpringDimen::Dimen(Dimen::from_unit(
+                            4.0,
+                            Unit::Point
+                        )),
+                    }
+                );
+
+                parser.parse_assignment();
+                assert_eq!(
+                    parser.state.get_muskip_register(0),
+                    MuGlue {
+                        space: Dimen::from_unit(4.0, Unit::Point),
+                        stretch: SpringDimen::FilDimen(FilDimen::new(
+                            FilKind::Fil,
+                            8.0
+                        )) / 3,
+                        shrink: SpringDimen::Dimen(Dimen::from_scaled_points(
+                            4 * 65536 / 3
+                        )),
+                    }
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn it_defines_muskip_registers_with_muskipdef() {
+        with_parser(
+            &[
+                r"\muskipdef\thinmuskip=1%",
+                r"\thinmuskip=3mu%",
+                r"\muskip2=2\thinmuskip%",
+            ],
+            |parser| {
+                parser.parse_assignment();
+                parser.parse_assignment();
+                assert_eq!(
+                    parser.state.get_muskip_register(1),
+                    MuGlue {
+                        space: Dimen::from_unit(3.0, Unit::Point),
+                        stretch: SpringDimen::Dimen(Dimen::zero()),
+                        shrink: SpringDimen::Dimen(Dimen::zero()),
+                    }
+                );
+
+                parser.parse_assignment();
+                assert_eq!(
+                    parser.state.get_muskip_register(2),
+                    MuGlue {
+                        space: Dimen::from_unit(6.0, Unit::Point),
+                        stretch: SpringDimen::Dimen(Dimen::zero()),
+                        shrink: SpringDimen::Dimen(Dimen::zero()),
+                    }
+                );
+            },
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Illegal unit of measure (mu inserted)")]
+    fn it_fails_to_assign_non_mu_units_to_muskip_registers() {
+        with_parser(&[r"\muskip0=3pt%"], |parser| {
+            parser.parse_assignment();
+        });
+    }
+
     #[test]
     fn it_sets_boxes() {
         with_parser(&["\\setbox123=\\hbox{a}%"], |parser| {
@@ -679,6 +1248,20 @@ mod tests {
         );
     }
 
+    #[test]
+    fn it_sets_delcodes() {
+        with_parser(&[r#"\delcode`*="123456%"#, r"\delcode`(=-1%"], |parser| {
+            assert!(parser.is_assignment_head());
+            parser.parse_assignment();
+
+            assert!(parser.is_assignment_head());
+            parser.parse_assignment();
+
+            assert_eq!(parser.state.get_delimiter_code('*'), 0x123456);
+            assert_eq!(parser.state.get_delimiter_code('('), -1);
+        });
+    }
+
     #[test]
     fn it_assigns_fonts() {
         with_parser(
@@ -822,4 +1405,23 @@ mod tests {
             },
         );
     }
+
+    #[test]
+    fn it_sets_the_output_routine() {
+        with_parser(&[r"\output={\box255 x}%"], |parser| {
+            parser.parse_assignment();
+
+            assert_eq!(
+                parser.state.get_output_routine(),
+                vec![
+                    Token::ControlSequence("box".to_string()),
+                    Token::Char('2', Category::Other),
+                    Token::Char('5', Category::Other),
+                    Token::Char('5', Category::Other),
+                    Token::Char(' ', Category::Space),
+                    Token::Char('x', Category::Letter),
+                ]
+            );
+        });
+    }
 }
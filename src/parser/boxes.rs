@@ -1,12 +1,19 @@
+use std::rc::Rc;
+
 use crate::boxes::{
-    GlueSetRatio, GlueSetRatioKind, HorizontalBox, TeXBox, VerticalBox,
+    compute_box_fit, compute_glue_set, BoxFit, GlueSetRatio, HorizontalBox,
+    TeXBox, VerticalBox,
 };
 use crate::category::Category;
-use crate::dimension::{Dimen, SpringDimen};
+use crate::dimension::Dimen;
 use crate::glue::Glue;
-use crate::list::HorizontalListElem;
+use crate::list::{
+    char_runs, pack_char_runs, unpack_char_runs, HorizontalListElem,
+    VerticalListElem,
+};
 use crate::parser::Parser;
 use crate::token::Token;
+use crate::variable::DimenParameter;
 
 pub enum BoxLayout {
     Natural,
@@ -14,90 +21,33 @@ pub enum BoxLayout {
     Spread(Dimen),
 }
 
-// Given the amount of stretch/shrink needed to set a given box and the amount
-// of stretch/shrink available, figure out the glue set ratio.
-fn set_glue(
-    stretch_needed: &Dimen,
-    stretch_available: &SpringDimen,
-) -> GlueSetRatio {
-    match stretch_available {
-        // If we have a finite amount of stretch/shrink available, then we set
-        // a finite glue ratio but have some limits on how much we can
-        // stretch/shrink
-        SpringDimen::Dimen(stretch_dimen) => GlueSetRatio::from(
-            GlueSetRatioKind::Finite,
-            // TODO(xymostech): Ensure this isn't <-1.0
-            // TODO(xymostech): Handle stretch_dimen = 0
-            stretch_needed / stretch_dimen,
-        ),
-
-        // If there's an infinite amount of stretch/shrink available, then we
-        // can stretch/shrink as much as is needed with no limits.
-        SpringDimen::FilDimen(stretch_fil_dimen) => GlueSetRatio::from(
-            GlueSetRatioKind::from_fil_kind(&stretch_fil_dimen.0),
-            // TODO(xymostech): Handle stretch_fil_dimen = 0
-            stretch_needed / stretch_fil_dimen,
-        ),
-    }
-}
-
 /// Based on the layout of a box and the stretchable dimension, return the
-/// resulting true dimension and the needed glue set ratio.
+/// resulting true dimension, the needed glue set ratio, and how well the glue
+/// could be set.
 fn get_set_dimen_and_ratio(
     glue: Glue,
     layout: &BoxLayout,
-) -> (Dimen, Option<GlueSetRatio>) {
-    match *layout {
+) -> (Dimen, Option<GlueSetRatio>, BoxFit) {
+    let final_dimen = match *layout {
         // If we just want the box at its natural dimension, we just return the
         // "space" component of our dimension.
-        BoxLayout::Natural => (glue.space, None),
-
-        BoxLayout::Fixed(final_dimen) => {
-            let natural_dimen = glue.space;
-
-            // If the natural dimension of the box exactly equals the desired
-            // dimension, then we don't need a glue set. This is probably very
-            // unlikely to happen except in unique cases, like when the
-            // dimension is 0.
-            if final_dimen == natural_dimen {
-                (final_dimen, None)
-            } else {
-                // If we need to stretch, calculate the amount we need to
-                // stretch.
-                let stretch_needed = final_dimen - natural_dimen;
-
-                // Figure out if we're stretching or shrinking
-                let stretch_or_shrink = if final_dimen > natural_dimen {
-                    &glue.stretch
-                } else {
-                    &glue.shrink
-                };
-
-                (
-                    // The resulting box dimension is exactly the fixed
-                    // dimension that was desired.
-                    final_dimen,
-                    Some(set_glue(&stretch_needed, stretch_or_shrink)),
-                )
-            }
-        }
-        BoxLayout::Spread(spread_needed) => {
-            // If we're spreading the box, we just need to figure out if
-            // we're stretching or shrinking, since we already know the
-            // amount of spread.
-            let stretch_or_shrink = if spread_needed > Dimen::zero() {
-                &glue.stretch
-            } else {
-                &glue.shrink
-            };
-
-            (
-                // The final dimension is the natural dimension + spread
-                glue.space + spread_needed,
-                Some(set_glue(&spread_needed, stretch_or_shrink)),
-            )
-        }
-    }
+        BoxLayout::Natural => return (glue.space, None, BoxFit::Stretched(0)),
+        BoxLayout::Fixed(final_dimen) => final_dimen,
+        // The final dimension is the natural dimension + spread
+        BoxLayout::Spread(spread_needed) => glue.space + spread_needed,
+    };
+
+    let set_ratio = compute_glue_set(
+        &glue.space,
+        &glue.stretch,
+        &glue.shrink,
+        &final_dimen,
+    );
+
+    let fit =
+        compute_box_fit(&glue.space, &glue.stretch, &glue.shrink, &final_dimen);
+
+    (final_dimen, set_ratio, fit)
 }
 
 impl<'a> Parser<'a> {
@@ -106,15 +56,60 @@ impl<'a> Parser<'a> {
         list: Vec<HorizontalListElem>,
         layout: &BoxLayout,
     ) -> HorizontalBox {
+        let (hbox, fit) = self.hpack(list, layout);
+
+        // TODO(xymostech): Report underfull and tight boxes once \hbadness
+        // exists, and only report overfull boxes past \hfuzz.
+        if let BoxFit::Overfull(excess) = fit {
+            self.add_message(format!(
+                "Overfull \\hbox ({} too wide)",
+                excess.to_tex_string()
+            ));
+        }
+
+        hbox
+    }
+
+    /// Packs a horizontal list into a box with the given layout, like TeX's
+    /// hpack. Along with the box, this returns how well the glue in the list
+    /// could be set, so callers can tell if the box ended up overfull or
+    /// underfull.
+    pub fn hpack(
+        &mut self,
+        list: Vec<HorizontalListElem>,
+        layout: &BoxLayout,
+    ) -> (HorizontalBox, BoxFit) {
         // Keep track of the max height/depth and the total amount of width of;
         // the elements in the list.
         let mut height = Dimen::zero();
         let mut depth = Dimen::zero();
         let mut width = Glue::zero();
 
-        for elem in &list {
+        for piece in char_runs(&list) {
+            if let HorizontalListElem::Char { font, .. } = &piece[0] {
+                // Runs of characters all use the same metrics, so we only
+                // look them up once and add up the widths as plain dimens.
+                let metrics = self.state.get_metrics_for_font(font).unwrap();
+                let mut run_width = Dimen::zero();
+                for elem in piece {
+                    if let HorizontalListElem::Char { chr, .. } = elem {
+                        let char_height = metrics.get_height(*chr);
+                        let char_depth = metrics.get_depth(*chr);
+                        if char_height > height {
+                            height = char_height;
+                        }
+                        if char_depth > depth {
+                            depth = char_depth;
+                        }
+                        run_width = run_width + metrics.get_width(*chr);
+                    }
+                }
+                width = width + Glue::from_dimen(run_width);
+                continue;
+            }
+
             let (elem_height, elem_depth, elem_width) =
-                elem.get_size(self.state);
+                piece[0].get_size(self.state);
 
             // Height and depth are just the maximum of all of the elements.
             if elem_height > height {
@@ -130,16 +125,21 @@ impl<'a> Parser<'a> {
         }
 
         // Figure out the final width and glue set needed.
-        let (set_width, set_ratio) = get_set_dimen_and_ratio(width, layout);
+        let (set_width, set_ratio, fit) =
+            get_set_dimen_and_ratio(width, layout);
 
-        HorizontalBox {
+        let hbox = HorizontalBox {
             height,
             depth,
             width: set_width,
 
-            list,
+            // Once the list is packed, it doesn't change much, so we store the
+            // characters in it as runs to save space.
+            list: Rc::new(pack_char_runs(list)),
             glue_set_ratio: set_ratio,
-        }
+        };
+
+        (hbox, fit)
     }
 
     pub fn add_to_natural_layout_horizontal_box(
@@ -161,7 +161,7 @@ impl<'a> Parser<'a> {
         }
 
         hbox.width = hbox.width + elem_width.space;
-        hbox.list.push(elem);
+        Rc::make_mut(&mut hbox.list).push(elem);
 
         hbox
     }
@@ -178,26 +178,14 @@ impl<'a> Parser<'a> {
         )
     }
 
-    /// Provides an easy way for external consumers of boxes to parse a
-    /// specific type of horizontal box, so they don't have to be concerned
-    /// with BoxLayout or TeXBox vs HorizontalBox.
-    pub fn parse_unrestricted_horizontal_box(
-        &mut self,
-        indent: bool,
-    ) -> TeXBox {
-        let hbox =
-            self.parse_horizontal_box(&BoxLayout::Natural, false, indent);
-        TeXBox::HorizontalBox(hbox)
-    }
-
-    fn parse_vertical_box(
+    /// Packages a vertical list into a box, limiting the depth of the
+    /// resulting box to `max_depth`.
+    pub fn combine_vertical_list_into_vertical_box_with_layout(
         &mut self,
+        list: Vec<VerticalListElem>,
         layout: &BoxLayout,
-        internal: bool,
+        max_depth: &Dimen,
     ) -> VerticalBox {
-        // Parse the actual list of elements
-        let list = self.parse_vertical_list(internal);
-
         // Keep track of the total height of the elements
         let mut height = Glue::zero();
         // Keep track of the depth of the most recently seen element. This will
@@ -207,6 +195,12 @@ impl<'a> Parser<'a> {
         let mut width = Dimen::zero();
 
         for elem in &list {
+            // Penalties don't take up any space, and unlike glue they don't
+            // reset the depth of the previous box.
+            if let VerticalListElem::Penalty(_) = elem {
+                continue;
+            }
+
             let (elem_height, elem_depth, elem_width) = elem.get_size();
 
             // Add up the height of the elements, plus the depths for all but
@@ -223,19 +217,49 @@ impl<'a> Parser<'a> {
             }
         }
 
+        // If the last element is deeper than the maximum depth, the reference
+        // point moves down and the box gets taller instead.
+        let depth = if prev_depth > *max_depth {
+            height = height + Glue::from_dimen(prev_depth - *max_depth);
+            *max_depth
+        } else {
+            prev_depth
+        };
+
         // Figure out the true height and set ratio
-        let (set_height, glue_set) = get_set_dimen_and_ratio(height, layout);
+        let (set_height, glue_set, _) = get_set_dimen_and_ratio(height, layout);
 
         VerticalBox {
             height: set_height,
-            depth: prev_depth,
+            depth,
             width,
 
-            list,
+            list: Rc::new(list),
             glue_set_ratio: glue_set,
         }
     }
 
+    fn parse_vertical_box(
+        &mut self,
+        layout: &BoxLayout,
+        internal: bool,
+    ) -> VerticalBox {
+        // Parse the actual list of elements
+        let list = self.parse_vertical_list(internal);
+
+        // The depth of the box is limited by \boxmaxdepth, or by \maxdepth
+        // when we're building a page.
+        let max_depth = self.state.get_dimen_parameter(if internal {
+            &DimenParameter::BoxMaxDepth
+        } else {
+            &DimenParameter::MaxDepth
+        });
+
+        self.combine_vertical_list_into_vertical_box_with_layout(
+            list, layout, &max_depth,
+        )
+    }
+
     fn parse_box_specification(&mut self) -> BoxLayout {
         if self.parse_optional_keyword_expanded("to") {
             let dimen = self.parse_dimen();
@@ -251,9 +275,56 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Parses the box specification and the {<vertical mode material>} that
+    /// come after \vbox or \vcenter.
+    pub fn parse_specified_vertical_box(&mut self) -> VerticalBox {
+        let layout = self.parse_box_specification();
+
+        // We expect a { after the box specification
+        match self.lex_expanded_token() {
+            Some(Token::Char(_, Category::BeginGroup)) => (),
+            _ => panic!("Expected { when parsing box"),
+        }
+
+        self.state.push_state();
+
+        let vbox = self.parse_vertical_box(&layout, true);
+
+        self.state.pop_state();
+
+        // And there should always be a } after the vertical list
+        match self.lex_expanded_token() {
+            Some(Token::Char(_, Category::EndGroup)) => (),
+            _ => panic!("Expected } when parsing box"),
+        }
+
+        vbox
+    }
+
+    /// Parses the box specification and the {<vertical mode material>} that
+    /// come after \vtop. This is built like a \vbox, but then the reference
+    /// point is moved up so that the height of the box is the height of its
+    /// first item if that's a box or rule (or zero otherwise), and the rest
+    /// goes into the depth.
+    fn parse_specified_vertical_top_box(&mut self) -> VerticalBox {
+        let vbox = self.parse_specified_vertical_box();
+
+        let top_height = match vbox.list.first() {
+            Some(VerticalListElem::Box { tex_box, .. }) => *tex_box.height(),
+            Some(VerticalListElem::Rule { height, .. }) => *height,
+            _ => Dimen::zero(),
+        };
+
+        VerticalBox {
+            height: top_height,
+            depth: vbox.height + vbox.depth - top_height,
+            ..vbox
+        }
+    }
+
     pub fn is_box_head(&mut self) -> bool {
         self.is_next_expanded_token_in_set_of_primitives(&[
-            "hbox", "vbox", "box", "copy",
+            "hbox", "vbox", "vtop", "box", "copy", "lastbox",
         ])
     }
 
@@ -283,26 +354,10 @@ impl<'a> Parser<'a> {
 
             Some(TeXBox::HorizontalBox(hbox))
         } else if self.state.is_token_equal_to_prim(&head, "vbox") {
-            let layout = self.parse_box_specification();
-
-            // We expect a { after the box specification
-            match self.lex_expanded_token() {
-                Some(Token::Char(_, Category::BeginGroup)) => (),
-                _ => panic!("Expected { when parsing box"),
-            }
-
-            self.state.push_state();
-
-            let vbox = self.parse_vertical_box(&layout, true);
-
-            self.state.pop_state();
-
-            // And there should always be a } after the horizontal list
-            match self.lex_expanded_token() {
-                Some(Token::Char(_, Category::EndGroup)) => (),
-                _ => panic!("Expected } when parsing box"),
-            }
-
+            let vbox = self.parse_specified_vertical_box();
+            Some(TeXBox::VerticalBox(vbox))
+        } else if self.state.is_token_equal_to_prim(&head, "vtop") {
+            let vbox = self.parse_specified_vertical_top_box();
             Some(TeXBox::VerticalBox(vbox))
         } else if self.state.is_token_equal_to_prim(&head, "box") {
             let box_index = self.parse_8bit_number();
@@ -310,11 +365,81 @@ impl<'a> Parser<'a> {
         } else if self.state.is_token_equal_to_prim(&head, "copy") {
             let box_index = self.parse_8bit_number();
             self.state.get_box_copy(box_index)
+        } else if self.state.is_token_equal_to_prim(&head, "lastbox") {
+            self.remove_last_box_from_current_list()
         } else {
             panic!("unimplemented");
         }
     }
 
+    pub fn is_unhbox_head(&mut self) -> bool {
+        self.is_next_expanded_token_in_set_of_primitives(&["unhbox", "unhcopy"])
+    }
+
+    pub fn is_unvbox_head(&mut self) -> bool {
+        self.is_next_expanded_token_in_set_of_primitives(&["unvbox", "unvcopy"])
+    }
+
+    // Takes the box out of the register given after \unhbox, \unvbox, etc.,
+    // or copies it for \unhcopy and \unvcopy.
+    fn parse_unpacked_box(&mut self, copy_primitive: &str) -> Option<TeXBox> {
+        let tok = self.lex_expanded_token().unwrap();
+        let box_index = self.parse_8bit_number();
+        if self.state.is_token_equal_to_prim(&tok, copy_primitive) {
+            self.state.get_box_copy(box_index)
+        } else {
+            self.state.get_box(box_index)
+        }
+    }
+
+    /// Parses \unhbox or \unhcopy, returning the contents of the horizontal
+    /// box in the given register. The glue in the list is set again when it
+    /// ends up in a new box. A void register gives an empty list.
+    pub fn parse_unhbox(&mut self) -> Vec<HorizontalListElem> {
+        match self.parse_unpacked_box("unhcopy") {
+            Some(TeXBox::HorizontalBox(hbox)) => unpack_char_runs(
+                Rc::try_unwrap(hbox.list)
+                    .unwrap_or_else(|list| (*list).clone()),
+            ),
+            Some(TeXBox::VerticalBox(_)) => {
+                panic!("Incompatible list can't be unboxed")
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// Parses \unvbox or \unvcopy, returning the contents of the vertical box
+    /// in the given register.
+    pub fn parse_unvbox(&mut self) -> Vec<VerticalListElem> {
+        match self.parse_unpacked_box("unvcopy") {
+            Some(TeXBox::VerticalBox(vbox)) => {
+                Rc::try_unwrap(vbox.list).unwrap_or_else(|list| (*list).clone())
+            }
+            Some(TeXBox::HorizontalBox(_)) => {
+                panic!("Incompatible list can't be unboxed")
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// Parses the box described by the given tokens (like `\hbox{abc}`) and
+    /// returns its natural width, height, and depth, without adding the box
+    /// to any list.
+    pub fn measure_box(&mut self, tokens: Vec<Token>) -> (Dimen, Dimen, Dimen) {
+        self.add_upcoming_tokens(tokens);
+
+        if !self.is_box_head() {
+            panic!("Expected a box to measure");
+        }
+
+        match self.parse_box() {
+            Some(tex_box) => {
+                (*tex_box.width(), *tex_box.height(), *tex_box.depth())
+            }
+            None => (Dimen::zero(), Dimen::zero(), Dimen::zero()),
+        }
+    }
+
     // Used for early testing, when we want to output test the output of
     // parsing an entire box.
     pub fn parse_outer_vertical_box(&mut self) -> VerticalBox {
@@ -326,9 +451,11 @@ impl<'a> Parser<'a> {
 mod tests {
     use super::*;
 
-    use crate::dimension::{Dimen, Unit};
+    use crate::boxes::{badness, GlueSetRatioKind, INF_BAD};
+    use crate::dimension::{Dimen, SpringDimen, Unit};
     use crate::font::Font;
     use crate::testing::with_parser;
+    use crate::variable::DimenVariable;
 
     lazy_static! {
         static ref CMR10: Font = Font {
@@ -387,6 +514,25 @@ mod tests {
         });
     }
 
+    #[test]
+    fn it_includes_box_shifts_in_box_height_and_depth() {
+        with_parser(&[r"x\raise 2pt\hbox{x}\lower 3pt\hbox{x}%"], |parser| {
+            let hbox =
+                parser.parse_horizontal_box(&BoxLayout::Natural, true, false);
+
+            let metrics = parser.state.get_metrics_for_font(&CMR10).unwrap();
+
+            assert_eq!(
+                hbox.height,
+                metrics.get_height('x') + Dimen::from_unit(2.0, Unit::Point)
+            );
+            assert_eq!(
+                hbox.depth,
+                metrics.get_depth('x') + Dimen::from_unit(3.0, Unit::Point)
+            );
+        });
+    }
+
     #[test]
     fn it_stretches_boxes_with_finite_glue_to_a_fixed_width() {
         with_parser(&["a\\hskip 0pt plus1pt b%"], |parser| {
@@ -607,17 +753,58 @@ mod tests {
         });
     }
 
+    #[test]
+    fn it_stores_the_computed_glue_set_ratio() {
+        with_parser(
+            &[r"\hbox to 12pt{a\hskip 3pt plus 2pt minus 2pt b}%"],
+            |parser| {
+                let metrics =
+                    parser.state.get_metrics_for_font(&CMR10).unwrap();
+                let natural = metrics.get_width('a')
+                    + Dimen::from_unit(3.0, Unit::Point)
+                    + metrics.get_width('b');
+
+                let ratio = compute_glue_set(
+                    &natural,
+                    &SpringDimen::Dimen(Dimen::from_unit(2.0, Unit::Point)),
+                    &SpringDimen::Dimen(Dimen::from_unit(2.0, Unit::Point)),
+                    &Dimen::from_unit(12.0, Unit::Point),
+                )
+                .unwrap();
+                assert_eq!(
+                    ratio,
+                    GlueSetRatio::from_scaled_points(
+                        GlueSetRatioKind::Finite,
+                        (Dimen::from_unit(12.0, Unit::Point) - natural)
+                            .as_scaled_points(),
+                        Dimen::from_unit(2.0, Unit::Point).as_scaled_points(),
+                    )
+                );
+                assert!(ratio.as_f64() < 0.0);
+
+                match parser.parse_box() {
+                    Some(TeXBox::HorizontalBox(hbox)) => {
+                        assert_eq!(hbox.glue_set_ratio, Some(ratio))
+                    }
+                    other => panic!("Expected hbox, found {:?}", other),
+                }
+            },
+        );
+    }
+
     #[test]
     fn it_parses_horizontal_boxes_with_natural_width() {
         with_parser(&["\\hbox{abc}%"], |parser| {
             let metrics = parser.state.get_metrics_for_font(&CMR10).unwrap();
             let expected_width = metrics.get_width('a')
                 + metrics.get_width('b')
+                + metrics.get_kern('b', 'c').unwrap()
                 + metrics.get_width('c');
 
             assert!(parser.is_box_head());
             let hbox = parser.parse_box().unwrap();
             if let TeXBox::HorizontalBox(hbox) = hbox {
+                // The "ab" before the kern is stored as a single run.
                 assert_eq!(hbox.list.len(), 3);
                 assert_eq!(hbox.glue_set_ratio, None);
                 assert_eq!(hbox.width, expected_width);
@@ -660,6 +847,57 @@ mod tests {
         });
     }
 
+    #[test]
+    fn it_sets_glue_in_spread_horizontal_boxes() {
+        with_parser(
+            &[
+                r"\hbox spread 10pt{a\hskip 2pt plus 20pt minus 1pt c}%",
+                r"\hbox spread -0.5pt{a\hskip 2pt plus 20pt minus 1pt c}%",
+            ],
+            |parser| {
+                let metrics =
+                    parser.state.get_metrics_for_font(&CMR10).unwrap();
+                let natural_width = metrics.get_width('a')
+                    + Dimen::from_unit(2.0, Unit::Point)
+                    + metrics.get_width('c');
+
+                match parser.parse_box() {
+                    Some(TeXBox::HorizontalBox(hbox)) => {
+                        assert_eq!(
+                            hbox.width,
+                            natural_width + Dimen::from_unit(10.0, Unit::Point)
+                        );
+                        assert_eq!(
+                            hbox.glue_set_ratio,
+                            Some(GlueSetRatio::from(
+                                GlueSetRatioKind::Finite,
+                                0.5
+                            ))
+                        );
+                    }
+                    other => panic!("Expected hbox, found {:?}", other),
+                }
+
+                match parser.parse_box() {
+                    Some(TeXBox::HorizontalBox(hbox)) => {
+                        assert_eq!(
+                            hbox.width,
+                            natural_width - Dimen::from_unit(0.5, Unit::Point)
+                        );
+                        assert_eq!(
+                            hbox.glue_set_ratio,
+                            Some(GlueSetRatio::from(
+                                GlueSetRatioKind::Finite,
+                                -0.5
+                            ))
+                        );
+                    }
+                    other => panic!("Expected hbox, found {:?}", other),
+                }
+            },
+        );
+    }
+
     #[test]
     fn it_parses_boxes_from_box_registers() {
         with_parser(&[r"\setbox0=\hbox{a}%", r"\box0", r"\box0"], |parser| {
@@ -691,6 +929,57 @@ mod tests {
         });
     }
 
+    #[test]
+    fn it_shares_the_contents_of_copied_boxes() {
+        let line = format!(
+            r"\setbox0=\vbox{{{}}}%",
+            r"\hbox{abc}\hbox{\hbox{def}ghi}".repeat(100)
+        );
+        with_parser(&[&line, r"\copy0\copy0%"], |parser| {
+            parser.parse_assignment();
+
+            let first_copy = parser.parse_box().unwrap();
+            let second_copy = parser.parse_box().unwrap();
+
+            // The copies and the box in the register all share one list
+            // instead of each copying all of the boxes inside of it.
+            match (&first_copy, &second_copy) {
+                (TeXBox::VerticalBox(first), TeXBox::VerticalBox(second)) => {
+                    assert!(Rc::ptr_eq(&first.list, &second.list));
+                    assert_eq!(Rc::strong_count(&first.list), 3);
+                    assert!(first.list.len() > 200);
+                }
+                _ => panic!("Expected vertical boxes"),
+            }
+            assert_eq!(first_copy, parser.state.get_box_copy(0).unwrap());
+        });
+    }
+
+    #[test]
+    fn it_measures_boxes_without_placing_them() {
+        with_parser(
+            &[r"{\hbox{abc}}%", r"\setbox0=\hbox{abc}%", r"x%"],
+            |parser| {
+                let tokens = parser.parse_general_text();
+                let (width, height, depth) = parser.measure_box(tokens);
+
+                parser.parse_assignment();
+                assert_eq!(width, DimenVariable::BoxWidth(0).get(parser.state));
+                assert_eq!(
+                    height,
+                    DimenVariable::BoxHeight(0).get(parser.state)
+                );
+                assert_eq!(depth, DimenVariable::BoxDepth(0).get(parser.state));
+
+                // The measured box isn't left in the input
+                assert_eq!(
+                    parser.lex_expanded_token(),
+                    Some(Token::Char('x', Category::Letter))
+                );
+            },
+        );
+    }
+
     #[test]
     fn it_parses_vertical_lists() {
         with_parser(&[r"aby%", r"\vskip 2pt%", r"g%"], |parser| {
@@ -712,13 +1001,9 @@ mod tests {
             // The depth will just be the depth of the second box.
             assert_eq!(vbox.depth, metrics.get_depth('g'));
 
-            // The width will be the width of the first box, which is indented
-            // and contains a, b, and y.
-            let expected_width = Dimen::from_unit(20.0, Unit::Point)
-                + metrics.get_width('a')
-                + metrics.get_width('b')
-                + metrics.get_width('y');
-            assert_eq!(vbox.width, expected_width);
+            // The width will be \hsize, since the lines of paragraphs are
+            // all set to that width.
+            assert_eq!(vbox.width, Dimen::from_unit(6.5, Unit::Inch));
         });
     }
 
@@ -760,7 +1045,11 @@ mod tests {
                 // The glue set ratio ends up being -8/5 pt/fil
                 assert_eq!(
                     vbox.glue_set_ratio,
-                    Some(GlueSetRatio::from(GlueSetRatioKind::Fil, -8.0 / 5.0))
+                    Some(GlueSetRatio::from_scaled_points(
+                        GlueSetRatioKind::Fil,
+                        -8,
+                        5
+                    ))
                 );
             },
         );
@@ -786,6 +1075,105 @@ mod tests {
         );
     }
 
+    #[test]
+    fn it_puts_the_reference_point_of_vtops_at_the_first_item() {
+        with_parser(
+            &[
+                r"\vbox{\hbox{a}\hbox{g}}%",
+                r"\vtop{\hbox{a}\hbox{g}}%",
+                r"\vtop{\hrule height 2pt\hbox{g}}%",
+                r"\vtop to 20pt{\vskip 1pt\hbox{a}\vskip 0pt plus 1fil}%",
+            ],
+            |parser| {
+                let metrics =
+                    parser.state.get_metrics_for_font(&CMR10).unwrap();
+
+                let vbox = parser.parse_box().unwrap();
+                let vtop = parser.parse_box().unwrap();
+                assert_eq!(*vtop.height(), metrics.get_height('a'));
+                assert_eq!(
+                    *vtop.height() + *vtop.depth(),
+                    *vbox.height() + *vbox.depth()
+                );
+                assert_eq!(*vtop.width(), *vbox.width());
+
+                let rule_vtop = parser.parse_box().unwrap();
+                assert_eq!(
+                    *rule_vtop.height(),
+                    Dimen::from_unit(2.0, Unit::Point)
+                );
+
+                let glue_vtop = parser.parse_box().unwrap();
+                assert_eq!(*glue_vtop.height(), Dimen::zero());
+                assert_eq!(
+                    *glue_vtop.depth(),
+                    Dimen::from_unit(20.0, Unit::Point)
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn it_adds_interline_glue_between_boxes_in_vboxes() {
+        with_parser(
+            &[
+                r"\vbox{\hbox{a}\hbox{b}}%",
+                r"\baselineskip=20pt \lineskip=3pt \lineskiplimit=2pt%",
+                r"\vbox{\hbox{g}\hbox{b}}%",
+                r"\vbox{\hbox{g}\hbox{\vbox to 18pt{}}}%",
+            ],
+            |parser| {
+                let metrics =
+                    parser.state.get_metrics_for_font(&CMR10).unwrap();
+
+                let first_vbox = match parser.parse_box() {
+                    Some(TeXBox::VerticalBox(vbox)) => vbox,
+                    other => panic!("Expected vbox, found {:?}", other),
+                };
+                let expected_glue = Glue::from_dimen(
+                    Dimen::from_unit(12.0, Unit::Point)
+                        - metrics.get_depth('a')
+                        - metrics.get_height('b'),
+                );
+                assert_eq!(
+                    first_vbox.list[1],
+                    VerticalListElem::VSkip(expected_glue)
+                );
+
+                parser.parse_assignment();
+                parser.parse_assignment();
+                parser.parse_assignment();
+
+                let second_vbox = match parser.parse_box() {
+                    Some(TeXBox::VerticalBox(vbox)) => vbox,
+                    other => panic!("Expected vbox, found {:?}", other),
+                };
+                let expected_glue = Glue::from_dimen(
+                    Dimen::from_unit(20.0, Unit::Point)
+                        - metrics.get_depth('g')
+                        - metrics.get_height('b'),
+                );
+                assert_eq!(
+                    second_vbox.list[1],
+                    VerticalListElem::VSkip(expected_glue)
+                );
+
+                // 20pt - 18pt - depth(g) is less than \lineskiplimit, so
+                // \lineskip is used instead.
+                let third_vbox = match parser.parse_box() {
+                    Some(TeXBox::VerticalBox(vbox)) => vbox,
+                    other => panic!("Expected vbox, found {:?}", other),
+                };
+                assert_eq!(
+                    third_vbox.list[1],
+                    VerticalListElem::VSkip(Glue::from_dimen(
+                        Dimen::from_unit(3.0, Unit::Point)
+                    ))
+                );
+            },
+        );
+    }
+
     #[test]
     fn it_parses_state_group_around_box_definitions() {
         with_parser(
@@ -808,4 +1196,146 @@ mod tests {
             },
         );
     }
+
+    #[test]
+    fn it_limits_vbox_depth_to_boxmaxdepth() {
+        with_parser(
+            &[
+                r"\setbox0=\hbox{}%",
+                r"\ht0=3pt \dp0=10pt%",
+                r"\boxmaxdepth=2pt%",
+                r"\vbox{\copy0}%",
+                r"\vbox to 20pt{\copy0}%",
+            ],
+            |parser| {
+                parser.parse_assignment();
+                parser.parse_assignment();
+                parser.parse_assignment();
+                parser.parse_assignment();
+
+                let vbox = parser.parse_box().unwrap();
+                assert_eq!(*vbox.height(), Dimen::from_unit(11.0, Unit::Point));
+                assert_eq!(*vbox.depth(), Dimen::from_unit(2.0, Unit::Point));
+
+                let fixed_vbox = parser.parse_box().unwrap();
+                assert_eq!(
+                    *fixed_vbox.height(),
+                    Dimen::from_unit(20.0, Unit::Point)
+                );
+                assert_eq!(
+                    *fixed_vbox.depth(),
+                    Dimen::from_unit(2.0, Unit::Point)
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn it_limits_page_depth_to_maxdepth() {
+        with_parser(
+            &[
+                r"\maxdepth=2pt%",
+                r"\boxmaxdepth=1pt%",
+                r"\setbox0=\hbox{}%",
+                r"\ht0=3pt \dp0=10pt%",
+                r"\box0%",
+                r"\end%",
+            ],
+            |parser| {
+                let page = parser.parse_outer_vertical_box();
+
+                // The 7pt of \topskip glue plus the 3pt box height, plus the
+                // 8pt of depth that doesn't fit in \maxdepth.
+                assert_eq!(page.height, Dimen::from_unit(18.0, Unit::Point));
+                assert_eq!(page.depth, Dimen::from_unit(2.0, Unit::Point));
+            },
+        );
+    }
+
+    #[test]
+    fn it_computes_badness_for_underfull_boxes() {
+        with_parser(&[r"a\hskip 0pt plus 1pt b%"], |parser| {
+            let list = parser.parse_horizontal_list(true, false);
+            let (hbox, fit) = parser.hpack(
+                list,
+                &BoxLayout::Fixed(Dimen::from_unit(100.0, Unit::Point)),
+            );
+
+            assert_eq!(hbox.width, Dimen::from_unit(100.0, Unit::Point));
+            assert_eq!(fit, BoxFit::Stretched(INF_BAD));
+        });
+
+        with_parser(&[r"a\hskip 0pt plus 2pt b%"], |parser| {
+            let list = parser.parse_horizontal_list(true, false);
+            let (hbox, fit) = parser.hpack(
+                list,
+                &BoxLayout::Spread(Dimen::from_unit(1.0, Unit::Point)),
+            );
+
+            assert_eq!(
+                hbox.glue_set_ratio,
+                Some(GlueSetRatio::from(GlueSetRatioKind::Finite, 0.5))
+            );
+            assert_eq!(
+                fit,
+                BoxFit::Stretched(badness(
+                    &Dimen::from_unit(1.0, Unit::Point),
+                    &Dimen::from_unit(2.0, Unit::Point)
+                ))
+            );
+        });
+    }
+
+    #[test]
+    fn it_doesnt_set_glue_in_boxes_that_fit_exactly() {
+        with_parser(&[r"a\hskip 3pt plus 1pt minus 1pt b%"], |parser| {
+            let metrics = parser.state.get_metrics_for_font(&CMR10).unwrap();
+            let natural = metrics.get_width('a')
+                + Dimen::from_unit(3.0, Unit::Point)
+                + metrics.get_width('b');
+
+            let list = parser.parse_horizontal_list(true, false);
+            let (hbox, fit) = parser.hpack(list, &BoxLayout::Fixed(natural));
+
+            assert_eq!(hbox.width, natural);
+            assert_eq!(hbox.glue_set_ratio, None);
+            assert_eq!(fit, BoxFit::Stretched(0));
+        });
+    }
+
+    #[test]
+    fn it_detects_overfull_boxes() {
+        with_parser(&[r"a\hskip 3pt minus 1pt b%"], |parser| {
+            let metrics = parser.state.get_metrics_for_font(&CMR10).unwrap();
+            let natural = metrics.get_width('a')
+                + Dimen::from_unit(3.0, Unit::Point)
+                + metrics.get_width('b');
+            let target = natural - Dimen::from_unit(3.0, Unit::Point);
+
+            let list = parser.parse_horizontal_list(true, false);
+            let (hbox, fit) = parser.hpack(list, &BoxLayout::Fixed(target));
+
+            // The glue shrinks as much as it can, but no further.
+            assert_eq!(hbox.width, target);
+            assert_eq!(
+                hbox.glue_set_ratio,
+                Some(GlueSetRatio::from(GlueSetRatioKind::Finite, -1.0))
+            );
+            assert_eq!(
+                fit,
+                BoxFit::Overfull(Dimen::from_unit(2.0, Unit::Point))
+            );
+        });
+    }
+
+    #[test]
+    fn it_reports_overfull_hboxes() {
+        with_parser(&[r"\hbox to 1pt{\hskip 3pt minus 1pt}%"], |parser| {
+            parser.parse_box();
+            assert_eq!(
+                parser.take_messages(),
+                vec![r"Overfull \hbox (1.0pt too wide)".to_string()]
+            );
+        });
+    }
 }
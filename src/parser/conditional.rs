@@ -1,4 +1,5 @@
 use crate::category::Category;
+use crate::parser::expand::ScannerStatus;
 use crate::parser::Parser;
 use crate::token::Token;
 
@@ -8,7 +9,14 @@ enum Relation {
     LessThan,
 }
 
-fn check_relation(rel: Relation, left: i32, right: i32) -> bool {
+// The token that ends a branch of an \ifcase.
+enum BranchEnd {
+    Or,
+    Else,
+    Fi,
+}
+
+fn check_relation<T: PartialOrd>(rel: Relation, left: T, right: T) -> bool {
     match rel {
         Relation::GreaterThan => left > right,
         Relation::EqualTo => left == right,
@@ -16,11 +24,29 @@ fn check_relation(rel: Relation, left: i32, right: i32) -> bool {
     }
 }
 
+// Compares two tokens' definitions of a single kind. If only one of the
+// tokens has this kind of definition, they're different, and if neither does,
+// this returns None so other kinds of definitions can be checked.
+fn compare_definitions<T: PartialEq>(
+    def1: Option<T>,
+    def2: Option<T>,
+) -> Option<bool> {
+    match (def1, def2) {
+        (Some(def1), Some(def2)) => Some(def1 == def2),
+        (None, None) => None,
+        _ => Some(false),
+    }
+}
+
 impl<'a> Parser<'a> {
     fn is_conditional_start(&mut self, token: &Token) -> bool {
         self.state.is_token_equal_to_prim(token, "iftrue")
             || self.state.is_token_equal_to_prim(token, "iffalse")
             || self.state.is_token_equal_to_prim(token, "ifnum")
+            || self.state.is_token_equal_to_prim(token, "ifdim")
+            || self.state.is_token_equal_to_prim(token, "ifx")
+            || self.state.is_token_equal_to_prim(token, "iffontchar")
+            || self.state.is_token_equal_to_prim(token, "ifcase")
     }
 
     pub fn is_conditional_head(&mut self) -> bool {
@@ -28,6 +54,7 @@ impl<'a> Parser<'a> {
             Some(token) => {
                 self.is_conditional_start(&token)
                     || self.state.is_token_equal_to_prim(&token, "else")
+                    || self.state.is_token_equal_to_prim(&token, "or")
                     || self.state.is_token_equal_to_prim(&token, "fi")
             }
             _ => false,
@@ -37,6 +64,7 @@ impl<'a> Parser<'a> {
     // Skips tokens until a \fi or \else is parsed. Returns true if the token
     // we found is \else, false if it is \fi.
     fn skip_to_fi_or_else(&mut self) -> bool {
+        let prev_status = self.set_scanner_status(ScannerStatus::Skipping);
         let mut ends_with_else = false;
         loop {
             let token = self.lex_unexpanded_token().unwrap();
@@ -52,11 +80,34 @@ impl<'a> Parser<'a> {
                 break;
             }
         }
+        self.set_scanner_status(prev_status);
         ends_with_else
     }
 
+    // Skips tokens in a branch of an \ifcase until an \or, \else, or \fi is
+    // parsed. Like the other skipping functions, \or tokens inside of inner
+    // conditionals are skipped along with the rest of the inner conditional.
+    fn skip_to_branch_end(&mut self) -> BranchEnd {
+        let prev_status = self.set_scanner_status(ScannerStatus::Skipping);
+        let branch_end = loop {
+            let token = self.lex_unexpanded_token().unwrap();
+            if self.is_conditional_start(&token) {
+                self.skip_to_fi();
+            } else if self.state.is_token_equal_to_prim(&token, "fi") {
+                break BranchEnd::Fi;
+            } else if self.state.is_token_equal_to_prim(&token, "else") {
+                break BranchEnd::Else;
+            } else if self.state.is_token_equal_to_prim(&token, "or") {
+                break BranchEnd::Or;
+            }
+        };
+        self.set_scanner_status(prev_status);
+        branch_end
+    }
+
     // Skips tokens until a \fi is found.
     fn skip_to_fi(&mut self) {
+        let prev_status = self.set_scanner_status(ScannerStatus::Skipping);
         loop {
             let token = self.lex_unexpanded_token().unwrap();
             if self.is_conditional_start(&token) {
@@ -68,6 +119,7 @@ impl<'a> Parser<'a> {
                 break;
             }
         }
+        self.set_scanner_status(prev_status);
     }
 
     fn handle_true(&mut self) {
@@ -83,6 +135,59 @@ impl<'a> Parser<'a> {
         }
     }
 
+    // Checks whether two tokens have the same meaning, like \ifx does. Macros
+    // are the same if their parameter texts, replacement texts, and \long and
+    // \outer statuses match, and other tokens are the same if they have the
+    // same definition.
+    fn tokens_have_same_meaning(&self, token1: &Token, token2: &Token) -> bool {
+        let state = &self.state;
+        let same = compare_definitions(
+            state.get_macro(token1),
+            state.get_macro(token2),
+        )
+        .or_else(|| {
+            compare_definitions(
+                state.get_primitive(token1),
+                state.get_primitive(token2),
+            )
+        })
+        .or_else(|| {
+            compare_definitions(
+                state.get_fontdef(token1),
+                state.get_fontdef(token2),
+            )
+        })
+        .or_else(|| {
+            compare_definitions(
+                state.get_math_chardef(token1),
+                state.get_math_chardef(token2),
+            )
+        })
+        .or_else(|| {
+            compare_definitions(
+                state.get_muskipdef(token1),
+                state.get_muskipdef(token2),
+            )
+        });
+        if let Some(same) = same {
+            return same;
+        }
+
+        // What's left are characters (or tokens \let to them), which are the
+        // same if their character codes and categories match, and undefined
+        // tokens, which are all the same as each other.
+        let get_char = |token: &Token| match state
+            .get_renamed_token(token)
+            .unwrap_or_else(|| token.clone())
+        {
+            Token::Char(_, Category::Active) | Token::ControlSequence(_) => {
+                None
+            }
+            chr => Some(chr),
+        };
+        get_char(token1) == get_char(token2)
+    }
+
     fn parse_relation(&mut self) -> Relation {
         let relation = match self.lex_expanded_token() {
             Some(Token::Char('<', Category::Other)) => Relation::LessThan,
@@ -112,6 +217,14 @@ impl<'a> Parser<'a> {
             // parse the \else token in skip_to_fi_or_else(). Thus, we just
             // need to skip tokens until we see a \fi.
             self.skip_to_fi();
+        } else if self.state.is_token_equal_to_prim(&token, "or") {
+            if self.conditional_depth == 0 {
+                panic!("Extra \\or");
+            }
+            self.conditional_depth -= 1;
+            // Like with \else, we only see an \or at the end of the branch of
+            // an \ifcase that was chosen, so we skip the rest of the cases.
+            self.skip_to_fi();
         } else if self.state.is_token_equal_to_prim(&token, "iftrue") {
             self.handle_true();
         } else if self.state.is_token_equal_to_prim(&token, "iffalse") {
@@ -126,6 +239,62 @@ impl<'a> Parser<'a> {
             } else {
                 self.handle_false();
             }
+        } else if self.state.is_token_equal_to_prim(&token, "ifdim") {
+            let dimen1 = self.parse_dimen();
+            let relation = self.parse_relation();
+            let dimen2 = self.parse_dimen();
+
+            if check_relation(relation, dimen1, dimen2) {
+                self.handle_true();
+            } else {
+                self.handle_false();
+            }
+        } else if self.state.is_token_equal_to_prim(&token, "ifx") {
+            // The tokens being compared aren't expanded.
+            let token1 = self.lex_unexpanded_token().unwrap();
+            let token2 = self.lex_unexpanded_token().unwrap();
+
+            if self.tokens_have_same_meaning(&token1, &token2) {
+                self.handle_true();
+            } else {
+                self.handle_false();
+            }
+        } else if self.state.is_token_equal_to_prim(&token, "ifcase") {
+            let mut case = self.parse_number();
+
+            // Skip over the cases before the chosen one. Negative numbers
+            // don't match any of the cases, so they go to the \else.
+            loop {
+                if case == 0 {
+                    self.handle_true();
+                    break;
+                }
+                match self.skip_to_branch_end() {
+                    BranchEnd::Or => {
+                        if case > 0 {
+                            case -= 1;
+                        }
+                    }
+                    BranchEnd::Else => {
+                        self.conditional_depth += 1;
+                        break;
+                    }
+                    BranchEnd::Fi => break,
+                }
+            }
+        } else if self.state.is_token_equal_to_prim(&token, "iffontchar") {
+            let font = self.parse_font_identifier();
+            let chr = self.parse_8bit_number() as char;
+            let exists = match self.state.get_metrics_for_font(&font) {
+                Some(metrics) => metrics.exists(chr),
+                None => false,
+            };
+
+            if exists {
+                self.handle_true();
+            } else {
+                self.handle_false();
+            }
         } else {
             panic!("unimplemented");
         }
@@ -342,6 +511,51 @@ mod tests {
         );
     }
 
+    #[test]
+    fn it_parses_ifdim() {
+        with_parser(
+            &[
+                r"\ifdim1pt<2pt t\else f\fi%",
+                r"\ifdim1in>72pt t\else f\fi%",
+                r"\dimen0=\maxdimen%",
+                r"\ifdim\dimen0>16000pt t\else f\fi%",
+            ],
+            |parser| {
+                // 1pt<2pt -> t
+                assert!(parser.is_conditional_head());
+                parser.expand_conditional();
+                assert_eq!(
+                    parser.lex_expanded_token(),
+                    Some(Token::Char('t', Category::Letter))
+                );
+                assert!(parser.is_conditional_head());
+                parser.expand_conditional();
+
+                // 1in>72pt -> t
+                assert!(parser.is_conditional_head());
+                parser.expand_conditional();
+                assert_eq!(
+                    parser.lex_expanded_token(),
+                    Some(Token::Char('t', Category::Letter))
+                );
+                assert!(parser.is_conditional_head());
+                parser.expand_conditional();
+
+                parser.parse_assignment();
+
+                // \maxdimen>16000pt -> t
+                assert!(parser.is_conditional_head());
+                parser.expand_conditional();
+                assert_eq!(
+                    parser.lex_expanded_token(),
+                    Some(Token::Char('t', Category::Letter))
+                );
+                assert!(parser.is_conditional_head());
+                parser.expand_conditional();
+            },
+        );
+    }
+
     #[test]
     fn it_allows_spaces_in_ifnum() {
         with_parser(&["\\ifnum 1       <      2      t\\fi%"], |parser| {
@@ -429,4 +643,129 @@ mod tests {
             },
         );
     }
+
+    // Expands all of the upcoming tokens and returns the chars in them.
+    fn expand_to_chars(parser: &mut Parser) -> String {
+        let mut result = String::new();
+        while let Some(token) = parser.lex_expanded_token() {
+            if let Token::Char(chr, _) = token {
+                result.push(chr);
+            }
+        }
+        result
+    }
+
+    #[test]
+    fn it_compares_characters_with_ifx() {
+        with_parser(
+            &[
+                r"\ifx aa1\else 2\fi%",
+                r"\ifx ab3\else 4\fi%",
+                r"\ifx\a\a 5\else 6\fi%",
+                r"\ifx\relax\relax 7\else 8\fi%",
+                r"\ifx\relax\a 9\else 0\fi%",
+            ],
+            |parser| {
+                assert_eq!(expand_to_chars(parser), "14570");
+            },
+        );
+    }
+
+    #[test]
+    fn it_compares_macros_with_ifx() {
+        with_parser(
+            &[
+                r"\def\a{x}\def\b{x}\def\c{y}\long\def\d{x}\def\e#1{x}%",
+                r"\let\f=\a%",
+                r"\ifx\a\b 1\else 2\fi%",
+                r"\ifx\a\c 3\else 4\fi%",
+                r"\ifx\a\d 5\else 6\fi%",
+                r"\ifx\a\e 7\else 8\fi%",
+                r"\ifx\a\f 9\else 0\fi%",
+                r"\ifx\a x1\else 2\fi%",
+                r"\ifx\a\relax 3\else 4\fi%",
+            ],
+            |parser| {
+                for _ in 0..6 {
+                    parser.parse_assignment();
+                }
+                assert_eq!(expand_to_chars(parser), "1468924");
+            },
+        );
+    }
+
+    #[test]
+    fn it_compares_other_definitions_with_ifx() {
+        with_parser(
+            &[
+                r"\let\a=b\let\b=b\let\c=\relax\let\d=\par%",
+                r"\mathchardef\e=1\mathchardef\f=1\mathchardef\g=2%",
+                r"\muskipdef\h=1\muskipdef\i=1\muskipdef\j=2 %",
+                r"\ifx\a\b 1\else 2\fi%",
+                r"\ifx\a b3\else 4\fi%",
+                r"\ifx\a c5\else 6\fi%",
+                r"\ifx\c\relax 7\else 8\fi%",
+                r"\ifx\c\d 9\else 0\fi%",
+                r"\ifx\e\f 1\else 2\fi%",
+                r"\ifx\e\g 3\else 4\fi%",
+                r"\ifx\h\i 5\else 6\fi%",
+                r"\ifx\h\j 7\else 8\fi%",
+                r"\ifx\e\h 9\else 0\fi%",
+                r"\ifx\undefineda\undefinedb 1\else 2\fi%",
+                r"\ifx\undefineda\a 3\else 4\fi%",
+            ],
+            |parser| {
+                for _ in 0..10 {
+                    parser.parse_assignment();
+                }
+                assert_eq!(expand_to_chars(parser), "136701458014");
+            },
+        );
+    }
+
+    #[test]
+    fn it_checks_whether_fonts_have_characters() {
+        with_parser(
+            &[
+                r"\font\a=cmr10 scaled 2000%",
+                r"\iffontchar\font`A t\else f\fi%",
+                r"\iffontchar\font200 t\else f\fi%",
+                r"\iffontchar\a`A t\else f\fi%",
+            ],
+            |parser| {
+                parser.parse_assignment();
+                assert_eq!(expand_to_chars(parser), "tft");
+            },
+        );
+    }
+
+    #[test]
+    fn it_chooses_cases_with_ifcase() {
+        with_parser(
+            &[
+                r"\ifcase2 a\or b\or c\else d\fi%",
+                r"\ifcase0 a\or b\fi%",
+                r"\ifcase 5 a\or b\else d\fi%",
+                r"\ifcase-1 a\or b\else e\fi%",
+                r"\ifcase3 a\or b\fi%",
+                r"\ifcase1 a\or f\else g\fi%",
+            ],
+            |parser| {
+                assert_eq!(expand_to_chars(parser), "cadef");
+            },
+        );
+    }
+
+    #[test]
+    fn it_only_counts_ors_at_the_current_level_in_ifcase() {
+        with_parser(
+            &[
+                r"\ifcase1 \ifcase0 a\or b\fi\or c\ifnum1=1 d\else e\fi",
+                r"\or f\fi%",
+            ],
+            |parser| {
+                assert_eq!(expand_to_chars(parser), "cd");
+            },
+        );
+    }
 }
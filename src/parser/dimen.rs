@@ -27,6 +27,7 @@ enum ParsedUnit {
     Fil,
     Fill,
     Filll,
+    Mu,
     PhysicalUnit(bool, Unit),
 }
 
@@ -58,17 +59,71 @@ impl<'a> Parser<'a> {
         value * sign
     }
 
+    /// Parses a SpringDimen measured in math units, like the components of
+    /// the glue in a \muskip. The value is stored as if 1mu were 1pt.
+    pub fn parse_mu_spring_dimen(&mut self, allow_fil: bool) -> SpringDimen {
+        let sign = self.parse_optional_signs();
+        let value = self.parse_unsigned_mu_dimen(allow_fil);
+
+        value * sign
+    }
+
+    fn parse_unsigned_mu_dimen(&mut self, allow_fil: bool) -> SpringDimen {
+        if self.is_mu_glue_variable_head() {
+            let variable = self.parse_mu_glue_variable();
+            return SpringDimen::Dimen(variable.get(self.state).space);
+        }
+
+        let factor = self.parse_factor();
+
+        // The unit can also be the natural width of some internal mu glue,
+        // like in 2\muskip0.
+        self.parse_optional_spaces_expanded();
+        if self.is_mu_glue_variable_head() {
+            let variable = self.parse_mu_glue_variable();
+            let scaled_factor = (factor * 65536.0).round() as i32;
+            return SpringDimen::Dimen(
+                variable.get(self.state).space * (scaled_factor, 65536),
+            );
+        }
+
+        match self.parse_unit(allow_fil) {
+            ParsedUnit::Mu => {
+                let units = (factor * 65536.0).round() / 65536.0;
+                SpringDimen::Dimen(Dimen::from_unit(units, Unit::Point))
+            }
+            ParsedUnit::Fil => {
+                SpringDimen::FilDimen(FilDimen::new(FilKind::Fil, factor))
+            }
+            ParsedUnit::Fill => {
+                SpringDimen::FilDimen(FilDimen::new(FilKind::Fill, factor))
+            }
+            ParsedUnit::Filll => {
+                SpringDimen::FilDimen(FilDimen::new(FilKind::Filll, factor))
+            }
+            _ => panic!("Illegal unit of measure (mu inserted)"),
+        }
+    }
+
     fn parse_unsigned_dimen(&mut self, allow_fil: bool) -> SpringDimen {
         self.parse_normal_dimen(allow_fil)
     }
 
     fn parse_normal_dimen(&mut self, allow_fil: bool) -> SpringDimen {
+        if self.is_internal_dimen_head() {
+            return SpringDimen::Dimen(self.parse_internal_dimen());
+        }
+
         let factor = self.parse_factor();
         let (unit_factor, unit_or_fil) = self.parse_unit_of_measure(allow_fil);
 
         match unit_or_fil {
             UnitOrFil::Unit(unit) => {
-                SpringDimen::Dimen(Dimen::from_unit(factor * unit_factor, unit))
+                // Like TeX, round the number of units to the nearest 1/65536
+                // before converting it, so e.g. 16383.99998pt is exactly the
+                // largest dimension.
+                let units = (factor * unit_factor * 65536.0).round() / 65536.0;
+                SpringDimen::Dimen(Dimen::from_unit(units, unit))
             }
             UnitOrFil::Fil => SpringDimen::FilDimen(FilDimen::new(
                 FilKind::Fil,
@@ -212,6 +267,7 @@ impl<'a> Parser<'a> {
                 ParsedUnit::Fil => (1.0, UnitOrFil::Fil),
                 ParsedUnit::Fill => (1.0, UnitOrFil::Fill),
                 ParsedUnit::Filll => (1.0, UnitOrFil::Filll),
+                ParsedUnit::Mu => panic!("Illegal unit of measure: mu"),
             }
         }
     }
@@ -278,6 +334,12 @@ impl<'a> Parser<'a> {
                 panic!("Invalid unit with true: ex");
             }
             ParsedUnit::Ex
+        } else if equals_unit(&unit_first, &unit_second, ['m', 'u']) {
+            self.parse_optional_space_expanded();
+            if is_true_unit {
+                panic!("Invalid unit with true: mu");
+            }
+            ParsedUnit::Mu
         } else if equals_unit(&unit_first, &unit_second, ['f', 'i']) {
             if !allow_fil {
                 panic!("Invalid unit: fil*");
@@ -322,12 +384,60 @@ impl<'a> Parser<'a> {
 
     pub fn is_internal_dimen_head(&mut self) -> bool {
         self.is_dimen_variable_head()
+            || self.is_next_expanded_token_in_set_of_primitives(&["maxdimen"])
+            || self.is_dimen_expression_head()
+            || self.is_font_char_dimen_head()
+    }
+
+    fn is_font_char_dimen_head(&mut self) -> bool {
+        self.is_next_expanded_token_in_set_of_primitives(&[
+            "fontcharwd",
+            "fontcharht",
+            "fontchardp",
+            "fontcharic",
+        ])
+    }
+
+    // Parses a \fontcharwd, \fontcharht, \fontchardp, or \fontcharic and the
+    // font and character after it, and returns that dimension of the
+    // character. Characters that aren't in the font have dimensions of zero.
+    fn parse_font_char_dimen(&mut self) -> Dimen {
+        let token = self.lex_expanded_token().unwrap();
+        let font = self.parse_font_identifier();
+        let chr = self.parse_8bit_number() as char;
+
+        let metrics = match self.state.get_metrics_for_font(&font) {
+            Some(metrics) if metrics.exists(chr) => metrics,
+            _ => return Dimen::zero(),
+        };
+
+        if self.state.is_token_equal_to_prim(&token, "fontcharwd") {
+            metrics.get_width(chr)
+        } else if self.state.is_token_equal_to_prim(&token, "fontcharht") {
+            metrics.get_height(chr)
+        } else if self.state.is_token_equal_to_prim(&token, "fontchardp") {
+            metrics.get_depth(chr)
+        } else {
+            metrics.get_italic_correction(chr)
+        }
     }
 
     pub fn parse_internal_dimen(&mut self) -> Dimen {
         if self.is_dimen_variable_head() {
             let variable = self.parse_dimen_variable();
             variable.get(self.state)
+        } else if self
+            .is_next_expanded_token_in_set_of_primitives(&["maxdimen"])
+        {
+            // TODO(xymostech): In plain.tex, this is a \dimen register that is
+            // set to the largest dimension. Once we have \newdimen, this
+            // should be defined there instead.
+            self.lex_expanded_token();
+            Dimen::max_value()
+        } else if self.is_dimen_expression_head() {
+            self.parse_dimen_expression()
+        } else if self.is_font_char_dimen_head() {
+            self.parse_font_char_dimen()
         } else {
             panic!("unimplemented");
         }
@@ -450,6 +560,34 @@ mod tests {
         });
     }
 
+    #[test]
+    fn it_parses_the_largest_dimension() {
+        with_parser(&[r"\dimen0=16383.99998pt%"], |parser| {
+            parser.parse_assignment();
+            assert_eq!(
+                parser.state.get_dimen_register(0),
+                Dimen::from_scaled_points((1 << 30) - 1)
+            );
+        });
+    }
+
+    #[test]
+    fn it_parses_maxdimen() {
+        with_parser(&[r"\maxdimen%", r"16383.99998pt%"], |parser| {
+            let max = parser.parse_dimen();
+            assert_eq!(max, Dimen::from_scaled_points((1 << 30) - 1));
+            assert_eq!(max, parser.parse_dimen());
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Dimension too large")]
+    fn it_fails_to_parse_dimensions_that_are_too_large() {
+        with_parser(&[r"\dimen0=16384pt%"], |parser| {
+            parser.parse_assignment();
+        });
+    }
+
     #[test]
     fn it_parses_negative_dimens() {
         with_parser(&["-3.4pt%"], |parser| {
@@ -517,4 +655,70 @@ mod tests {
             assert_eq!(parser.parse_internal_dimen(), metrics.get_height('a'));
         });
     }
+
+    #[test]
+    fn it_parses_dimen_registers_as_dimens() {
+        with_parser(&[r"\dimen1=2.5pt%", r"\dimen1 -\dimen1%"], |parser| {
+            parser.parse_assignment();
+
+            assert_eq!(
+                parser.parse_dimen(),
+                Dimen::from_unit(2.5, Unit::Point)
+            );
+            assert_eq!(
+                parser.parse_dimen(),
+                Dimen::from_unit(-2.5, Unit::Point)
+            );
+        });
+    }
+
+    #[test]
+    fn it_parses_the_dimensions_of_font_characters() {
+        with_parser(
+            &[
+                r"\font\a=cmr10 scaled 2000%",
+                r"\dimen0=\fontcharwd\font`A%",
+                r"\dimen1=\fontcharht\a`A%",
+                r"\dimen2=\fontchardp\font`g%",
+                r"\dimen3=\fontcharic\font`f%",
+                r"\dimen4=\fontcharwd\font200 %",
+                r"\the\fontcharwd\font`A%",
+            ],
+            |parser| {
+                for _ in 0..6 {
+                    parser.parse_assignment();
+                }
+
+                let metrics = parser
+                    .state
+                    .get_metrics_for_font(&parser.state.get_current_font())
+                    .unwrap();
+                assert_eq!(
+                    parser.state.get_dimen_register(0),
+                    metrics.get_width('A')
+                );
+                assert_eq!(
+                    parser.state.get_dimen_register(1),
+                    metrics.get_height('A') * 2
+                );
+                assert_eq!(
+                    parser.state.get_dimen_register(2),
+                    metrics.get_depth('g')
+                );
+                assert_eq!(
+                    parser.state.get_dimen_register(3),
+                    metrics.get_italic_correction('f')
+                );
+                assert!(parser.state.get_dimen_register(3) > Dimen::zero());
+                assert_eq!(parser.state.get_dimen_register(4), Dimen::zero());
+
+                let printed: String = parser
+                    .expand_print()
+                    .iter()
+                    .map(|token| token.to_tex_string())
+                    .collect();
+                assert_eq!(printed, metrics.get_width('A').to_tex_string());
+            },
+        );
+    }
 }
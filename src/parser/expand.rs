@@ -1,32 +1,212 @@
+use crate::category::Category;
 use crate::parser::Parser;
 use crate::token::Token;
 
+// What kind of tokens are currently being scanned without expansion, which
+// determines whether \outer macros are allowed to show up.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ScannerStatus {
+    Normal,
+    // Skipping over the text of a conditional that isn't being used.
+    Skipping,
+    // Parsing the parameter and replacement text of a macro definition.
+    Defining,
+    // Parsing the arguments to a macro.
+    Matching,
+    // Parsing a balanced text, like the value of a token list.
+    Absorbing,
+}
+
+impl ScannerStatus {
+    fn describe(&self) -> &'static str {
+        match self {
+            ScannerStatus::Normal => "text",
+            ScannerStatus::Skipping => "skipped conditional text",
+            ScannerStatus::Defining => "definition",
+            ScannerStatus::Matching => "use",
+            ScannerStatus::Absorbing => "text",
+        }
+    }
+}
+
 impl<'a> Parser<'a> {
+    // Changes the scanner status, returning the previous status so that it
+    // can be restored afterwards.
+    pub fn set_scanner_status(
+        &mut self,
+        status: ScannerStatus,
+    ) -> ScannerStatus {
+        std::mem::replace(&mut self.scanner_status, status)
+    }
+
     pub fn lex_expanded_token(&mut self) -> Option<Token> {
+        while self.expand_next_token() {}
+        self.lex_unexpanded_token()
+    }
+
+    // Expands the next token once if it can be expanded. Returns whether
+    // anything was expanded.
+    fn expand_next_token(&mut self) -> bool {
         if self.is_conditional_head() {
             // Handle conditionals, like \ifnum
             self.expand_conditional();
-            return self.lex_expanded_token();
         } else if self.is_print_head() {
             // Handle printing, like \number\count1
             let replacement = self.expand_print();
             self.add_upcoming_tokens(replacement);
-            return self.lex_expanded_token();
-        }
-
-        match self.lex_unexpanded_token() {
-            None => None,
-            Some(token) => {
-                // Handle macro expansion
-                if let Some(makro) = self.state.get_macro(&token) {
+        } else if self.is_unexpanded_head() {
+            // Outside of expanded text, \unexpanded just produces its tokens
+            let replacement = self.expand_unexpanded();
+            self.add_upcoming_tokens(replacement);
+        } else if self.is_expanded_head() {
+            // Handle \expanded, which expands its tokens all at once
+            let replacement = self.expand_expanded();
+            self.add_upcoming_tokens(replacement);
+        } else if self.is_next_unexpanded_token_prim("expandafter") {
+            // Handle \expandafter, which expands the token after the next
+            // one before the next one
+            self.lex_unexpanded_token();
+            if let Some(token) = self.lex_unexpanded_token() {
+                self.expand_next_token();
+                self.add_upcoming_token(token);
+            }
+        } else if self.is_next_unexpanded_token_prim("csname") {
+            // Handle \csname, which makes a control sequence from characters
+            let token = self.expand_csname();
+            self.add_upcoming_token(token);
+        } else {
+            // Handle macro expansion
+            let makro = match self.peek_unexpanded_token() {
+                Some(token) => self.state.get_macro(&token),
+                None => None,
+            };
+            match makro {
+                Some(makro) => {
+                    self.lex_unexpanded_token();
                     let replacement_map = self.parse_replacement_map(&makro);
                     let replacement = makro.get_replacement(&replacement_map);
                     self.add_upcoming_tokens(replacement);
-                    self.lex_expanded_token()
-                } else {
-                    // Passthrough anything else
-                    Some(token)
                 }
+                // Anything else can't be expanded
+                None => return false,
+            }
+        }
+
+        true
+    }
+
+    fn is_next_unexpanded_token_prim(&mut self, prim: &str) -> bool {
+        match self.peek_unexpanded_token() {
+            Some(token) => self.state.is_token_equal_to_prim(&token, prim),
+            None => false,
+        }
+    }
+
+    // Parses a \csname<tokens>\endcsname, expanding the tokens, and returns
+    // the control sequence whose name is the characters in them. Like in
+    // TeX, the control sequence is set to \relax if it isn't defined yet.
+    fn expand_csname(&mut self) -> Token {
+        self.lex_unexpanded_token();
+
+        let mut name = String::new();
+        loop {
+            match self.lex_expanded_token() {
+                Some(Token::Char(chr, _)) => name.push(chr),
+                Some(ref token)
+                    if self
+                        .state
+                        .is_token_equal_to_prim(token, "endcsname") =>
+                {
+                    break
+                }
+                _ => panic!("Missing \\endcsname inserted"),
+            }
+        }
+
+        let token = Token::ControlSequence(name);
+        if !self.state.is_defined(&token) {
+            self.state.set_let(
+                false,
+                &token,
+                &Token::ControlSequence("relax".to_string()),
+            );
+        }
+        token
+    }
+
+    fn is_unexpanded_head(&mut self) -> bool {
+        match self.peek_unexpanded_token() {
+            Some(token) => {
+                self.state.is_token_equal_to_prim(&token, "unexpanded")
+            }
+            None => false,
+        }
+    }
+
+    // Parses an \unexpanded<general text> and returns the tokens in the text.
+    fn expand_unexpanded(&mut self) -> Vec<Token> {
+        self.lex_unexpanded_token();
+        self.parse_general_text()
+    }
+
+    fn is_expanded_head(&mut self) -> bool {
+        match self.peek_unexpanded_token() {
+            Some(token) => {
+                self.state.is_token_equal_to_prim(&token, "expanded")
+            }
+            None => false,
+        }
+    }
+
+    // Parses an \expanded<general text>, and returns the tokens in the text
+    // after they have been expanded like the replacement text of an \edef.
+    fn expand_expanded(&mut self) -> Vec<Token> {
+        self.lex_unexpanded_token();
+        self.parse_filler_expanded();
+        match self.lex_expanded_token() {
+            Some(Token::Char(_, Category::BeginGroup)) => {}
+            tok => panic!("Missing {{ inserted, found {:?}", tok),
+        }
+
+        let mut result = Vec::new();
+        let mut group_level = 0;
+        loop {
+            if let Some(tokens) = self.expand_for_expanded_text() {
+                result.extend(tokens);
+                continue;
+            }
+
+            let token = match self.lex_unexpanded_token() {
+                Some(token) => token,
+                None => panic!("EOF found parsing \\expanded"),
+            };
+            match token {
+                Token::Char(_, Category::BeginGroup) => group_level += 1,
+                Token::Char(_, Category::EndGroup) => {
+                    if group_level == 0 {
+                        return result;
+                    }
+                    group_level -= 1;
+                }
+                _ => {}
+            }
+            result.push(token);
+        }
+    }
+
+    // Expands the upcoming tokens of text that is expanded as it is read, like
+    // the replacement text of an \edef, until the next token can't be
+    // expanded. The tokens produced by \unexpanded and by printing commands
+    // like \the aren't expanded any further, so they are returned so that
+    // they can be added to the text as-is.
+    pub fn expand_for_expanded_text(&mut self) -> Option<Vec<Token>> {
+        loop {
+            if self.is_print_head() {
+                return Some(self.expand_print());
+            } else if self.is_unexpanded_head() {
+                return Some(self.expand_unexpanded());
+            } else if !self.expand_next_token() {
+                return None;
             }
         }
     }
@@ -42,11 +222,30 @@ impl<'a> Parser<'a> {
     }
 
     pub fn lex_unexpanded_token(&mut self) -> Option<Token> {
-        if self.upcoming_tokens.is_empty() {
+        let token = if self.upcoming_tokens.is_empty() {
             self.lexer.lex_token()
         } else {
             self.upcoming_tokens.pop()
+        };
+
+        // \outer macros are only allowed when we're not in the middle of
+        // scanning something.
+        if self.scanner_status != ScannerStatus::Normal {
+            if let Some(ref tok) = token {
+                if let Some(makro) = self.state.get_macro(tok) {
+                    if makro.is_outer() {
+                        panic!(
+                            "Forbidden control sequence found while scanning \
+                             {}: {:?}",
+                            self.scanner_status.describe(),
+                            tok
+                        );
+                    }
+                }
+            }
         }
+
+        token
     }
 
     pub fn peek_unexpanded_token(&mut self) -> Option<Token> {
@@ -230,4 +429,166 @@ mod tests {
             );
         });
     }
+
+    #[test]
+    fn it_expands_unexpanded_tokens_outside_of_expanded_text() {
+        with_parser(&[r"\def\a{b}%", r"\unexpanded{\a}%"], |parser| {
+            parser.parse_assignment();
+            assert_eq!(
+                parser.lex_expanded_token(),
+                Some(Token::Char('b', Category::Letter))
+            );
+            assert_eq!(parser.lex_expanded_token(), None);
+        });
+    }
+
+    #[test]
+    fn it_expands_expanded_tokens_all_at_once() {
+        with_parser(
+            &[r"\def\a{x}%", r"\expanded{\a\a{\a}\unexpanded{\a}}%"],
+            |parser| {
+                parser.parse_assignment();
+
+                // Look at the tokens without lexing them one at a time, so
+                // the \a that's protected by \unexpanded isn't expanded.
+                assert!(parser.is_expanded_head());
+                assert_eq!(
+                    parser.expand_expanded(),
+                    vec![
+                        Token::Char('x', Category::Letter),
+                        Token::Char('x', Category::Letter),
+                        Token::Char('{', Category::BeginGroup),
+                        Token::Char('x', Category::Letter),
+                        Token::Char('}', Category::EndGroup),
+                        Token::ControlSequence("a".to_string()),
+                    ]
+                );
+                assert_eq!(parser.lex_unexpanded_token(), None);
+            },
+        );
+    }
+
+    #[test]
+    fn it_inserts_expanded_tokens_into_the_input() {
+        with_parser(&[r"\def\a{x}\expanded{\a\a}%"], |parser| {
+            parser.parse_assignment();
+            assert_eq!(
+                parser.lex_expanded_token(),
+                Some(Token::Char('x', Category::Letter))
+            );
+            assert_eq!(
+                parser.lex_expanded_token(),
+                Some(Token::Char('x', Category::Letter))
+            );
+            assert_eq!(parser.lex_expanded_token(), None);
+        });
+    }
+
+    #[test]
+    fn it_expands_outer_macros_outside_of_other_scanning() {
+        with_parser(&[r"\outer\def\a{x}%", r"\a%"], |parser| {
+            parser.parse_assignment();
+            assert_eq!(
+                parser.lex_expanded_token(),
+                Some(Token::Char('x', Category::Letter))
+            );
+        });
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Forbidden control sequence found while scanning use"
+    )]
+    fn it_fails_on_outer_macros_in_macro_arguments() {
+        with_parser(
+            &[r"\outer\def\a{x}%", r"\def\b#1{#1}%", r"\b{\a}%"],
+            |parser| {
+                parser.parse_assignment();
+                parser.parse_assignment();
+                parser.lex_expanded_token();
+            },
+        );
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Forbidden control sequence found while scanning definition"
+    )]
+    fn it_fails_on_outer_macros_in_definitions() {
+        with_parser(&[r"\outer\def\a{x}%", r"\def\b{\a}%"], |parser| {
+            parser.parse_assignment();
+            parser.parse_assignment();
+        });
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Forbidden control sequence found while scanning skipped \
+                    conditional text"
+    )]
+    fn it_fails_on_outer_macros_in_skipped_conditionals() {
+        with_parser(&[r"\outer\def\a{x}%", r"\iffalse\a\fi%"], |parser| {
+            parser.parse_assignment();
+            parser.lex_expanded_token();
+        });
+    }
+
+    #[test]
+    fn it_expands_csname_to_control_sequences() {
+        with_parser(
+            &[r"\def\a{la}%", r"\csname re\a x\endcsname%"],
+            |parser| {
+                parser.parse_assignment();
+                let token = parser.lex_expanded_token().unwrap();
+                assert_eq!(token, Token::ControlSequence("relax".to_string()));
+                assert!(parser.state.is_token_equal_to_prim(&token, "relax"));
+            },
+        );
+    }
+
+    #[test]
+    fn it_defines_undefined_csnames_as_relax() {
+        with_parser(&[r"\csname foo\endcsname%"], |parser| {
+            let token = parser.lex_expanded_token().unwrap();
+            assert_eq!(token, Token::ControlSequence("foo".to_string()));
+            assert!(parser.state.is_token_equal_to_prim(&token, "relax"));
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Missing \\endcsname inserted")]
+    fn it_fails_on_non_characters_in_csname() {
+        with_parser(&[r"\csname foo\relax\endcsname%"], |parser| {
+            parser.lex_expanded_token();
+        });
+    }
+
+    #[test]
+    fn it_expands_after_the_next_token() {
+        with_parser(
+            &[
+                r"\expandafter\def\csname foo\endcsname{x}\foo%",
+                r"\def\a{\b}\def\b{y}\expandafter\a\b%",
+            ],
+            |parser| {
+                parser.parse_assignment();
+                assert_eq!(
+                    parser.lex_expanded_token(),
+                    Some(Token::Char('x', Category::Letter))
+                );
+
+                parser.parse_assignment();
+                parser.parse_assignment();
+                assert_eq!(
+                    parser.lex_expanded_token(),
+                    Some(Token::Char('y', Category::Letter))
+                );
+                assert_eq!(
+                    parser.lex_expanded_token(),
+                    Some(Token::Char('y', Category::Letter))
+                );
+                assert_eq!(parser.lex_expanded_token(), None);
+            },
+        );
+    }
 }
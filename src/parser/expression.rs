@@ -0,0 +1,235 @@
+use crate::category::Category;
+use crate::dimension::Dimen;
+use crate::parser::Parser;
+use crate::token::Token;
+
+// The largest absolute values that the results of integer and dimen
+// expressions can have.
+const INTEGER_EXPRESSION_MAX: i64 = (1 << 31) - 1;
+const DIMEN_EXPRESSION_MAX: i64 = (1 << 30) - 1;
+
+// The kind of value that an expression produces. Dimen expressions are
+// computed in scaled points.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ExpressionKind {
+    Integer,
+    Dimen,
+}
+
+impl ExpressionKind {
+    fn check_overflow(&self, value: i64) -> i64 {
+        let max = match self {
+            ExpressionKind::Integer => INTEGER_EXPRESSION_MAX,
+            ExpressionKind::Dimen => DIMEN_EXPRESSION_MAX,
+        };
+        if value.abs() > max {
+            panic!("Arithmetic overflow");
+        }
+        value
+    }
+}
+
+// Divides `numerator` by `denominator` and rounds the result to the nearest
+// integer, with ties rounded away from zero like in e-TeX.
+fn divide_and_round(numerator: i64, denominator: i64) -> i64 {
+    if denominator == 0 {
+        panic!("Arithmetic overflow");
+    }
+
+    let negative = (numerator < 0) != (denominator < 0);
+    let numerator = numerator.abs();
+    let denominator = denominator.abs();
+    let quotient = (2 * numerator + denominator) / (2 * denominator);
+    if negative {
+        -quotient
+    } else {
+        quotient
+    }
+}
+
+impl<'a> Parser<'a> {
+    pub fn is_integer_expression_head(&mut self) -> bool {
+        self.is_next_expanded_token_in_set_of_primitives(&["numexpr"])
+    }
+
+    pub fn is_dimen_expression_head(&mut self) -> bool {
+        self.is_next_expanded_token_in_set_of_primitives(&["dimexpr"])
+    }
+
+    /// Parses a \numexpr and returns the value of the expression.
+    pub fn parse_integer_expression(&mut self) -> i32 {
+        self.lex_expanded_token();
+        self.parse_whole_expression(ExpressionKind::Integer) as i32
+    }
+
+    /// Parses a \dimexpr and returns the value of the expression.
+    pub fn parse_dimen_expression(&mut self) -> Dimen {
+        self.lex_expanded_token();
+        Dimen::from_scaled_points(
+            self.parse_whole_expression(ExpressionKind::Dimen) as i32,
+        )
+    }
+
+    // Parses an expression, which can optionally be ended with a \relax.
+    fn parse_whole_expression(&mut self, kind: ExpressionKind) -> i64 {
+        let value = self.parse_expression(kind);
+        if self.is_next_expanded_token_in_set_of_primitives(&["relax"]) {
+            self.lex_expanded_token();
+        }
+        value
+    }
+
+    // Skips spaces and then lexes the next token if it is the given
+    // character, returning whether it was found.
+    fn parse_expression_char(&mut self, chr: char) -> bool {
+        self.parse_optional_spaces_expanded();
+        if self.peek_expanded_token() == Some(Token::Char(chr, Category::Other))
+        {
+            self.lex_expanded_token();
+            true
+        } else {
+            false
+        }
+    }
+
+    // Parses terms separated by + and -.
+    fn parse_expression(&mut self, kind: ExpressionKind) -> i64 {
+        let mut value = self.parse_expression_term(kind);
+        loop {
+            if self.parse_expression_char('+') {
+                let term = self.parse_expression_term(kind);
+                value = kind.check_overflow(value + term);
+            } else if self.parse_expression_char('-') {
+                let term = self.parse_expression_term(kind);
+                value = kind.check_overflow(value - term);
+            } else {
+                return value;
+            }
+        }
+    }
+
+    // Parses a factor which is multiplied or divided by any number of integer
+    // factors. Like in e-TeX, a multiplication that's directly followed by a
+    // division is done all at once without rounding in between, so that
+    // values can be scaled by fractions precisely.
+    fn parse_expression_term(&mut self, kind: ExpressionKind) -> i64 {
+        let mut value = self.parse_expression_factor(kind);
+        loop {
+            if self.parse_expression_char('*') {
+                let numerator =
+                    self.parse_expression_factor(ExpressionKind::Integer);
+                value = if self.parse_expression_char('/') {
+                    let denominator =
+                        self.parse_expression_factor(ExpressionKind::Integer);
+                    divide_and_round(value * numerator, denominator)
+                } else {
+                    value * numerator
+                };
+            } else if self.parse_expression_char('/') {
+                let denominator =
+                    self.parse_expression_factor(ExpressionKind::Integer);
+                value = divide_and_round(value, denominator);
+            } else {
+                return value;
+            }
+            value = kind.check_overflow(value);
+        }
+    }
+
+    // Parses either a parenthesized expression or a single value.
+    fn parse_expression_factor(&mut self, kind: ExpressionKind) -> i64 {
+        if self.parse_expression_char('(') {
+            let value = self.parse_expression(kind);
+            if !self.parse_expression_char(')') {
+                panic!("Missing ) inserted for expression");
+            }
+            value
+        } else {
+            match kind {
+                ExpressionKind::Integer => self.parse_number() as i64,
+                ExpressionKind::Dimen => {
+                    self.parse_dimen().as_scaled_points() as i64
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::dimension::Unit;
+    use crate::testing::with_parser;
+
+    #[test]
+    fn it_parses_integer_expressions() {
+        with_parser(
+            &[
+                r"\count1=\numexpr 1 + 2*3 - (4 - 1)\relax",
+                r"\count2=\numexpr7/2 \count3=\numexpr-7/2\relax",
+                r"\count4=\numexpr 5 * (2 + \count1 ) / 3\relax%",
+            ],
+            |parser| {
+                parser.parse_assignment();
+                parser.parse_assignment();
+                parser.parse_assignment();
+                parser.parse_assignment();
+
+                assert_eq!(parser.state.get_count(1), 4);
+                // Division rounds to the nearest integer, away from zero.
+                assert_eq!(parser.state.get_count(2), 4);
+                assert_eq!(parser.state.get_count(3), -4);
+                assert_eq!(parser.state.get_count(4), 10);
+                assert_eq!(parser.lex_unexpanded_token(), None);
+            },
+        );
+    }
+
+    #[test]
+    fn it_scales_integers_without_rounding_in_between() {
+        with_parser(&[r"\count1=\numexpr 2147483647*2/3\relax%"], |parser| {
+            parser.parse_assignment();
+            assert_eq!(parser.state.get_count(1), 1431655765);
+        });
+    }
+
+    #[test]
+    fn it_parses_dimen_expressions() {
+        with_parser(
+            &[
+                r"\dimen0=\dimexpr 10pt*7/3\relax",
+                r"\dimen1=\dimexpr (1pt + 2pt) * 2 - 0.5pt\relax%",
+            ],
+            |parser| {
+                parser.parse_assignment();
+                parser.parse_assignment();
+
+                assert_eq!(
+                    parser.state.get_dimen_register(0).to_tex_string(),
+                    "23.33333pt"
+                );
+                assert_eq!(
+                    parser.state.get_dimen_register(1),
+                    Dimen::from_unit(5.5, Unit::Point)
+                );
+            },
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Arithmetic overflow")]
+    fn it_fails_on_division_by_zero() {
+        with_parser(&[r"\count1=\numexpr 1/0\relax%"], |parser| {
+            parser.parse_assignment();
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Arithmetic overflow")]
+    fn it_fails_on_dimen_expressions_that_are_too_large() {
+        with_parser(&[r"\dimen0=\dimexpr 16383pt*2\relax%"], |parser| {
+            parser.parse_assignment();
+        });
+    }
+}
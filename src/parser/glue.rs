@@ -1,10 +1,66 @@
 use crate::parser::Parser;
 
-use crate::dimension::{Dimen, SpringDimen};
+use crate::dimension::{Dimen, FilDimen, FilKind, SpringDimen};
 use crate::glue::Glue;
+use crate::mu_glue::MuGlue;
 
 impl<'a> Parser<'a> {
+    pub fn is_horizontal_fil_glue_head(&mut self) -> bool {
+        self.is_next_expanded_token_in_set_of_primitives(&[
+            "hfil", "hfill", "hss", "hfilneg",
+        ])
+    }
+
+    pub fn is_vertical_fil_glue_head(&mut self) -> bool {
+        self.is_next_expanded_token_in_set_of_primitives(&[
+            "vfil", "vfill", "vss", "vfilneg",
+        ])
+    }
+
+    /// Parses one of the infinite glue shortcuts like \hfil or \vss, which
+    /// are the same as \hskip or \vskip with glue of 0pt plus 1fil (\hfil),
+    /// 0pt plus 1fill (\hfill), 0pt plus 1fil minus 1fil (\hss), and 0pt plus
+    /// -1fil (\hfilneg).
+    pub fn parse_fil_glue(&mut self) -> Glue {
+        let tok = self.lex_expanded_token().unwrap();
+        let is_prim = |parser: &Parser, name: &str| {
+            parser
+                .state
+                .is_token_equal_to_prim(&tok, &format!("h{}", name))
+                || parser
+                    .state
+                    .is_token_equal_to_prim(&tok, &format!("v{}", name))
+        };
+        let fil = |kind: FilKind, value: f64| {
+            SpringDimen::FilDimen(FilDimen::new(kind, value))
+        };
+        let zero = SpringDimen::Dimen(Dimen::zero());
+
+        let (stretch, shrink) = if is_prim(self, "fil") {
+            (fil(FilKind::Fil, 1.0), zero)
+        } else if is_prim(self, "fill") {
+            (fil(FilKind::Fill, 1.0), zero)
+        } else if is_prim(self, "ss") {
+            (fil(FilKind::Fil, 1.0), fil(FilKind::Fil, 1.0))
+        } else if is_prim(self, "filneg") {
+            (fil(FilKind::Fil, -1.0), zero)
+        } else {
+            panic!("Invalid fil glue head: {:?}", tok);
+        };
+
+        Glue {
+            space: Dimen::zero(),
+            stretch,
+            shrink,
+        }
+    }
+
     pub fn parse_glue(&mut self) -> Glue {
+        if self.is_glue_variable_head() {
+            let variable = self.parse_glue_variable();
+            return variable.get(self.state);
+        }
+
         let space = self.parse_dimen();
 
         let mut stretch = SpringDimen::Dimen(Dimen::zero());
@@ -24,6 +80,35 @@ impl<'a> Parser<'a> {
             shrink,
         }
     }
+
+    pub fn parse_mu_glue(&mut self) -> MuGlue {
+        if self.is_mu_glue_variable_head() {
+            let variable = self.parse_mu_glue_variable();
+            return variable.get(self.state);
+        }
+
+        let space = match self.parse_mu_spring_dimen(false) {
+            SpringDimen::Dimen(dimen) => dimen,
+            _ => unreachable!(),
+        };
+
+        let mut stretch = SpringDimen::Dimen(Dimen::zero());
+        let mut shrink = SpringDimen::Dimen(Dimen::zero());
+
+        if self.parse_optional_keyword_expanded("plus") {
+            stretch = self.parse_mu_spring_dimen(true);
+        }
+
+        if self.parse_optional_keyword_expanded("minus") {
+            shrink = self.parse_mu_spring_dimen(true);
+        }
+
+        MuGlue {
+            space,
+            stretch,
+            shrink,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -35,6 +120,35 @@ mod tests {
     use crate::testing::with_parser;
     use crate::token::Token;
 
+    #[test]
+    fn it_parses_fil_glue_shortcuts() {
+        with_parser(
+            &[
+                r"\hfil\hfill\hss\hfilneg%",
+                r"\vfil\vfill\vss\vfilneg%",
+                r"0pt plus 1fil%",
+                r"0pt plus 1fill%",
+                r"0pt plus 1fil minus 1fil%",
+                r"0pt plus-1fil%",
+            ],
+            |parser| {
+                let mut fil_glues = Vec::new();
+                while parser.is_horizontal_fil_glue_head() {
+                    fil_glues.push(parser.parse_fil_glue());
+                }
+                while parser.is_vertical_fil_glue_head() {
+                    fil_glues.push(parser.parse_fil_glue());
+                }
+                assert_eq!(fil_glues.len(), 8);
+
+                let explicit_glues: Vec<Glue> =
+                    (0..4).map(|_| parser.parse_glue()).collect();
+                assert_eq!(&fil_glues[..4], &explicit_glues[..]);
+                assert_eq!(&fil_glues[4..], &explicit_glues[..]);
+            },
+        );
+    }
+
     #[test]
     fn it_parses_glue_without_stretch_and_shrink() {
         with_parser(&["1pt %"], |parser| {
@@ -2,10 +2,15 @@ use crate::boxes::{HorizontalBox, TeXBox};
 use crate::category::Category;
 use crate::dimension::{Dimen, SpringDimen, Unit};
 use crate::glue::Glue;
-use crate::list::HorizontalListElem;
+use crate::list::{
+    with_unbroken_discretionaries, HorizontalListElem, VerticalListElem,
+};
 use crate::math_list::MathStyle;
+use crate::parser::boxes::BoxLayout;
+use crate::parser::printing::printable_char;
 use crate::parser::Parser;
 use crate::token::Token;
+use crate::variable::{DimenParameter, GlueParameter, IntegerParameter};
 
 fn get_space_glue() -> Glue {
     Glue {
@@ -18,6 +23,10 @@ fn get_space_glue() -> Glue {
 enum ElemResult {
     Elem(HorizontalListElem),
     Elems(Vec<HorizontalListElem>),
+    // The start of a display, which interrupts the paragraph.
+    DisplayMath,
+    // An italic correction, which depends on the last elem in the list.
+    ItalicCorrection,
     Nothing,
 }
 
@@ -25,7 +34,10 @@ impl<'a> Parser<'a> {
     /// Returns if the next token is the start of something that only makes
     /// sense in vertical mode.
     fn is_vertical_material_head(&mut self) -> bool {
-        self.is_next_expanded_token_in_set_of_primitives(&["vskip", "end"])
+        self.is_next_expanded_token_in_set_of_primitives(&[
+            "vskip", "vfil", "vfill", "vss", "vfilneg", "end", "hrule",
+            "unvbox", "unvcopy", "halign",
+        ])
     }
 
     fn parse_horizontal_list_elem(
@@ -37,80 +49,87 @@ impl<'a> Parser<'a> {
         let expanded_renamed_token = self.replace_renamed_token(expanded_token);
         match expanded_renamed_token {
             None => ElemResult::Nothing,
-            Some(Token::Char(ch, cat)) => match cat {
-                Category::Letter => {
-                    self.lex_expanded_token();
-                    ElemResult::Elem(HorizontalListElem::Char {
-                        chr: ch,
-                        font: self.state.get_current_font(),
-                    })
-                }
-                Category::Other => {
-                    self.lex_expanded_token();
-                    ElemResult::Elem(HorizontalListElem::Char {
-                        chr: ch,
-                        font: self.state.get_current_font(),
-                    })
-                }
-                Category::Space => {
-                    self.lex_expanded_token();
-                    ElemResult::Elem(
-                        HorizontalListElem::HSkip(get_space_glue()),
-                    )
-                }
-                Category::BeginGroup => {
-                    self.lex_expanded_token();
-                    *group_level += 1;
-                    self.state.push_state();
-                    self.parse_horizontal_list_elem(group_level, restricted)
-                }
-                Category::EndGroup => {
-                    if *group_level == 0 {
-                        ElemResult::Nothing
-                    } else {
+            // Active characters which weren't expanded as macros have been
+            // \let to something else, so they're handled below.
+            Some(Token::Char(ch, cat)) if cat != Category::Active => {
+                match cat {
+                    Category::Letter => {
                         self.lex_expanded_token();
-                        *group_level -= 1;
-                        self.state.pop_state();
+                        let chr = self.get_font_position(ch);
+                        self.make_kerned_char_elem(chr)
+                    }
+                    Category::Other => {
+                        self.lex_expanded_token();
+                        let chr = self.get_font_position(ch);
+                        self.make_kerned_char_elem(chr)
+                    }
+                    Category::Space => {
+                        self.lex_expanded_token();
+                        ElemResult::Elem(HorizontalListElem::HSkip(
+                            get_space_glue(),
+                        ))
+                    }
+                    Category::BeginGroup => {
+                        self.lex_expanded_token();
+                        *group_level += 1;
+                        self.state.push_state();
                         self.parse_horizontal_list_elem(group_level, restricted)
                     }
-                }
-                Category::MathShift => {
-                    self.lex_expanded_token();
+                    Category::EndGroup => {
+                        if *group_level == 0 {
+                            ElemResult::Nothing
+                        } else {
+                            self.lex_expanded_token();
+                            *group_level -= 1;
+                            self.state.pop_state();
+                            self.parse_horizontal_list_elem(
+                                group_level,
+                                restricted,
+                            )
+                        }
+                    }
+                    Category::MathShift => {
+                        self.lex_expanded_token();
 
-                    let next_token = self.peek_unexpanded_token();
-                    let is_next_token_math_shift = match next_token {
-                        Some(Token::Char(_, Category::MathShift)) => true,
-                        _ => false,
-                    };
+                        let next_token = self.peek_unexpanded_token();
+                        let is_next_token_math_shift = match next_token {
+                            Some(Token::Char(_, Category::MathShift)) => true,
+                            _ => false,
+                        };
 
-                    if !restricted && is_next_token_math_shift {
-                        self.lex_unexpanded_token();
+                        if !restricted && is_next_token_math_shift {
+                            self.lex_unexpanded_token();
+                            ElemResult::DisplayMath
+                        } else {
+                            self.state.push_state();
 
-                        panic!("display math mode unimplemented!");
-                    } else {
-                        self.state.push_state();
+                            let math_list = self.parse_math_list();
+                            self.check_math_fonts();
+                            let horizontal_list = self
+                                .convert_math_list_to_horizontal_list(
+                                    math_list,
+                                    MathStyle::TextStyle,
+                                    true,
+                                );
 
-                        let math_list = self.parse_math_list();
-                        let horizontal_list = self
-                            .convert_math_list_to_horizontal_list(
-                                math_list,
-                                MathStyle::TextStyle,
-                            );
-
-                        match self.lex_expanded_token() {
-                            Some(Token::Char(_, Category::MathShift)) => {}
-                            rest => {
-                                panic!("Invalid end to math mode: {:?}", rest)
+                            match self.lex_expanded_token() {
+                                Some(Token::Char(_, Category::MathShift)) => {}
+                                rest => {
+                                    panic!(
+                                        "Invalid end to math mode: {:?}",
+                                        rest
+                                    )
+                                }
                             }
-                        }
 
-                        self.state.pop_state();
+                            self.state.pop_state();
 
-                        ElemResult::Elems(horizontal_list)
+                            ElemResult::Elems(horizontal_list)
+                        }
                     }
+                    _ => panic!("unimplemented"),
                 }
-                _ => panic!("unimplemented"),
-            },
+            }
             Some(ref tok) if self.state.is_token_equal_to_prim(tok, "par") => {
                 self.lex_expanded_token();
 
@@ -131,6 +150,47 @@ impl<'a> Parser<'a> {
                 let glue = self.parse_glue();
                 ElemResult::Elem(HorizontalListElem::HSkip(glue))
             }
+            Some(ref tok) if self.state.is_token_equal_to_prim(tok, "kern") => {
+                self.lex_expanded_token();
+                let kern = self.parse_dimen();
+                ElemResult::Elem(HorizontalListElem::Kern(kern))
+            }
+            Some(ref tok)
+                if self.state.is_token_equal_to_prim(tok, "penalty") =>
+            {
+                self.lex_expanded_token();
+                let penalty = self.parse_number();
+                ElemResult::Elem(HorizontalListElem::Penalty(penalty))
+            }
+            Some(ref tok) if self.state.is_token_equal_to_prim(tok, "char") => {
+                self.lex_expanded_token();
+                let chr = self.parse_8bit_number() as char;
+                self.make_char_elem(chr)
+            }
+            Some(ref tok)
+                if self.state.is_token_equal_to_prim(tok, "leavevmode") =>
+            {
+                // We're already in horizontal mode, so there's nothing to do.
+                self.lex_expanded_token();
+                self.parse_horizontal_list_elem(group_level, restricted)
+            }
+            Some(ref tok)
+                if self.state.is_token_equal_to_prim(tok, "accent") =>
+            {
+                ElemResult::Elems(self.parse_accent())
+            }
+            Some(ref tok) if self.state.is_token_equal_to_prim(tok, "/") => {
+                self.lex_expanded_token();
+                ElemResult::ItalicCorrection
+            }
+            Some(ref tok)
+                if self.state.is_token_equal_to_prim(tok, "discretionary") =>
+            {
+                ElemResult::Elem(self.parse_discretionary())
+            }
+            Some(ref tok) if self.state.is_token_equal_to_prim(tok, "-") => {
+                ElemResult::Elem(self.parse_discretionary_hyphen())
+            }
             Some(ref tok)
                 if self.state.is_token_equal_to_prim(tok, "raise") =>
             {
@@ -160,6 +220,24 @@ impl<'a> Parser<'a> {
                 if self.is_assignment_head() {
                     self.parse_assignment();
                     self.parse_horizontal_list_elem(group_level, restricted)
+                } else if self.is_shipout_head() {
+                    self.parse_shipout();
+                    self.parse_horizontal_list_elem(group_level, restricted)
+                } else if self.is_show_head() {
+                    self.parse_show();
+                    self.parse_horizontal_list_elem(group_level, restricted)
+                } else if self.is_vrule_head() {
+                    ElemResult::Elem(self.parse_vrule())
+                } else if self.is_leaders_head() {
+                    ElemResult::Elem(self.parse_horizontal_leaders())
+                } else if self.is_hrule_head() && restricted {
+                    panic!(r"You can't use `\hrule' here except with leaders");
+                } else if self.is_horizontal_fil_glue_head() {
+                    ElemResult::Elem(HorizontalListElem::HSkip(
+                        self.parse_fil_glue(),
+                    ))
+                } else if self.is_unhbox_head() {
+                    ElemResult::Elems(self.parse_unhbox())
                 } else if self.is_box_head() {
                     let maybe_tex_box = self.parse_box();
                     if let Some(tex_box) = maybe_tex_box {
@@ -185,12 +263,487 @@ impl<'a> Parser<'a> {
         }
     }
 
+    // Finds the position in the font that an input character should be
+    // typeset with.
+    fn get_font_position(&mut self, chr: char) -> char {
+        match self.state.get_font_position(chr) {
+            Some(position) => position as char,
+            None => panic!(
+                "No font position for character {} (U+{:04X})",
+                chr, chr as u32
+            ),
+        }
+    }
+
+    // Returns whether the given character exists in the current font. If it
+    // doesn't, a "Missing character" message is logged.
+    fn check_char_exists(&mut self, chr: char) -> bool {
+        let font = self.state.get_current_font();
+        let exists = match self.state.get_metrics_for_font(&font) {
+            Some(metrics) => metrics.exists(chr),
+            None => false,
+        };
+
+        if !exists {
+            self.add_message(format!(
+                "Missing character: There is no {} in font {}!",
+                printable_char(chr),
+                font.font_name
+            ));
+        }
+
+        exists
+    }
+
+    // Makes a character in the current font, or nothing if the font doesn't
+    // have that character.
+    fn make_char_elem(&mut self, chr: char) -> ElemResult {
+        if self.check_char_exists(chr) {
+            ElemResult::Elem(HorizontalListElem::Char {
+                chr,
+                font: self.state.get_current_font(),
+            })
+        } else {
+            ElemResult::Elems(Vec::new())
+        }
+    }
+
+    // Makes a character in the current font like `make_char_elem()`, followed
+    // by the font kern between it and the next character if the next token is
+    // also a character. If the character forms a ligature with the next
+    // character, both are replaced by the ligature, which can then form more
+    // ligatures or kerns with the characters after it. Anything else in
+    // between, like a group boundary, stops ligatures and kerns.
+    fn make_kerned_char_elem(&mut self, chr: char) -> ElemResult {
+        if !self.check_char_exists(chr) {
+            return ElemResult::Elems(Vec::new());
+        }
+
+        let mut chr = chr;
+        while let Some(ligature) = self.get_font_ligature_after(chr) {
+            self.lex_expanded_token();
+            chr = ligature;
+        }
+
+        let char_elem = HorizontalListElem::Char {
+            chr,
+            font: self.state.get_current_font(),
+        };
+        match self.get_font_kern_after(chr) {
+            Some(kern) => ElemResult::Elems(vec![
+                char_elem,
+                HorizontalListElem::Kern(kern),
+            ]),
+            None => ElemResult::Elem(char_elem),
+        }
+    }
+
+    // Looks ahead at the next token, and returns the position of the
+    // character it is in the current font, if it's a character that exists
+    // in the font.
+    fn peek_next_font_char(&mut self) -> Option<char> {
+        let next_token = self.peek_expanded_token();
+        let next_chr = match self.replace_renamed_token(next_token) {
+            Some(Token::Char(next, Category::Letter))
+            | Some(Token::Char(next, Category::Other)) => {
+                self.state.get_font_position(next)? as char
+            }
+            _ => return None,
+        };
+
+        let font = self.state.get_current_font();
+        let metrics = self.state.get_metrics_for_font(&font)?;
+        if !metrics.exists(next_chr) {
+            return None;
+        }
+        Some(next_chr)
+    }
+
+    // Looks ahead at the next token, and returns the kern that the current
+    // font's lig/kern program puts between `chr` and it, if any.
+    fn get_font_kern_after(&mut self, chr: char) -> Option<Dimen> {
+        let next_chr = self.peek_next_font_char()?;
+
+        let font = self.state.get_current_font();
+        let metrics = self.state.get_metrics_for_font(&font)?;
+        metrics.get_kern(chr, next_chr)
+    }
+
+    // Looks ahead at the next token, and returns the ligature that the
+    // current font's lig/kern program replaces `chr` and it with, if any.
+    fn get_font_ligature_after(&mut self, chr: char) -> Option<char> {
+        let next_chr = self.peek_next_font_char()?;
+
+        let font = self.state.get_current_font();
+        let metrics = self.state.get_metrics_for_font(&font)?;
+        metrics.get_ligature(chr, next_chr)
+    }
+
+    // Parses a \discretionary{<pre-break>}{<post-break>}{<no-break>}.
+    fn parse_discretionary(&mut self) -> HorizontalListElem {
+        self.lex_expanded_token();
+
+        let pre_break = self.parse_discretionary_list();
+        let post_break = self.parse_discretionary_list();
+        let no_break = self.parse_discretionary_list();
+
+        HorizontalListElem::Discretionary {
+            pre_break,
+            post_break,
+            no_break,
+        }
+    }
+
+    // Parses one of the braced lists of a \discretionary. Like in TeX, these
+    // can only contain characters, boxes, rules, and kerns.
+    fn parse_discretionary_list(&mut self) -> Vec<HorizontalListElem> {
+        match self.lex_expanded_token() {
+            Some(Token::Char(_, Category::BeginGroup)) => (),
+            _ => panic!("Expected { when parsing discretionary"),
+        }
+
+        self.state.push_state();
+        let list = self.parse_horizontal_list(true, false);
+        self.state.pop_state();
+
+        match self.lex_expanded_token() {
+            Some(Token::Char(_, Category::EndGroup)) => (),
+            _ => panic!("Expected } when parsing discretionary"),
+        }
+
+        for elem in &list {
+            match elem {
+                HorizontalListElem::Char { .. }
+                | HorizontalListElem::Box { .. }
+                | HorizontalListElem::Rule { .. }
+                | HorizontalListElem::Kern(_) => {}
+                _ => panic!("Improper discretionary list"),
+            }
+        }
+
+        list
+    }
+
+    // Parses a \-, which is a discretionary that adds the current font's
+    // \hyphenchar if the line is broken there.
+    fn parse_discretionary_hyphen(&mut self) -> HorizontalListElem {
+        self.lex_expanded_token();
+
+        let font = self.state.get_current_font();
+        let hyphen_char = self.state.get_hyphen_char(&font);
+        let pre_break = if (0..256).contains(&hyphen_char)
+            && self.check_char_exists(hyphen_char as u8 as char)
+        {
+            vec![HorizontalListElem::Char {
+                chr: hyphen_char as u8 as char,
+                font,
+            }]
+        } else {
+            vec![]
+        };
+
+        HorizontalListElem::Discretionary {
+            pre_break,
+            post_break: vec![],
+            no_break: vec![],
+        }
+    }
+
+    // Parses an \accent<number> and the character after it (if there is one),
+    // and returns the list of elements that position the accent over the
+    // character.
+    fn parse_accent(&mut self) -> Vec<HorizontalListElem> {
+        self.lex_expanded_token();
+        let accent_chr = self.parse_8bit_number() as char;
+        if !self.check_char_exists(accent_chr) {
+            return Vec::new();
+        }
+        let accent_font = self.state.get_current_font();
+
+        let (x_height, accent_slant, accent_width) = {
+            let metrics =
+                self.state.get_metrics_for_font(&accent_font).unwrap();
+            (
+                metrics.get_font_parameter(5),
+                metrics.get_font_parameter(1),
+                metrics.get_width(accent_chr),
+            )
+        };
+
+        let accent = HorizontalListElem::Char {
+            chr: accent_chr,
+            font: accent_font,
+        };
+
+        // Assignments (like font changes) are allowed between the accent and
+        // the character being accented.
+        while self.is_assignment_head() {
+            self.parse_assignment();
+        }
+
+        let expanded_token = self.peek_expanded_token();
+        let base_chr = match self.replace_renamed_token(expanded_token) {
+            Some(Token::Char(ch, Category::Letter))
+            | Some(Token::Char(ch, Category::Other)) => {
+                self.lex_expanded_token();
+                self.get_font_position(ch)
+            }
+            Some(ref tok) if self.state.is_token_equal_to_prim(tok, "char") => {
+                self.lex_expanded_token();
+                self.parse_8bit_number() as char
+            }
+            // If there's no character after the accent, the accent is just
+            // typeset on its own.
+            _ => return vec![accent],
+        };
+        if !self.check_char_exists(base_chr) {
+            return vec![accent];
+        }
+        let base_font = self.state.get_current_font();
+
+        let (base_slant, base_width, base_height) = {
+            let metrics = self.state.get_metrics_for_font(&base_font).unwrap();
+            (
+                metrics.get_font_parameter(1),
+                metrics.get_width(base_chr),
+                metrics.get_height(base_chr),
+            )
+        };
+
+        // The accent is designed to sit on a character with height equal to
+        // the x-height, so it needs to be moved up or down if the base
+        // character is taller or shorter than that.
+        let accent = if base_height != x_height {
+            let accent_box = self
+                .combine_horizontal_list_into_horizontal_box_with_layout(
+                    vec![accent],
+                    &BoxLayout::Natural,
+                );
+            HorizontalListElem::Box {
+                tex_box: TeXBox::HorizontalBox(accent_box),
+                shift: base_height - x_height,
+            }
+        } else {
+            accent
+        };
+
+        // Center the accent over the character, taking the slants of the
+        // fonts into account.
+        let slant_factor =
+            |slant: Dimen| slant.as_scaled_points() as f64 / 65536.0;
+        let delta = Dimen::from_scaled_points(
+            ((base_width - accent_width).as_scaled_points() as f64 / 2.0
+                + base_height.as_scaled_points() as f64
+                    * slant_factor(base_slant)
+                - x_height.as_scaled_points() as f64
+                    * slant_factor(accent_slant))
+            .round() as i32,
+        );
+
+        vec![
+            HorizontalListElem::Kern(delta),
+            accent,
+            HorizontalListElem::Kern(Dimen::zero() - accent_width - delta),
+            HorizontalListElem::Char {
+                chr: base_chr,
+                font: base_font,
+            },
+        ]
+    }
+
+    // Computes the value of \predisplaysize from the line before a display,
+    // which is the position of the right edge of the last box or character on
+    // the line plus two quads in the current font. The glue in the line is
+    // measured the way it was set when the line was packaged.
+    fn compute_pre_display_size(&self, line: Option<&HorizontalBox>) -> Dimen {
+        let font = self.state.get_current_font();
+        let quad = self
+            .state
+            .get_metrics_for_font(&font)
+            .map(|metrics| metrics.get_font_parameter(6))
+            .unwrap_or_else(Dimen::zero);
+
+        // If there's nothing on the line, the display is treated as if it
+        // follows a very short line.
+        let mut pre_display_size = Dimen::from_scaled_points(1 - (1 << 30));
+        let line = match line {
+            Some(line) => line,
+            None => return pre_display_size,
+        };
+
+        let mut position = quad * 2;
+        for elem in with_unbroken_discretionaries(&line.list).iter() {
+            let (_, _, width) = elem.get_size(self.state);
+            position = position
+                + match &line.glue_set_ratio {
+                    Some(ratio) => ratio.apply_to_glue(&width),
+                    None => width.space,
+                };
+
+            match elem {
+                HorizontalListElem::Char { .. }
+                | HorizontalListElem::CharRun { .. }
+                | HorizontalListElem::Box { .. }
+                | HorizontalListElem::Rule { .. }
+                | HorizontalListElem::RuleLeaders { .. } => {
+                    pre_display_size = position;
+                }
+                HorizontalListElem::HSkip(_)
+                | HorizontalListElem::Kern(_)
+                | HorizontalListElem::Penalty(_)
+                | HorizontalListElem::Discretionary { .. } => {}
+            }
+        }
+
+        pre_display_size
+    }
+
+    // Parses the contents of a display after the opening $$ up to and
+    // including the closing $$, and returns the material that should be added
+    // to the vertical list for it: the display packaged into a box that
+    // centers it in the line described by \displaywidth and \displayindent,
+    // along with the penalties and glue around it. `line` is the last line of
+    // the part of the paragraph that came before the display, if there is one.
+    fn parse_display_math(
+        &mut self,
+        line: Option<&HorizontalBox>,
+    ) -> Vec<VerticalListElem> {
+        let pre_display_size = self.compute_pre_display_size(line);
+
+        self.state.push_state();
+
+        // TODO(xymostech): These should take \parshape and \hangindent into
+        // account.
+        let hsize = self.state.get_dimen_parameter(&DimenParameter::HSize);
+        self.state.set_dimen_parameter(
+            false,
+            &DimenParameter::PreDisplaySize,
+            &pre_display_size,
+        );
+        self.state.set_dimen_parameter(
+            false,
+            &DimenParameter::DisplayWidth,
+            &hsize,
+        );
+        self.state.set_dimen_parameter(
+            false,
+            &DimenParameter::DisplayIndent,
+            &Dimen::zero(),
+        );
+
+        let math_list = self.parse_math_list();
+        self.check_math_fonts();
+        let horizontal_list = self.convert_math_list_to_horizontal_list(
+            math_list,
+            MathStyle::DisplayStyle,
+            false,
+        );
+
+        for _ in 0..2 {
+            match self.lex_expanded_token() {
+                Some(Token::Char(_, Category::MathShift)) => {}
+                rest => panic!("Display math should end with $$: {:?}", rest),
+            }
+        }
+
+        // The values of these might have been changed inside of the display,
+        // so we read them before leaving the group.
+        let display_width = self
+            .state
+            .get_dimen_parameter(&DimenParameter::DisplayWidth);
+        let display_indent = self
+            .state
+            .get_dimen_parameter(&DimenParameter::DisplayIndent);
+
+        self.state.pop_state();
+
+        // TODO(xymostech): Displays that are too wide should be shrunk to fit
+        // in the display width.
+        let display = TeXBox::HorizontalBox(
+            self.combine_horizontal_list_into_horizontal_box_with_layout(
+                horizontal_list,
+                &BoxLayout::Natural,
+            ),
+        );
+        let centering_shift = (display_width - *display.width()) / 2;
+
+        // If the line before the display ends before the display starts, we
+        // use the short skips.
+        let (above_skip, below_skip) =
+            if centering_shift + display_indent <= pre_display_size {
+                (
+                    GlueParameter::AboveDisplaySkip,
+                    GlueParameter::BelowDisplaySkip,
+                )
+            } else {
+                (
+                    GlueParameter::AboveDisplayShortSkip,
+                    GlueParameter::BelowDisplayShortSkip,
+                )
+            };
+
+        let display_box = TeXBox::HorizontalBox(
+            self.combine_horizontal_list_into_horizontal_box_with_layout(
+                vec![
+                    HorizontalListElem::Kern(display_indent + centering_shift),
+                    HorizontalListElem::Box {
+                        tex_box: display,
+                        shift: Dimen::zero(),
+                    },
+                ],
+                &BoxLayout::Natural,
+            ),
+        );
+
+        // TODO(xymostech): Displays with multiple lines should have
+        // \interdisplaylinepenalty between the lines.
+        vec![
+            VerticalListElem::Penalty(
+                self.state.get_integer_parameter(
+                    &IntegerParameter::PreDisplayPenalty,
+                ),
+            ),
+            VerticalListElem::VSkip(self.state.get_glue_parameter(&above_skip)),
+            VerticalListElem::Box {
+                tex_box: display_box,
+                shift: Dimen::zero(),
+            },
+            VerticalListElem::Penalty(
+                self.state.get_integer_parameter(
+                    &IntegerParameter::PostDisplayPenalty,
+                ),
+            ),
+            VerticalListElem::VSkip(self.state.get_glue_parameter(&below_skip)),
+        ]
+    }
+
+    fn current_horizontal_list(&mut self) -> &mut Vec<HorizontalListElem> {
+        self.current_horizontal_lists
+            .last_mut()
+            .expect("Not currently building a horizontal list")
+    }
+
+    /// Removes the last element of the current horizontal list for \lastbox
+    /// if it is a box.
+    pub fn remove_last_box_from_horizontal_list(&mut self) -> Option<TeXBox> {
+        let current_list = self.current_horizontal_list();
+        match current_list.pop() {
+            Some(HorizontalListElem::Box { tex_box, .. }) => Some(tex_box),
+            Some(elem) => {
+                current_list.push(elem);
+                None
+            }
+            None => None,
+        }
+    }
+
     pub fn parse_horizontal_list(
         &mut self,
         restricted: bool,
         indent: bool,
     ) -> Vec<HorizontalListElem> {
         let mut result = Vec::new();
+        self.current_vertical_lists.push(None);
 
         // Optionally add in indentation
         // TODO(xymostech): If I think about adding more flags for deciding the
@@ -207,6 +760,7 @@ impl<'a> Parser<'a> {
                 shift: Dimen::zero(),
             });
         }
+        self.current_horizontal_lists.push(result);
 
         let mut group_level = 0;
 
@@ -214,12 +768,47 @@ impl<'a> Parser<'a> {
             match self.parse_horizontal_list_elem(&mut group_level, restricted)
             {
                 ElemResult::Nothing => break,
-                ElemResult::Elem(elem) => result.push(elem),
-                ElemResult::Elems(mut elems) => result.append(&mut elems),
+                ElemResult::Elem(elem) => {
+                    self.current_horizontal_list().push(elem)
+                }
+                ElemResult::Elems(mut elems) => {
+                    self.current_horizontal_list().append(&mut elems)
+                }
+                ElemResult::DisplayMath => {
+                    // Displays interrupt the paragraph, so we break the part
+                    // of the paragraph before the display into lines, which
+                    // \predisplaysize is measured from, and then stash the
+                    // lines and the display for the vertical list to pick up.
+                    let list = std::mem::take(self.current_horizontal_list());
+                    let lines = if list.is_empty() {
+                        Vec::new()
+                    } else {
+                        self.break_paragraph_into_lines(list)
+                    };
+                    let display = self.parse_display_math(lines.last());
+                    self.interrupting_display = Some((lines, display));
+                    break;
+                }
+                ElemResult::ItalicCorrection => {
+                    // The italic correction is only added after characters,
+                    // and is a kern of the character's italic correction.
+                    if let Some(HorizontalListElem::Char { chr, font }) =
+                        self.current_horizontal_list().last().cloned()
+                    {
+                        let metrics =
+                            self.state.get_metrics_for_font(&font).unwrap();
+                        self.current_horizontal_list().push(
+                            HorizontalListElem::Kern(
+                                metrics.get_italic_correction(chr),
+                            ),
+                        );
+                    }
+                }
             }
         }
 
-        result
+        self.current_vertical_lists.pop();
+        self.current_horizontal_lists.pop().unwrap()
     }
 }
 
@@ -227,6 +816,8 @@ impl<'a> Parser<'a> {
 mod tests {
     use super::*;
 
+    use std::rc::Rc;
+
     use crate::dimension::{FilDimen, FilKind};
     use crate::font::Font;
     use crate::math_code::MathCode;
@@ -307,6 +898,8 @@ mod tests {
                     chr: 'b',
                     font: CMR10.clone(),
                 },
+                // The b-c kern in cmr10
+                HorizontalListElem::Kern(Dimen::from_scaled_points(18205)),
                 HorizontalListElem::Char {
                     chr: 'c',
                     font: CMR10.clone(),
@@ -394,6 +987,99 @@ mod tests {
         );
     }
 
+    #[test]
+    fn it_parses_horizontal_kerns() {
+        assert_parses_to(
+            &[r"a\kern 1.5pt b%"],
+            &[
+                HorizontalListElem::Char {
+                    chr: 'a',
+                    font: CMR10.clone(),
+                },
+                HorizontalListElem::Kern(Dimen::from_unit(1.5, Unit::Point)),
+                HorizontalListElem::Char {
+                    chr: 'b',
+                    font: CMR10.clone(),
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn it_parses_fil_glue_shortcuts() {
+        let fil = SpringDimen::FilDimen(FilDimen::new(FilKind::Fil, 1.0));
+        assert_parses_to(
+            &[r"a\hss b%"],
+            &[
+                HorizontalListElem::Char {
+                    chr: 'a',
+                    font: CMR10.clone(),
+                },
+                HorizontalListElem::HSkip(Glue {
+                    space: Dimen::zero(),
+                    stretch: fil.clone(),
+                    shrink: fil,
+                }),
+                HorizontalListElem::Char {
+                    chr: 'b',
+                    font: CMR10.clone(),
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn it_parses_penalties() {
+        assert_parses_to(
+            &[r"a\penalty 100 b\penalty-50%"],
+            &[
+                HorizontalListElem::Char {
+                    chr: 'a',
+                    font: CMR10.clone(),
+                },
+                HorizontalListElem::Penalty(100),
+                HorizontalListElem::Char {
+                    chr: 'b',
+                    font: CMR10.clone(),
+                },
+                HorizontalListElem::Penalty(-50),
+            ],
+        );
+    }
+
+    #[test]
+    fn it_unpacks_horizontal_boxes() {
+        with_parser(&[r"ab%"], |typed_parser| {
+            let typed_list = typed_parser.parse_horizontal_list(true, false);
+
+            with_parser(
+                &[
+                    r"\setbox0=\hbox to 20pt{ab}%",
+                    r"\unhcopy0\unhbox0\unhbox0%",
+                ],
+                |parser| {
+                    parser.parse_assignment();
+                    let list = parser.parse_horizontal_list(true, false);
+
+                    // \unhcopy leaves the box in the register, so it can be
+                    // unpacked again, but then it's void.
+                    let mut expected_list = typed_list.clone();
+                    expected_list.extend(typed_list);
+                    assert_eq!(list, expected_list);
+                    assert_eq!(parser.state.get_box(0), None);
+                },
+            );
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Incompatible list can't be unboxed")]
+    fn it_fails_to_unpack_vertical_boxes_in_horizontal_lists() {
+        with_parser(&[r"\setbox0=\vbox{}\unhbox0%"], |parser| {
+            parser.parse_horizontal_list(true, false);
+        });
+    }
+
     #[test]
     fn it_parses_explicit_box_elems() {
         with_parser(&[r"a\hbox{a\hskip 2pt plus1filg}b%"], |parser| {
@@ -416,7 +1102,7 @@ mod tests {
                             height: metrics.get_height('a'),
                             depth: metrics.get_depth('g'),
 
-                            list: vec![
+                            list: Rc::new(vec![
                                 HorizontalListElem::Char {
                                     chr: 'a',
                                     font: CMR10.clone(),
@@ -432,7 +1118,7 @@ mod tests {
                                     chr: 'g',
                                     font: CMR10.clone(),
                                 },
-                            ],
+                            ]),
                             glue_set_ratio: None,
                         }),
                         shift: Dimen::zero()
@@ -497,6 +1183,8 @@ mod tests {
                     chr: 'b',
                     font: CMR10.clone(),
                 },
+                // The b-c kern in cmr10
+                HorizontalListElem::Kern(Dimen::from_scaled_points(18205)),
                 HorizontalListElem::Char {
                     chr: 'c',
                     font: CMR10.clone(),
@@ -609,19 +1297,214 @@ mod tests {
     }
 
     #[test]
-    #[should_panic(expected = "unimplemented")]
-    fn it_fails_parsing_mathchardefs() {
-        with_parser(&[r"\hello%", r"\hello%"], |parser| {
-            let tok = parser.lex_unexpanded_token().unwrap();
-            parser.state.set_math_chardef(
-                false,
-                &tok,
-                &MathCode::from_number(0x7161),
-            );
-
-            parser.parse_horizontal_list(false, false);
-        });
-    }
+    fn it_parses_empty_discretionaries() {
+        assert_parses_to(
+            &[r"a\discretionary{}{}{}b%"],
+            &[
+                HorizontalListElem::Char {
+                    chr: 'a',
+                    font: CMR10.clone(),
+                },
+                HorizontalListElem::Discretionary {
+                    pre_break: vec![],
+                    post_break: vec![],
+                    no_break: vec![],
+                },
+                HorizontalListElem::Char {
+                    chr: 'b',
+                    font: CMR10.clone(),
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn it_doesnt_add_anything_for_empty_discretionaries() {
+        with_parser(
+            &[
+                r"\hbox{unbreakable}%",
+                r"\def\d{\discretionary{}{}{}}%",
+                r"\hbox{unbre\d akabl\d e}%",
+            ],
+            |parser| {
+                let unbroken = parser.parse_box().unwrap();
+                parser.parse_assignment();
+                let with_breaks = parser.parse_box().unwrap();
+
+                assert_eq!(with_breaks.width(), unbroken.width());
+                assert_eq!(with_breaks.height(), unbroken.height());
+                assert_eq!(with_breaks.depth(), unbroken.depth());
+            },
+        );
+    }
+
+    #[test]
+    fn it_parses_non_empty_discretionaries() {
+        let char_elem = |chr| HorizontalListElem::Char {
+            chr,
+            font: CMR10.clone(),
+        };
+
+        assert_parses_to(
+            &[r"\discretionary{b-}{\kern1pt c}{x}%"],
+            &[HorizontalListElem::Discretionary {
+                pre_break: vec![char_elem('b'), char_elem('-')],
+                post_break: vec![
+                    HorizontalListElem::Kern(Dimen::from_unit(
+                        1.0,
+                        Unit::Point,
+                    )),
+                    char_elem('c'),
+                ],
+                no_break: vec![char_elem('x')],
+            }],
+        );
+    }
+
+    #[test]
+    fn it_sizes_discretionaries_like_their_no_break_material() {
+        with_parser(
+            &[r"\hbox{ab}%", r"\hbox{\discretionary{-}{}{ab}}%"],
+            |parser| {
+                let unbroken = parser.parse_box().unwrap();
+                let discretionary = parser.parse_box().unwrap();
+
+                assert_eq!(discretionary.width(), unbroken.width());
+                assert_eq!(discretionary.height(), unbroken.height());
+                assert_eq!(discretionary.depth(), unbroken.depth());
+            },
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Improper discretionary list")]
+    fn it_fails_on_glue_in_discretionaries() {
+        with_parser(&[r"\discretionary{}{}{a\hskip1pt b}%"], |parser| {
+            parser.parse_horizontal_list(true, false);
+        });
+    }
+
+    #[test]
+    fn it_parses_discretionary_hyphens() {
+        with_parser(&[r"a\-%", r"\hyphenchar\font=-1 b\-%"], |parser| {
+            assert_eq!(
+                parser.parse_horizontal_list(false, false),
+                &[
+                    HorizontalListElem::Char {
+                        chr: 'a',
+                        font: CMR10.clone(),
+                    },
+                    HorizontalListElem::Discretionary {
+                        pre_break: vec![HorizontalListElem::Char {
+                            chr: '-',
+                            font: CMR10.clone(),
+                        }],
+                        post_break: vec![],
+                        no_break: vec![],
+                    },
+                    HorizontalListElem::Char {
+                        chr: 'b',
+                        font: CMR10.clone(),
+                    },
+                    HorizontalListElem::Discretionary {
+                        pre_break: vec![],
+                        post_break: vec![],
+                        no_break: vec![],
+                    },
+                ]
+            );
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "You can't use `\\hrule' here")]
+    fn it_fails_on_hrules_in_restricted_horizontal_mode() {
+        with_parser(&[r"\hbox{\hrule}%"], |parser| {
+            parser.parse_box();
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "unimplemented")]
+    fn it_fails_parsing_mathchardefs() {
+        with_parser(&[r"\hello%", r"\hello%"], |parser| {
+            let tok = parser.lex_unexpanded_token().unwrap();
+            parser.state.set_math_chardef(
+                false,
+                &tok,
+                &MathCode::from_number(0x7161),
+            );
+
+            parser.parse_horizontal_list(false, false);
+        });
+    }
+
+    #[test]
+    fn it_uses_fonts_set_with_textfont_in_math() {
+        with_parser(
+            &[r"\font\x=cmr10\hbox{\x a}%", r"{\textfont1=\x $a$}$a$%"],
+            |parser| {
+                parser.parse_assignment();
+                let box_a = parser.parse_box().unwrap();
+
+                let list = parser.parse_horizontal_list(false, false);
+                assert_eq!(
+                    list[0],
+                    HorizontalListElem::Box {
+                        tex_box: box_a,
+                        shift: Dimen::zero()
+                    },
+                );
+                // Outside of the group, family 1 is back to cmmi10.
+                match &list[1] {
+                    HorizontalListElem::Box {
+                        tex_box: TeXBox::HorizontalBox(hbox),
+                        ..
+                    } => assert_eq!(
+                        &hbox.list[..],
+                        &[HorizontalListElem::Char {
+                            chr: 'a',
+                            font: Font {
+                                font_name: "cmmi10".to_string(),
+                                scale: Dimen::from_unit(10.0, Unit::Point),
+                            },
+                        }]
+                    ),
+                    elem => panic!("Expected a box: {:?}", elem),
+                }
+            },
+        );
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Math formula deleted: Insufficient symbol fonts"
+    )]
+    fn it_fails_on_math_with_insufficient_symbol_fonts() {
+        with_parser(&[r"\font\x=cmr10 \scriptfont2=\x $a$%"], |parser| {
+            parser.parse_horizontal_list(false, false);
+        });
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Math formula deleted: Insufficient extension fonts"
+    )]
+    fn it_fails_on_math_with_insufficient_extension_fonts() {
+        with_parser(&[r"\font\x=cmr10 \textfont3=\x $a$%"], |parser| {
+            parser.parse_horizontal_list(false, false);
+        });
+    }
+
+    #[test]
+    fn it_checks_math_fonts_at_the_end_of_math_mode() {
+        with_parser(
+            &[r"\font\x=cmr10 \font\y=cmsy10 \textfont2=\x $a\textfont2=\y$%"],
+            |parser| {
+                parser.parse_horizontal_list(false, false);
+            },
+        );
+    }
 
     #[test]
     fn it_parses_math_shifts() {
@@ -764,6 +1647,34 @@ mod tests {
         );
     }
 
+    #[test]
+    fn it_parses_internal_dimens_in_raise_and_lower() {
+        with_parser(
+            &[
+                r"\hbox{a}%",
+                r"\dimen0=3pt%",
+                r"\raise\dimen0\hbox{a}\lower\dimen0\hbox{a}%",
+            ],
+            |parser| {
+                let abox = parser.parse_box().unwrap();
+
+                assert_eq!(
+                    parser.parse_horizontal_list(false, false),
+                    &[
+                        HorizontalListElem::Box {
+                            tex_box: abox.clone(),
+                            shift: Dimen::from_unit(3.0, Unit::Point),
+                        },
+                        HorizontalListElem::Box {
+                            tex_box: abox,
+                            shift: Dimen::from_unit(-3.0, Unit::Point),
+                        },
+                    ]
+                );
+            },
+        );
+    }
+
     #[test]
     fn it_ignores_empty_boxes_in_raise_and_lower() {
         with_parser(
@@ -795,4 +1706,460 @@ mod tests {
             },
         );
     }
+
+    #[test]
+    fn it_raises_accents_over_tall_characters() {
+        with_parser(&[r"\accent18 A\accent18 a%"], |parser| {
+            let list = parser.parse_horizontal_list(false, false);
+            let metrics = parser.state.get_metrics_for_font(&CMR10).unwrap();
+            let x_height = metrics.get_font_parameter(5);
+
+            assert_eq!(list.len(), 8);
+
+            // The accent over the A is moved up by the difference between the
+            // height of the A and the x-height.
+            match &list[1] {
+                HorizontalListElem::Box { shift, .. } => {
+                    assert_eq!(*shift, metrics.get_height('A') - x_height);
+                    assert!(*shift > Dimen::zero());
+                }
+                elem => panic!("Expected raised accent, found {:?}", elem),
+            }
+
+            // The a is exactly as tall as the x-height, so its accent doesn't
+            // need to move.
+            assert_eq!(metrics.get_height('a'), x_height);
+            assert_eq!(
+                list[5],
+                HorizontalListElem::Char {
+                    chr: 18 as char,
+                    font: CMR10.clone(),
+                }
+            );
+
+            // The kerns around the accent cancel out the accent's width, so
+            // the accented character takes up just the character's width.
+            let total_width = list[4..]
+                .iter()
+                .map(|elem| elem.get_size(parser.state).2.space)
+                .fold(Dimen::zero(), |a, b| a + b);
+            assert_eq!(total_width, metrics.get_width('a'));
+        });
+    }
+
+    #[test]
+    fn it_centers_accents_over_characters() {
+        with_parser(&[r"\accent19 o%"], |parser| {
+            let list = parser.parse_horizontal_list(false, false);
+            let metrics = parser.state.get_metrics_for_font(&CMR10).unwrap();
+            let delta = Dimen::from_scaled_points(
+                ((metrics.get_width('o') - metrics.get_width(19 as char))
+                    .as_scaled_points() as f64
+                    / 2.0)
+                    .round() as i32,
+            );
+
+            assert_eq!(
+                list,
+                &[
+                    HorizontalListElem::Kern(delta),
+                    HorizontalListElem::Char {
+                        chr: 19 as char,
+                        font: CMR10.clone(),
+                    },
+                    HorizontalListElem::Kern(
+                        Dimen::zero() - metrics.get_width(19 as char) - delta
+                    ),
+                    HorizontalListElem::Char {
+                        chr: 'o',
+                        font: CMR10.clone(),
+                    },
+                ]
+            );
+
+            let accented_box = parser
+                .combine_horizontal_list_into_horizontal_box_with_layout(
+                    list,
+                    &BoxLayout::Natural,
+                );
+            assert_eq!(accented_box.width, metrics.get_width('o'));
+        });
+    }
+
+    #[test]
+    fn it_parses_chars_by_number() {
+        with_parser(&[r"\char97\char`b%"], |parser| {
+            assert_eq!(
+                parser.parse_horizontal_list(false, false),
+                &[
+                    HorizontalListElem::Char {
+                        chr: 'a',
+                        font: CMR10.clone(),
+                    },
+                    HorizontalListElem::Char {
+                        chr: 'b',
+                        font: CMR10.clone(),
+                    },
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn it_adds_font_kerns_between_characters() {
+        with_parser(&[r"AV\hbox to 50pt{AV\hfil}%"], |parser| {
+            let list = parser.parse_horizontal_list(false, false);
+            let metrics = parser.state.get_metrics_for_font(&CMR10).unwrap();
+            let kern = metrics.get_kern('A', 'V').unwrap();
+            assert!(kern < Dimen::zero());
+
+            assert_eq!(
+                &list[..3],
+                &[
+                    HorizontalListElem::Char {
+                        chr: 'A',
+                        font: CMR10.clone(),
+                    },
+                    HorizontalListElem::Kern(kern),
+                    HorizontalListElem::Char {
+                        chr: 'V',
+                        font: CMR10.clone(),
+                    },
+                ]
+            );
+
+            // Font kerns stay the same size when the glue around them is set.
+            match &list[3] {
+                HorizontalListElem::Box {
+                    tex_box: TeXBox::HorizontalBox(hbox),
+                    ..
+                } => {
+                    assert!(hbox.glue_set_ratio.is_some());
+                    assert_eq!(hbox.list[1], HorizontalListElem::Kern(kern));
+                }
+                other => panic!("Expected an hbox, found {:?}", other),
+            }
+        });
+    }
+
+    #[test]
+    fn it_adds_italic_corrections_after_characters() {
+        with_parser(&[r"f\/\/\kern 1pt\/%"], |parser| {
+            let metrics = parser.state.get_metrics_for_font(&CMR10).unwrap();
+            let italic_correction = metrics.get_italic_correction('f');
+            assert!(italic_correction > Dimen::zero());
+
+            // Italic corrections after anything other than a character don't
+            // add anything.
+            assert_eq!(
+                parser.parse_horizontal_list(false, false),
+                &[
+                    HorizontalListElem::Char {
+                        chr: 'f',
+                        font: CMR10.clone(),
+                    },
+                    HorizontalListElem::Kern(italic_correction),
+                    HorizontalListElem::Kern(Dimen::from_unit(
+                        1.0,
+                        Unit::Point
+                    )),
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn it_replaces_characters_with_ligatures() {
+        with_parser(&[r"ffi f{}i%"], |parser| {
+            assert_eq!(
+                parser.parse_horizontal_list(false, false),
+                &[
+                    // cmr10 has the ffi ligature at position '016.
+                    HorizontalListElem::Char {
+                        chr: '\x0e',
+                        font: CMR10.clone(),
+                    },
+                    HorizontalListElem::HSkip(get_space_glue()),
+                    // Ligatures aren't formed across group boundaries.
+                    HorizontalListElem::Char {
+                        chr: 'f',
+                        font: CMR10.clone(),
+                    },
+                    HorizontalListElem::Char {
+                        chr: 'i',
+                        font: CMR10.clone(),
+                    },
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn it_parses_char_codes_in_any_number_form() {
+        with_parser(
+            &[r#"\char65\char`A\char`\A\char"41\char'101%"#],
+            |parser| {
+                let a = HorizontalListElem::Char {
+                    chr: 'A',
+                    font: CMR10.clone(),
+                };
+                assert_eq!(
+                    parser.parse_horizontal_list(false, false),
+                    &[a.clone(), a.clone(), a.clone(), a.clone(), a]
+                );
+            },
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid 8-bit number: 256")]
+    fn it_fails_on_char_codes_outside_of_fonts() {
+        with_parser(&[r"\char256%"], |parser| {
+            parser.parse_horizontal_list(false, false);
+        });
+    }
+
+    #[test]
+    fn it_drops_characters_missing_from_the_font() {
+        with_parser(&[r"\char200%"], |parser| {
+            assert_eq!(parser.parse_horizontal_list(false, false), &[]);
+            assert_eq!(
+                parser.take_messages(),
+                vec!["Missing character: There is no ^^c8 in font cmr10!"]
+            );
+        });
+    }
+
+    #[test]
+    fn it_typesets_unicode_characters_at_their_mapped_positions() {
+        with_parser(&["\u{00e6}\u{0131}%"], |parser| {
+            parser.state.set_font_position(false, '\u{00e6}', 26);
+            parser.state.set_font_position(false, '\u{0131}', 16);
+
+            assert_eq!(
+                parser.parse_horizontal_list(false, false),
+                &[
+                    HorizontalListElem::Char {
+                        chr: 26 as char,
+                        font: CMR10.clone(),
+                    },
+                    HorizontalListElem::Char {
+                        chr: 16 as char,
+                        font: CMR10.clone(),
+                    },
+                ]
+            );
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "No font position for character")]
+    fn it_fails_on_unmapped_unicode_characters() {
+        with_parser(&["\u{0131}%"], |parser| {
+            parser.parse_horizontal_list(false, false);
+        });
+    }
+
+    #[test]
+    fn it_computes_predisplaysize_from_the_line_before_a_display() {
+        with_parser(
+            &[
+                r"\hsize=100pt%",
+                r"a\hskip 5pt$$\global\dimen0=\predisplaysize$$%",
+            ],
+            |parser| {
+                parser.parse_assignment();
+                parser.parse_horizontal_list(false, false);
+
+                let metrics =
+                    parser.state.get_metrics_for_font(&CMR10).unwrap();
+                // The glue after the "a" doesn't count towards the size.
+                assert_eq!(
+                    parser.state.get_dimen_register(0),
+                    metrics.get_width('a') + metrics.get_font_parameter(6) * 2
+                );
+
+                // The empty display is centered in the 100pt line.
+                let (_, display) = parser.interrupting_display.take().unwrap();
+                match &display[2] {
+                    VerticalListElem::Box {
+                        tex_box: TeXBox::HorizontalBox(hbox),
+                        ..
+                    } => {
+                        assert_eq!(
+                            hbox.list[0],
+                            HorizontalListElem::Kern(Dimen::from_unit(
+                                50.0,
+                                Unit::Point
+                            ))
+                        )
+                    }
+                    _ => panic!("Display should be an hbox"),
+                }
+            },
+        );
+    }
+
+    #[test]
+    fn it_places_displays_using_displaywidth_and_displayindent() {
+        with_parser(
+            &[
+                r"$$\displaywidth=20pt \displayindent=5pt \global\dimen0=\predisplaysize$$%",
+            ],
+            |parser| {
+                parser.parse_horizontal_list(false, false);
+
+                // There's nothing before the display, so \predisplaysize is
+                // as small as possible.
+                assert_eq!(
+                    parser.state.get_dimen_register(0),
+                    Dimen::from_scaled_points(1 - (1 << 30))
+                );
+
+                let (_, display) = parser.interrupting_display.take().unwrap();
+                match &display[2] {
+                    VerticalListElem::Box {
+                        tex_box: TeXBox::HorizontalBox(hbox),
+                        ..
+                    } => {
+                        assert_eq!(
+                            hbox.list[0],
+                            HorizontalListElem::Kern(Dimen::from_unit(
+                                15.0,
+                                Unit::Point
+                            ))
+                        )
+                    }
+                    _ => panic!("Display should be an hbox"),
+                }
+            },
+        );
+    }
+
+    #[test]
+    fn it_uses_short_display_skips_after_short_lines() {
+        let get_display_skips = |hsize: &str| {
+            let mut skips = Vec::new();
+            with_parser(
+                &[
+                    hsize,
+                    r"\abovedisplayskip=1pt%",
+                    r"\belowdisplayskip=2pt%",
+                    r"\abovedisplayshortskip=3pt%",
+                    r"\belowdisplayshortskip=4pt%",
+                    r"a$$$$%",
+                ],
+                |parser| {
+                    for _ in 0..5 {
+                        parser.parse_assignment();
+                    }
+                    parser.parse_horizontal_list(false, false);
+
+                    let (_, display) =
+                        parser.interrupting_display.take().unwrap();
+                    skips.push(display[1].clone());
+                    skips.push(display[4].clone());
+                },
+            );
+            skips
+        };
+
+        // The "a" ends well before the middle of a 100pt line.
+        assert_eq!(
+            get_display_skips(r"\hsize=100pt%"),
+            vec![
+                VerticalListElem::VSkip(Glue::from_dimen(Dimen::from_unit(
+                    3.0,
+                    Unit::Point
+                ))),
+                VerticalListElem::VSkip(Glue::from_dimen(Dimen::from_unit(
+                    4.0,
+                    Unit::Point
+                ))),
+            ]
+        );
+
+        // The "a" goes past the middle of a 10pt line.
+        assert_eq!(
+            get_display_skips(r"\hsize=10pt%"),
+            vec![
+                VerticalListElem::VSkip(Glue::from_dimen(Dimen::from_unit(
+                    1.0,
+                    Unit::Point
+                ))),
+                VerticalListElem::VSkip(Glue::from_dimen(Dimen::from_unit(
+                    2.0,
+                    Unit::Point
+                ))),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_measures_predisplaysize_from_the_last_line_of_the_paragraph() {
+        with_parser(
+            &[
+                r"\hsize=100pt%",
+                r"\abovedisplayskip=1pt%",
+                r"\belowdisplayskip=2pt%",
+                r"\abovedisplayshortskip=3pt%",
+                r"\belowdisplayshortskip=4pt%",
+                r"aaaaaaaaaaaaaaaaaaaa\penalty-10000 a%",
+                r"$$\global\dimen0=\predisplaysize$$%",
+            ],
+            |parser| {
+                for _ in 0..5 {
+                    parser.parse_assignment();
+                }
+                parser.parse_horizontal_list(false, false);
+
+                // Only the "a" on the last line counts, even though the whole
+                // paragraph is wider than the line.
+                let metrics =
+                    parser.state.get_metrics_for_font(&CMR10).unwrap();
+                assert_eq!(
+                    parser.state.get_dimen_register(0),
+                    metrics.get_width('a') + metrics.get_font_parameter(6) * 2
+                );
+
+                let (lines, display) =
+                    parser.interrupting_display.take().unwrap();
+                assert_eq!(lines.len(), 2);
+                assert_eq!(
+                    display[1],
+                    VerticalListElem::VSkip(Glue::from_dimen(
+                        Dimen::from_unit(3.0, Unit::Point)
+                    ))
+                );
+                assert_eq!(
+                    display[4],
+                    VerticalListElem::VSkip(Glue::from_dimen(
+                        Dimen::from_unit(4.0, Unit::Point)
+                    ))
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn it_removes_the_last_box_with_lastbox() {
+        with_parser(
+            &[r"\hbox to 1pt{}\setbox0=\lastbox%", r"a\setbox1=\lastbox%"],
+            |parser| {
+                assert_eq!(
+                    parser.parse_horizontal_list(true, false),
+                    vec![HorizontalListElem::Char {
+                        chr: 'a',
+                        font: CMR10.clone(),
+                    }]
+                );
+
+                assert_eq!(
+                    parser.state.get_box(0).unwrap().width(),
+                    &Dimen::from_unit(1.0, Unit::Point)
+                );
+                assert_eq!(parser.state.get_box(1), None);
+            },
+        );
+    }
 }
@@ -0,0 +1,107 @@
+use crate::category::Category;
+use crate::parser::Parser;
+use crate::token::Token;
+
+impl<'a> Parser<'a> {
+    pub fn is_patterns_assignment_head(&mut self) -> bool {
+        self.is_next_expanded_token_in_set_of_primitives(&["patterns"])
+    }
+
+    // Parses \patterns{...}, which is a list of space-separated patterns like
+    // "hy3ph".
+    pub fn parse_patterns_assignment(&mut self) {
+        self.lex_expanded_token();
+
+        self.parse_filler_expanded();
+        match self.lex_expanded_token() {
+            Some(Token::Char(_, Category::BeginGroup)) => (),
+            tok => panic!("Missing {{ inserted, found {:?}", tok),
+        }
+
+        let mut pattern = String::new();
+        loop {
+            match self.lex_expanded_token() {
+                Some(Token::Char(ch, Category::Letter))
+                | Some(Token::Char(ch, Category::Other)) => {
+                    pattern.extend(ch.to_lowercase());
+                }
+                Some(Token::Char(_, Category::Space)) => {
+                    if !pattern.is_empty() {
+                        self.state.add_hyphenation_pattern(&pattern);
+                        pattern.clear();
+                    }
+                }
+                Some(Token::Char(_, Category::EndGroup)) => {
+                    if !pattern.is_empty() {
+                        self.state.add_hyphenation_pattern(&pattern);
+                    }
+                    break;
+                }
+                tok => panic!("Bad \\patterns, found {:?}", tok),
+            }
+        }
+    }
+
+    // Parses the text after a \showhyphens and returns the words in it with
+    // hyphens at each of the places they could be hyphenated, like
+    // "hy-phen-ation".
+    pub fn parse_show_hyphens(&mut self) -> String {
+        let text = self.parse_general_text();
+
+        let mut words: Vec<Vec<char>> = vec![Vec::new()];
+        for token in text {
+            match token {
+                Token::Char(ch, Category::Letter) => {
+                    words.last_mut().unwrap().push(ch)
+                }
+                _ => {
+                    if !words.last().unwrap().is_empty() {
+                        words.push(Vec::new());
+                    }
+                }
+            }
+        }
+
+        words
+            .iter()
+            .filter(|word| !word.is_empty())
+            .map(|word| {
+                let hyphens = self.state.hyphenate(word);
+
+                let mut result = String::new();
+                for (i, ch) in word.iter().enumerate() {
+                    if hyphens.contains(&i) {
+                        result.push('-');
+                    }
+                    result.push(*ch);
+                }
+                result
+            })
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::with_parser;
+
+    #[test]
+    fn it_shows_hyphenation_points() {
+        with_parser(
+            &[
+                r"\patterns{hy3ph he2n hena4 hen5at 1na n2at 1tio 2io o2n}%",
+                r"\showhyphens{hyphenation nation}%",
+            ],
+            |parser| {
+                parser.parse_assignment();
+                parser.parse_show();
+
+                assert_eq!(
+                    parser.take_messages(),
+                    vec!["hy-phen-ation na-tion"]
+                );
+            },
+        );
+    }
+}
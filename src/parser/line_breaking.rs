@@ -0,0 +1,604 @@
+use crate::boxes::{badness, HorizontalBox, INF_BAD};
+use crate::dimension::{Dimen, FilDimen, FilKind, SpringDimen};
+use crate::glue::Glue;
+use crate::list::HorizontalListElem;
+use crate::parser::boxes::BoxLayout;
+use crate::parser::Parser;
+use crate::variable::{DimenParameter, GlueParameter, IntegerParameter};
+
+// Penalties at least this large prevent breaks entirely
+const INF_PENALTY: i32 = 10000;
+// Penalties at most this large force a break
+const EJECT_PENALTY: i32 = -10000;
+// The demerits of a break that can't be used at all
+const AWFUL_BAD: i64 = (1 << 30) - 1;
+
+// How loose or tight a line is. Adjacent lines whose fitness classes aren't
+// next to each other get \adjdemerits added, so that very loose lines don't
+// end up right next to tight ones.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Fitness {
+    VeryLoose,
+    Loose,
+    Decent,
+    Tight,
+}
+
+const ALL_FITNESSES: [Fitness; 4] = [
+    Fitness::VeryLoose,
+    Fitness::Loose,
+    Fitness::Decent,
+    Fitness::Tight,
+];
+
+// The total natural width, stretch, and shrink of part of a paragraph, in
+// scaled points. Stretch is kept separately for each order of infinity so
+// that the totals for a line can be found by subtracting the totals before
+// the line from the totals at the end of it. The totals are 64-bit so that
+// they don't overflow in long paragraphs, even though the totals for a
+// single line always fit in a dimen.
+#[derive(Clone, Copy, Default)]
+struct Totals {
+    width: i64,
+    stretch: [i64; 4],
+    shrink: i64,
+}
+
+impl Totals {
+    fn add_glue(&mut self, glue: &Glue) {
+        self.width += glue.space.as_scaled_points() as i64;
+        match &glue.stretch {
+            SpringDimen::Dimen(dimen) => {
+                self.stretch[0] += dimen.as_scaled_points() as i64
+            }
+            SpringDimen::FilDimen(FilDimen(kind, value)) => {
+                let order = match kind {
+                    FilKind::Fil => 1,
+                    FilKind::Fill => 2,
+                    FilKind::Filll => 3,
+                };
+                self.stretch[order] += *value as i64;
+            }
+        }
+        match &glue.shrink {
+            SpringDimen::Dimen(dimen) => {
+                self.shrink += dimen.as_scaled_points() as i64
+            }
+            SpringDimen::FilDimen(FilDimen(_, 0)) => {}
+            SpringDimen::FilDimen(_) => {
+                panic!("Infinite glue shrinkage found in a paragraph")
+            }
+        }
+    }
+
+    fn minus(&self, other: &Totals) -> Totals {
+        let mut stretch = self.stretch;
+        for (total, other_total) in stretch.iter_mut().zip(&other.stretch) {
+            *total -= other_total;
+        }
+
+        Totals {
+            width: self.width - other.width,
+            stretch,
+            shrink: self.shrink - other.shrink,
+        }
+    }
+}
+
+// A break which lines ending at later breaks could start after. Breaks stop
+// being active once the material after them is too wide to fit on a line.
+struct ActiveBreak {
+    // The index of the first element of the line after this break, once
+    // discardable elements have been removed.
+    line_start: usize,
+    fitness: Fitness,
+    // The total demerits of all of the lines before this break.
+    total_demerits: i64,
+    // The index of this break in the list of all chosen breaks, or None for
+    // the start of the paragraph.
+    break_index: Option<usize>,
+}
+
+// Glue, kerns, and penalties are removed from the start of a line.
+fn is_discardable(elem: &HorizontalListElem) -> bool {
+    matches!(
+        elem,
+        HorizontalListElem::HSkip(_)
+            | HorizontalListElem::RuleLeaders { .. }
+            | HorizontalListElem::Kern(_)
+            | HorizontalListElem::Penalty(_)
+    )
+}
+
+// Returns the penalty for breaking the list at the element at `index`, or
+// None if that isn't a legal breakpoint. Glue is a breakpoint if it follows
+// something that isn't discardable, kerns are breakpoints if they are
+// followed by glue, and penalties and discretionaries are always
+// breakpoints.
+fn get_break_penalty(
+    list: &[HorizontalListElem],
+    index: usize,
+    hyphen_penalty: i32,
+) -> Option<i32> {
+    match &list[index] {
+        HorizontalListElem::HSkip(_)
+        | HorizontalListElem::RuleLeaders { .. } => {
+            if index > 0 && !is_discardable(&list[index - 1]) {
+                Some(0)
+            } else {
+                None
+            }
+        }
+        HorizontalListElem::Kern(_) => match list.get(index + 1) {
+            Some(HorizontalListElem::HSkip(_))
+            | Some(HorizontalListElem::RuleLeaders { .. }) => Some(0),
+            _ => None,
+        },
+        HorizontalListElem::Penalty(penalty) => {
+            if *penalty >= INF_PENALTY {
+                None
+            } else if *penalty <= EJECT_PENALTY {
+                Some(EJECT_PENALTY)
+            } else {
+                Some(*penalty)
+            }
+        }
+        HorizontalListElem::Discretionary { .. } => Some(hyphen_penalty),
+        _ => None,
+    }
+}
+
+// Returns the index of the first element of the line after a break at
+// `position`, skipping over the discardable elements after the break.
+fn get_line_start(list: &[HorizontalListElem], position: usize) -> usize {
+    let mut line_start = position + 1;
+    while line_start < list.len() && is_discardable(&list[line_start]) {
+        line_start += 1;
+    }
+    line_start
+}
+
+// Computes the badness and fitness class of a line with the given totals when
+// it is set to `line_width`. Lines that can't shrink enough to fit have a
+// badness larger than INF_BAD.
+fn get_line_badness(totals: &Totals, line_width: &Dimen) -> (i32, Fitness) {
+    let shortfall = line_width.as_scaled_points() as i64 - totals.width;
+
+    if shortfall > 0 {
+        // Lines with infinite stretch can stretch to any width for free.
+        if totals.stretch[1..].iter().any(|stretch| *stretch != 0) {
+            return (0, Fitness::Decent);
+        }
+
+        let badness = badness(
+            &Dimen::from_scaled_points(shortfall as i32),
+            &Dimen::from_scaled_points(totals.stretch[0] as i32),
+        );
+        let fitness = if badness > 99 {
+            Fitness::VeryLoose
+        } else if badness > 12 {
+            Fitness::Loose
+        } else {
+            Fitness::Decent
+        };
+        (badness, fitness)
+    } else if -shortfall > totals.shrink {
+        (INF_BAD + 1, Fitness::Tight)
+    } else {
+        let badness = badness(
+            &Dimen::from_scaled_points(-shortfall as i32),
+            &Dimen::from_scaled_points(totals.shrink as i32),
+        );
+        let fitness = if badness > 12 {
+            Fitness::Tight
+        } else {
+            Fitness::Decent
+        };
+        (badness, fitness)
+    }
+}
+
+// Computes the demerits of a line with the given badness that ends at a break
+// with the given penalty, not including \adjdemerits.
+fn get_line_demerits(line_penalty: i32, badness: i32, penalty: i32) -> i64 {
+    let line_cost = (line_penalty + badness) as i64;
+    let mut demerits = if line_cost.abs() >= 10000 {
+        100_000_000
+    } else {
+        line_cost * line_cost
+    };
+
+    let penalty = penalty as i64;
+    if penalty > 0 {
+        demerits += penalty * penalty;
+    } else if penalty > EJECT_PENALTY as i64 {
+        demerits -= penalty * penalty;
+    }
+
+    demerits
+}
+
+impl<'a> Parser<'a> {
+    /// Breaks the horizontal list of a paragraph into lines which are each
+    /// set to \hsize. The breaks are chosen to have the fewest total demerits
+    /// like in TeX, using \tolerance, \linepenalty, \hyphenpenalty, and
+    /// \adjdemerits. Glue at the end of the paragraph is replaced with
+    /// \parfillskip.
+    pub fn break_paragraph_into_lines(
+        &mut self,
+        mut list: Vec<HorizontalListElem>,
+    ) -> Vec<HorizontalBox> {
+        if let Some(HorizontalListElem::HSkip(_)) = list.last() {
+            list.pop();
+        }
+        let par_fill_skip =
+            self.state.get_glue_parameter(&GlueParameter::ParFillSkip);
+        list.push(HorizontalListElem::Penalty(INF_PENALTY));
+        list.push(HorizontalListElem::HSkip(par_fill_skip));
+        list.push(HorizontalListElem::Penalty(EJECT_PENALTY));
+
+        let hsize = self.state.get_dimen_parameter(&DimenParameter::HSize);
+        let get_integer_parameter =
+            |param| self.state.get_integer_parameter(&param);
+        let tolerance = get_integer_parameter(IntegerParameter::Tolerance);
+        let line_penalty = get_integer_parameter(IntegerParameter::LinePenalty);
+        let hyphen_penalty =
+            get_integer_parameter(IntegerParameter::HyphenPenalty);
+        let adj_demerits =
+            get_integer_parameter(IntegerParameter::AdjDemerits) as i64;
+
+        // totals[i] holds the totals of the first i elements of the list.
+        let mut totals = Vec::with_capacity(list.len() + 1);
+        let mut running_totals = Totals::default();
+        totals.push(running_totals);
+        for elem in &list {
+            let (_, _, width) = elem.get_size(self.state);
+            running_totals.add_glue(&width);
+            totals.push(running_totals);
+        }
+
+        // Each chosen break is stored as its position in the list along with
+        // the index of the break before it.
+        let mut breaks: Vec<(usize, Option<usize>)> = Vec::new();
+        let mut active = vec![ActiveBreak {
+            line_start: 0,
+            fitness: Fitness::Decent,
+            total_demerits: 0,
+            break_index: None,
+        }];
+
+        for position in 0..list.len() {
+            let penalty =
+                match get_break_penalty(&list, position, hyphen_penalty) {
+                    Some(penalty) => penalty,
+                    None => continue,
+                };
+
+            // The best break to start a line ending here after, for each
+            // fitness class of the line.
+            let mut minimal_demerits = [AWFUL_BAD; 4];
+            let mut best_breaks: [Option<usize>; 4] = [None; 4];
+            let mut minimum_demerits = AWFUL_BAD;
+
+            let num_active = active.len();
+            let mut still_active = Vec::with_capacity(num_active);
+            for (i, node) in active.into_iter().enumerate() {
+                let line_totals =
+                    totals[position].minus(&totals[node.line_start]);
+                let (badness, fitness) = get_line_badness(&line_totals, &hsize);
+
+                // If nothing else works, an overfull line is made from the
+                // last active break so that there's always some way to break
+                // the paragraph.
+                let mut artificial_demerits = false;
+                let stays_active =
+                    if badness > INF_BAD || penalty == EJECT_PENALTY {
+                        if minimum_demerits == AWFUL_BAD
+                            && still_active.is_empty()
+                            && i + 1 == num_active
+                        {
+                            artificial_demerits = true;
+                        } else if badness > tolerance {
+                            continue;
+                        }
+                        false
+                    } else {
+                        if badness > tolerance {
+                            still_active.push(node);
+                            continue;
+                        }
+                        true
+                    };
+
+                let mut demerits = if artificial_demerits {
+                    0
+                } else {
+                    get_line_demerits(line_penalty, badness, penalty)
+                };
+                if (fitness as i32 - node.fitness as i32).abs() > 1 {
+                    demerits += adj_demerits;
+                }
+                demerits += node.total_demerits;
+
+                if demerits <= minimal_demerits[fitness as usize] {
+                    minimal_demerits[fitness as usize] = demerits;
+                    best_breaks[fitness as usize] = node.break_index;
+                    if demerits < minimum_demerits {
+                        minimum_demerits = demerits;
+                    }
+                }
+
+                if stays_active {
+                    still_active.push(node);
+                }
+            }
+
+            // Breaks here are only kept if they could end up being better
+            // than the best break here once \adjdemerits are added.
+            if minimum_demerits < AWFUL_BAD {
+                let limit =
+                    (minimum_demerits + adj_demerits.abs()).min(AWFUL_BAD - 1);
+                for fitness in &ALL_FITNESSES {
+                    let demerits = minimal_demerits[*fitness as usize];
+                    if demerits <= limit {
+                        breaks.push((position, best_breaks[*fitness as usize]));
+                        still_active.push(ActiveBreak {
+                            line_start: get_line_start(&list, position),
+                            fitness: *fitness,
+                            total_demerits: demerits,
+                            break_index: Some(breaks.len() - 1),
+                        });
+                    }
+                }
+            }
+
+            active = still_active;
+        }
+
+        // The paragraph ends with a forced break, so the only active breaks
+        // left are at the end of it.
+        let best = active
+            .iter()
+            .min_by_key(|node| node.total_demerits)
+            .expect("No way to break the paragraph was found");
+
+        let mut positions = Vec::new();
+        let mut break_index = best.break_index;
+        while let Some(index) = break_index {
+            let (position, prev_index) = breaks[index];
+            positions.push(position);
+            break_index = prev_index;
+        }
+        positions.reverse();
+
+        // TODO(xymostech): Add the pre-break and post-break material of
+        // discretionaries that lines are broken at, and count its width when
+        // finding the breaks. For now, the discretionary is just dropped.
+        let mut lines = Vec::new();
+        let mut line_start = 0;
+        for position in positions {
+            // If there's another break in the discardable elements after a
+            // break, the line between them is empty.
+            let line = list[line_start.min(position)..position].to_vec();
+            lines.push(
+                self.combine_horizontal_list_into_horizontal_box_with_layout(
+                    line,
+                    &BoxLayout::Fixed(hsize),
+                ),
+            );
+            line_start = get_line_start(&list, position);
+        }
+
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::rc::Rc;
+
+    use crate::boxes::TeXBox;
+    use crate::dimension::Unit;
+    use crate::testing::with_parser;
+
+    // Returns the widths of the boxes on each line, which stand in for the
+    // words of the paragraph.
+    fn get_word_widths(lines: &[HorizontalBox]) -> Vec<Vec<Dimen>> {
+        lines
+            .iter()
+            .map(|line| {
+                line.list
+                    .iter()
+                    .filter_map(|elem| match elem {
+                        HorizontalListElem::Box { tex_box, .. } => {
+                            Some(*tex_box.width())
+                        }
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn make_word(width: f64) -> HorizontalListElem {
+        let mut hbox = HorizontalBox::empty();
+        hbox.width = Dimen::from_unit(width, Unit::Point);
+        HorizontalListElem::Box {
+            tex_box: TeXBox::HorizontalBox(hbox),
+            shift: Dimen::zero(),
+        }
+    }
+
+    fn points(widths: &[f64]) -> Vec<Dimen> {
+        widths
+            .iter()
+            .map(|width| Dimen::from_unit(*width, Unit::Point))
+            .collect()
+    }
+
+    #[test]
+    fn it_breaks_paragraphs_into_lines() {
+        with_parser(
+            &[
+                r"\hsize=110pt%",
+                r"\def\w{\hbox to20pt{}}%",
+                r"\def\s{\hskip10pt plus5pt minus3pt}%",
+                r"\w\s\w\s\w\s\w\s\w\s\w\s\w\s\w\s\w\s\w%",
+            ],
+            |parser| {
+                parser.parse_assignment();
+                parser.parse_assignment();
+                parser.parse_assignment();
+                let list = parser.parse_horizontal_list(false, false);
+                let lines = parser.break_paragraph_into_lines(list);
+
+                // Four words fit exactly, and three words would be too loose.
+                assert_eq!(
+                    get_word_widths(&lines),
+                    vec![
+                        points(&[20.0, 20.0, 20.0, 20.0]),
+                        points(&[20.0, 20.0, 20.0, 20.0]),
+                        points(&[20.0, 20.0]),
+                    ]
+                );
+
+                for line in &lines {
+                    assert_eq!(
+                        line.width,
+                        Dimen::from_unit(110.0, Unit::Point)
+                    );
+                }
+                // The glue on the full lines doesn't need to be set, and the
+                // last line is filled out by \parfillskip.
+                assert_eq!(lines[0].glue_set_ratio, None);
+                assert_eq!(lines[1].glue_set_ratio, None);
+                assert_eq!(
+                    lines[2].list.last(),
+                    Some(&HorizontalListElem::HSkip(
+                        parser
+                            .state
+                            .get_glue_parameter(&GlueParameter::ParFillSkip)
+                    ))
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn it_chooses_the_breaks_with_the_fewest_total_demerits() {
+        with_parser(
+            &[
+                r"\hsize=100pt%",
+                r"\def\s{\hskip10pt plus10pt minus5pt}%",
+                r"\hbox to40pt{}\s\hbox to45pt{}\s\hbox to4pt{}\s\hbox to50pt{}%",
+            ],
+            |parser| {
+                parser.parse_assignment();
+                parser.parse_assignment();
+                let list = parser.parse_horizontal_list(false, false);
+                let lines = parser.break_paragraph_into_lines(list);
+
+                // Fitting as much as possible on the first line would make it
+                // shrink with a badness of 73, but putting the short word on
+                // the next line only stretches the first line with a badness
+                // of 12, so TeX does that instead.
+                assert_eq!(
+                    get_word_widths(&lines),
+                    vec![points(&[40.0, 45.0]), points(&[4.0, 50.0])]
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn it_breaks_at_forced_penalties_but_not_infinite_ones() {
+        with_parser(&[r"\hsize=100pt%"], |parser| {
+            parser.parse_assignment();
+
+            let space = HorizontalListElem::HSkip(Glue {
+                space: Dimen::from_unit(10.0, Unit::Point),
+                stretch: SpringDimen::Dimen(Dimen::from_unit(
+                    10.0,
+                    Unit::Point,
+                )),
+                shrink: SpringDimen::Dimen(Dimen::from_unit(5.0, Unit::Point)),
+            });
+
+            let lines = parser.break_paragraph_into_lines(vec![
+                make_word(20.0),
+                HorizontalListElem::Penalty(-10000),
+                make_word(20.0),
+                space.clone(),
+                make_word(20.0),
+            ]);
+            assert_eq!(
+                get_word_widths(&lines),
+                vec![points(&[20.0]), points(&[20.0, 20.0])]
+            );
+
+            // Without the penalty, the line would be broken after the second
+            // word. The first word can't be on a line by itself since
+            // the line can't stretch, so everything ends up on one overfull
+            // line.
+            let lines = parser.break_paragraph_into_lines(vec![
+                make_word(45.0),
+                space.clone(),
+                make_word(45.0),
+                HorizontalListElem::Penalty(10000),
+                space,
+                make_word(45.0),
+            ]);
+            assert_eq!(
+                get_word_widths(&lines),
+                vec![points(&[45.0, 45.0, 45.0])]
+            );
+        });
+    }
+
+    #[test]
+    fn it_breaks_at_discretionaries() {
+        with_parser(&[r"\hsize=100pt%", r"\tolerance=10000%"], |parser| {
+            parser.parse_assignment();
+            parser.parse_assignment();
+
+            // The word is too long for one line, so it's broken at the
+            // discretionary even though the first line can't stretch.
+            let lines = parser.break_paragraph_into_lines(vec![
+                make_word(60.0),
+                HorizontalListElem::Discretionary {
+                    pre_break: vec![],
+                    post_break: vec![],
+                    no_break: vec![],
+                },
+                make_word(60.0),
+            ]);
+            assert_eq!(
+                get_word_widths(&lines),
+                vec![points(&[60.0]), points(&[60.0])]
+            );
+            assert_eq!(lines[0].list, Rc::new(vec![make_word(60.0)]));
+        });
+    }
+
+    #[test]
+    fn it_makes_overfull_lines_when_there_are_no_good_breaks() {
+        with_parser(&[r"\hsize=100pt%"], |parser| {
+            parser.parse_assignment();
+
+            let lines = parser.break_paragraph_into_lines(vec![
+                make_word(150.0),
+                HorizontalListElem::HSkip(Glue::from_dimen(Dimen::from_unit(
+                    10.0,
+                    Unit::Point,
+                ))),
+                make_word(150.0),
+            ]);
+            assert_eq!(
+                get_word_widths(&lines),
+                vec![points(&[150.0]), points(&[150.0])]
+            );
+        });
+    }
+}
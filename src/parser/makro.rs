@@ -2,6 +2,7 @@ use std::collections::HashMap;
 
 use crate::category::Category;
 use crate::makro::{Macro, MacroListElem};
+use crate::parser::expand::ScannerStatus;
 use crate::parser::Parser;
 use crate::token::Token;
 
@@ -35,7 +36,8 @@ enum SingleTokenGroup {
 
 impl<'a> Parser<'a> {
     // Parses a parameter list and replacement list into a macro object
-    pub fn parse_macro_definition(&mut self) -> Macro {
+    pub fn parse_macro_definition(&mut self, expand: bool) -> Macro {
+        let prev_status = self.set_scanner_status(ScannerStatus::Defining);
         let mut parameter_list: Vec<MacroListElem> = Vec::new();
 
         // When the last character of the parameter list (right before the {)
@@ -92,6 +94,15 @@ impl<'a> Parser<'a> {
         let mut group_level = 0;
 
         loop {
+            // For an \edef, the replacement text is expanded as it is read.
+            if expand {
+                if let Some(tokens) = self.expand_for_expanded_text() {
+                    replacement_list
+                        .extend(tokens.into_iter().map(MacroListElem::Token));
+                    continue;
+                }
+            }
+
             if let Some(token) = self.lex_unexpanded_token() {
                 match token {
                     Token::Char(_, Category::EndGroup) => {
@@ -139,6 +150,7 @@ impl<'a> Parser<'a> {
             replacement_list.push(MacroListElem::Token(token));
         }
 
+        self.set_scanner_status(prev_status);
         Macro::new(parameter_list, replacement_list)
     }
 
@@ -173,6 +185,23 @@ impl<'a> Parser<'a> {
         }
     }
 
+    // Parses a <general text>, which is a balanced text surrounded by braces,
+    // optionally preceded by spaces and \relax. This is used for things like
+    // the value of token list assignments. Returns the tokens inside of the
+    // braces.
+    pub fn parse_general_text(&mut self) -> Vec<Token> {
+        self.parse_filler_expanded();
+        match self.lex_expanded_token() {
+            Some(Token::Char(_, Category::BeginGroup)) => {}
+            tok => panic!("Missing {{ inserted, found {:?}", tok),
+        }
+
+        let prev_status = self.set_scanner_status(ScannerStatus::Absorbing);
+        let (tokens, _) = self.parse_balanced_text();
+        self.set_scanner_status(prev_status);
+        tokens
+    }
+
     // While we're parsing tokens for macro parameters, we often want to get
     // either a single token or, if the first token is a {, parse an entire
     // balanced group. This function handles that and returns all the
@@ -286,6 +315,7 @@ impl<'a> Parser<'a> {
         &mut self,
         makro: &Macro,
     ) -> HashMap<usize, Vec<Token>> {
+        let prev_status = self.set_scanner_status(ScannerStatus::Matching);
         let mut replacement_map: HashMap<usize, Vec<Token>> = HashMap::new();
 
         // We manually iterate through the replacement_list because when we
@@ -328,6 +358,18 @@ impl<'a> Parser<'a> {
                         self.parse_optional_spaces_unexpanded();
                         self.parse_single_token_or_balanced_text_unwrapped()
                     };
+
+                    // Only \long macros can have \par in their arguments.
+                    if !makro.is_long()
+                        && toks.contains(&Token::ControlSequence(
+                            "par".to_string(),
+                        ))
+                    {
+                        panic!(
+                            "Paragraph ended before macro argument was complete"
+                        );
+                    }
+
                     replacement_map.insert(*parameter_index, toks);
                 }
                 MacroListElem::Token(search_token) => {
@@ -344,6 +386,7 @@ impl<'a> Parser<'a> {
             }
         }
 
+        self.set_scanner_status(prev_status);
         replacement_map
     }
 }
@@ -365,7 +408,10 @@ mod tests {
                     Some(Token::ControlSequence("a".to_string())),
                     parser.lex_unexpanded_token()
                 );
-                assert_eq!(expected_macro, parser.parse_macro_definition());
+                assert_eq!(
+                    expected_macro,
+                    parser.parse_macro_definition(false)
+                );
             });
         }
 
@@ -379,7 +425,7 @@ mod tests {
                     Some(Token::ControlSequence("a".to_string())),
                     parser.lex_unexpanded_token()
                 );
-                parser.parse_macro_definition();
+                parser.parse_macro_definition(false);
             });
         }
 
@@ -768,5 +814,48 @@ mod tests {
                 ],
             );
         }
+
+        #[test]
+        #[should_panic(
+            expected = "Paragraph ended before macro argument was complete"
+        )]
+        fn it_fails_on_par_in_arguments_to_non_long_macros() {
+            with_parser(&[r"\def\a#1{(#1)}%", r"\a{x\par y}%"], |parser| {
+                parser.parse_assignment();
+                parser.lex_expanded_token();
+            });
+        }
+
+        #[test]
+        fn it_allows_par_in_arguments_to_long_macros() {
+            with_parser(
+                &[
+                    r"\long\def\a#1{#1}%",
+                    r"\def\b#1\par{#1}%",
+                    r"\a{\par}\b x\par%",
+                ],
+                |parser| {
+                    parser.parse_assignment();
+                    parser.parse_assignment();
+                    assert!(parser
+                        .state
+                        .get_macro(&Token::ControlSequence("a".to_string()))
+                        .unwrap()
+                        .is_long());
+
+                    assert_eq!(
+                        parser.lex_expanded_token(),
+                        Some(Token::ControlSequence("par".to_string()))
+                    );
+                    // A \par that ends a delimited argument isn't part of the
+                    // argument, so it's allowed in non-\long macros.
+                    assert_eq!(
+                        parser.lex_expanded_token(),
+                        Some(Token::Char('x', Category::Letter))
+                    );
+                    assert_eq!(parser.lex_expanded_token(), None);
+                },
+            );
+        }
     }
 }
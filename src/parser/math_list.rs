@@ -1,19 +1,25 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
 
-use crate::boxes::{HorizontalBox, TeXBox};
+use crate::boxes::{HorizontalBox, TeXBox, VerticalBox};
 use crate::category::Category;
-use crate::dimension::{Dimen, SpringDimen, Unit};
+use crate::dimension::Dimen;
 use crate::font::Font;
 use crate::glue::Glue;
-use crate::list::HorizontalListElem;
+use crate::list::{HorizontalListElem, VerticalListElem};
 use crate::math_code::MathCode;
 use crate::math_list::{
-    AtomKind, MathAtom, MathField, MathList, MathListElem, MathStyle,
-    MathSymbol,
+    AtomKind, BoundaryKind, GeneralizedFraction, MathAtom, MathDelimiter,
+    MathField, MathList, MathListElem, MathStyle, MathSymbol, OpLimits,
 };
+use crate::mu_glue::MuGlue;
 use crate::parser::boxes::BoxLayout;
 use crate::parser::Parser;
+use crate::tfm::ExtensibleRecipe;
 use crate::token::Token;
+use crate::variable::{DimenParameter, IntegerParameter, MuGlueParameter};
+
+const INF_PENALTY: i32 = 10000;
 
 #[derive(Clone)]
 enum InterAtomSpacing {
@@ -108,58 +114,107 @@ lazy_static! {
     ].iter().cloned().collect();
 }
 
-lazy_static! {
-    // TODO: pull these from \textfont, \scriptfont, and \scriptscriptfont
-    static ref MATH_FONTS: HashMap<(MathStyle, u8), Font> = [
-        ((MathStyle::TextStyle, 0), Font {
-            font_name: "cmr10".to_string(),
-            scale: Dimen::from_unit(10.0, Unit::Point),
-        }),
-        ((MathStyle::ScriptStyle, 0), Font {
-            font_name: "cmr7".to_string(),
-            scale: Dimen::from_unit(7.0, Unit::Point),
-        }),
-        ((MathStyle::ScriptScriptStyle, 0), Font {
-            font_name: "cmr5".to_string(),
-            scale: Dimen::from_unit(5.0, Unit::Point),
-        }),
-        ((MathStyle::TextStyle, 1), Font {
-            font_name: "cmmi10".to_string(),
-            scale: Dimen::from_unit(10.0, Unit::Point),
-        }),
-        ((MathStyle::ScriptStyle, 1), Font {
-            font_name: "cmmi7".to_string(),
-            scale: Dimen::from_unit(7.0, Unit::Point),
-        }),
-        ((MathStyle::ScriptScriptStyle, 1), Font {
-            font_name: "cmmi5".to_string(),
-            scale: Dimen::from_unit(5.0, Unit::Point),
-        }),
-        ((MathStyle::TextStyle, 2), Font {
-            font_name: "cmsy10".to_string(),
-            scale: Dimen::from_unit(10.0, Unit::Point),
-        }),
-        ((MathStyle::ScriptStyle, 2), Font {
-            font_name: "cmsy7".to_string(),
-            scale: Dimen::from_unit(7.0, Unit::Point),
-        }),
-        ((MathStyle::ScriptScriptStyle, 2), Font {
-            font_name: "cmsy5".to_string(),
-            scale: Dimen::from_unit(5.0, Unit::Point),
-        }),
-        ((MathStyle::TextStyle, 3), Font {
-            font_name: "cmex10".to_string(),
-            scale: Dimen::from_unit(10.0, Unit::Point),
-        }),
-        ((MathStyle::ScriptStyle, 3), Font {
-            font_name: "cmex7".to_string(),
-            scale: Dimen::from_unit(7.0, Unit::Point),
-        }),
-        ((MathStyle::ScriptScriptStyle, 3), Font {
-            font_name: "cmex5".to_string(),
-            scale: Dimen::from_unit(5.0, Unit::Point),
-        }),
-    ].iter().cloned().collect();
+// Finds the style whose fonts are used in the given style. Display and text
+// styles both use the text fonts, and cramped styles use the same fonts as
+// their uncramped versions.
+fn get_math_font_style(style: &MathStyle) -> MathStyle {
+    match style {
+        MathStyle::DisplayStyle => MathStyle::TextStyle,
+        MathStyle::DisplayStylePrime => MathStyle::TextStyle,
+        MathStyle::TextStyle => MathStyle::TextStyle,
+        MathStyle::TextStylePrime => MathStyle::TextStyle,
+        MathStyle::ScriptStyle => MathStyle::ScriptStyle,
+        MathStyle::ScriptStylePrime => MathStyle::ScriptStyle,
+        MathStyle::ScriptScriptStyle => MathStyle::ScriptScriptStyle,
+        MathStyle::ScriptScriptStylePrime => MathStyle::ScriptScriptStyle,
+    }
+}
+
+// The primitive which sets the fonts used in the given style.
+fn get_family_member_primitive(style: &MathStyle) -> &'static str {
+    match get_math_font_style(style) {
+        MathStyle::TextStyle => "textfont",
+        MathStyle::ScriptStyle => "scriptfont",
+        _ => "scriptscriptfont",
+    }
+}
+
+fn max_dimen(a: Dimen, b: Dimen) -> Dimen {
+    if a > b {
+        a
+    } else {
+        b
+    }
+}
+
+// Puts a box in the middle of a new box with the given width, so that rows
+// of different widths can be stacked on top of each other.
+fn center_box_in_width(tex_box: TeXBox, width: Dimen) -> TeXBox {
+    let left_space = (width - *tex_box.width()) / 2;
+    let right_space = width - *tex_box.width() - left_space;
+
+    TeXBox::HorizontalBox(HorizontalBox {
+        height: *tex_box.height(),
+        depth: *tex_box.depth(),
+        width,
+        list: Rc::new(vec![
+            HorizontalListElem::Kern(left_space),
+            HorizontalListElem::Box {
+                tex_box,
+                shift: Dimen::zero(),
+            },
+            HorizontalListElem::Kern(right_space),
+        ]),
+        glue_set_ratio: None,
+    })
+}
+
+// Whether an atom is the Inner atom from a \left ... \right pair, whose
+// nucleus is the list between the delimiters including the boundaries.
+fn is_left_right_atom(atom: &MathAtom) -> bool {
+    atom.kind == AtomKind::Inner
+        && matches!(
+            &atom.nucleus,
+            Some(MathField::MathList(list)) if matches!(
+                list.first(),
+                Some(MathListElem::Boundary(BoundaryKind::Left, _))
+            )
+        )
+}
+
+fn get_last_atom(list: &mut MathList) -> Option<&mut MathAtom> {
+    list.iter_mut().rev().find_map(|elem| match elem {
+        MathListElem::Atom(atom) => Some(atom),
+        _ => None,
+    })
+}
+
+// Bin atoms need to be between two things that they can apply to, so they get
+// turned into Ord atoms if they're at the beginning of a list or next to
+// things like Rel atoms or punctuation, following the rules in Appendix G of
+// the TeXbook. `atom` is the atom being added to the end of `list`.
+fn reclassify_bin_atoms(list: &mut MathList, atom: &mut MathAtom) {
+    let last_atom = get_last_atom(list);
+
+    match atom.kind {
+        AtomKind::Bin => match last_atom.map(|last_atom| last_atom.kind) {
+            None
+            | Some(AtomKind::Bin)
+            | Some(AtomKind::Op)
+            | Some(AtomKind::Rel)
+            | Some(AtomKind::Open)
+            | Some(AtomKind::Punct) => atom.kind = AtomKind::Ord,
+            _ => {}
+        },
+        AtomKind::Rel | AtomKind::Close | AtomKind::Punct => {
+            if let Some(last_atom) = last_atom {
+                if last_atom.kind == AtomKind::Bin {
+                    last_atom.kind = AtomKind::Ord;
+                }
+            }
+        }
+        _ => {}
+    }
 }
 
 impl<'a> Parser<'a> {
@@ -259,6 +314,245 @@ impl<'a> Parser<'a> {
         }
     }
 
+    fn is_math_atom_kind_head(&mut self) -> bool {
+        self.is_next_expanded_token_in_set_of_primitives(&[
+            "mathord",
+            "mathop",
+            "mathbin",
+            "mathrel",
+            "mathopen",
+            "mathclose",
+            "mathpunct",
+            "mathinner",
+        ])
+    }
+
+    // Parses a math field after one of \mathord, \mathop, etc. into an atom
+    // of the corresponding kind, so that it is spaced like that kind of atom.
+    fn parse_math_atom_with_kind(&mut self) -> MathAtom {
+        let tok = self.lex_expanded_token().unwrap();
+
+        let kind = if self.state.is_token_equal_to_prim(&tok, "mathord") {
+            AtomKind::Ord
+        } else if self.state.is_token_equal_to_prim(&tok, "mathop") {
+            AtomKind::Op
+        } else if self.state.is_token_equal_to_prim(&tok, "mathbin") {
+            AtomKind::Bin
+        } else if self.state.is_token_equal_to_prim(&tok, "mathrel") {
+            AtomKind::Rel
+        } else if self.state.is_token_equal_to_prim(&tok, "mathopen") {
+            AtomKind::Open
+        } else if self.state.is_token_equal_to_prim(&tok, "mathclose") {
+            AtomKind::Close
+        } else if self.state.is_token_equal_to_prim(&tok, "mathpunct") {
+            AtomKind::Punct
+        } else if self.state.is_token_equal_to_prim(&tok, "mathinner") {
+            AtomKind::Inner
+        } else {
+            panic!("Invalid atom kind: {:?}", tok);
+        };
+
+        MathAtom {
+            kind,
+            nucleus: Some(self.parse_math_field()),
+            ..MathAtom::empty_ord()
+        }
+    }
+
+    fn is_vcenter_head(&mut self) -> bool {
+        self.is_next_expanded_token_in_set_of_primitives(&["vcenter"])
+    }
+
+    fn parse_vcenter(&mut self) -> MathAtom {
+        self.lex_expanded_token();
+        let vbox = self.parse_specified_vertical_box();
+
+        MathAtom {
+            kind: AtomKind::Vcent,
+            nucleus: Some(MathField::TeXBox(TeXBox::VerticalBox(vbox))),
+            superscript: None,
+            subscript: None,
+            limits: OpLimits::DisplayLimits,
+            accent: None,
+            delimiter: None,
+        }
+    }
+
+    fn is_math_accent_head(&mut self) -> bool {
+        self.is_next_expanded_token_in_set_of_primitives(&["mathaccent"])
+    }
+
+    fn parse_math_accent(&mut self) -> MathAtom {
+        self.lex_expanded_token();
+        let math_code = MathCode::from_number(self.parse_15bit_number() as u32);
+        let nucleus = self.parse_math_field();
+
+        MathAtom {
+            kind: AtomKind::Acc,
+            nucleus: Some(nucleus),
+            superscript: None,
+            subscript: None,
+            limits: OpLimits::DisplayLimits,
+            accent: Some(MathSymbol::from_math_code(&math_code)),
+            delimiter: None,
+        }
+    }
+
+    fn is_radical_head(&mut self) -> bool {
+        self.is_next_expanded_token_in_set_of_primitives(&["radical", "sqrt"])
+    }
+
+    // Parses a \radical<delimiter code> followed by the math field for the
+    // radicand into a Rad atom. \sqrt is the same as \radical with plain
+    // TeX's delimiter code for the radical sign.
+    fn parse_radical(&mut self) -> MathAtom {
+        let tok = self.lex_expanded_token().unwrap();
+
+        let delimiter_code = if self.state.is_token_equal_to_prim(&tok, "sqrt")
+        {
+            0x270370
+        } else {
+            // The top 3 bits are the class, which doesn't matter here.
+            (self.parse_number() as u32) % 0x1000000
+        };
+        let nucleus = self.parse_math_field();
+
+        MathAtom {
+            kind: AtomKind::Rad,
+            nucleus: Some(nucleus),
+            delimiter: Some(MathDelimiter::from_number(delimiter_code)),
+            ..MathAtom::empty_ord()
+        }
+    }
+
+    // Parses a delimiter after \left or \right, which is either given
+    // explicitly with \delimiter or is a character with a \delcode.
+    fn parse_delimiter(&mut self) -> Option<MathDelimiter> {
+        self.parse_filler_expanded();
+
+        let delimiter_code = if self
+            .is_next_expanded_token_in_set_of_primitives(&["delimiter"])
+        {
+            self.lex_expanded_token();
+            // The top 3 bits are the class, which doesn't matter here.
+            (self.parse_number() as u32) % 0x1000000
+        } else if self.is_character_head() {
+            let expanded_token = self.lex_expanded_token();
+            let ch = match self.replace_renamed_token(expanded_token) {
+                Some(Token::Char(ch, _)) => ch,
+                _ => unreachable!(),
+            };
+
+            let delimiter_code = self.state.get_delimiter_code(ch);
+            if delimiter_code < 0 {
+                panic!("Missing delimiter, found {:?}", ch);
+            }
+            delimiter_code as u32
+        } else {
+            panic!("Missing delimiter, found {:?}", self.peek_expanded_token());
+        };
+
+        if delimiter_code == 0 {
+            None
+        } else {
+            Some(MathDelimiter::from_number(delimiter_code))
+        }
+    }
+
+    fn is_mathstrut_head(&mut self) -> bool {
+        self.is_next_expanded_token_in_set_of_primitives(&["mathstrut"])
+    }
+
+    // Parses a \mathstrut, which is an empty Ord atom with the height and
+    // depth of a parenthesis from family 0, like plain TeX's \vphantom(. The
+    // size of the parenthesis depends on the style, so a strut is made for
+    // each style and the right one is picked when the list is converted.
+    fn parse_mathstrut(&mut self) -> MathListElem {
+        let tok = self.lex_expanded_token().unwrap();
+        if !self.state.is_token_equal_to_prim(&tok, "mathstrut") {
+            panic!("Invalid mathstrut head: {:?}", tok);
+        }
+
+        let make_strut_list = |style: MathStyle| {
+            let font = self.get_math_font(&style, 0);
+            let metrics = self.state.get_metrics_for_font(&font).unwrap();
+            let strut = HorizontalBox::strut(
+                metrics.get_height('('),
+                metrics.get_depth('('),
+            );
+
+            let mut atom = MathAtom::empty_ord();
+            atom.nucleus =
+                Some(MathField::TeXBox(TeXBox::HorizontalBox(strut)));
+            vec![MathListElem::Atom(atom)]
+        };
+
+        MathListElem::FourWayChoice {
+            display: make_strut_list(MathStyle::DisplayStyle),
+            text: make_strut_list(MathStyle::TextStyle),
+            script: make_strut_list(MathStyle::ScriptStyle),
+            scriptscript: make_strut_list(MathStyle::ScriptScriptStyle),
+        }
+    }
+
+    fn is_left_head(&mut self) -> bool {
+        self.is_next_expanded_token_in_set_of_primitives(&["left"])
+    }
+
+    fn is_right_head(&mut self) -> bool {
+        self.is_next_expanded_token_in_set_of_primitives(&["right"])
+    }
+
+    // Parses a \left ... \right pair into an Inner atom whose nucleus is the
+    // list between them, starting and ending with the two delimiters.
+    fn parse_left_right(&mut self) -> MathAtom {
+        self.lex_expanded_token();
+        let left_delimiter = self.parse_delimiter();
+
+        self.state.push_state();
+
+        let inner_list = self.parse_math_list();
+        if !self.is_right_head() {
+            panic!("Missing \\right");
+        }
+        self.lex_expanded_token();
+        let right_delimiter = self.parse_delimiter();
+
+        self.state.pop_state();
+
+        let mut list =
+            vec![MathListElem::Boundary(BoundaryKind::Left, left_delimiter)];
+        list.extend(inner_list);
+        list.push(MathListElem::Boundary(BoundaryKind::Right, right_delimiter));
+
+        MathAtom {
+            kind: AtomKind::Inner,
+            ..MathAtom::from_math_list(list)
+        }
+    }
+
+    fn is_limits_head(&mut self) -> bool {
+        self.is_next_expanded_token_in_set_of_primitives(&[
+            "limits",
+            "nolimits",
+            "displaylimits",
+        ])
+    }
+
+    fn parse_limits(&mut self) -> OpLimits {
+        let tok = self.lex_expanded_token().unwrap();
+
+        if self.state.is_token_equal_to_prim(&tok, "limits") {
+            OpLimits::Limits
+        } else if self.state.is_token_equal_to_prim(&tok, "nolimits") {
+            OpLimits::NoLimits
+        } else if self.state.is_token_equal_to_prim(&tok, "displaylimits") {
+            OpLimits::DisplayLimits
+        } else {
+            panic!("Invalid limits");
+        }
+    }
+
     fn is_math_superscript_head(&mut self) -> bool {
         let expanded_token = self.peek_expanded_token();
         match self.replace_renamed_token(expanded_token) {
@@ -297,6 +591,62 @@ impl<'a> Parser<'a> {
         atom.with_subscript(subscript)
     }
 
+    fn is_fraction_head(&mut self) -> bool {
+        self.is_next_expanded_token_in_set_of_primitives(&[
+            "over",
+            "atop",
+            "above",
+            "overwithdelims",
+            "atopwithdelims",
+            "abovewithdelims",
+        ])
+    }
+
+    // Parses one of the generalized fraction commands, along with the
+    // delimiters and rule thickness that come after it. The rule is the
+    // default thickness for \over, zero for \atop so that no rule is drawn,
+    // and the given thickness for \above. The denominator is filled in later,
+    // once the rest of the list has been parsed.
+    fn parse_fraction(&mut self, numerator: MathList) -> GeneralizedFraction {
+        let tok = self.lex_expanded_token().unwrap();
+        let is_prim = |parser: &Parser, name: &str| {
+            parser.state.is_token_equal_to_prim(&tok, name)
+                || parser.state.is_token_equal_to_prim(
+                    &tok,
+                    &format!("{}withdelims", name),
+                )
+        };
+        let has_delims =
+            self.state.is_token_equal_to_prim(&tok, "overwithdelims")
+                || self.state.is_token_equal_to_prim(&tok, "atopwithdelims")
+                || self.state.is_token_equal_to_prim(&tok, "abovewithdelims");
+
+        let (left_delim, right_delim) = if has_delims {
+            let left_delim = self.parse_delimiter();
+            let right_delim = self.parse_delimiter();
+            (left_delim, right_delim)
+        } else {
+            (None, None)
+        };
+
+        let bar_height = if is_prim(self, "over") {
+            None
+        } else if is_prim(self, "atop") {
+            Some(Dimen::zero())
+        } else if is_prim(self, "above") {
+            Some(self.parse_dimen())
+        } else {
+            panic!("Invalid fraction head: {:?}", tok);
+        };
+
+        GeneralizedFraction {
+            left_delim,
+            right_delim,
+            bar_height,
+            ..GeneralizedFraction::over(numerator, Vec::new())
+        }
+    }
+
     fn is_style_change_head(&mut self) -> bool {
         self.is_next_expanded_token_in_set_of_primitives(&[
             "displaystyle",
@@ -322,854 +672,3564 @@ impl<'a> Parser<'a> {
         }
     }
 
-    pub fn parse_math_list(&mut self) -> MathList {
-        let mut current_list = Vec::new();
+    // Moves the box in a \vcenter atom so that it is vertically centered on
+    // the axis. After that, the atom is treated like an Ord atom.
+    fn center_vcenter_atom_on_axis(
+        &mut self,
+        atom: &mut MathAtom,
+        style: &MathStyle,
+    ) {
+        let axis_height = self
+            .state
+            .get_metrics_for_font(&self.get_math_font(style, 2))
+            .unwrap()
+            .get_font_parameter(22);
 
-        loop {
-            if self.is_math_symbol_head() {
-                let math_code = self.parse_math_symbol();
+        if let Some(MathField::TeXBox(tex_box)) = &mut atom.nucleus {
+            let total_height = *tex_box.height() + *tex_box.depth();
+            let height = total_height / 2 + axis_height;
 
-                current_list.push(MathListElem::Atom(
-                    MathAtom::from_math_code(&math_code),
-                ));
-            } else if self.is_math_superscript_head()
-                || self.is_math_subscript_head()
-            {
-                let is_superscript = self.is_math_superscript_head();
+            *tex_box.mut_height() = height;
+            *tex_box.mut_depth() = total_height - height;
+        }
 
-                let last_atom = match current_list.pop() {
-                    Some(MathListElem::Atom(atom)) => atom,
-                    Some(other_elem) => {
-                        current_list.push(other_elem);
-                        MathAtom::empty_ord()
-                    }
-                    None => MathAtom::empty_ord(),
-                };
+        atom.kind = AtomKind::Ord;
+    }
 
-                current_list.push(MathListElem::Atom(if is_superscript {
-                    self.parse_math_superscript(last_atom)
-                } else {
-                    self.parse_math_subscript(last_atom)
-                }));
-            } else if self.is_assignment_head() {
-                self.parse_assignment();
-            } else if self.is_style_change_head() {
-                let style_change = self.parse_style_change();
-                current_list.push(MathListElem::StyleChange(style_change));
-            } else {
-                match self.peek_expanded_token() {
-                    Some(Token::Char(_, Category::BeginGroup)) => {
-                        let inner_list = self.parse_math_group();
-                        current_list.push(MathListElem::Atom(
-                            MathAtom::from_math_list(inner_list),
-                        ));
-                    }
-                    Some(Token::Char(_, Category::Space)) => {
-                        self.lex_expanded_token();
-                    }
-                    Some(Token::Char(_, Category::EndGroup)) => break,
-                    Some(Token::Char(_, Category::MathShift)) => break,
-                    None => break,
-                    _ => panic!("unimplemented"),
+    // Finds the font for the given family in the given style.
+    pub fn get_math_font(&self, style: &MathStyle, family: u8) -> Font {
+        self.state
+            .get_family_font(&get_math_font_style(style), family)
+            .unwrap_or_else(|| {
+                panic!(
+                    "\\{} {} is undefined",
+                    get_family_member_primitive(style),
+                    family
+                )
+            })
+    }
+
+    /// Checks that the symbol and extension fonts have all of the
+    /// parameters that are needed to typeset math. TeX does this when math
+    /// mode ends rather than when it starts, since the fonts can be changed
+    /// inside of the formula. Family 2 needs 22 parameters and family 3 needs
+    /// 13 in each of the text, script, and scriptscript sizes.
+    pub fn check_math_fonts(&self) {
+        for (family, num_parameters, kind) in
+            [(2, 22, "symbol"), (3, 13, "extension")].iter()
+        {
+            for style in [
+                MathStyle::TextStyle,
+                MathStyle::ScriptStyle,
+                MathStyle::ScriptScriptStyle,
+            ]
+            .iter()
+            {
+                let font = self.get_math_font(style, *family);
+                let metrics = self
+                    .state
+                    .get_metrics_for_font(&font)
+                    .unwrap_or_else(|| panic!("Invalid font: {:?}", font));
+
+                if metrics.get_num_font_parameters() < *num_parameters {
+                    panic!("Math formula deleted: Insufficient {} fonts", kind);
                 }
             }
         }
+    }
 
-        current_list
+    fn get_math_font_parameter(
+        &mut self,
+        style: &MathStyle,
+        family: u8,
+        index: usize,
+    ) -> Dimen {
+        self.state
+            .get_metrics_for_font(&self.get_math_font(style, family))
+            .unwrap()
+            .get_font_parameter(index)
     }
 
-    fn get_skip_for_atom_pair(
+    // Turns a math field into a box by typesetting it in the given style.
+    fn convert_math_field_to_box(
         &mut self,
-        left_type: &AtomKind,
-        right_type: &AtomKind,
+        field: MathField,
         style: &MathStyle,
-    ) -> Option<Glue> {
-        // TODO: These should come from the state variables \thinmuskip,
-        // \mediummuskip, and \thickmuskip.
-        // TODO: These should be MuGlue, not plain Glue
-        let thinskip = Glue {
-            space: Dimen::from_unit(3.0, Unit::Point),
-            stretch: SpringDimen::Dimen(Dimen::zero()),
-            shrink: SpringDimen::Dimen(Dimen::zero()),
-        };
-        let mediumskip = Glue {
-            space: Dimen::from_unit(4.0, Unit::Point),
-            stretch: SpringDimen::Dimen(Dimen::from_unit(2.0, Unit::Point)),
-            shrink: SpringDimen::Dimen(Dimen::from_unit(4.0, Unit::Point)),
-        };
-        let thickskip = Glue {
-            space: Dimen::from_unit(5.0, Unit::Point),
-            stretch: SpringDimen::Dimen(Dimen::from_unit(5.0, Unit::Point)),
-            shrink: SpringDimen::Dimen(Dimen::zero()),
-        };
+    ) -> TeXBox {
+        match field {
+            MathField::Symbol(symbol) => {
+                let font = self.get_math_font(style, symbol.family_number);
 
-        if let Some(space) = INTER_ATOM_SPACING.get(&(*left_type, *right_type))
-        {
-            match (space, style.is_script()) {
-                (InterAtomSpacing::None, _) => None,
-                (InterAtomSpacing::ThinSkip, _) => Some(thinskip),
-                (InterAtomSpacing::ThinSkipNonScript, false) => Some(thinskip),
-                (InterAtomSpacing::ThinSkipNonScript, true) => None,
-                (InterAtomSpacing::MediumSkipNonScript, false) => {
-                    Some(mediumskip)
-                }
-                (InterAtomSpacing::MediumSkipNonScript, true) => None,
-                (InterAtomSpacing::ThickSkipNonScript, false) => {
-                    Some(thickskip)
-                }
-                (InterAtomSpacing::ThickSkipNonScript, true) => None,
+                let char_elem = HorizontalListElem::Char {
+                    chr: symbol.position_number as char,
+                    font,
+                };
+
+                TeXBox::HorizontalBox(
+                    self.add_to_natural_layout_horizontal_box(
+                        HorizontalBox::empty(),
+                        char_elem,
+                    ),
+                )
+            }
+            MathField::TeXBox(tex_box) => tex_box,
+            MathField::MathList(list) => {
+                let hlist = self.convert_math_list_to_horizontal_list(
+                    list,
+                    style.clone(),
+                    false,
+                );
+
+                TeXBox::HorizontalBox(
+                    self.combine_horizontal_list_into_horizontal_box_with_layout(
+                        hlist,
+                        &BoxLayout::Natural,
+                    ),
+                )
             }
-        } else {
-            panic!("Invalid atom type pair: {:?}/{:?}", left_type, right_type);
         }
     }
 
-    pub fn convert_math_list_to_horizontal_list(
-        &mut self,
-        list: MathList,
-        start_style: MathStyle,
-    ) -> Vec<HorizontalListElem> {
-        let mut elems_after_first_pass: MathList = Vec::new();
-        let mut current_style = start_style.clone();
+    // Places the accent of an Acc atom over its nucleus, following rule 12 of
+    // Appendix G. When the nucleus is a single character, the accent is moved
+    // over by the kern between that character and the \skewchar of its font,
+    // so that accents sit in the right place over slanted letters. After
+    // that, the atom is treated like an Ord atom.
+    fn make_math_accent(&mut self, atom: &mut MathAtom, style: &MathStyle) {
+        let accent = atom.accent.take().unwrap();
 
-        for elem in list {
-            match elem {
-                MathListElem::Atom(mut atom) => {
-                    match atom.nucleus {
-                        Some(MathField::Symbol(symbol)) => {
-                            let font_style = match current_style {
-                                MathStyle::DisplayStyle => MathStyle::TextStyle,
-                                MathStyle::DisplayStylePrime => {
-                                    MathStyle::TextStyle
-                                }
-                                MathStyle::TextStyle => MathStyle::TextStyle,
-                                MathStyle::TextStylePrime => {
-                                    MathStyle::TextStyle
-                                }
-                                MathStyle::ScriptStyle => {
-                                    MathStyle::ScriptStyle
-                                }
-                                MathStyle::ScriptStylePrime => {
-                                    MathStyle::ScriptStyle
-                                }
-                                MathStyle::ScriptScriptStyle => {
-                                    MathStyle::ScriptScriptStyle
-                                }
-                                MathStyle::ScriptScriptStylePrime => {
-                                    MathStyle::ScriptScriptStyle
-                                }
-                            };
+        let skew = match &atom.nucleus {
+            Some(MathField::Symbol(symbol)) => {
+                let font = self.get_math_font(style, symbol.family_number);
+                let skew_char = self.state.get_skew_char(&font);
 
-                            let font = MATH_FONTS
-                                .get(&(font_style, symbol.family_number))
-                                .unwrap();
+                if (0..256).contains(&skew_char) {
+                    self.state
+                        .get_metrics_for_font(&font)
+                        .unwrap()
+                        .get_kern(
+                            symbol.position_number as char,
+                            skew_char as u8 as char,
+                        )
+                        .unwrap_or_else(Dimen::zero)
+                } else {
+                    Dimen::zero()
+                }
+            }
+            _ => Dimen::zero(),
+        };
 
-                            let char_elem = HorizontalListElem::Char {
-                                chr: symbol.position_number as char,
-                                font: font.clone(),
-                            };
+        let nucleus = match atom.nucleus.take() {
+            Some(field) => {
+                self.convert_math_field_to_box(field, &style.cramped_style())
+            }
+            None => TeXBox::HorizontalBox(HorizontalBox::empty()),
+        };
 
-                            let hbox = self
-                                .add_to_natural_layout_horizontal_box(
-                                    HorizontalBox::empty(),
-                                    char_elem,
-                                );
+        let accent_font = self.get_math_font(style, accent.family_number);
+        let x_height = self
+            .state
+            .get_metrics_for_font(&accent_font)
+            .unwrap()
+            .get_font_parameter(5);
+        let accent_box =
+            self.convert_math_field_to_box(MathField::Symbol(accent), style);
 
-                            atom.nucleus = Some(MathField::TeXBox(
-                                TeXBox::HorizontalBox(hbox),
-                            ));
-                        }
-                        Some(MathField::TeXBox(_)) => {
-                            // Nothing to do
-                        }
-                        Some(MathField::MathList(list)) => {
-                            let hlist = self
-                                .convert_math_list_to_horizontal_list(
-                                    list,
-                                    current_style.clone(),
-                                );
-                            let hbox = self.combine_horizontal_list_into_horizontal_box_with_layout(hlist, &BoxLayout::Natural);
+        // The accent is lowered so that it sits the same distance above the
+        // nucleus as it would above an x.
+        let delta = if *nucleus.height() < x_height {
+            *nucleus.height()
+        } else {
+            x_height
+        };
+        let accent_shift = skew + (*nucleus.width() - *accent_box.width()) / 2;
 
-                            atom.nucleus = Some(MathField::TeXBox(
-                                TeXBox::HorizontalBox(hbox),
-                            ));
-                        }
-                        None => {}
-                    }
+        let mut height = *accent_box.height() + *accent_box.depth() - delta
+            + *nucleus.height();
+        let mut list = vec![
+            VerticalListElem::Box {
+                tex_box: TeXBox::HorizontalBox(HorizontalBox {
+                    height: *accent_box.height(),
+                    depth: *accent_box.depth(),
+                    width: *nucleus.width(),
+                    list: Rc::new(vec![
+                        HorizontalListElem::Kern(accent_shift),
+                        HorizontalListElem::Box {
+                            tex_box: accent_box,
+                            shift: Dimen::zero(),
+                        },
+                    ]),
+                    glue_set_ratio: None,
+                }),
+                shift: Dimen::zero(),
+            },
+            VerticalListElem::VSkip(Glue::from_dimen(Dimen::zero() - delta)),
+        ];
+        if height < *nucleus.height() {
+            list.insert(
+                0,
+                VerticalListElem::VSkip(Glue::from_dimen(
+                    *nucleus.height() - height,
+                )),
+            );
+            height = *nucleus.height();
+        }
 
-                    if atom.has_subscript() || atom.has_superscript() {
-                        panic!("Unimplemented superscript/subscript");
+        let width = *nucleus.width();
+        let depth = *nucleus.depth();
+        list.push(VerticalListElem::Box {
+            tex_box: nucleus,
+            shift: Dimen::zero(),
+        });
+
+        atom.nucleus =
+            Some(MathField::TeXBox(TeXBox::VerticalBox(VerticalBox {
+                height,
+                depth,
+                width,
+                list: Rc::new(list),
+                glue_set_ratio: None,
+            })));
+        atom.kind = AtomKind::Ord;
+    }
+
+    // Finds the character to use for a delimiter whose height plus depth should
+    // be at least `size`, like TeX's var_delimiter. We look through the chain
+    // of larger characters starting at the small variant of the delimiter in
+    // the fonts for the current size and then the larger sizes, and then do
+    // the same for the large variant, and use the first character that's big
+    // enough. If none of them are, we use the biggest one. If we find an
+    // extensible character along the way, we stop there and build the
+    // delimiter out of its pieces instead. If there are no characters at all,
+    // like for null delimiters, we make an empty box that is
+    // \nulldelimiterspace wide.
+    fn make_variable_delimiter(
+        &mut self,
+        delimiter: &MathDelimiter,
+        size: Dimen,
+        style: &MathStyle,
+    ) -> TeXBox {
+        let font_styles: &[MathStyle] = match get_math_font_style(style) {
+            MathStyle::ScriptScriptStyle => &[
+                MathStyle::ScriptScriptStyle,
+                MathStyle::ScriptStyle,
+                MathStyle::TextStyle,
+            ],
+            MathStyle::ScriptStyle => {
+                &[MathStyle::ScriptStyle, MathStyle::TextStyle]
+            }
+            _ => &[MathStyle::TextStyle],
+        };
+        let variants = [
+            (delimiter.small_font_family, delimiter.small_position),
+            (delimiter.large_font_family, delimiter.large_position),
+        ];
+
+        let mut best: Option<(Font, char)> = None;
+        let mut best_size = Dimen::zero();
+        'search: for (family, position) in variants.iter() {
+            // A variant of family 0 and position 0 is missing.
+            if *family == 0 && *position == 0 {
+                continue;
+            }
+
+            for font_style in font_styles {
+                let font = self.get_math_font(font_style, *family);
+                let metrics = self.state.get_metrics_for_font(&font).unwrap();
+
+                let mut maybe_chr = Some(*position as char);
+                while let Some(chr) = maybe_chr {
+                    if !metrics.exists(chr) {
+                        break;
                     }
 
-                    elems_after_first_pass.push(MathListElem::Atom(atom));
+                    if metrics.get_extensible_recipe(chr).is_some() {
+                        best = Some((font.clone(), chr));
+                        break 'search;
+                    }
+
+                    let char_size =
+                        metrics.get_height(chr) + metrics.get_depth(chr);
+                    if char_size > best_size {
+                        best = Some((font.clone(), chr));
+                        best_size = char_size;
+                        if char_size >= size {
+                            break 'search;
+                        }
+                    }
+
+                    maybe_chr = metrics.get_next_larger_char(chr);
                 }
-                MathListElem::StyleChange(new_style) => {
-                    current_style = new_style.clone();
-                    elems_after_first_pass
-                        .push(MathListElem::StyleChange(new_style));
+            }
+        }
+
+        match best {
+            Some((font, chr)) => {
+                let recipe = self
+                    .state
+                    .get_metrics_for_font(&font)
+                    .unwrap()
+                    .get_extensible_recipe(chr);
+
+                match recipe {
+                    Some(recipe) => {
+                        self.make_extensible_stack(&font, &recipe, size)
+                    }
+                    None => TeXBox::HorizontalBox(
+                        self.add_to_natural_layout_horizontal_box(
+                            HorizontalBox::empty(),
+                            HorizontalListElem::Char { chr, font },
+                        ),
+                    ),
                 }
-                _ => {
-                    panic!("unimplemented math list elem: {:?}", elem);
+            }
+            None => {
+                let mut hbox = HorizontalBox::empty();
+                hbox.width = self
+                    .state
+                    .get_dimen_parameter(&DimenParameter::NullDelimiterSpace);
+                TeXBox::HorizontalBox(hbox)
+            }
+        }
+    }
+
+    // Stacks the pieces of an extensible character into a vertical box whose
+    // height plus depth is at least `size`. This is how big delimiters and
+    // radical signs are built, and how braces should be built once they exist.
+    // Each piece is a box exactly as tall and deep as its character, so
+    // consecutive pieces touch with no gaps in between. The repeated piece is
+    // used as few times as possible, and if there's a middle piece, the
+    // repeated piece is stacked the same number of times above and below it
+    // so that the middle piece stays centered.
+    fn make_extensible_stack(
+        &mut self,
+        font: &Font,
+        recipe: &ExtensibleRecipe,
+        size: Dimen,
+    ) -> TeXBox {
+        let metrics = self.state.get_metrics_for_font(font).unwrap();
+        let piece_size =
+            |chr: char| metrics.get_height(chr) + metrics.get_depth(chr);
+
+        let repeat_size = piece_size(recipe.repeat);
+        let mut total_size = [recipe.top, recipe.middle, recipe.bottom]
+            .iter()
+            .flatten()
+            .fold(Dimen::zero(), |total, chr| total + piece_size(*chr));
+
+        let mut num_repeats = 0;
+        if repeat_size > Dimen::zero() {
+            while total_size < size {
+                total_size = total_size + repeat_size;
+                if recipe.middle.is_some() {
+                    total_size = total_size + repeat_size;
                 }
+                num_repeats += 1;
             }
         }
 
-        let mut resulting_horizontal_list: Vec<HorizontalListElem> = Vec::new();
-        let mut maybe_last_atom_kind: Option<AtomKind> = None;
-        let mut current_style = start_style;
+        let width = metrics.get_width(recipe.repeat);
+        drop(metrics);
 
-        for elem in elems_after_first_pass {
-            match elem {
-                MathListElem::Atom(atom) => {
-                    if let Some(last_atom_kind) = maybe_last_atom_kind {
-                        if let Some(skip) = self.get_skip_for_atom_pair(
-                            &last_atom_kind,
-                            &atom.kind,
-                            &current_style,
-                        ) {
-                            resulting_horizontal_list
-                                .push(HorizontalListElem::HSkip(skip));
-                        }
-                    }
+        let mut pieces = Vec::new();
+        pieces.extend(recipe.top);
+        pieces.extend((0..num_repeats).map(|_| recipe.repeat));
+        if let Some(middle) = recipe.middle {
+            pieces.push(middle);
+            pieces.extend((0..num_repeats).map(|_| recipe.repeat));
+        }
+        pieces.extend(recipe.bottom);
 
-                    if atom.has_subscript() || atom.has_superscript() {
-                        panic!("Atoms should be sub/superscript free in second pass!");
+        let list: Vec<VerticalListElem> = pieces
+            .into_iter()
+            .map(|chr| VerticalListElem::Box {
+                tex_box: TeXBox::HorizontalBox(
+                    self.add_to_natural_layout_horizontal_box(
+                        HorizontalBox::empty(),
+                        HorizontalListElem::Char {
+                            chr,
+                            font: font.clone(),
+                        },
+                    ),
+                ),
+                shift: Dimen::zero(),
+            })
+            .collect();
+
+        // The reference point of the delimiter is the baseline of the top
+        // piece.
+        let height = match list.first() {
+            Some(VerticalListElem::Box { tex_box, .. }) => *tex_box.height(),
+            _ => Dimen::zero(),
+        };
+
+        TeXBox::VerticalBox(VerticalBox {
+            height,
+            depth: total_size - height,
+            width,
+            list: Rc::new(list),
+            glue_set_ratio: None,
+        })
+    }
+
+    // Makes an Open or Close atom for one of the delimiters from \left and
+    // \right, grown to at least `size` and centered on the axis.
+    fn make_boundary_atom(
+        &mut self,
+        kind: AtomKind,
+        delimiter: Option<MathDelimiter>,
+        size: Dimen,
+        style: &MathStyle,
+    ) -> MathAtom {
+        let mut atom = MathAtom::empty_ord();
+        atom.kind = kind;
+
+        if let Some(delimiter) = delimiter {
+            let tex_box = self.make_variable_delimiter(&delimiter, size, style);
+            let axis_height = self.get_math_font_parameter(style, 2, 22);
+            let shift =
+                axis_height - (*tex_box.height() - *tex_box.depth()) / 2;
+
+            let hbox = self
+                .combine_horizontal_list_into_horizontal_box_with_layout(
+                    vec![HorizontalListElem::Box { tex_box, shift }],
+                    &BoxLayout::Natural,
+                );
+            atom.nucleus = Some(MathField::TeXBox(TeXBox::HorizontalBox(hbox)));
+        }
+
+        atom
+    }
+
+    // Splits the Inner atom from a \left ... \right pair into an Open atom
+    // for the left delimiter, an Inner atom for the list between the
+    // delimiters, and a Close atom for the right delimiter, so that the
+    // delimiters are spaced like Open and Close atoms next to the things
+    // around them. Following rule 19 of Appendix G, the delimiters are grown
+    // so that they cover the list as far as it extends from the axis in
+    // either direction. Any scripts on the pair go on the right delimiter.
+    fn make_left_right(
+        &mut self,
+        mut atom: MathAtom,
+        style: &MathStyle,
+    ) -> (MathAtom, MathAtom, MathAtom) {
+        let mut list = match atom.nucleus.take() {
+            Some(MathField::MathList(list)) => list,
+            _ => unreachable!(),
+        };
+
+        let left_delimiter = match list.remove(0) {
+            MathListElem::Boundary(_, delimiter) => delimiter,
+            _ => unreachable!(),
+        };
+        let right_delimiter = match list.pop() {
+            Some(MathListElem::Boundary(BoundaryKind::Right, delimiter)) => {
+                delimiter
+            }
+            elem => panic!("Expected a \\right delimiter, found {:?}", elem),
+        };
+
+        let inner =
+            self.convert_math_field_to_box(MathField::MathList(list), style);
+
+        let axis_height = self.get_math_font_parameter(style, 2, 22);
+        let max_distance = max_dimen(
+            *inner.height() - axis_height,
+            *inner.depth() + axis_height,
+        );
+        // The delimiters have to cover at least \delimiterfactor/1000 of the
+        // formula, and can't fall short of covering it by more than
+        // \delimitershortfall.
+        let delimiter_factor = self
+            .state
+            .get_integer_parameter(&IntegerParameter::DelimiterFactor);
+        let delimiter_shortfall = self
+            .state
+            .get_dimen_parameter(&DimenParameter::DelimiterShortfall);
+        let size = max_dimen(
+            max_distance / 500 * delimiter_factor,
+            max_distance * 2 - delimiter_shortfall,
+        );
+
+        let left_atom = self.make_boundary_atom(
+            AtomKind::Open,
+            left_delimiter,
+            size,
+            style,
+        );
+        let mut right_atom = self.make_boundary_atom(
+            AtomKind::Close,
+            right_delimiter,
+            size,
+            style,
+        );
+        right_atom.superscript = atom.superscript.take();
+        right_atom.subscript = atom.subscript.take();
+        atom.nucleus = Some(MathField::TeXBox(inner));
+
+        (left_atom, atom, right_atom)
+    }
+
+    // Puts the radical sign of a Rad atom in front of its nucleus, with a rule
+    // over the nucleus that lines up with the top of the radical sign,
+    // following rule 11 of Appendix G. After that, the atom is treated like
+    // an Ord atom.
+    fn make_radical(&mut self, atom: &mut MathAtom, style: &MathStyle) {
+        let delimiter = atom.delimiter.take().unwrap();
+
+        let nucleus = match atom.nucleus.take() {
+            Some(field) => {
+                self.convert_math_field_to_box(field, &style.cramped_style())
+            }
+            None => TeXBox::HorizontalBox(HorizontalBox::empty()),
+        };
+
+        let abs = |dimen: Dimen| max_dimen(dimen, Dimen::zero() - dimen);
+        let rule_thickness = self.get_math_font_parameter(style, 3, 8);
+        let mut clearance = if style.is_display() {
+            rule_thickness + abs(self.get_math_font_parameter(style, 2, 5)) / 4
+        } else {
+            rule_thickness + abs(rule_thickness) / 4
+        };
+
+        let nucleus_size = *nucleus.height() + *nucleus.depth();
+        let surd = self.make_variable_delimiter(
+            &delimiter,
+            nucleus_size + clearance + rule_thickness,
+            style,
+        );
+
+        // If the radical sign is bigger than it needs to be, the extra space
+        // is split between the clearance and below the nucleus.
+        let delta = *surd.depth() - (nucleus_size + clearance);
+        if delta > Dimen::zero() {
+            clearance = clearance + delta / 2;
+        }
+
+        // The top of the radical sign is as thick as the rule should be, and
+        // the radical sign is raised so that its top lines up with the rule.
+        let bar_thickness = *surd.height();
+        let shift = *nucleus.height() + clearance;
+        let overbar = TeXBox::VerticalBox(VerticalBox {
+            height: bar_thickness * 2 + clearance + *nucleus.height(),
+            depth: *nucleus.depth(),
+            width: *nucleus.width(),
+            list: Rc::new(vec![
+                VerticalListElem::VSkip(Glue::from_dimen(bar_thickness)),
+                VerticalListElem::Rule {
+                    height: bar_thickness,
+                    depth: Dimen::zero(),
+                    width: None,
+                },
+                VerticalListElem::VSkip(Glue::from_dimen(clearance)),
+                VerticalListElem::Box {
+                    tex_box: nucleus,
+                    shift: Dimen::zero(),
+                },
+            ]),
+            glue_set_ratio: None,
+        });
+
+        let hbox = self
+            .combine_horizontal_list_into_horizontal_box_with_layout(
+                vec![
+                    HorizontalListElem::Box {
+                        tex_box: surd,
+                        shift,
+                    },
+                    HorizontalListElem::Box {
+                        tex_box: overbar,
+                        shift: Dimen::zero(),
+                    },
+                ],
+                &BoxLayout::Natural,
+            );
+
+        atom.nucleus = Some(MathField::TeXBox(TeXBox::HorizontalBox(hbox)));
+        atom.kind = AtomKind::Ord;
+    }
+
+    // Builds the nucleus of an Op atom whose nucleus is a single symbol,
+    // following the start of rule 13 of Appendix G. In display style, the next
+    // larger variant of the symbol is used if there is one, and the symbol is
+    // shifted so that it's centered vertically on the axis.
+    fn make_op_symbol(
+        &mut self,
+        symbol: &MathSymbol,
+        style: &MathStyle,
+    ) -> TeXBox {
+        let font = self.get_math_font(style, symbol.family_number);
+        let mut chr = symbol.position_number as char;
+        if style.is_display() {
+            let metrics = self.state.get_metrics_for_font(&font).unwrap();
+            if let Some(larger_chr) = metrics.get_next_larger_char(chr) {
+                if metrics.exists(larger_chr) {
+                    chr = larger_chr;
+                }
+            }
+        }
+
+        let char_box =
+            TeXBox::HorizontalBox(self.add_to_natural_layout_horizontal_box(
+                HorizontalBox::empty(),
+                HorizontalListElem::Char { chr, font },
+            ));
+        let axis_height = self.get_math_font_parameter(style, 2, 22);
+        let shift = axis_height - (*char_box.height() - *char_box.depth()) / 2;
+
+        TeXBox::HorizontalBox(
+            self.combine_horizontal_list_into_horizontal_box_with_layout(
+                vec![HorizontalListElem::Box {
+                    tex_box: char_box,
+                    shift,
+                }],
+                &BoxLayout::Natural,
+            ),
+        )
+    }
+
+    // Handles an Op atom whose nucleus has already been turned into a box,
+    // following rule 13 of Appendix G. If the atom's scripts should be set as
+    // limits, the nucleus and scripts are stacked into a single box.
+    fn make_op(&mut self, atom: &mut MathAtom, style: &MathStyle) {
+        let use_limits = match atom.limits {
+            OpLimits::Limits => true,
+            OpLimits::NoLimits => false,
+            OpLimits::DisplayLimits => style.is_display(),
+        };
+
+        if !use_limits || !(atom.has_superscript() || atom.has_subscript()) {
+            return;
+        }
+
+        let nucleus = match atom.nucleus.take() {
+            Some(MathField::TeXBox(tex_box)) => tex_box,
+            _ => TeXBox::HorizontalBox(HorizontalBox::empty()),
+        };
+        let superscript = atom.superscript.take().map(|field| {
+            self.convert_math_field_to_box(field, &style.superscript_style())
+        });
+        let subscript = atom.subscript.take().map(|field| {
+            self.convert_math_field_to_box(field, &style.subscript_style())
+        });
+
+        let big_op_spacing1 = self.get_math_font_parameter(style, 3, 9);
+        let big_op_spacing2 = self.get_math_font_parameter(style, 3, 10);
+        let big_op_spacing3 = self.get_math_font_parameter(style, 3, 11);
+        let big_op_spacing4 = self.get_math_font_parameter(style, 3, 12);
+        let big_op_spacing5 = self.get_math_font_parameter(style, 3, 13);
+
+        let width = [&superscript, &subscript]
+            .iter()
+            .filter_map(|script| script.as_ref())
+            .fold(*nucleus.width(), |width, script| {
+                max_dimen(width, *script.width())
+            });
+
+        let mut height = *nucleus.height();
+        let mut depth = *nucleus.depth();
+        let mut list = Vec::new();
+
+        if let Some(superscript) = superscript {
+            let shift_up = max_dimen(
+                big_op_spacing1,
+                big_op_spacing3 - *superscript.depth(),
+            );
+            height = height
+                + big_op_spacing5
+                + *superscript.height()
+                + *superscript.depth()
+                + shift_up;
+
+            list.push(VerticalListElem::VSkip(Glue::from_dimen(
+                big_op_spacing5,
+            )));
+            list.push(VerticalListElem::Box {
+                tex_box: center_box_in_width(superscript, width),
+                shift: Dimen::zero(),
+            });
+            list.push(VerticalListElem::VSkip(Glue::from_dimen(shift_up)));
+        }
+
+        list.push(VerticalListElem::Box {
+            tex_box: center_box_in_width(nucleus, width),
+            shift: Dimen::zero(),
+        });
+
+        if let Some(subscript) = subscript {
+            let shift_down = max_dimen(
+                big_op_spacing2,
+                big_op_spacing4 - *subscript.height(),
+            );
+            depth = depth
+                + shift_down
+                + *subscript.height()
+                + *subscript.depth()
+                + big_op_spacing5;
+
+            list.push(VerticalListElem::VSkip(Glue::from_dimen(shift_down)));
+            list.push(VerticalListElem::Box {
+                tex_box: center_box_in_width(subscript, width),
+                shift: Dimen::zero(),
+            });
+            list.push(VerticalListElem::VSkip(Glue::from_dimen(
+                big_op_spacing5,
+            )));
+        }
+
+        atom.nucleus =
+            Some(MathField::TeXBox(TeXBox::VerticalBox(VerticalBox {
+                height,
+                depth,
+                width,
+                list: Rc::new(list),
+                glue_set_ratio: None,
+            })));
+    }
+
+    // Attaches the superscript and subscript of an atom to its nucleus,
+    // following rules 18a-f of Appendix G. `nucleus_is_char` says whether
+    // the nucleus was a single character. Following rule 18a, scripts on a
+    // character start with both shifts at zero, and scripts on other nuclei
+    // start from the height and depth of the nucleus's box.
+    fn make_scripts(
+        &mut self,
+        atom: &mut MathAtom,
+        nucleus_is_char: bool,
+        style: &MathStyle,
+    ) {
+        let nucleus = match atom.nucleus.take() {
+            Some(MathField::TeXBox(tex_box)) => tex_box,
+            _ => TeXBox::HorizontalBox(HorizontalBox::empty()),
+        };
+
+        let (mut shift_up, mut shift_down) = if nucleus_is_char {
+            (Dimen::zero(), Dimen::zero())
+        } else {
+            let script_style = style.superscript_style();
+            (
+                *nucleus.height()
+                    - self.get_math_font_parameter(&script_style, 2, 18),
+                *nucleus.depth()
+                    + self.get_math_font_parameter(&script_style, 2, 19),
+            )
+        };
+
+        let x_height = self.get_math_font_parameter(style, 2, 5);
+        let script_space =
+            self.state.get_dimen_parameter(&DimenParameter::ScriptSpace);
+
+        let scripts_elem =
+            match (atom.superscript.take(), atom.subscript.take()) {
+                (None, Some(subscript)) => {
+                    let mut sub_box = self.convert_math_field_to_box(
+                        subscript,
+                        &style.subscript_style(),
+                    );
+                    *sub_box.mut_width() = *sub_box.width() + script_space;
+
+                    shift_down = max_dimen(
+                        max_dimen(
+                            shift_down,
+                            self.get_math_font_parameter(style, 2, 16),
+                        ),
+                        *sub_box.height() - x_height * 4 / 5,
+                    );
+
+                    HorizontalListElem::Box {
+                        tex_box: sub_box,
+                        shift: Dimen::zero() - shift_down,
                     }
+                }
+                (Some(superscript), maybe_subscript) => {
+                    let mut sup_box = self.convert_math_field_to_box(
+                        superscript,
+                        &style.superscript_style(),
+                    );
+                    *sup_box.mut_width() = *sup_box.width() + script_space;
 
-                    match atom.nucleus {
-                        Some(MathField::TeXBox(tex_box)) => {
-                            resulting_horizontal_list.push(
-                                HorizontalListElem::Box {
-                                    tex_box,
-                                    shift: Dimen::zero(),
-                                },
+                    let sup_param = if style.is_cramped() {
+                        15
+                    } else if style.is_display() {
+                        13
+                    } else {
+                        14
+                    };
+                    shift_up = max_dimen(
+                        max_dimen(
+                            shift_up,
+                            self.get_math_font_parameter(style, 2, sup_param),
+                        ),
+                        *sup_box.depth() + x_height / 4,
+                    );
+
+                    match maybe_subscript {
+                        None => HorizontalListElem::Box {
+                            tex_box: sup_box,
+                            shift: shift_up,
+                        },
+                        Some(subscript) => {
+                            let mut sub_box = self.convert_math_field_to_box(
+                                subscript,
+                                &style.subscript_style(),
                             );
-                        }
-                        None => {}
-                        _ => {
-                            panic!("Atom nucleuses should only be boxes in second pass!");
+                            *sub_box.mut_width() =
+                                *sub_box.width() + script_space;
+
+                            shift_down = max_dimen(
+                                shift_down,
+                                self.get_math_font_parameter(style, 2, 17),
+                            );
+
+                            // Make sure there's enough space between the two
+                            // scripts.
+                            let rule_thickness =
+                                self.get_math_font_parameter(style, 3, 8);
+                            let clearance = rule_thickness * 4
+                                - ((shift_up - *sup_box.depth())
+                                    - (*sub_box.height() - shift_down));
+                            if clearance > Dimen::zero() {
+                                shift_down = shift_down + clearance;
+
+                                let clearance = x_height * 4 / 5
+                                    - (shift_up - *sup_box.depth());
+                                if clearance > Dimen::zero() {
+                                    shift_up = shift_up + clearance;
+                                    shift_down = shift_down - clearance;
+                                }
+                            }
+
+                            let gap = (shift_up - *sup_box.depth())
+                                - (*sub_box.height() - shift_down);
+                            let vbox = VerticalBox {
+                                height: *sup_box.height()
+                                    + *sup_box.depth()
+                                    + gap
+                                    + *sub_box.height(),
+                                depth: *sub_box.depth(),
+                                width: max_dimen(
+                                    *sup_box.width(),
+                                    *sub_box.width(),
+                                ),
+                                list: Rc::new(vec![
+                                    VerticalListElem::Box {
+                                        tex_box: sup_box,
+                                        shift: Dimen::zero(),
+                                    },
+                                    VerticalListElem::VSkip(Glue::from_dimen(
+                                        gap,
+                                    )),
+                                    VerticalListElem::Box {
+                                        tex_box: sub_box,
+                                        shift: Dimen::zero(),
+                                    },
+                                ]),
+                                glue_set_ratio: None,
+                            };
+
+                            HorizontalListElem::Box {
+                                tex_box: TeXBox::VerticalBox(vbox),
+                                shift: Dimen::zero() - shift_down,
+                            }
                         }
                     }
-
-                    maybe_last_atom_kind = Some(atom.kind);
-                }
-                MathListElem::StyleChange(new_style) => {
-                    current_style = new_style;
-                }
-                _ => {
-                    panic!("unimplemented math list elem: {:?}", elem);
                 }
+                (None, None) => panic!("Atom has no scripts to attach"),
+            };
+
+        let hbox = self
+            .combine_horizontal_list_into_horizontal_box_with_layout(
+                vec![
+                    HorizontalListElem::Box {
+                        tex_box: nucleus,
+                        shift: Dimen::zero(),
+                    },
+                    scripts_elem,
+                ],
+                &BoxLayout::Natural,
+            );
+        atom.nucleus = Some(MathField::TeXBox(TeXBox::HorizontalBox(hbox)));
+    }
+
+    // Makes a box for one of the delimiters around a generalized fraction,
+    // grown to at least `size` and centered on the axis. Fractions without
+    // delimiters get null delimiters, which are just \nulldelimiterspace.
+    fn make_fraction_delimiter(
+        &mut self,
+        delimiter: Option<MathDelimiter>,
+        size: Dimen,
+        style: &MathStyle,
+    ) -> HorizontalListElem {
+        let delimiter =
+            delimiter.unwrap_or_else(|| MathDelimiter::from_number(0));
+        let tex_box = self.make_variable_delimiter(&delimiter, size, style);
+        let axis_height = self.get_math_font_parameter(style, 2, 22);
+        let shift = axis_height - (*tex_box.height() - *tex_box.depth()) / 2;
+
+        HorizontalListElem::Box { tex_box, shift }
+    }
+
+    // Stacks the numerator of a generalized fraction over its denominator,
+    // with a rule between them centered on the axis, following rule 15 of
+    // Appendix G. The resulting atom is treated like an Inner atom.
+    fn make_fraction(
+        &mut self,
+        fraction: GeneralizedFraction,
+        style: &MathStyle,
+    ) -> MathAtom {
+        let default_rule_thickness = self.get_math_font_parameter(style, 3, 8);
+        let thickness = fraction.bar_height.unwrap_or(default_rule_thickness);
+
+        let numerator = self.convert_math_field_to_box(
+            MathField::MathList(fraction.numerator),
+            &style.fraction_numerator_style(),
+        );
+        let denominator = self.convert_math_field_to_box(
+            MathField::MathList(fraction.denominator),
+            &style.fraction_denominator_style(),
+        );
+
+        // The narrower of the numerator and denominator is centered over the
+        // wider one.
+        let width = max_dimen(*numerator.width(), *denominator.width());
+        let numerator = if *numerator.width() < width {
+            center_box_in_width(numerator, width)
+        } else {
+            numerator
+        };
+        let denominator = if *denominator.width() < width {
+            center_box_in_width(denominator, width)
+        } else {
+            denominator
+        };
+
+        let (mut shift_up, mut shift_down) = if style.is_display() {
+            (
+                self.get_math_font_parameter(style, 2, 8),
+                self.get_math_font_parameter(style, 2, 11),
+            )
+        } else if thickness != Dimen::zero() {
+            (
+                self.get_math_font_parameter(style, 2, 9),
+                self.get_math_font_parameter(style, 2, 12),
+            )
+        } else {
+            (
+                self.get_math_font_parameter(style, 2, 10),
+                self.get_math_font_parameter(style, 2, 12),
+            )
+        };
+
+        let numerator_height = *numerator.height();
+        let denominator_depth = *denominator.depth();
+        let list = if thickness == Dimen::zero() {
+            let min_clearance = if style.is_display() {
+                default_rule_thickness * 7
+            } else {
+                default_rule_thickness * 3
+            };
+
+            let clearance = (shift_up - *numerator.depth())
+                - (*denominator.height() - shift_down);
+            if clearance < min_clearance {
+                shift_up = shift_up + (min_clearance - clearance) / 2;
+                shift_down = shift_down + (min_clearance - clearance) / 2;
+            }
+
+            let gap = (shift_up - *numerator.depth())
+                - (*denominator.height() - shift_down);
+            vec![
+                VerticalListElem::Box {
+                    tex_box: numerator,
+                    shift: Dimen::zero(),
+                },
+                VerticalListElem::VSkip(Glue::from_dimen(gap)),
+                VerticalListElem::Box {
+                    tex_box: denominator,
+                    shift: Dimen::zero(),
+                },
+            ]
+        } else {
+            let min_clearance = if style.is_display() {
+                thickness * 3
+            } else {
+                thickness
+            };
+            let axis_height = self.get_math_font_parameter(style, 2, 22);
+
+            let clearance_above =
+                (shift_up - *numerator.depth()) - (axis_height + thickness / 2);
+            if clearance_above < min_clearance {
+                shift_up = shift_up + (min_clearance - clearance_above);
+            }
+
+            let clearance_below = (axis_height - thickness / 2)
+                - (*denominator.height() - shift_down);
+            if clearance_below < min_clearance {
+                shift_down = shift_down + (min_clearance - clearance_below);
             }
+
+            let gap_above =
+                (shift_up - *numerator.depth()) - (axis_height + thickness / 2);
+            let gap_below = (axis_height - thickness / 2)
+                - (*denominator.height() - shift_down);
+            vec![
+                VerticalListElem::Box {
+                    tex_box: numerator,
+                    shift: Dimen::zero(),
+                },
+                VerticalListElem::VSkip(Glue::from_dimen(gap_above)),
+                VerticalListElem::Rule {
+                    height: thickness,
+                    depth: Dimen::zero(),
+                    width: None,
+                },
+                VerticalListElem::VSkip(Glue::from_dimen(gap_below)),
+                VerticalListElem::Box {
+                    tex_box: denominator,
+                    shift: Dimen::zero(),
+                },
+            ]
+        };
+
+        let vbox = VerticalBox {
+            height: shift_up + numerator_height,
+            depth: denominator_depth + shift_down,
+            width,
+            list: Rc::new(list),
+            glue_set_ratio: None,
+        };
+
+        let delimiter_size = if style.is_display() {
+            self.get_math_font_parameter(style, 2, 20)
+        } else {
+            self.get_math_font_parameter(style, 2, 21)
+        };
+
+        let hlist = vec![
+            self.make_fraction_delimiter(
+                fraction.left_delim,
+                delimiter_size,
+                style,
+            ),
+            HorizontalListElem::Box {
+                tex_box: TeXBox::VerticalBox(vbox),
+                shift: Dimen::zero(),
+            },
+            self.make_fraction_delimiter(
+                fraction.right_delim,
+                delimiter_size,
+                style,
+            ),
+        ];
+
+        let hbox = self
+            .combine_horizontal_list_into_horizontal_box_with_layout(
+                hlist,
+                &BoxLayout::Natural,
+            );
+
+        MathAtom {
+            kind: AtomKind::Inner,
+            nucleus: Some(MathField::TeXBox(TeXBox::HorizontalBox(hbox))),
+            ..MathAtom::empty_ord()
         }
+    }
+
+    pub fn parse_math_list(&mut self) -> MathList {
+        let mut current_list = Vec::new();
+        // After an \over, everything before it becomes the numerator of the
+        // fraction and we start collecting the denominator in current_list.
+        let mut fraction: Option<GeneralizedFraction> = None;
+
+        loop {
+            if self.is_math_symbol_head() {
+                let math_code = self.parse_math_symbol();
+
+                current_list.push(MathListElem::Atom(
+                    MathAtom::from_math_code(&math_code),
+                ));
+            } else if self.is_math_superscript_head()
+                || self.is_math_subscript_head()
+            {
+                let is_superscript = self.is_math_superscript_head();
+
+                let last_atom = match current_list.pop() {
+                    Some(MathListElem::Atom(atom)) => atom,
+                    Some(other_elem) => {
+                        current_list.push(other_elem);
+                        MathAtom::empty_ord()
+                    }
+                    None => MathAtom::empty_ord(),
+                };
+
+                current_list.push(MathListElem::Atom(if is_superscript {
+                    self.parse_math_superscript(last_atom)
+                } else {
+                    self.parse_math_subscript(last_atom)
+                }));
+            } else if self.is_assignment_head() {
+                self.parse_assignment();
+            } else if self.is_style_change_head() {
+                let style_change = self.parse_style_change();
+                current_list.push(MathListElem::StyleChange(style_change));
+            } else if self.is_fraction_head() {
+                if fraction.is_some() {
+                    panic!("{}", "Ambiguous; you need another { and }");
+                }
+                let numerator = std::mem::take(&mut current_list);
+                fraction = Some(self.parse_fraction(numerator));
+            } else if self
+                .is_next_expanded_token_in_set_of_primitives(&["leavevmode"])
+            {
+                // We're already in math mode, which is never vertical mode.
+                self.lex_expanded_token();
+            } else if self.is_math_atom_kind_head() {
+                let atom = self.parse_math_atom_with_kind();
+                current_list.push(MathListElem::Atom(atom));
+            } else if self.is_vcenter_head() {
+                let atom = self.parse_vcenter();
+                current_list.push(MathListElem::Atom(atom));
+            } else if self.is_mathstrut_head() {
+                current_list.push(self.parse_mathstrut());
+            } else if self.is_left_head() {
+                let atom = self.parse_left_right();
+                current_list.push(MathListElem::Atom(atom));
+            } else if self.is_right_head() {
+                break;
+            } else if self.is_math_accent_head() {
+                let atom = self.parse_math_accent();
+                current_list.push(MathListElem::Atom(atom));
+            } else if self.is_radical_head() {
+                let atom = self.parse_radical();
+                current_list.push(MathListElem::Atom(atom));
+            } else if self
+                .is_next_expanded_token_in_set_of_primitives(&["hskip"])
+            {
+                self.lex_expanded_token();
+                let glue = self.parse_glue();
+                current_list.push(MathListElem::Glue(glue));
+            } else if self
+                .is_next_expanded_token_in_set_of_primitives(&["mskip"])
+            {
+                self.lex_expanded_token();
+                let mu_glue = self.parse_mu_glue();
+                current_list.push(MathListElem::MuGlue(mu_glue));
+            } else if self
+                .is_next_expanded_token_in_set_of_primitives(&["nonscript"])
+            {
+                self.lex_expanded_token();
+                current_list.push(MathListElem::NonScript);
+            } else if self.is_limits_head() {
+                let limits = self.parse_limits();
+
+                match current_list.last_mut() {
+                    Some(MathListElem::Atom(atom))
+                        if atom.kind == AtomKind::Op =>
+                    {
+                        atom.limits = limits;
+                    }
+                    _ => panic!("Limit controls must follow a math operator"),
+                }
+            } else {
+                match self.peek_expanded_token() {
+                    Some(Token::Char(_, Category::BeginGroup)) => {
+                        let inner_list = self.parse_math_group();
+                        current_list.push(MathListElem::Atom(
+                            MathAtom::from_math_list(inner_list),
+                        ));
+                    }
+                    Some(Token::Char(_, Category::Space)) => {
+                        self.lex_expanded_token();
+                    }
+                    Some(Token::Char(_, Category::EndGroup)) => break,
+                    Some(Token::Char(_, Category::MathShift)) => break,
+                    None => break,
+                    _ => panic!("unimplemented"),
+                }
+            }
+        }
+
+        match fraction {
+            Some(mut fraction) => {
+                fraction.denominator = current_list;
+                vec![MathListElem::GeneralizedFraction(fraction)]
+            }
+            None => current_list,
+        }
+    }
+
+    fn get_skip_for_atom_pair(
+        &mut self,
+        left_type: &AtomKind,
+        right_type: &AtomKind,
+        style: &MathStyle,
+    ) -> Option<MuGlue> {
+        let thinskip = self
+            .state
+            .get_mu_glue_parameter(&MuGlueParameter::ThinMuSkip);
+        let mediumskip = self
+            .state
+            .get_mu_glue_parameter(&MuGlueParameter::MedMuSkip);
+        let thickskip = self
+            .state
+            .get_mu_glue_parameter(&MuGlueParameter::ThickMuSkip);
+
+        if let Some(space) = INTER_ATOM_SPACING.get(&(*left_type, *right_type))
+        {
+            match (space, style.is_script()) {
+                (InterAtomSpacing::None, _) => None,
+                (InterAtomSpacing::ThinSkip, _) => Some(thinskip),
+                (InterAtomSpacing::ThinSkipNonScript, false) => Some(thinskip),
+                (InterAtomSpacing::ThinSkipNonScript, true) => None,
+                (InterAtomSpacing::MediumSkipNonScript, false) => {
+                    Some(mediumskip)
+                }
+                (InterAtomSpacing::MediumSkipNonScript, true) => None,
+                (InterAtomSpacing::ThickSkipNonScript, false) => {
+                    Some(thickskip)
+                }
+                (InterAtomSpacing::ThickSkipNonScript, true) => None,
+            }
+        } else {
+            panic!("Invalid atom type pair: {:?}/{:?}", left_type, right_type);
+        }
+    }
+
+    /// Converts a math list into a horizontal list, starting in the given
+    /// style. If `add_penalties` is true, penalties are added after Bin and
+    /// Rel atoms so that the list can be broken there, like in inline math.
+    pub fn convert_math_list_to_horizontal_list(
+        &mut self,
+        list: MathList,
+        start_style: MathStyle,
+        add_penalties: bool,
+    ) -> Vec<HorizontalListElem> {
+        let mut elems_after_first_pass: MathList = Vec::new();
+        let mut current_style = start_style.clone();
+
+        let mut remaining_elems: VecDeque<MathListElem> = list.into();
+        while let Some(elem) = remaining_elems.pop_front() {
+            match elem {
+                MathListElem::Atom(mut atom) => {
+                    if is_left_right_atom(&atom) {
+                        let (left_atom, inner_atom, right_atom) =
+                            self.make_left_right(atom, &current_style);
+                        for mut boundary_atom in [left_atom, inner_atom] {
+                            reclassify_bin_atoms(
+                                &mut elems_after_first_pass,
+                                &mut boundary_atom,
+                            );
+                            elems_after_first_pass
+                                .push(MathListElem::Atom(boundary_atom));
+                        }
+                        atom = right_atom;
+                    }
+
+                    if atom.kind == AtomKind::Vcent {
+                        self.center_vcenter_atom_on_axis(
+                            &mut atom,
+                            &current_style,
+                        );
+                    }
+
+                    if atom.kind == AtomKind::Acc {
+                        self.make_math_accent(&mut atom, &current_style);
+                    }
+
+                    if atom.kind == AtomKind::Rad {
+                        self.make_radical(&mut atom, &current_style);
+                    }
+
+                    reclassify_bin_atoms(
+                        &mut elems_after_first_pass,
+                        &mut atom,
+                    );
+
+                    let nucleus_is_symbol =
+                        matches!(atom.nucleus, Some(MathField::Symbol(_)));
+
+                    let is_op = atom.kind == AtomKind::Op;
+                    atom.nucleus =
+                        atom.nucleus.take().map(|nucleus| match nucleus {
+                            MathField::Symbol(symbol) if is_op => {
+                                MathField::TeXBox(
+                                    self.make_op_symbol(
+                                        &symbol,
+                                        &current_style,
+                                    ),
+                                )
+                            }
+                            nucleus => MathField::TeXBox(
+                                self.convert_math_field_to_box(
+                                    nucleus,
+                                    &current_style,
+                                ),
+                            ),
+                        });
+
+                    if atom.kind == AtomKind::Op {
+                        self.make_op(&mut atom, &current_style);
+                    }
+
+                    if atom.has_subscript() || atom.has_superscript() {
+                        // Scripts on Op symbols are placed like they are on
+                        // boxes, since the symbols are usually large.
+                        let nucleus_is_char =
+                            nucleus_is_symbol && atom.kind != AtomKind::Op;
+                        self.make_scripts(
+                            &mut atom,
+                            nucleus_is_char,
+                            &current_style,
+                        );
+                    }
+
+                    elems_after_first_pass.push(MathListElem::Atom(atom));
+                }
+                MathListElem::StyleChange(new_style) => {
+                    current_style = new_style.clone();
+                    elems_after_first_pass
+                        .push(MathListElem::StyleChange(new_style));
+                }
+                MathListElem::GeneralizedFraction(fraction) => {
+                    let mut atom = self.make_fraction(fraction, &current_style);
+                    reclassify_bin_atoms(
+                        &mut elems_after_first_pass,
+                        &mut atom,
+                    );
+                    elems_after_first_pass.push(MathListElem::Atom(atom));
+                }
+                elem @ MathListElem::Glue(_)
+                | elem @ MathListElem::MuGlue(_)
+                | elem @ MathListElem::NonScript => {
+                    elems_after_first_pass.push(elem);
+                }
+                MathListElem::FourWayChoice {
+                    display,
+                    text,
+                    script,
+                    scriptscript,
+                } => {
+                    // The list for the current style replaces the choice,
+                    // and the others are thrown away.
+                    let chosen = if current_style.is_display() {
+                        display
+                    } else {
+                        match get_math_font_style(&current_style) {
+                            MathStyle::TextStyle => text,
+                            MathStyle::ScriptStyle => script,
+                            _ => scriptscript,
+                        }
+                    };
+                    for chosen_elem in chosen.into_iter().rev() {
+                        remaining_elems.push_front(chosen_elem);
+                    }
+                }
+                _ => {
+                    panic!("unimplemented math list elem: {:?}", elem);
+                }
+            }
+        }
+
+        // A Bin atom at the end of the list has nothing to apply to.
+        if let Some(last_atom) = get_last_atom(&mut elems_after_first_pass) {
+            if last_atom.kind == AtomKind::Bin {
+                last_atom.kind = AtomKind::Ord;
+            }
+        }
+
+        let mut resulting_horizontal_list: Vec<HorizontalListElem> = Vec::new();
+        // Space only goes between atoms, so nothing is added before the
+        // first atom of a list, including the lists inside subformulas and
+        // \left...\right groups, which are converted on their own.
+        let mut maybe_last_atom_kind: Option<AtomKind> = None;
+        let mut current_style = start_style;
+
+        let mut elems = elems_after_first_pass.into_iter().peekable();
+        while let Some(elem) = elems.next() {
+            match elem {
+                MathListElem::Atom(atom) => {
+                    if let Some(last_atom_kind) = maybe_last_atom_kind {
+                        if let Some(skip) = self.get_skip_for_atom_pair(
+                            &last_atom_kind,
+                            &atom.kind,
+                            &current_style,
+                        ) {
+                            // The skip is in mu, which are measured using the
+                            // quad of the math font in the current style.
+                            let math_quad = self.get_math_font_parameter(
+                                &current_style,
+                                2,
+                                6,
+                            );
+                            resulting_horizontal_list.push(
+                                HorizontalListElem::HSkip(
+                                    skip.to_glue(math_quad),
+                                ),
+                            );
+                        }
+                    }
+
+                    if atom.has_subscript() || atom.has_superscript() {
+                        panic!("Atoms should be sub/superscript free in second pass!");
+                    }
+
+                    match atom.nucleus {
+                        Some(MathField::TeXBox(tex_box)) => {
+                            resulting_horizontal_list.push(
+                                HorizontalListElem::Box {
+                                    tex_box,
+                                    shift: Dimen::zero(),
+                                },
+                            );
+                        }
+                        None => {}
+                        _ => {
+                            panic!("Atom nucleuses should only be boxes in second pass!");
+                        }
+                    }
+
+                    // Inline math can be broken after Bin and Rel atoms, as
+                    // long as it isn't at the end of the list or between two
+                    // Rel atoms.
+                    let penalty = match atom.kind {
+                        AtomKind::Bin => self.state.get_integer_parameter(
+                            &IntegerParameter::BinOpPenalty,
+                        ),
+                        AtomKind::Rel => self.state.get_integer_parameter(
+                            &IntegerParameter::RelPenalty,
+                        ),
+                        _ => INF_PENALTY,
+                    };
+                    let can_break_after = match elems.peek() {
+                        Some(MathListElem::Atom(next_atom)) => {
+                            next_atom.kind != AtomKind::Rel
+                        }
+                        Some(_) => true,
+                        None => false,
+                    };
+                    if add_penalties && can_break_after && penalty < INF_PENALTY
+                    {
+                        resulting_horizontal_list
+                            .push(HorizontalListElem::Penalty(penalty));
+                    }
+
+                    maybe_last_atom_kind = Some(atom.kind);
+                }
+                MathListElem::StyleChange(new_style) => {
+                    current_style = new_style;
+                }
+                // Glue doesn't affect the spacing between the atoms around
+                // it.
+                MathListElem::Glue(glue) => {
+                    resulting_horizontal_list
+                        .push(HorizontalListElem::HSkip(glue));
+                }
+                MathListElem::MuGlue(mu_glue) => {
+                    let math_quad =
+                        self.get_math_font_parameter(&current_style, 2, 6);
+                    resulting_horizontal_list.push(HorizontalListElem::HSkip(
+                        mu_glue.to_glue(math_quad),
+                    ));
+                }
+                MathListElem::NonScript => {
+                    if current_style.is_script() {
+                        if let Some(
+                            MathListElem::Glue(_) | MathListElem::MuGlue(_),
+                        ) = elems.peek()
+                        {
+                            elems.next();
+                        }
+                    }
+                }
+                _ => {
+                    panic!("unimplemented math list elem: {:?}", elem);
+                }
+            }
+        }
+
+        resulting_horizontal_list
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dimension::Unit;
+    use crate::testing::with_parser;
+
+    // Makes definitions of \, \> and \; as \hskips of the default
+    // \thinmuskip, \medmuskip and \thickmuskip in text style.
+    fn get_math_skip_definitions() -> Vec<String> {
+        let mut definitions = Vec::new();
+        with_parser(&["%"], |parser| {
+            let math_quad =
+                parser.get_math_font_parameter(&MathStyle::TextStyle, 2, 6);
+            for (name, param) in &[
+                (",", MuGlueParameter::ThinMuSkip),
+                (">", MuGlueParameter::MedMuSkip),
+                (";", MuGlueParameter::ThickMuSkip),
+            ] {
+                let glue = parser
+                    .state
+                    .get_mu_glue_parameter(param)
+                    .to_glue(math_quad);
+                definitions.push(format!(
+                    r"\def\{}{{\hskip {}}}%",
+                    name,
+                    glue.to_tex_string()
+                ));
+            }
+        });
+        definitions
+    }
+
+    // Checks that a math list converts to a horizontal list. The math skips
+    // are available as \, \> and \; in the horizontal list.
+    fn assert_math_list_converts_to_horizontal_list(
+        math_list_lines: &[&str],
+        horizontal_list_lines: &[&str],
+    ) {
+        let definitions = get_math_skip_definitions();
+        let mut hlist_lines: Vec<&str> =
+            definitions.iter().map(String::as_str).collect();
+        hlist_lines.extend(horizontal_list_lines);
+
+        with_parser(math_list_lines, |math_parser| {
+            with_parser(&hlist_lines, |hlist_parser| {
+                let math_list = math_parser.parse_math_list();
+                let horizontal_list =
+                    hlist_parser.parse_horizontal_list(false, false);
+
+                assert_eq!(
+                    math_parser.convert_math_list_to_horizontal_list(
+                        math_list,
+                        MathStyle::TextStyle,
+                        false,
+                    ),
+                    horizontal_list
+                );
+            });
+        });
+    }
+
+    #[test]
+    fn it_parses_math_symbols() {
+        with_parser(&["a2*%"], |parser| {
+            assert_eq!(
+                parser.parse_math_symbol(),
+                MathCode::from_number(0x7161)
+            );
+            assert_eq!(
+                parser.parse_math_symbol(),
+                MathCode::from_number(0x7032)
+            );
+            assert_eq!(
+                parser.parse_math_symbol(),
+                MathCode::from_number(0x002a)
+            );
+        });
+    }
+
+    #[test]
+    fn it_parses_math_symbols_from_chardefs() {
+        with_parser(&[r"\let\x=z%", r"\x%"], |parser| {
+            parser.parse_assignment();
+
+            assert_eq!(
+                parser.parse_math_symbol(),
+                MathCode::from_number(0x717a)
+            );
+        });
+    }
+
+    #[test]
+    fn it_parses_basic_atoms_in_math_lists() {
+        with_parser(&[r"a*%"], |parser| {
+            assert_eq!(
+                parser.parse_math_list(),
+                vec![
+                    MathListElem::Atom(MathAtom::from_math_code(
+                        &MathCode::from_number(0x7161)
+                    )),
+                    MathListElem::Atom(MathAtom::from_math_code(
+                        &MathCode::from_number(0x002a)
+                    )),
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn it_parses_basic_math_groups() {
+        with_parser(&[r"{a}%"], |parser| {
+            assert_eq!(
+                parser.parse_math_group(),
+                vec![MathListElem::Atom(MathAtom::from_math_code(
+                    &MathCode::from_number(0x7161)
+                )),],
+            );
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid start of math group")]
+    fn it_fails_parsing_math_groups_not_starting_with_begin_group() {
+        with_parser(&[r"a%"], |parser| {
+            parser.parse_math_group();
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Math group didn't end with an EndGroup")]
+    fn it_fails_parsing_math_groups_not_ending_with_end_group() {
+        with_parser(&[r"{a%"], |parser| {
+            parser.parse_math_group();
+        });
+    }
+
+    #[test]
+    fn it_scopes_assignments_in_math_fields() {
+        with_parser(&[r"\count 0=1%", r"a^{\count 0=2}%"], |parser| {
+            parser.parse_math_list();
+
+            assert_eq!(parser.state.get_count(0), 1);
+        });
+    }
+
+    #[test]
+    fn it_parses_symbols_as_math_fields() {
+        with_parser(&[r"a2%"], |parser| {
+            assert_eq!(
+                parser.parse_math_field(),
+                MathField::Symbol(MathSymbol::from_math_code(
+                    &MathCode::from_number(0x7161)
+                ))
+            );
+            assert_eq!(
+                parser.parse_math_field(),
+                MathField::Symbol(MathSymbol::from_math_code(
+                    &MathCode::from_number(0x7032)
+                ))
+            );
+        })
+    }
+
+    #[test]
+    fn it_parses_groups_as_math_fields() {
+        with_parser(&[r"{ab}{}%"], |parser| {
+            assert_eq!(
+                parser.parse_math_field(),
+                MathField::MathList(vec![
+                    MathListElem::Atom(MathAtom::from_math_code(
+                        &MathCode::from_number(0x7161)
+                    )),
+                    MathListElem::Atom(MathAtom::from_math_code(
+                        &MathCode::from_number(0x7162)
+                    )),
+                ],)
+            );
+            assert_eq!(parser.parse_math_field(), MathField::MathList(vec![],));
+        });
+    }
+
+    #[test]
+    fn it_ignores_filler_before_math_fields() {
+        with_parser(&[r"  a   {a}%"], |parser| {
+            assert_eq!(
+                parser.parse_math_field(),
+                MathField::Symbol(MathSymbol::from_math_code(
+                    &MathCode::from_number(0x7161)
+                ))
+            );
+            assert_eq!(
+                parser.parse_math_field(),
+                MathField::MathList(vec![MathListElem::Atom(
+                    MathAtom::from_math_code(&MathCode::from_number(0x7161))
+                ),],)
+            );
+        });
+    }
+
+    #[test]
+    fn it_parses_superscripts() {
+        let a_code = MathCode::from_number(0x7161);
+        let b_code = MathCode::from_number(0x7162);
+
+        with_parser(&[r"a^a%", r"a^{ab}%"], |parser| {
+            assert_eq!(
+                parser.parse_math_list(),
+                vec![
+                    MathListElem::Atom(
+                        MathAtom::from_math_code(&a_code).with_superscript(
+                            MathField::Symbol(MathSymbol::from_math_code(
+                                &a_code
+                            ))
+                        )
+                    ),
+                    MathListElem::Atom(
+                        MathAtom::from_math_code(&a_code).with_superscript(
+                            MathField::MathList(vec![
+                                MathListElem::Atom(MathAtom::from_math_code(
+                                    &a_code
+                                )),
+                                MathListElem::Atom(MathAtom::from_math_code(
+                                    &b_code
+                                )),
+                            ])
+                        )
+                    )
+                ],
+            );
+        });
+    }
+
+    #[test]
+    fn it_parses_superscripts_at_beginning_of_lists() {
+        let a_code = MathCode::from_number(0x7161);
+
+        with_parser(&[r"^a%"], |parser| {
+            assert_eq!(
+                parser.parse_math_list(),
+                vec![MathListElem::Atom(
+                    MathAtom::empty_ord().with_superscript(MathField::Symbol(
+                        MathSymbol::from_math_code(&a_code)
+                    ))
+                ),],
+            );
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Double superscript")]
+    fn it_fails_on_multiple_superscripts() {
+        with_parser(&[r"a^a^a%"], |parser| {
+            parser.parse_math_list();
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Double superscript")]
+    fn it_fails_on_multiple_superscripts_after_subscript() {
+        with_parser(&[r"a^a_a^a%"], |parser| {
+            parser.parse_math_list();
+        });
+    }
+
+    #[test]
+    fn it_parses_subscripts() {
+        let a_code = MathCode::from_number(0x7161);
+        let b_code = MathCode::from_number(0x7162);
+
+        with_parser(&[r"a_a%", r"a_{ab}%"], |parser| {
+            assert_eq!(
+                parser.parse_math_list(),
+                vec![
+                    MathListElem::Atom(
+                        MathAtom::from_math_code(&a_code).with_subscript(
+                            MathField::Symbol(MathSymbol::from_math_code(
+                                &a_code
+                            ))
+                        )
+                    ),
+                    MathListElem::Atom(
+                        MathAtom::from_math_code(&a_code).with_subscript(
+                            MathField::MathList(vec![
+                                MathListElem::Atom(MathAtom::from_math_code(
+                                    &a_code
+                                )),
+                                MathListElem::Atom(MathAtom::from_math_code(
+                                    &b_code
+                                )),
+                            ])
+                        )
+                    )
+                ],
+            );
+        });
+    }
+
+    #[test]
+    fn it_parses_subscripts_at_beginning_of_lists() {
+        let a_code = MathCode::from_number(0x7161);
+
+        with_parser(&[r"_a%"], |parser| {
+            assert_eq!(
+                parser.parse_math_list(),
+                vec![MathListElem::Atom(MathAtom::empty_ord().with_subscript(
+                    MathField::Symbol(MathSymbol::from_math_code(&a_code))
+                )),],
+            );
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Double subscript")]
+    fn it_fails_on_multiple_subscripts() {
+        with_parser(&[r"a_a_a%"], |parser| {
+            parser.parse_math_list();
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Double subscript")]
+    fn it_fails_on_multiple_subscripts_after_superscript() {
+        with_parser(&[r"a_a^a_a%"], |parser| {
+            parser.parse_math_list();
+        });
+    }
+
+    #[test]
+    fn it_parses_mathchardefs() {
+        let a_code = MathCode::from_number(0x7161);
+        let b_code = MathCode::from_number(0x7162);
+        let c_code = MathCode::from_number(0x7163);
+
+        with_parser(&[r"\hello%", r"a\hello b%"], |parser| {
+            let tok = parser.lex_unexpanded_token().unwrap();
+            parser.state.set_math_chardef(false, &tok, &c_code);
+
+            assert_eq!(
+                parser.parse_math_list(),
+                vec![
+                    MathListElem::Atom(MathAtom::from_math_code(&a_code)),
+                    MathListElem::Atom(MathAtom::from_math_code(&c_code)),
+                    MathListElem::Atom(MathAtom::from_math_code(&b_code)),
+                ],
+            );
+        });
+    }
+
+    #[test]
+    fn it_parses_assignments_in_math_mode() {
+        let a_code = MathCode::from_number(0x7161);
+        let b_code = MathCode::from_number(0x7162);
+        let c_code = MathCode::from_number(0x7163);
+
+        with_parser(&[r"a\def\x #1{a#1b}%", r"b\x c%"], |parser| {
+            assert_eq!(
+                parser.parse_math_list(),
+                vec![
+                    MathListElem::Atom(MathAtom::from_math_code(&a_code)),
+                    MathListElem::Atom(MathAtom::from_math_code(&b_code)),
+                    MathListElem::Atom(MathAtom::from_math_code(&a_code)),
+                    MathListElem::Atom(MathAtom::from_math_code(&c_code)),
+                    MathListElem::Atom(MathAtom::from_math_code(&b_code)),
+                ]
+            );
+        });
+
+        with_parser(&[r"a\def\x{b}_\x%"], |parser| {
+            assert_eq!(
+                parser.parse_math_list(),
+                vec![MathListElem::Atom(
+                    MathAtom::from_math_code(&a_code).with_subscript(
+                        MathField::Symbol(MathSymbol::from_math_code(&b_code))
+                    )
+                ),]
+            );
+        });
+    }
+
+    #[test]
+    fn it_parses_style_changes() {
+        with_parser(
+            &[r"\displaystyle \textstyle \scriptstyle \scriptscriptstyle%"],
+            |parser| {
+                assert_eq!(
+                    parser.parse_math_list(),
+                    vec![
+                        MathListElem::StyleChange(MathStyle::DisplayStyle),
+                        MathListElem::StyleChange(MathStyle::TextStyle),
+                        MathListElem::StyleChange(MathStyle::ScriptStyle),
+                        MathListElem::StyleChange(MathStyle::ScriptScriptStyle),
+                    ]
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn it_parses_superscripts_after_non_atoms() {
+        let a_code = MathCode::from_number(0x7161);
+
+        with_parser(&[r"\displaystyle ^a%"], |parser| {
+            assert_eq!(
+                parser.parse_math_list(),
+                vec![
+                    MathListElem::StyleChange(MathStyle::DisplayStyle),
+                    MathListElem::Atom(MathAtom::empty_ord().with_superscript(
+                        MathField::Symbol(MathSymbol::from_math_code(&a_code))
+                    )),
+                ],
+            );
+        });
+    }
+
+    #[test]
+    fn it_ends_on_math_shifts() {
+        let a_code = MathCode::from_number(0x7161);
+
+        with_parser(&[r"a$%"], |parser| {
+            assert_eq!(
+                parser.parse_math_list(),
+                vec![MathListElem::Atom(MathAtom::from_math_code(&a_code)),]
+            );
+
+            assert_eq!(
+                parser.lex_expanded_token(),
+                Some(Token::Char('$', Category::MathShift))
+            );
+        });
+    }
+
+    #[test]
+    fn it_produces_empty_horizontal_lists_from_empty_math_lists() {
+        assert_math_list_converts_to_horizontal_list(&[r"%"], &[r"%"]);
+    }
+
+    #[test]
+    fn it_produces_single_characters_from_single_atom_math_lists() {
+        assert_math_list_converts_to_horizontal_list(
+            &[r"a%"],
+            &[r"\font\teni=cmmi10\teni \hbox{a}%"],
+        );
+    }
+
+    #[test]
+    fn it_produces_multiple_characters_from_multiple_ord_math_lists() {
+        assert_math_list_converts_to_horizontal_list(
+            &[r"ab%"],
+            &[r"\font\teni=cmmi10\teni \hbox{a}\hbox{b}%"],
+        );
+    }
+
+    #[test]
+    fn it_attaches_superscripts_to_symbols() {
+        assert_math_list_converts_to_horizontal_list(
+            &[r"a^b%"],
+            &[
+                r"\font\teni=cmmi10 \font\seveni=cmmi7 \font\tensy=cmsy10%",
+                r"\hbox{\teni\hbox{a}%",
+                r"\raise\fontdimen14\tensy\hbox{\seveni b}}%",
+            ],
+        );
+    }
+
+    #[test]
+    fn it_raises_superscripts_by_sup3_in_cramped_display_style() {
+        with_parser(&[r"a^b%"], |parser| {
+            let list = parser.parse_math_list();
+            let hlist = parser.convert_math_list_to_horizontal_list(
+                list,
+                MathStyle::DisplayStylePrime,
+                false,
+            );
+
+            let shift = match &hlist[..] {
+                [HorizontalListElem::Box {
+                    tex_box: TeXBox::HorizontalBox(hbox),
+                    ..
+                }] => match &hbox.list[..] {
+                    [_, HorizontalListElem::Box { shift, .. }] => *shift,
+                    list => panic!("Unexpected script list: {:?}", list),
+                },
+                list => panic!("Unexpected list: {:?}", list),
+            };
+            assert_eq!(
+                shift,
+                parser.get_math_font_parameter(
+                    &MathStyle::DisplayStylePrime,
+                    2,
+                    15
+                )
+            );
+        });
+    }
+
+    #[test]
+    fn it_attaches_subscripts_to_symbols() {
+        assert_math_list_converts_to_horizontal_list(
+            &[r"a_b%"],
+            &[
+                r"\font\teni=cmmi10 \font\seveni=cmmi7 \font\tensy=cmsy10%",
+                r"\hbox{\teni\hbox{a}%",
+                r"\lower\fontdimen16\tensy\hbox{\seveni b}}%",
+            ],
+        );
+    }
+
+    #[test]
+    fn it_attaches_superscripts_and_subscripts_to_symbols() {
+        // The scripts are stacked in a vbox, with enough space between them
+        // that the superscript is raised by sup2 and the subscript is lowered
+        // by sub2.
+        assert_math_list_converts_to_horizontal_list(
+            &[r"a^b_c%"],
+            &[
+                r"\font\teni=cmmi10 \font\seveni=cmmi7 \font\tensy=cmsy10%",
+                r"\setbox0=\hbox{\seveni b}\setbox1=\hbox{\seveni c}%",
+                r"\dimen0=\fontdimen14\tensy%",
+                r"\advance\dimen0 by\fontdimen17\tensy%",
+                r"\advance\dimen0 by-\dp0 \advance\dimen0 by-\ht1 %",
+                r"\hbox{\teni\hbox{a}\lower\fontdimen17\tensy",
+                r"\vbox{\box0\vskip\dimen0\prevdepth=-1000pt\box1}}%",
+            ],
+        );
+    }
+
+    #[test]
+    fn it_adds_space_between_atoms_of_different_types_in_math_lists() {
+        // o = ord
+        // p = op
+        // b = bin
+        // r = rel
+        // n = open
+        // c = close
+        // t = punct
+        assert_math_list_converts_to_horizontal_list(
+            &[
+                r#"\mathcode`o="006F%"#,
+                r#"\mathcode`p="1070%"#,
+                r#"\mathcode`b="2062%"#,
+                r#"\mathcode`r="3072%"#,
+                r#"\mathcode`n="406E%"#,
+                r#"\mathcode`c="5063%"#,
+                r#"\mathcode`t="6074%"#,
+                r"oopoboronocoto%",
+                r"pprpnpcptpo%",
+                r"bnobo%",
+                r"rrnrcrtr%",
+                r"nncntn%",
+                r"cctc%",
+                r"tt%",
+            ],
+            &[
+                r"\def\o{\hbox{o}}%",
+                // Op symbols are centered on the axis.
+                r"\def\p{\hbox{\raise 86472sp\hbox{p}}}%",
+                r"\def\b{\hbox{b}}%",
+                r"\def\r{\hbox{r}}%",
+                r"\def\n{\hbox{n}}%",
+                r"\def\c{\hbox{c}}%",
+                r"\def\t{\hbox{t}}%",
+                r"\o\o\,\p\,\o\>\b\>\o\;\r\;\o\n\o\c\o\t\,\o\,%",
+                r"\p\,\p\;\r\;\p\n\p\c\,\p\t\,\p\,\o\>%",
+                r"\b\>\n\o\>\b\>\o\;%",
+                r"\r\r\;\n\r\c\;\r\t\,\r\;%",
+                r"\n\n\c\n\t\,\n%",
+                r"\c\c\t\,\c%",
+                r"\t\,\t%",
+            ],
+        );
+    }
+
+    #[test]
+    fn it_does_not_add_some_inter_atom_space_in_script_styles() {
+        // o = ord
+        // b = bin
+        // r = rel
+        // p = punct
+        assert_math_list_converts_to_horizontal_list(
+            &[
+                r#"\mathcode`o="006F%"#,
+                r#"\mathcode`b="2062%"#,
+                r#"\mathcode`r="3072%"#,
+                r#"\mathcode`p="6070%"#,
+                r"\displaystyle orpob%",
+                r"\textstyle orpob%",
+                r"\scriptstyle orpob%",
+                r"\scriptscriptstyle orpob%",
+            ],
+            &[
+                r"\font\sevenrm=cmr7%",
+                r"\font\fiverm=cmr5%",
+                r"\def\o{\hbox{o}}%",
+                r"\def\b{\hbox{b}}%",
+                r"\def\r{\hbox{r}}%",
+                r"\def\p{\hbox{p}}%",
+                r"\o\;\r\p\,\o\>\b\>%",
+                r"\o\;\r\p\,\o\>\b%",
+                r"\sevenrm%",
+                r"\o\r\p\o\b%",
+                r"\fiverm%",
+                r"\o\r\p\o\b%",
+            ],
+        );
+    }
+
+    #[test]
+    fn it_adds_thin_spaces_after_punctuation() {
+        assert_math_list_converts_to_horizontal_list(
+            &[
+                r#"\mathcode`a="0061%"#,
+                r#"\mathcode`b="0062%"#,
+                r#"\mathcode`,="602C%"#,
+                r"a,b%",
+            ],
+            &[r"\hbox{a}\hbox{,}\,\hbox{b}%"],
+        );
+    }
+
+    #[test]
+    fn it_uses_the_math_skip_parameters_for_spacing() {
+        fn get_skips(parser: &mut Parser) -> Vec<Glue> {
+            let list = parser.parse_math_list();
+            parser.lex_expanded_token();
+            parser
+                .convert_math_list_to_horizontal_list(
+                    list,
+                    MathStyle::TextStyle,
+                    false,
+                )
+                .into_iter()
+                .filter_map(|elem| match elem {
+                    HorizontalListElem::HSkip(glue) => Some(glue),
+                    _ => None,
+                })
+                .collect()
+        }
+
+        with_parser(
+            &[
+                r#"\mathcode`,="602C%"#,
+                r"a,b$%",
+                r"\thinmuskip=6mu%",
+                r"a,b$%",
+            ],
+            |parser| {
+                parser.parse_assignment();
+                let default_skips = get_skips(parser);
+                parser.parse_assignment();
+                let doubled_skips = get_skips(parser);
+
+                // 1mu is 1/18 of the quad.
+                let math_quad =
+                    parser.get_math_font_parameter(&MathStyle::TextStyle, 2, 6);
+                assert_eq!(
+                    default_skips,
+                    vec![Glue::from_dimen((math_quad / 18) * 3)]
+                );
+                assert_eq!(
+                    doubled_skips,
+                    vec![Glue::from_dimen(default_skips[0].space * 2)]
+                );
+            },
+        );
+    }
 
-        resulting_horizontal_list
+    #[test]
+    fn it_turns_bin_atoms_without_operands_into_ords() {
+        // o = ord
+        // b = bin
+        // r = rel
+        // t = punct
+        assert_math_list_converts_to_horizontal_list(
+            &[
+                r#"\mathcode`o="006F%"#,
+                r#"\mathcode`b="2062%"#,
+                r#"\mathcode`r="3072%"#,
+                r#"\mathcode`t="6074%"#,
+                r"botbobrbob%",
+            ],
+            &[
+                r"\def\o{\hbox{o}}%",
+                r"\def\b{\hbox{b}}%",
+                r"\def\r{\hbox{r}}%",
+                r"\def\t{\hbox{t}}%",
+                r"\b\o\t\,\b\o\b\;\r\;\b\o\b%",
+            ],
+        );
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::testing::with_parser;
+    #[test]
+    fn it_adds_penalties_after_rels_and_bins_in_inline_math() {
+        with_parser(
+            &[
+                r#"\mathcode`a="0061%"#,
+                r#"\mathcode`b="0062%"#,
+                r#"\mathcode`c="0063%"#,
+                r#"\mathcode`=="303D%"#,
+                r#"\mathcode`+="202B%"#,
+                r"\relpenalty=500%",
+                r"\binoppenalty=700%",
+                r"a=b+c=%",
+            ],
+            |parser| {
+                let math_list = parser.parse_math_list();
+                let hlist = parser.convert_math_list_to_horizontal_list(
+                    math_list,
+                    MathStyle::TextStyle,
+                    true,
+                );
 
-    fn assert_math_list_converts_to_horizontal_list(
-        math_list_lines: &[&str],
-        horizontal_list_lines: &[&str],
-    ) {
-        with_parser(math_list_lines, |math_parser| {
-            with_parser(horizontal_list_lines, |hlist_parser| {
-                let math_list = math_parser.parse_math_list();
-                let horizontal_list =
-                    hlist_parser.parse_horizontal_list(false, false);
+                let penalties: Vec<(usize, &HorizontalListElem)> = hlist
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, elem)| {
+                        matches!(elem, HorizontalListElem::Penalty(_))
+                    })
+                    .collect();
 
+                // The penalties come right after the "=" and "+" boxes, and
+                // there's no penalty after the "=" at the end.
                 assert_eq!(
-                    math_parser.convert_math_list_to_horizontal_list(
-                        math_list,
-                        MathStyle::TextStyle
-                    ),
-                    horizontal_list
+                    penalties,
+                    vec![
+                        (3, &HorizontalListElem::Penalty(500)),
+                        (8, &HorizontalListElem::Penalty(700)),
+                    ]
                 );
-            });
-        });
+            },
+        );
     }
 
     #[test]
-    fn it_parses_math_symbols() {
-        with_parser(&["a2*%"], |parser| {
-            assert_eq!(
-                parser.parse_math_symbol(),
-                MathCode::from_number(0x7161)
-            );
-            assert_eq!(
-                parser.parse_math_symbol(),
-                MathCode::from_number(0x7032)
-            );
-            assert_eq!(
-                parser.parse_math_symbol(),
-                MathCode::from_number(0x002a)
-            );
-        });
+    fn it_chooses_correct_fonts_for_different_styles() {
+        assert_math_list_converts_to_horizontal_list(
+            &[
+                r"\displaystyle a%",
+                r"\textstyle a%",
+                r"\scriptstyle a%",
+                r"\scriptscriptstyle a%",
+            ],
+            &[
+                r"\font\teni=cmmi10%",
+                r"\font\seveni=cmmi7%",
+                r"\font\fivei=cmmi5%",
+                r"\hbox{\teni a}%",
+                r"\hbox{\teni a}%",
+                r"\hbox{\seveni a}%",
+                r"\hbox{\fivei a}%",
+            ],
+        );
     }
 
     #[test]
-    fn it_parses_math_symbols_from_chardefs() {
-        with_parser(&[r"\let\x=z%", r"\x%"], |parser| {
-            parser.parse_assignment();
+    fn it_parses_math_fields_as_elements() {
+        let a_code = MathCode::from_number(0x7161);
+        let b_code = MathCode::from_number(0x7162);
+        let c_code = MathCode::from_number(0x7163);
+        let d_code = MathCode::from_number(0x7164);
 
+        with_parser(&[r"a{bc}d%"], |parser| {
             assert_eq!(
-                parser.parse_math_symbol(),
-                MathCode::from_number(0x717a)
+                parser.parse_math_list(),
+                vec![
+                    MathListElem::Atom(MathAtom::from_math_code(&a_code)),
+                    MathListElem::Atom(MathAtom::from_math_list(vec![
+                        MathListElem::Atom(MathAtom::from_math_code(&b_code)),
+                        MathListElem::Atom(MathAtom::from_math_code(&c_code)),
+                    ],)),
+                    MathListElem::Atom(MathAtom::from_math_code(&d_code)),
+                ]
             );
         });
     }
 
     #[test]
-    fn it_parses_basic_atoms_in_math_lists() {
-        with_parser(&[r"a*%"], |parser| {
+    fn it_pushes_state_in_math_fields() {
+        let one_code = MathCode::from_number(0x7031);
+        let two_code = MathCode::from_number(0x7032);
+
+        with_parser(
+            &[
+                r"\count0=1 \number\count0%",
+                r"{\number\count0 \count0=2 \number\count0}%",
+                r"\number\count0%",
+            ],
+            |parser| {
+                assert_eq!(
+                    parser.parse_math_list(),
+                    vec![
+                        MathListElem::Atom(MathAtom::from_math_code(&one_code)),
+                        MathListElem::Atom(MathAtom::from_math_list(vec![
+                            MathListElem::Atom(MathAtom::from_math_code(
+                                &one_code
+                            )),
+                            MathListElem::Atom(MathAtom::from_math_code(
+                                &two_code
+                            )),
+                        ],)),
+                        MathListElem::Atom(MathAtom::from_math_code(&one_code)),
+                    ]
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn it_converts_math_field_nuclei_to_boxes() {
+        assert_math_list_converts_to_horizontal_list(
+            &[r"a{bc}d%"],
+            &[
+                r"\font\teni=cmmi10\teni%",
+                r"\hbox{a}\hbox{\hbox{b}\hbox{c}}\hbox{d}%",
+            ],
+        );
+    }
+
+    #[test]
+    fn it_ignores_spaces_in_math_lists() {
+        let a_code = MathCode::from_number(0x7161);
+        let b_code = MathCode::from_number(0x7162);
+        let c_code = MathCode::from_number(0x7163);
+        let d_code = MathCode::from_number(0x7164);
+
+        with_parser(&[r"a b ", r"c          d"], |parser| {
             assert_eq!(
                 parser.parse_math_list(),
                 vec![
-                    MathListElem::Atom(MathAtom::from_math_code(
-                        &MathCode::from_number(0x7161)
-                    )),
-                    MathListElem::Atom(MathAtom::from_math_code(
-                        &MathCode::from_number(0x002a)
-                    )),
+                    MathListElem::Atom(MathAtom::from_math_code(&a_code)),
+                    MathListElem::Atom(MathAtom::from_math_code(&b_code)),
+                    MathListElem::Atom(MathAtom::from_math_code(&c_code)),
+                    MathListElem::Atom(MathAtom::from_math_code(&d_code)),
                 ]
-            );
+            )
         });
     }
 
     #[test]
-    fn it_parses_basic_math_groups() {
-        with_parser(&[r"{a}%"], |parser| {
-            assert_eq!(
-                parser.parse_math_group(),
-                vec![MathListElem::Atom(MathAtom::from_math_code(
-                    &MathCode::from_number(0x7161)
-                )),],
-            );
+    fn it_parses_vcenter_atoms() {
+        with_parser(&[r"\vcenter{\hbox{a}\hbox{b}}%"], |parser| {
+            let list = parser.parse_math_list();
+            assert_eq!(list.len(), 1);
+
+            match &list[0] {
+                MathListElem::Atom(MathAtom {
+                    kind: AtomKind::Vcent,
+                    nucleus: Some(MathField::TeXBox(TeXBox::VerticalBox(vbox))),
+                    superscript: None,
+                    subscript: None,
+                    ..
+                }) => {
+                    // The two rows are stacked in the box.
+                    assert_eq!(vbox.list.len(), 3);
+                }
+                elem => panic!("Expected a \\vcenter atom: {:?}", elem),
+            }
         });
     }
 
     #[test]
-    #[should_panic(expected = "Invalid start of math group")]
-    fn it_fails_parsing_math_groups_not_starting_with_begin_group() {
-        with_parser(&[r"a%"], |parser| {
-            parser.parse_math_group();
+    fn it_centers_vcenter_boxes_on_the_axis() {
+        with_parser(&[r"\vcenter{\hbox{a}\hbox{b}}%"], |parser| {
+            let list = parser.parse_math_list();
+
+            let total_height = match &list[0] {
+                MathListElem::Atom(MathAtom {
+                    nucleus: Some(MathField::TeXBox(tex_box)),
+                    ..
+                }) => *tex_box.height() + *tex_box.depth(),
+                elem => panic!("Expected a \\vcenter atom: {:?}", elem),
+            };
+
+            let axis_height = parser
+                .state
+                .get_metrics_for_font(&Font {
+                    font_name: "cmsy10".to_string(),
+                    scale: Dimen::from_unit(10.0, Unit::Point),
+                })
+                .unwrap()
+                .get_font_parameter(22);
+
+            let hlist = parser.convert_math_list_to_horizontal_list(
+                list,
+                MathStyle::TextStyle,
+                false,
+            );
+            assert_eq!(hlist.len(), 1);
+
+            let (height, depth, _) = hlist[0].get_size(parser.state);
+            assert_eq!(height, total_height / 2 + axis_height);
+            assert_eq!(height + depth, total_height);
         });
     }
 
     #[test]
-    #[should_panic(expected = "Math group didn't end with an EndGroup")]
-    fn it_fails_parsing_math_groups_not_ending_with_end_group() {
-        with_parser(&[r"{a%"], |parser| {
-            parser.parse_math_group();
-        });
+    fn it_sets_op_limits_based_on_style() {
+        with_parser(
+            &[
+                r#"\mathcode`a="0161%"#,
+                r#"\mathcode`b="0162%"#,
+                r#"\mathchardef\sum="1350%"#,
+                r"\displaystyle\sum_a^b$%",
+                r"\textstyle\sum_a^b$%",
+                r"\displaystyle\sum\nolimits_a^b$%",
+                r"\textstyle\sum\limits_a^b$%",
+            ],
+            |parser| {
+                let mut is_stacked = Vec::new();
+
+                for _ in 0..4 {
+                    let list = parser.parse_math_list();
+                    parser.lex_expanded_token();
+
+                    let hlist = parser.convert_math_list_to_horizontal_list(
+                        list,
+                        MathStyle::TextStyle,
+                        false,
+                    );
+                    assert_eq!(hlist.len(), 1);
+
+                    match &hlist[0] {
+                        HorizontalListElem::Box {
+                            tex_box: TeXBox::VerticalBox(vbox),
+                            ..
+                        } => {
+                            // The superscript, operator, and subscript are
+                            // stacked with space around them.
+                            assert_eq!(vbox.list.len(), 7);
+                            is_stacked.push(true);
+                        }
+                        HorizontalListElem::Box {
+                            tex_box: TeXBox::HorizontalBox(hbox),
+                            ..
+                        } => {
+                            // The operator is followed by the scripts.
+                            assert_eq!(hbox.list.len(), 2);
+                            is_stacked.push(false);
+                        }
+                        elem => panic!("Expected a box: {:?}", elem),
+                    }
+                }
+
+                assert_eq!(is_stacked, vec![true, false, false, true]);
+            },
+        );
     }
 
     #[test]
-    fn it_scopes_assignments_in_math_fields() {
-        with_parser(&[r"\count 0=1%", r"a^{\count 0=2}%"], |parser| {
-            parser.parse_math_list();
+    fn it_centers_op_symbols_on_the_axis() {
+        with_parser(
+            &[
+                r#"\mathchardef\sum="1350%"#,
+                r"\displaystyle\sum\nolimits$%",
+            ],
+            |parser| {
+                let list = parser.parse_math_list();
+                parser.lex_expanded_token();
+                let hlist = parser.convert_math_list_to_horizontal_list(
+                    list,
+                    MathStyle::TextStyle,
+                    false,
+                );
 
-            assert_eq!(parser.state.get_count(0), 1);
-        });
+                let tex_box = match &hlist[..] {
+                    [HorizontalListElem::Box { tex_box, .. }] => tex_box,
+                    list => panic!("Expected an op box: {:?}", list),
+                };
+
+                // The box reaches as far above the axis as below it, up to
+                // rounding.
+                let axis_height = parser.get_math_font_parameter(
+                    &MathStyle::DisplayStyle,
+                    2,
+                    22,
+                );
+                let above = *tex_box.height() - axis_height;
+                let below = *tex_box.depth() + axis_height;
+                let difference = max_dimen(above - below, below - above);
+                assert!(difference <= Dimen::from_scaled_points(1));
+            },
+        );
     }
 
     #[test]
-    fn it_parses_symbols_as_math_fields() {
-        with_parser(&[r"a2%"], |parser| {
-            assert_eq!(
-                parser.parse_math_field(),
-                MathField::Symbol(MathSymbol::from_math_code(
-                    &MathCode::from_number(0x7161)
-                ))
-            );
-            assert_eq!(
-                parser.parse_math_field(),
-                MathField::Symbol(MathSymbol::from_math_code(
-                    &MathCode::from_number(0x7032)
-                ))
-            );
-        })
+    #[should_panic(expected = "Limit controls must follow a math operator")]
+    fn it_fails_on_limits_after_non_op_atoms() {
+        with_parser(&[r#"\mathcode`a="0161 a\limits%"#], |parser| {
+            parser.parse_math_list();
+        });
     }
 
     #[test]
-    fn it_parses_groups_as_math_fields() {
-        with_parser(&[r"{ab}{}%"], |parser| {
-            assert_eq!(
-                parser.parse_math_field(),
-                MathField::MathList(vec![
-                    MathListElem::Atom(MathAtom::from_math_code(
-                        &MathCode::from_number(0x7161)
-                    )),
-                    MathListElem::Atom(MathAtom::from_math_code(
-                        &MathCode::from_number(0x7162)
-                    )),
-                ],)
-            );
-            assert_eq!(parser.parse_math_field(), MathField::MathList(vec![],));
+    fn it_parses_radicals() {
+        with_parser(&[r#"\sqrt x\radical"270370{y}%"#], |parser| {
+            let list = parser.parse_math_list();
+            assert_eq!(list.len(), 2);
+
+            for elem in list {
+                match elem {
+                    MathListElem::Atom(atom) => {
+                        assert_eq!(atom.kind, AtomKind::Rad);
+                        assert_eq!(
+                            atom.delimiter,
+                            Some(MathDelimiter::from_number(0x270370))
+                        );
+                        assert!(atom.nucleus.is_some());
+                    }
+                    elem => panic!("Expected a radical atom: {:?}", elem),
+                }
+            }
         });
     }
 
     #[test]
-    fn it_ignores_filler_before_math_fields() {
-        with_parser(&[r"  a   {a}%"], |parser| {
-            assert_eq!(
-                parser.parse_math_field(),
-                MathField::Symbol(MathSymbol::from_math_code(
-                    &MathCode::from_number(0x7161)
-                ))
-            );
+    fn it_makes_radicals_taller_than_their_radicands() {
+        with_parser(&[r"\sqrt x$x$%"], |parser| {
+            let mut boxes = Vec::new();
+            for _ in 0..2 {
+                let list = parser.parse_math_list();
+                parser.lex_expanded_token();
+
+                let mut hlist = parser.convert_math_list_to_horizontal_list(
+                    list,
+                    MathStyle::TextStyle,
+                    false,
+                );
+                assert_eq!(hlist.len(), 1);
+                match hlist.pop() {
+                    Some(HorizontalListElem::Box { tex_box, .. }) => {
+                        boxes.push(tex_box)
+                    }
+                    elem => panic!("Expected a box: {:?}", elem),
+                }
+            }
+
+            let radicand = boxes.pop().unwrap();
+            let radical = boxes.pop().unwrap();
+            assert!(radical.height() > radicand.height());
+            assert!(radical.width() > radicand.width());
+
+            let (surd, surd_shift, overbar) = match &radical {
+                TeXBox::HorizontalBox(hbox) => match &hbox.list[..] {
+                    [HorizontalListElem::Box {
+                        tex_box: surd,
+                        shift,
+                    }, HorizontalListElem::Box {
+                        tex_box: TeXBox::VerticalBox(overbar),
+                        ..
+                    }] => (surd, *shift, overbar),
+                    list => panic!("Unexpected radical list: {:?}", list),
+                },
+                _ => panic!("Expected a horizontal box: {:?}", radical),
+            };
+
+            // The rule is at least the default rule thickness away from the
+            // radicand, and its top lines up with the top of the radical
+            // sign.
+            let default_rule_thickness =
+                parser.get_math_font_parameter(&MathStyle::TextStyle, 3, 8);
+            let (rule_thickness, clearance) = match &overbar.list[..] {
+                [VerticalListElem::VSkip(_), VerticalListElem::Rule { height, .. }, VerticalListElem::VSkip(clearance), VerticalListElem::Box { .. }] => {
+                    (*height, clearance.space)
+                }
+                list => panic!("Unexpected overbar list: {:?}", list),
+            };
+            assert!(clearance >= default_rule_thickness);
             assert_eq!(
-                parser.parse_math_field(),
-                MathField::MathList(vec![MathListElem::Atom(
-                    MathAtom::from_math_code(&MathCode::from_number(0x7161))
-                ),],)
+                surd_shift + *surd.height(),
+                overbar.height - rule_thickness
             );
+            assert_eq!(overbar.width, *radicand.width());
         });
     }
 
     #[test]
-    fn it_parses_superscripts() {
-        let a_code = MathCode::from_number(0x7161);
-        let b_code = MathCode::from_number(0x7162);
+    fn it_skews_math_accents_using_the_skewchar_kern() {
+        with_parser(
+            &[
+                r"\font\tenrm=cmr10%",
+                r#"\mathcode`A="0041%"#,
+                r"\skewchar\tenrm=`V%",
+                r#"\mathaccent"0016 A$%"#,
+                r"\skewchar\tenrm=-1%",
+                r#"\mathaccent"0016 A$%"#,
+            ],
+            |parser| {
+                let mut accent_shifts = Vec::new();
 
-        with_parser(&[r"a^a%", r"a^{ab}%"], |parser| {
-            assert_eq!(
-                parser.parse_math_list(),
-                vec![
-                    MathListElem::Atom(
-                        MathAtom::from_math_code(&a_code).with_superscript(
-                            MathField::Symbol(MathSymbol::from_math_code(
-                                &a_code
-                            ))
+                for _ in 0..2 {
+                    let list = parser.parse_math_list();
+                    parser.lex_expanded_token();
+
+                    let hlist = parser.convert_math_list_to_horizontal_list(
+                        list,
+                        MathStyle::TextStyle,
+                        false,
+                    );
+                    assert_eq!(hlist.len(), 1);
+
+                    let vbox = match &hlist[0] {
+                        HorizontalListElem::Box {
+                            tex_box: TeXBox::VerticalBox(vbox),
+                            ..
+                        } => vbox,
+                        elem => panic!("Expected an accent box: {:?}", elem),
+                    };
+
+                    let accent_shift = vbox
+                        .list
+                        .iter()
+                        .find_map(|elem| match elem {
+                            VerticalListElem::Box {
+                                tex_box:
+                                    TeXBox::HorizontalBox(HorizontalBox {
+                                        list,
+                                        ..
+                                    }),
+                                ..
+                            } => match list[0] {
+                                HorizontalListElem::Kern(shift) => Some(shift),
+                                _ => None,
+                            },
+                            _ => None,
+                        })
+                        .unwrap();
+                    accent_shifts.push(accent_shift);
+                }
+
+                let kern = parser
+                    .state
+                    .get_metrics_for_font(&Font {
+                        font_name: "cmr10".to_string(),
+                        scale: Dimen::from_unit(10.0, Unit::Point),
+                    })
+                    .unwrap()
+                    .get_kern('A', 'V')
+                    .unwrap();
+                assert_ne!(kern, Dimen::zero());
+                assert_eq!(accent_shifts[0] - accent_shifts[1], kern);
+            },
+        );
+    }
+
+    #[test]
+    fn it_parses_left_right_delimiters_from_delcodes() {
+        with_parser(&[r"\left[x\right]%"], |parser| {
+            let list = parser.parse_math_list();
+            assert_eq!(list.len(), 1);
+
+            match &list[0] {
+                MathListElem::Atom(MathAtom {
+                    kind: AtomKind::Inner,
+                    nucleus: Some(MathField::MathList(inner_list)),
+                    ..
+                }) => {
+                    assert_eq!(inner_list.len(), 3);
+                    assert_eq!(
+                        inner_list[0],
+                        MathListElem::Boundary(
+                            BoundaryKind::Left,
+                            Some(MathDelimiter {
+                                small_font_family: 0,
+                                small_position: 0x5B,
+                                large_font_family: 3,
+                                large_position: 0x02,
+                            })
                         )
-                    ),
-                    MathListElem::Atom(
-                        MathAtom::from_math_code(&a_code).with_superscript(
-                            MathField::MathList(vec![
-                                MathListElem::Atom(MathAtom::from_math_code(
-                                    &a_code
-                                )),
-                                MathListElem::Atom(MathAtom::from_math_code(
-                                    &b_code
-                                )),
-                            ])
+                    );
+                    assert_eq!(
+                        inner_list[2],
+                        MathListElem::Boundary(
+                            BoundaryKind::Right,
+                            Some(MathDelimiter::from_number(0x05D303))
                         )
-                    )
-                ],
-            );
+                    );
+                }
+                elem => panic!("Expected an Inner atom: {:?}", elem),
+            }
         });
     }
 
     #[test]
-    fn it_parses_superscripts_at_beginning_of_lists() {
-        let a_code = MathCode::from_number(0x7161);
+    fn it_parses_explicit_and_null_delimiters() {
+        with_parser(&[r#"\left\delimiter"4162301 x\right.%"#], |parser| {
+            let list = parser.parse_math_list();
 
-        with_parser(&[r"^a%"], |parser| {
-            assert_eq!(
-                parser.parse_math_list(),
-                vec![MathListElem::Atom(
-                    MathAtom::empty_ord().with_superscript(MathField::Symbol(
-                        MathSymbol::from_math_code(&a_code)
-                    ))
-                ),],
-            );
+            match &list[0] {
+                MathListElem::Atom(MathAtom {
+                    nucleus: Some(MathField::MathList(inner_list)),
+                    ..
+                }) => {
+                    assert_eq!(
+                        inner_list[0],
+                        MathListElem::Boundary(
+                            BoundaryKind::Left,
+                            Some(MathDelimiter::from_number(0x162301))
+                        )
+                    );
+                    assert_eq!(
+                        inner_list[2],
+                        MathListElem::Boundary(BoundaryKind::Right, None)
+                    );
+                }
+                elem => panic!("Expected an Inner atom: {:?}", elem),
+            }
         });
     }
 
     #[test]
-    #[should_panic(expected = "Double superscript")]
-    fn it_fails_on_multiple_superscripts() {
-        with_parser(&[r"a^a^a%"], |parser| {
+    #[should_panic(expected = "Missing delimiter")]
+    fn it_fails_on_characters_without_delcodes_after_left() {
+        with_parser(&[r"\left a x\right)%"], |parser| {
             parser.parse_math_list();
         });
     }
 
     #[test]
-    #[should_panic(expected = "Double superscript")]
-    fn it_fails_on_multiple_superscripts_after_subscript() {
-        with_parser(&[r"a^a_a^a%"], |parser| {
-            parser.parse_math_list();
-        });
+    fn it_spaces_left_right_delimiters_like_open_and_close_atoms() {
+        with_parser(
+            &[
+                r#"\mathcode`x="0078%"#,
+                r#"\mathcode`a="0061%"#,
+                r#"\mathcode`(="4028%"#,
+                r#"\mathcode`)="5029%"#,
+                r"a\left(x\right)a$%",
+                r"a({x})a$%",
+            ],
+            |parser| {
+                let fenced_list = parser.parse_math_list();
+                parser.lex_expanded_token();
+                let manual_list = parser.parse_math_list();
+                parser.lex_expanded_token();
+
+                let fenced_hlist = parser.convert_math_list_to_horizontal_list(
+                    fenced_list,
+                    MathStyle::TextStyle,
+                    false,
+                );
+                let manual_hlist = parser.convert_math_list_to_horizontal_list(
+                    manual_list,
+                    MathStyle::TextStyle,
+                    false,
+                );
+
+                // The fenced delimiters are centered on the axis, so only the
+                // spacing between the atoms matches the manual list.
+                let get_skips = |hlist: Vec<HorizontalListElem>| {
+                    hlist
+                        .into_iter()
+                        .map(|elem| match elem {
+                            HorizontalListElem::HSkip(glue) => Some(glue),
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>()
+                };
+                assert_eq!(get_skips(fenced_hlist), get_skips(manual_hlist));
+            },
+        );
     }
 
     #[test]
-    fn it_parses_subscripts() {
-        let a_code = MathCode::from_number(0x7161);
-        let b_code = MathCode::from_number(0x7162);
-
-        with_parser(&[r"a_a%", r"a_{ab}%"], |parser| {
-            assert_eq!(
-                parser.parse_math_list(),
-                vec![
-                    MathListElem::Atom(
-                        MathAtom::from_math_code(&a_code).with_subscript(
-                            MathField::Symbol(MathSymbol::from_math_code(
-                                &a_code
-                            ))
-                        )
-                    ),
-                    MathListElem::Atom(
-                        MathAtom::from_math_code(&a_code).with_subscript(
-                            MathField::MathList(vec![
-                                MathListElem::Atom(MathAtom::from_math_code(
-                                    &a_code
-                                )),
-                                MathListElem::Atom(MathAtom::from_math_code(
-                                    &b_code
-                                )),
-                            ])
+    fn it_cancels_glue_after_nonscript_in_script_styles() {
+        with_parser(
+            &[
+                r#"\mathcode`a="0061%"#,
+                r#"\mathcode`b="0062%"#,
+                r"\def\;{\mskip\thickmuskip}%",
+                r"a\nonscript\;b$%",
+                r"a\nonscript\;b$%",
+                r"a\nonscript\hskip 2pt b$%",
+                r"a\nonscript\hskip 2pt b$%",
+            ],
+            |parser| {
+                let mut get_skips = |style: MathStyle| {
+                    let list = parser.parse_math_list();
+                    parser.lex_expanded_token();
+                    parser
+                        .convert_math_list_to_horizontal_list(
+                            list, style, false,
                         )
-                    )
-                ],
-            );
-        });
+                        .into_iter()
+                        .filter_map(|elem| match elem {
+                            HorizontalListElem::HSkip(glue) => Some(glue),
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>()
+                };
+
+                let text_skips = get_skips(MathStyle::TextStyle);
+                let script_skips = get_skips(MathStyle::ScriptStyle);
+                let display_skips = get_skips(MathStyle::DisplayStyle);
+                let scriptscript_skips =
+                    get_skips(MathStyle::ScriptScriptStyle);
+
+                let math_quad =
+                    parser.get_math_font_parameter(&MathStyle::TextStyle, 2, 6);
+                let thick_skip = parser
+                    .state
+                    .get_mu_glue_parameter(&MuGlueParameter::ThickMuSkip)
+                    .to_glue(math_quad);
+                assert_eq!(text_skips, vec![thick_skip]);
+                assert_eq!(script_skips, vec![]);
+                assert_eq!(
+                    display_skips,
+                    vec![Glue::from_dimen(Dimen::from_unit(2.0, Unit::Point))]
+                );
+                assert_eq!(scriptscript_skips, vec![]);
+            },
+        );
     }
 
     #[test]
-    fn it_parses_subscripts_at_beginning_of_lists() {
-        let a_code = MathCode::from_number(0x7161);
+    fn it_doesnt_add_space_at_the_ends_of_subformulas() {
+        with_parser(
+            &[
+                r#"\mathcode`x="0078%"#,
+                r#"\mathcode`+="202B%"#,
+                r#"\mathcode`(="4028%"#,
+                r#"\mathcode`)="5029%"#,
+                r"x+{+x+}$%",
+                r"x+\left(+x+\right)$%",
+            ],
+            |parser| {
+                // Returns the horizontal list inside the box that the given
+                // element of the converted list is.
+                fn get_sublist(
+                    elem: &HorizontalListElem,
+                ) -> &[HorizontalListElem] {
+                    match elem {
+                        HorizontalListElem::Box {
+                            tex_box: TeXBox::HorizontalBox(hbox),
+                            ..
+                        } => &hbox.list,
+                        elem => panic!("Expected a subformula: {:?}", elem),
+                    }
+                }
 
-        with_parser(&[r"_a%"], |parser| {
-            assert_eq!(
-                parser.parse_math_list(),
-                vec![MathListElem::Atom(MathAtom::empty_ord().with_subscript(
-                    MathField::Symbol(MathSymbol::from_math_code(&a_code))
-                )),],
-            );
-        });
+                // The leading and trailing + in each subformula don't have
+                // anything to operate on, so they are treated as Ord atoms
+                // and there is no space anywhere inside the subformula, even
+                // though the subformula comes right after a Bin atom.
+                let list = parser.parse_math_list();
+                parser.lex_expanded_token();
+                let hlist = parser.convert_math_list_to_horizontal_list(
+                    list,
+                    MathStyle::TextStyle,
+                    false,
+                );
+                let sublist = get_sublist(hlist.last().unwrap());
+                assert_eq!(sublist.len(), 3);
+                assert!(sublist.iter().all(|elem| matches!(
+                    elem,
+                    HorizontalListElem::Box { .. }
+                )));
+
+                let list = parser.parse_math_list();
+                parser.lex_expanded_token();
+                let hlist = parser.convert_math_list_to_horizontal_list(
+                    list,
+                    MathStyle::TextStyle,
+                    false,
+                );
+                // The list is x, medium space, +, medium space, and then the
+                // left delimiter, the subformula, and the right delimiter.
+                assert_eq!(hlist.len(), 7);
+                let sublist = get_sublist(&hlist[5]);
+                assert_eq!(sublist.len(), 3);
+                assert!(sublist.iter().all(|elem| matches!(
+                    elem,
+                    HorizontalListElem::Box { .. }
+                )));
+            },
+        );
     }
 
     #[test]
-    #[should_panic(expected = "Double subscript")]
-    fn it_fails_on_multiple_subscripts() {
-        with_parser(&[r"a_a_a%"], |parser| {
-            parser.parse_math_list();
-        });
+    fn it_grows_left_right_delimiters_to_cover_the_list_between_them() {
+        with_parser(
+            &[
+                r"\delimiterfactor=1000 \delimitershortfall=0pt%",
+                r"\left(a\over b\right)$%",
+            ],
+            |parser| {
+                let list = parser.parse_math_list();
+                parser.lex_expanded_token();
+                let hlist = parser.convert_math_list_to_horizontal_list(
+                    list,
+                    MathStyle::DisplayStyle,
+                    false,
+                );
+
+                let (left, inner) = match &hlist[..] {
+                    [HorizontalListElem::Box { tex_box: left, .. }, HorizontalListElem::Box { tex_box: inner, .. }, HorizontalListElem::Box { .. }] => {
+                        (left, inner)
+                    }
+                    list => panic!("Unexpected fenced list: {:?}", list),
+                };
+
+                let (delimiter, shift) = match left {
+                    TeXBox::HorizontalBox(hbox) => match &hbox.list[..] {
+                        [HorizontalListElem::Box { tex_box, shift }] => {
+                            (tex_box, *shift)
+                        }
+                        list => panic!("Unexpected delimiter list: {:?}", list),
+                    },
+                    _ => panic!("Expected a horizontal box: {:?}", left),
+                };
+
+                // The delimiter is bigger than the base parenthesis, and it's big
+                // enough to cover the fraction above and below the axis.
+                let paren = parser.state.get_math_code('(');
+                let paren_font =
+                    parser.get_math_font(&MathStyle::TextStyle, paren.family);
+                let metrics =
+                    parser.state.get_metrics_for_font(&paren_font).unwrap();
+                let paren_size = metrics.get_height(paren.position as char)
+                    + metrics.get_depth(paren.position as char);
+                let delimiter_size = *delimiter.height() + *delimiter.depth();
+                assert!(delimiter_size > paren_size);
+
+                let axis_height = parser.get_math_font_parameter(
+                    &MathStyle::DisplayStyle,
+                    2,
+                    22,
+                );
+                assert!(delimiter_size / 2 >= *inner.height() - axis_height);
+                assert!(delimiter_size / 2 >= *inner.depth() + axis_height);
+
+                // The delimiter is centered on the axis.
+                assert_eq!(
+                    shift + (*delimiter.height() - *delimiter.depth()) / 2,
+                    axis_height
+                );
+            },
+        );
     }
 
     #[test]
-    #[should_panic(expected = "Double subscript")]
-    fn it_fails_on_multiple_subscripts_after_superscript() {
-        with_parser(&[r"a_a^a_a%"], |parser| {
-            parser.parse_math_list();
+    fn it_stacks_extensible_pieces_around_the_middle_piece() {
+        with_parser(&[r"%"], |parser| {
+            let recipe = ExtensibleRecipe {
+                top: Some('a'),
+                middle: Some('b'),
+                bottom: Some('c'),
+                repeat: 'd',
+            };
+            let font = parser.get_math_font(&MathStyle::TextStyle, 0);
+            let size = Dimen::from_unit(40.0, Unit::Point);
+            let stack = parser.make_extensible_stack(&font, &recipe, size);
+
+            let vbox = match &stack {
+                TeXBox::VerticalBox(vbox) => vbox,
+                tex_box => panic!("Expected a vertical box: {:?}", tex_box),
+            };
+            let chars: Vec<char> = vbox
+                .list
+                .iter()
+                .map(|elem| match elem {
+                    VerticalListElem::Box { tex_box, .. } => {
+                        get_box_chars(tex_box)[0].0
+                    }
+                    elem => panic!("Unexpected piece: {:?}", elem),
+                })
+                .collect();
+
+            let num_repeats = (chars.len() - 3) / 2;
+            let mut expected = vec!['a'];
+            expected.extend((0..num_repeats).map(|_| 'd'));
+            expected.push('b');
+            expected.extend((0..num_repeats).map(|_| 'd'));
+            expected.push('c');
+            assert_eq!(chars, expected);
+
+            // The box is exactly as big as its pieces, which is big enough
+            // but would be too small with one fewer pair of repeated pieces.
+            let metrics = parser.state.get_metrics_for_font(&font).unwrap();
+            let piece_size =
+                |chr: char| metrics.get_height(chr) + metrics.get_depth(chr);
+            let total_size = chars
+                .iter()
+                .fold(Dimen::zero(), |total, chr| total + piece_size(*chr));
+            assert_eq!(vbox.height, metrics.get_height('a'));
+            assert_eq!(vbox.height + vbox.depth, total_size);
+            assert!(total_size >= size);
+            assert!(total_size - piece_size('d') * 2 < size);
         });
     }
 
     #[test]
-    fn it_parses_mathchardefs() {
-        let a_code = MathCode::from_number(0x7161);
-        let b_code = MathCode::from_number(0x7162);
-        let c_code = MathCode::from_number(0x7163);
+    fn it_builds_tall_delimiters_from_extensible_pieces() {
+        with_parser(
+            &[r"\left(\vcenter{\hrule height 40pt depth 40pt}\right.$%"],
+            |parser| {
+                let list = parser.parse_math_list();
+                parser.lex_expanded_token();
+                let hlist = parser.convert_math_list_to_horizontal_list(
+                    list,
+                    MathStyle::TextStyle,
+                    false,
+                );
 
-        with_parser(&[r"\hello%", r"a\hello b%"], |parser| {
-            let tok = parser.lex_unexpanded_token().unwrap();
-            parser.state.set_math_chardef(false, &tok, &c_code);
+                let delimiter = match &hlist[0] {
+                    HorizontalListElem::Box {
+                        tex_box: TeXBox::HorizontalBox(hbox),
+                        ..
+                    } => match &hbox.list[..] {
+                        [HorizontalListElem::Box {
+                            tex_box: TeXBox::VerticalBox(vbox),
+                            ..
+                        }] => vbox.clone(),
+                        list => {
+                            panic!("Expected a stacked delimiter: {:?}", list)
+                        }
+                    },
+                    elem => panic!("Expected a delimiter box: {:?}", elem),
+                };
 
-            assert_eq!(
-                parser.parse_math_list(),
-                vec![
-                    MathListElem::Atom(MathAtom::from_math_code(&a_code)),
-                    MathListElem::Atom(MathAtom::from_math_code(&c_code)),
-                    MathListElem::Atom(MathAtom::from_math_code(&b_code)),
-                ],
-            );
-        });
+                // The parenthesis is built from a top piece, some number of
+                // repeated pieces, and a bottom piece.
+                let chars: Vec<char> = delimiter
+                    .list
+                    .iter()
+                    .map(|elem| match elem {
+                        VerticalListElem::Box { tex_box, .. } => {
+                            match &get_box_chars(tex_box)[..] {
+                                [(chr, font_name)] => {
+                                    assert_eq!(font_name, "cmex10");
+                                    *chr
+                                }
+                                chars => {
+                                    panic!("Unexpected piece: {:?}", chars)
+                                }
+                            }
+                        }
+                        elem => panic!("Unexpected piece: {:?}", elem),
+                    })
+                    .collect();
+                assert!(chars.len() > 2);
+                assert_eq!(chars[0], '\x30');
+                assert!(chars[1..chars.len() - 1].iter().all(|c| *c == '\x42'));
+                assert_eq!(chars[chars.len() - 1], '\x40');
+
+                // The \vcenter box reaches 40pt above and below the axis, and
+                // the delimiter uses just enough repeated pieces to be as tall
+                // as \delimiterfactor and \delimitershortfall require.
+                let max_distance = Dimen::from_unit(40.0, Unit::Point);
+                let size = max_dimen(
+                    max_distance / 500 * 901,
+                    max_distance * 2 - Dimen::from_unit(5.0, Unit::Point),
+                );
+                let metrics = parser
+                    .state
+                    .get_metrics_for_font(
+                        &parser.get_math_font(&MathStyle::TextStyle, 3),
+                    )
+                    .unwrap();
+                let repeat_size =
+                    metrics.get_height('\x42') + metrics.get_depth('\x42');
+                let delimiter_size = delimiter.height + delimiter.depth;
+                assert!(delimiter_size >= size);
+                assert!(delimiter_size - repeat_size < size);
+            },
+        );
     }
 
     #[test]
-    fn it_parses_assignments_in_math_mode() {
-        let a_code = MathCode::from_number(0x7161);
-        let b_code = MathCode::from_number(0x7162);
-        let c_code = MathCode::from_number(0x7163);
+    fn it_parses_over_into_generalized_fractions() {
+        with_parser(&[r"a\over b$%", r"{a\over b}c%"], |parser| {
+            let a_code = parser.state.get_math_code('a');
+            let b_code = parser.state.get_math_code('b');
+            let c_code = parser.state.get_math_code('c');
 
-        with_parser(&[r"a\def\x #1{a#1b}%", r"b\x c%"], |parser| {
+            let make_fraction = || {
+                MathListElem::GeneralizedFraction(GeneralizedFraction::over(
+                    vec![MathListElem::Atom(MathAtom::from_math_code(&a_code))],
+                    vec![MathListElem::Atom(MathAtom::from_math_code(&b_code))],
+                ))
+            };
+
+            assert_eq!(parser.parse_math_list(), vec![make_fraction()]);
+            parser.lex_expanded_token();
             assert_eq!(
                 parser.parse_math_list(),
                 vec![
-                    MathListElem::Atom(MathAtom::from_math_code(&a_code)),
-                    MathListElem::Atom(MathAtom::from_math_code(&b_code)),
-                    MathListElem::Atom(MathAtom::from_math_code(&a_code)),
+                    MathListElem::Atom(MathAtom::from_math_list(vec![
+                        make_fraction()
+                    ])),
                     MathListElem::Atom(MathAtom::from_math_code(&c_code)),
-                    MathListElem::Atom(MathAtom::from_math_code(&b_code)),
                 ]
             );
         });
-
-        with_parser(&[r"a\def\x{b}_\x%"], |parser| {
-            assert_eq!(
-                parser.parse_math_list(),
-                vec![MathListElem::Atom(
-                    MathAtom::from_math_code(&a_code).with_subscript(
-                        MathField::Symbol(MathSymbol::from_math_code(&b_code))
-                    )
-                ),]
-            );
-        });
     }
 
     #[test]
-    fn it_parses_style_changes() {
+    fn it_parses_generalized_fraction_variants() {
         with_parser(
-            &[r"\displaystyle \textstyle \scriptstyle \scriptscriptstyle%"],
+            &[
+                r"a\atop b$%",
+                r"a\above 2pt b$%",
+                r#"a\atopwithdelims\delimiter"4028300\delimiter"5029301 b$%"#,
+                r#"a\abovewithdelims.\delimiter"5029301 1pt b$%"#,
+            ],
             |parser| {
+                let mut parse_fraction = || {
+                    let mut list = parser.parse_math_list();
+                    parser.lex_expanded_token();
+                    match list.pop() {
+                        Some(MathListElem::GeneralizedFraction(fraction)) => {
+                            fraction
+                        }
+                        elem => panic!("Expected a fraction: {:?}", elem),
+                    }
+                };
+                let left_paren = MathDelimiter::from_number(0x028300);
+                let right_paren = MathDelimiter::from_number(0x029301);
+
+                let atop = parse_fraction();
+                assert_eq!(atop.bar_height, Some(Dimen::zero()));
+                assert_eq!(atop.left_delim, None);
+                assert_eq!(atop.right_delim, None);
+
+                let above = parse_fraction();
+                assert_eq!(
+                    above.bar_height,
+                    Some(Dimen::from_unit(2.0, Unit::Point))
+                );
+
+                let atop_with_delims = parse_fraction();
+                assert_eq!(atop_with_delims.bar_height, Some(Dimen::zero()));
+                assert_eq!(atop_with_delims.left_delim, Some(left_paren));
+                assert_eq!(atop_with_delims.right_delim, Some(right_paren));
+
+                let above_with_delims = parse_fraction();
+                assert_eq!(
+                    above_with_delims.bar_height,
+                    Some(Dimen::from_unit(1.0, Unit::Point))
+                );
+                assert_eq!(above_with_delims.left_delim, None);
                 assert_eq!(
-                    parser.parse_math_list(),
-                    vec![
-                        MathListElem::StyleChange(MathStyle::DisplayStyle),
-                        MathListElem::StyleChange(MathStyle::TextStyle),
-                        MathListElem::StyleChange(MathStyle::ScriptStyle),
-                        MathListElem::StyleChange(MathStyle::ScriptScriptStyle),
-                    ]
+                    above_with_delims.right_delim,
+                    Some(MathDelimiter::from_number(0x029301))
                 );
+                assert_eq!(above_with_delims.numerator.len(), 1);
+                assert_eq!(above_with_delims.denominator.len(), 1);
             },
         );
     }
 
     #[test]
-    fn it_parses_superscripts_after_non_atoms() {
-        let a_code = MathCode::from_number(0x7161);
-
-        with_parser(&[r"\displaystyle ^a%"], |parser| {
-            assert_eq!(
-                parser.parse_math_list(),
-                vec![
-                    MathListElem::StyleChange(MathStyle::DisplayStyle),
-                    MathListElem::Atom(MathAtom::empty_ord().with_superscript(
-                        MathField::Symbol(MathSymbol::from_math_code(&a_code))
-                    )),
-                ],
-            );
+    #[should_panic(expected = "Ambiguous")]
+    fn it_fails_on_multiple_overs_in_one_list() {
+        with_parser(&[r"a\over b\over c%"], |parser| {
+            parser.parse_math_list();
         });
     }
 
+    // Collects the characters in a box along with the names of their fonts.
+    fn get_box_chars(tex_box: &TeXBox) -> Vec<(char, String)> {
+        match tex_box {
+            TeXBox::HorizontalBox(hbox) => hbox
+                .list
+                .iter()
+                .flat_map(|elem| match elem {
+                    HorizontalListElem::Char { chr, font } => {
+                        vec![(*chr, font.font_name.clone())]
+                    }
+                    HorizontalListElem::Box { tex_box, .. } => {
+                        get_box_chars(tex_box)
+                    }
+                    _ => vec![],
+                })
+                .collect(),
+            TeXBox::VerticalBox(_) => vec![],
+        }
+    }
+
+    // Finds the numerator, rule, and denominator in the box that `a\over b`
+    // turns into in the given style.
+    fn get_fraction_parts(
+        parser: &mut Parser,
+        style: MathStyle,
+    ) -> (VerticalBox, TeXBox, Dimen, TeXBox) {
+        let list = parser.parse_math_list();
+        let hlist = parser.convert_math_list_to_horizontal_list(
+            list,
+            style.clone(),
+            false,
+        );
+
+        let vbox = match &hlist[..] {
+            [HorizontalListElem::Box {
+                tex_box: TeXBox::HorizontalBox(hbox),
+                ..
+            }] => match &hbox.list[..] {
+                [_, HorizontalListElem::Box {
+                    tex_box: TeXBox::VerticalBox(vbox),
+                    ..
+                }, _] => vbox.clone(),
+                list => panic!("Expected a fraction vbox: {:?}", list),
+            },
+            list => panic!("Expected a fraction box: {:?}", list),
+        };
+
+        match &vbox.list[..] {
+            [VerticalListElem::Box {
+                tex_box: numerator, ..
+            }, VerticalListElem::VSkip(_), VerticalListElem::Rule {
+                height,
+                depth,
+                width: None,
+            }, VerticalListElem::VSkip(_), VerticalListElem::Box {
+                tex_box: denominator,
+                ..
+            }] => {
+                assert_eq!(*depth, Dimen::zero());
+                (
+                    vbox.clone(),
+                    numerator.clone(),
+                    *height,
+                    denominator.clone(),
+                )
+            }
+            list => {
+                panic!("Expected a numerator, rule and denominator: {:?}", list)
+            }
+        }
+    }
+
     #[test]
-    fn it_ends_on_math_shifts() {
-        let a_code = MathCode::from_number(0x7161);
+    fn it_stacks_fraction_numerators_over_denominators() {
+        with_parser(&[r"a\over b%"], |parser| {
+            let (vbox, numerator, thickness, denominator) =
+                get_fraction_parts(parser, MathStyle::TextStyle);
 
-        with_parser(&[r"a$%"], |parser| {
+            let default_rule_thickness =
+                parser.get_math_font_parameter(&MathStyle::TextStyle, 3, 8);
+            let axis_height =
+                parser.get_math_font_parameter(&MathStyle::TextStyle, 2, 22);
+            assert_eq!(thickness, default_rule_thickness);
+
+            let gap_above = match &vbox.list[1] {
+                VerticalListElem::VSkip(glue) => glue.space,
+                _ => unreachable!(),
+            };
+            let gap_below = match &vbox.list[3] {
+                VerticalListElem::VSkip(glue) => glue.space,
+                _ => unreachable!(),
+            };
+            assert!(gap_above >= thickness);
+            assert!(gap_below >= thickness);
+
+            // The middle of the rule is on the axis.
+            let rule_bottom = vbox.height
+                - *numerator.height()
+                - *numerator.depth()
+                - gap_above
+                - thickness;
+            assert_eq!(rule_bottom + thickness / 2, axis_height);
             assert_eq!(
-                parser.parse_math_list(),
-                vec![MathListElem::Atom(MathAtom::from_math_code(&a_code)),]
+                vbox.height + vbox.depth,
+                *numerator.height()
+                    + *numerator.depth()
+                    + gap_above
+                    + thickness
+                    + gap_below
+                    + *denominator.height()
+                    + *denominator.depth()
             );
 
+            // In text style, the numerator and denominator are set in script
+            // style.
+            assert_eq!(get_box_chars(&numerator), [('a', "cmmi7".to_string())]);
             assert_eq!(
-                parser.lex_expanded_token(),
-                Some(Token::Char('$', Category::MathShift))
+                get_box_chars(&denominator),
+                [('b', "cmmi7".to_string())]
             );
         });
     }
 
     #[test]
-    fn it_produces_empty_horizontal_lists_from_empty_math_lists() {
-        assert_math_list_converts_to_horizontal_list(&[r"%"], &[r"%"]);
-    }
+    fn it_only_draws_rules_in_fractions_with_a_thickness() {
+        with_parser(
+            &[r"a\over b$%", r"a\above 2pt b$%", r"a\atop b%"],
+            |parser| {
+                // \over draws a rule with the default rule thickness.
+                let (_, _, thickness, _) =
+                    get_fraction_parts(parser, MathStyle::TextStyle);
+                assert_eq!(
+                    thickness,
+                    parser.get_math_font_parameter(&MathStyle::TextStyle, 3, 8)
+                );
+                parser.lex_expanded_token();
 
-    #[test]
-    fn it_produces_single_characters_from_single_atom_math_lists() {
-        assert_math_list_converts_to_horizontal_list(
-            &[r"a%"],
-            &[r"\font\teni=cmmi10\teni \hbox{a}%"],
-        );
-    }
+                let (_, _, thickness, _) =
+                    get_fraction_parts(parser, MathStyle::TextStyle);
+                assert_eq!(thickness, Dimen::from_unit(2.0, Unit::Point));
+                parser.lex_expanded_token();
 
-    #[test]
-    fn it_produces_multiple_characters_from_multiple_ord_math_lists() {
-        assert_math_list_converts_to_horizontal_list(
-            &[r"ab%"],
-            &[r"\font\teni=cmmi10\teni \hbox{a}\hbox{b}%"],
-        );
-    }
+                let list = parser.parse_math_list();
+                let hlist = parser.convert_math_list_to_horizontal_list(
+                    list,
+                    MathStyle::TextStyle,
+                    false,
+                );
+                let vbox = match &hlist[..] {
+                    [HorizontalListElem::Box {
+                        tex_box: TeXBox::HorizontalBox(hbox),
+                        ..
+                    }] => match &hbox.list[..] {
+                        [_, HorizontalListElem::Box {
+                            tex_box: TeXBox::VerticalBox(vbox),
+                            ..
+                        }, _] => vbox.clone(),
+                        list => panic!("Expected a fraction vbox: {:?}", list),
+                    },
+                    list => panic!("Expected a fraction box: {:?}", list),
+                };
 
-    #[test]
-    fn it_adds_space_between_atoms_of_different_types_in_math_lists() {
-        // o = ord
-        // p = op
-        // b = bin
-        // r = rel
-        // n = open
-        // c = close
-        // t = punct
-        assert_math_list_converts_to_horizontal_list(
-            &[
-                r#"\mathcode`o="006F%"#,
-                r#"\mathcode`p="1070%"#,
-                r#"\mathcode`b="2062%"#,
-                r#"\mathcode`r="3072%"#,
-                r#"\mathcode`n="406E%"#,
-                r#"\mathcode`c="5063%"#,
-                r#"\mathcode`t="6074%"#,
-                r"oopoboronocoto%",
-                r"pprpnpcptpo%",
-                r"bnobo%",
-                r"rrnrcrtr%",
-                r"nncntn%",
-                r"cctc%",
-                r"tt%",
-            ],
-            &[
-                r"\def\,{\hskip 3pt}%",
-                r"\def\>{\hskip 4pt plus 2pt minus 4pt}%",
-                r"\def\;{\hskip 5pt plus 5pt}%",
-                r"\def\o{\hbox{o}}%",
-                r"\def\p{\hbox{p}}%",
-                r"\def\b{\hbox{b}}%",
-                r"\def\r{\hbox{r}}%",
-                r"\def\n{\hbox{n}}%",
-                r"\def\c{\hbox{c}}%",
-                r"\def\t{\hbox{t}}%",
-                r"\o\o\,\p\,\o\>\b\>\o\;\r\;\o\n\o\c\o\t\,\o\,%",
-                r"\p\,\p\;\r\;\p\n\p\c\,\p\t\,\p\,\o\>%",
-                r"\b\>\n\o\>\b\>\o\;%",
-                r"\r\r\;\n\r\c\;\r\t\,\r\;%",
-                r"\n\n\c\n\t\,\n%",
-                r"\c\c\t\,\c%",
-                r"\t\,\t%",
-            ],
+                // With \atop, the numerator and denominator are separated by
+                // just glue, which is at least 3 times the default rule
+                // thickness in text style.
+                let gap = match &vbox.list[..] {
+                    [VerticalListElem::Box { .. }, VerticalListElem::VSkip(glue), VerticalListElem::Box { .. }] => {
+                        glue.space
+                    }
+                    list => {
+                        panic!("Expected a fraction without a rule: {:?}", list)
+                    }
+                };
+                let default_rule_thickness =
+                    parser.get_math_font_parameter(&MathStyle::TextStyle, 3, 8);
+                assert!(gap >= default_rule_thickness * 3);
+            },
         );
     }
 
     #[test]
-    fn it_does_not_add_some_inter_atom_space_in_script_styles() {
-        // o = ord
-        // b = bin
-        // r = rel
-        // p = punct
-        assert_math_list_converts_to_horizontal_list(
-            &[
-                r#"\mathcode`o="006F%"#,
-                r#"\mathcode`b="2062%"#,
-                r#"\mathcode`r="3072%"#,
-                r#"\mathcode`p="6070%"#,
-                r"\displaystyle orpob%",
-                r"\textstyle orpob%",
-                r"\scriptstyle orpob%",
-                r"\scriptscriptstyle orpob%",
-            ],
-            &[
-                r"\font\sevenrm=cmr7%",
-                r"\font\fiverm=cmr5%",
-                r"\def\,{\hskip 3pt}%",
-                r"\def\>{\hskip 4pt plus 2pt minus 4pt}%",
-                r"\def\;{\hskip 5pt plus 5pt}%",
-                r"\def\o{\hbox{o}}%",
-                r"\def\b{\hbox{b}}%",
-                r"\def\r{\hbox{r}}%",
-                r"\def\p{\hbox{p}}%",
-                r"\o\;\r\p\,\o\>\b\>%",
-                r"\o\;\r\p\,\o\>\b%",
-                r"\sevenrm%",
-                r"\o\r\p\o\b%",
-                r"\fiverm%",
-                r"\o\r\p\o\b%",
-            ],
+    fn it_grows_fraction_delimiters_to_delim1_or_delim2() {
+        with_parser(
+            &[r"a\atopwithdelims() b$a\atopwithdelims() b$a\over b%"],
+            |parser| {
+                for (style, param) in
+                    &[(MathStyle::DisplayStyle, 20), (MathStyle::TextStyle, 21)]
+                {
+                    let list = parser.parse_math_list();
+                    parser.lex_expanded_token();
+                    let hlist = parser.convert_math_list_to_horizontal_list(
+                        list,
+                        style.clone(),
+                        false,
+                    );
+                    let delimiters = match &hlist[..] {
+                        [HorizontalListElem::Box {
+                            tex_box: TeXBox::HorizontalBox(hbox),
+                            ..
+                        }] => match &hbox.list[..] {
+                            [left @ HorizontalListElem::Box { .. }, _, right @ HorizontalListElem::Box { .. }] =>
+                            {
+                                vec![left.clone(), right.clone()]
+                            }
+                            list => panic!("Unexpected fraction: {:?}", list),
+                        },
+                        list => panic!("Expected a fraction box: {:?}", list),
+                    };
+
+                    let size = parser.get_math_font_parameter(style, 2, *param);
+                    let axis_height =
+                        parser.get_math_font_parameter(style, 2, 22);
+                    for delimiter in delimiters {
+                        match delimiter {
+                            HorizontalListElem::Box { tex_box, shift } => {
+                                assert!(
+                                    *tex_box.height() + *tex_box.depth()
+                                        >= size
+                                );
+                                // The delimiters are centered on the axis.
+                                assert_eq!(
+                                    shift
+                                        + (*tex_box.height()
+                                            - *tex_box.depth())
+                                            / 2,
+                                    axis_height
+                                );
+                            }
+                            _ => unreachable!(),
+                        }
+                    }
+                }
+
+                // Fractions without delimiters get null delimiters.
+                let list = parser.parse_math_list();
+                let hlist = parser.convert_math_list_to_horizontal_list(
+                    list,
+                    MathStyle::TextStyle,
+                    false,
+                );
+                let null_delimiter = HorizontalListElem::Box {
+                    tex_box: TeXBox::HorizontalBox(HorizontalBox {
+                        width: Dimen::from_unit(1.2, Unit::Point),
+                        ..HorizontalBox::empty()
+                    }),
+                    shift: parser.get_math_font_parameter(
+                        &MathStyle::TextStyle,
+                        2,
+                        22,
+                    ),
+                };
+                match &hlist[..] {
+                    [HorizontalListElem::Box {
+                        tex_box: TeXBox::HorizontalBox(hbox),
+                        ..
+                    }] => {
+                        assert_eq!(hbox.list[0], null_delimiter);
+                        assert_eq!(hbox.list[2], null_delimiter);
+                    }
+                    list => panic!("Expected a fraction box: {:?}", list),
+                }
+            },
         );
     }
 
     #[test]
-    fn it_chooses_correct_fonts_for_different_styles() {
-        assert_math_list_converts_to_horizontal_list(
-            &[
-                r"\displaystyle a%",
-                r"\textstyle a%",
-                r"\scriptstyle a%",
-                r"\scriptscriptstyle a%",
-            ],
-            &[
-                r"\font\teni=cmmi10%",
-                r"\font\seveni=cmmi7%",
-                r"\font\fivei=cmmi5%",
-                r"\hbox{\teni a}%",
-                r"\hbox{\teni a}%",
-                r"\hbox{\seveni a}%",
-                r"\hbox{\fivei a}%",
-            ],
+    fn it_uses_assigned_font_parameters_for_the_axis() {
+        with_parser(
+            &[r"\font\symfont=cmsy10 \fontdimen22\symfont=2.5pt a\over b%"],
+            |parser| {
+                parser.parse_assignment();
+
+                let (vbox, numerator, thickness, _) =
+                    get_fraction_parts(parser, MathStyle::TextStyle);
+
+                let gap_above = match &vbox.list[1] {
+                    VerticalListElem::VSkip(glue) => glue.space,
+                    _ => unreachable!(),
+                };
+                let rule_bottom = vbox.height
+                    - *numerator.height()
+                    - *numerator.depth()
+                    - gap_above
+                    - thickness;
+                assert_eq!(
+                    rule_bottom + thickness / 2,
+                    Dimen::from_unit(2.5, Unit::Point)
+                );
+            },
         );
     }
 
     #[test]
-    fn it_parses_math_fields_as_elements() {
-        let a_code = MathCode::from_number(0x7161);
-        let b_code = MathCode::from_number(0x7162);
-        let c_code = MathCode::from_number(0x7163);
-        let d_code = MathCode::from_number(0x7164);
+    fn it_sets_display_fractions_in_text_style() {
+        with_parser(&[r"a\over b%"], |parser| {
+            let (vbox, numerator, _, _) =
+                get_fraction_parts(parser, MathStyle::DisplayStyle);
 
-        with_parser(&[r"a{bc}d%"], |parser| {
             assert_eq!(
-                parser.parse_math_list(),
-                vec![
-                    MathListElem::Atom(MathAtom::from_math_code(&a_code)),
-                    MathListElem::Atom(MathAtom::from_math_list(vec![
-                        MathListElem::Atom(MathAtom::from_math_code(&b_code)),
-                        MathListElem::Atom(MathAtom::from_math_code(&c_code)),
-                    ],)),
-                    MathListElem::Atom(MathAtom::from_math_code(&d_code)),
-                ]
+                get_box_chars(&numerator),
+                [('a', "cmmi10".to_string())]
             );
+
+            // Display fractions are raised at least as far as \fontdimen8 of
+            // the symbol font.
+            let num1 =
+                parser.get_math_font_parameter(&MathStyle::DisplayStyle, 2, 8);
+            assert!(vbox.height - *numerator.height() >= num1);
         });
     }
 
     #[test]
-    fn it_pushes_state_in_math_fields() {
-        let one_code = MathCode::from_number(0x7031);
-        let two_code = MathCode::from_number(0x7032);
-
+    fn it_parses_atoms_with_explicit_kinds() {
         with_parser(
             &[
-                r"\count0=1 \number\count0%",
-                r"{\number\count0 \count0=2 \number\count0}%",
-                r"\number\count0%",
+                r"\mathord a\mathop a\mathbin a\mathrel a%",
+                r"\mathopen a\mathclose a\mathpunct a\mathinner{ab}%",
             ],
             |parser| {
+                let a_code = parser.state.get_math_code('a');
+                let b_code = parser.state.get_math_code('b');
+                let a_field = || {
+                    Some(MathField::Symbol(MathSymbol::from_math_code(&a_code)))
+                };
+
+                let list = parser.parse_math_list();
+                let kinds: Vec<AtomKind> = list
+                    .iter()
+                    .map(|elem| match elem {
+                        MathListElem::Atom(atom) => {
+                            if atom.kind != AtomKind::Inner {
+                                assert_eq!(atom.nucleus, a_field());
+                            }
+                            atom.kind
+                        }
+                        _ => panic!("Expected an atom: {:?}", elem),
+                    })
+                    .collect();
+
                 assert_eq!(
-                    parser.parse_math_list(),
+                    kinds,
                     vec![
-                        MathListElem::Atom(MathAtom::from_math_code(&one_code)),
-                        MathListElem::Atom(MathAtom::from_math_list(vec![
+                        AtomKind::Ord,
+                        AtomKind::Op,
+                        AtomKind::Bin,
+                        AtomKind::Rel,
+                        AtomKind::Open,
+                        AtomKind::Close,
+                        AtomKind::Punct,
+                        AtomKind::Inner,
+                    ]
+                );
+                assert_eq!(
+                    list[7],
+                    MathListElem::Atom(MathAtom {
+                        kind: AtomKind::Inner,
+                        ..MathAtom::from_math_list(vec![
                             MathListElem::Atom(MathAtom::from_math_code(
-                                &one_code
+                                &a_code
                             )),
                             MathListElem::Atom(MathAtom::from_math_code(
-                                &two_code
+                                &b_code
                             )),
-                        ],)),
-                        MathListElem::Atom(MathAtom::from_math_code(&one_code)),
-                    ]
+                        ])
+                    })
                 );
             },
         );
     }
 
     #[test]
-    fn it_converts_math_field_nuclei_to_boxes() {
+    fn it_spaces_atoms_with_explicit_kinds_like_their_kind() {
         assert_math_list_converts_to_horizontal_list(
-            &[r"a{bc}d%"],
-            &[
-                r"\font\teni=cmmi10\teni%",
-                r"\hbox{a}\hbox{\hbox{b}\hbox{c}}\hbox{d}%",
-            ],
+            &[r"a\mathbin xb%"],
+            &[r"\font\teni=cmmi10\teni%", r"\hbox{a}\>\hbox{x}\>\hbox{b}%"],
         );
     }
 
     #[test]
-    fn it_ignores_spaces_in_math_lists() {
-        let a_code = MathCode::from_number(0x7161);
-        let b_code = MathCode::from_number(0x7162);
-        let c_code = MathCode::from_number(0x7163);
-        let d_code = MathCode::from_number(0x7164);
+    fn it_gives_atoms_the_same_height_with_mathstruts() {
+        with_parser(
+            &[
+                r#"\mathcode`a="0061%"#,
+                r#"\mathcode`p="0070%"#,
+                r"\mathstrut a$%",
+                r"\mathstrut p$%",
+                r"\mathstrut a$%",
+            ],
+            |parser| {
+                let mut get_box = |style: MathStyle| {
+                    let list = parser.parse_math_list();
+                    parser.lex_expanded_token();
+                    let hlist = parser.convert_math_list_to_horizontal_list(
+                        list, style, false,
+                    );
+                    parser
+                        .combine_horizontal_list_into_horizontal_box_with_layout(
+                            hlist,
+                            &BoxLayout::Natural,
+                        )
+                };
 
-        with_parser(&[r"a b ", r"c          d"], |parser| {
-            assert_eq!(
-                parser.parse_math_list(),
-                vec![
-                    MathListElem::Atom(MathAtom::from_math_code(&a_code)),
-                    MathListElem::Atom(MathAtom::from_math_code(&b_code)),
-                    MathListElem::Atom(MathAtom::from_math_code(&c_code)),
-                    MathListElem::Atom(MathAtom::from_math_code(&d_code)),
-                ]
-            )
-        });
+                let a_box = get_box(MathStyle::TextStyle);
+                let p_box = get_box(MathStyle::TextStyle);
+                let script_a_box = get_box(MathStyle::ScriptStyle);
+
+                let text_font = parser.get_math_font(&MathStyle::TextStyle, 0);
+                let text_metrics =
+                    parser.state.get_metrics_for_font(&text_font).unwrap();
+                assert_eq!(a_box.height, text_metrics.get_height('('));
+                assert_eq!(a_box.depth, text_metrics.get_depth('('));
+                assert_eq!(p_box.height, a_box.height);
+                assert_eq!(p_box.depth, a_box.depth);
+
+                // The struts are only as big as the parentheses in the
+                // current style.
+                let script_font =
+                    parser.get_math_font(&MathStyle::ScriptStyle, 0);
+                let script_metrics =
+                    parser.state.get_metrics_for_font(&script_font).unwrap();
+                assert_eq!(script_a_box.height, script_metrics.get_height('('));
+            },
+        );
     }
 }
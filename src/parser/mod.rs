@@ -1,4 +1,9 @@
+use crate::boxes::{HorizontalBox, TeXBox};
+use crate::category::CategoryPreset;
 use crate::lexer::Lexer;
+use crate::list::{HorizontalListElem, VerticalListElem};
+use crate::parser::expand::ScannerStatus;
+use crate::parser::vertical_list::CurrentVerticalList;
 use crate::state::TeXState;
 use crate::token::Token;
 
@@ -12,6 +17,35 @@ pub struct Parser<'a> {
     // Used in conditional module to keep track of the level of nesting of
     // conditionals
     conditional_depth: usize,
+
+    // Used in expand module to keep track of what is being scanned, so that
+    // \outer macros can be found where they aren't allowed
+    scanner_status: ScannerStatus,
+
+    // Used in page_builder module to keep track of the boxes that have been
+    // shipped out with \shipout
+    shipped_out_boxes: Vec<TeXBox>,
+
+    // Used in printing module to keep track of messages that should be shown
+    // to the user, like the ones from \showthe
+    messages: Vec<String>,
+
+    // Used in horizontal_list module to hold the lines of the part of the
+    // paragraph before a display that interrupted it, along with the vertical
+    // list material for the display, so the vertical_list module can add them
+    // to the vertical list.
+    interrupting_display: Option<(Vec<HorizontalBox>, Vec<VerticalListElem>)>,
+
+    // Used in vertical_list module to keep track of the vertical lists that
+    // are currently being built, with the innermost one last, so that
+    // commands like \lastbox can modify them. Horizontal lists push None so
+    // that these commands don't reach through them.
+    current_vertical_lists: Vec<Option<CurrentVerticalList>>,
+
+    // Used in horizontal_list module to keep track of the horizontal lists
+    // that are currently being built, with the innermost one last, so that
+    // \lastbox can remove boxes from them.
+    current_horizontal_lists: Vec<Vec<HorizontalListElem>>,
 }
 
 impl<'a> Parser<'a> {
@@ -26,21 +60,104 @@ impl<'a> Parser<'a> {
             state,
             upcoming_tokens: Vec::new(),
             conditional_depth: 0,
+            scanner_status: ScannerStatus::Normal,
+            shipped_out_boxes: Vec::new(),
+            messages: Vec::new(),
+            interrupting_display: None,
+            current_vertical_lists: Vec::new(),
+            current_horizontal_lists: Vec::new(),
         }
     }
+
+    /// Creates a parser after setting up the categories in the state to match
+    /// the given preset.
+    pub fn new_with_category_preset<T>(
+        lines: &[T],
+        state: &'a TeXState,
+        preset: CategoryPreset,
+    ) -> Parser<'a>
+    where
+        T: AsRef<str>,
+        T: std::string::ToString,
+    {
+        for (ch, cat) in preset.get_categories() {
+            state.set_category(true, ch, cat);
+        }
+
+        Parser::new(lines, state)
+    }
 }
 
+mod alignment;
 mod assignment;
 mod boxes;
 mod conditional;
 mod dimen;
 mod expand;
+mod expression;
 mod glue;
 mod horizontal_list;
+mod hyphenation;
+mod line_breaking;
 mod makro;
 mod math_list;
 mod number;
+mod page_builder;
 mod primitives;
 mod printing;
+mod rule;
 mod variable;
 mod vertical_list;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::category::Category;
+
+    #[test]
+    fn it_uses_category_presets() {
+        let lines = &[r"{^^41}%"];
+
+        let initex_state = TeXState::new();
+        let mut initex_parser = Parser::new_with_category_preset(
+            lines,
+            &initex_state,
+            CategoryPreset::IniTeX,
+        );
+        let mut initex_tokens = Vec::new();
+        while let Some(token) = initex_parser.lex_unexpanded_token() {
+            initex_tokens.push(token);
+        }
+        assert_eq!(
+            initex_tokens,
+            vec![
+                Token::Char('{', Category::Other),
+                Token::Char('^', Category::Other),
+                Token::Char('^', Category::Other),
+                Token::Char('4', Category::Other),
+                Token::Char('1', Category::Other),
+                Token::Char('}', Category::Other),
+            ]
+        );
+
+        let plain_state = TeXState::new();
+        let mut plain_parser = Parser::new_with_category_preset(
+            lines,
+            &plain_state,
+            CategoryPreset::Plain,
+        );
+        let mut plain_tokens = Vec::new();
+        while let Some(token) = plain_parser.lex_unexpanded_token() {
+            plain_tokens.push(token);
+        }
+        assert_eq!(
+            plain_tokens,
+            vec![
+                Token::Char('{', Category::BeginGroup),
+                Token::Char('A', Category::Letter),
+                Token::Char('}', Category::EndGroup),
+            ]
+        );
+    }
+}
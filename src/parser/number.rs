@@ -9,6 +9,13 @@ pub fn is_token_digit(token: &Token) -> bool {
     }
 }
 
+pub fn is_token_octal_digit(token: &Token) -> bool {
+    match token {
+        Token::Char(ch, Category::Other) => *ch >= '0' && *ch <= '7',
+        _ => false,
+    }
+}
+
 pub fn is_token_hex_digit(token: &Token) -> bool {
     match token {
         Token::Char(ch, Category::Other) => {
@@ -39,6 +46,16 @@ pub fn token_digit_value(token: &Token) -> u8 {
     }
 }
 
+// Adds a digit to the end of a number in the given radix, making sure that
+// the result still fits in TeX's integer range.
+fn add_digit(value: u32, radix: u32, digit: u8) -> u32 {
+    let new_value = value as u64 * radix as u64 + digit as u64;
+    if new_value > i32::MAX as u64 {
+        panic!("Number too big");
+    }
+    new_value as u32
+}
+
 impl<'a> Parser<'a> {
     fn is_integer_constant_head(&mut self) -> bool {
         match self.peek_expanded_token() {
@@ -60,7 +77,43 @@ impl<'a> Parser<'a> {
             match self.peek_expanded_token() {
                 Some(ref token) if is_token_digit(token) => {
                     self.lex_expanded_token();
-                    value = 10 * value + token_digit_value(token) as u32;
+                    value = add_digit(value, 10, token_digit_value(token));
+                }
+                _ => break,
+            }
+        }
+
+        self.parse_optional_space_expanded();
+
+        value
+    }
+
+    fn is_octal_constant_head(&mut self) -> bool {
+        match self.peek_expanded_token() {
+            Some(token) => token == Token::Char('\'', Category::Other),
+            _ => false,
+        }
+    }
+
+    fn parse_octal_constant(&mut self) -> u32 {
+        let quote = self.lex_expanded_token().unwrap();
+        if quote != Token::Char('\'', Category::Other) {
+            panic!("Invalid octal number start");
+        }
+
+        let mut value: u32 = match self.peek_expanded_token() {
+            Some(ref token) if is_token_octal_digit(token) => {
+                self.lex_expanded_token();
+                token_digit_value(token) as u32
+            }
+            _ => panic!("Invalid octal number start"),
+        };
+
+        loop {
+            match self.peek_expanded_token() {
+                Some(ref token) if is_token_octal_digit(token) => {
+                    self.lex_expanded_token();
+                    value = add_digit(value, 8, token_digit_value(token));
                 }
                 _ => break,
             }
@@ -96,7 +149,7 @@ impl<'a> Parser<'a> {
             match self.peek_expanded_token() {
                 Some(ref token) if is_token_hex_digit(token) => {
                     self.lex_expanded_token();
-                    value = 16 * value + token_digit_value(token) as u32;
+                    value = add_digit(value, 16, token_digit_value(token));
                 }
                 _ => break,
             }
@@ -141,12 +194,22 @@ impl<'a> Parser<'a> {
 
     pub fn is_internal_integer_head(&mut self) -> bool {
         self.is_integer_variable_head()
+            || self
+                .is_next_expanded_token_in_set_of_primitives(&["inputlineno"])
+            || self.is_integer_expression_head()
     }
 
     pub fn parse_internal_integer(&mut self) -> i32 {
         if self.is_integer_variable_head() {
             let variable = self.parse_integer_variable();
             variable.get(self.state)
+        } else if self
+            .is_next_expanded_token_in_set_of_primitives(&["inputlineno"])
+        {
+            self.lex_expanded_token();
+            self.lexer.get_line_number() as i32
+        } else if self.is_integer_expression_head() {
+            self.parse_integer_expression()
         } else {
             panic!("unimplemented");
         }
@@ -155,6 +218,7 @@ impl<'a> Parser<'a> {
     fn is_normal_integer_head(&mut self) -> bool {
         self.is_internal_integer_head()
             || self.is_integer_constant_head()
+            || self.is_octal_constant_head()
             || self.is_hexadecimal_constant_head()
             || self.is_character_number_constant_head()
     }
@@ -164,6 +228,8 @@ impl<'a> Parser<'a> {
             self.parse_internal_integer()
         } else if self.is_integer_constant_head() {
             self.parse_integer_constant() as i32
+        } else if self.is_octal_constant_head() {
+            self.parse_octal_constant() as i32
         } else if self.is_hexadecimal_constant_head() {
             self.parse_hexadecimal_constant() as i32
         } else if self.is_character_number_constant_head() {
@@ -198,6 +264,9 @@ impl<'a> Parser<'a> {
         let mut sign: i32 = 1;
 
         loop {
+            // Spaces are allowed before and between the signs.
+            self.parse_optional_spaces_expanded();
+
             match self.peek_expanded_token() {
                 Some(Token::Char(chr, Category::Other))
                     if chr == '+' || chr == '-' =>
@@ -211,11 +280,17 @@ impl<'a> Parser<'a> {
             }
         }
 
-        self.parse_optional_spaces_expanded();
-
         sign
     }
 
+    pub fn parse_4bit_number(&mut self) -> u8 {
+        let number = self.parse_number();
+        if !(0..=15).contains(&number) {
+            panic!("Invalid 4-bit number: {}", number);
+        }
+        number as u8
+    }
+
     pub fn parse_8bit_number(&mut self) -> u8 {
         let number = self.parse_number();
         if number < 0 || number > 255 {
@@ -245,6 +320,7 @@ mod tests {
     use crate::dimension::{Dimen, Unit};
     use crate::font::Font;
     use crate::testing::with_parser;
+    use crate::token::Token;
 
     #[test]
     fn it_parses_8bit_numbers() {
@@ -279,6 +355,14 @@ mod tests {
         });
     }
 
+    #[test]
+    fn it_parses_spaces_around_signs() {
+        with_parser(&[r" - 12 % ", r"+ - -3 %"], |parser| {
+            assert_eq!(parser.parse_number(), -12);
+            assert_eq!(parser.parse_number(), 3);
+        });
+    }
+
     #[test]
     fn it_parses_hexadecimal_numbers() {
         with_parser(&[r#""0"#, r#""1289"#, r#""ABEF"#, r#""F0F"#], |parser| {
@@ -289,6 +373,81 @@ mod tests {
         });
     }
 
+    #[test]
+    fn it_parses_octal_numbers() {
+        with_parser(&["'0 %", "'17 %", "-'777 %", "'18%"], |parser| {
+            assert_eq!(parser.parse_number(), 0o0);
+            assert_eq!(parser.parse_number(), 0o17);
+            assert_eq!(parser.parse_number(), -0o777);
+            // 8 isn't an octal digit, so it isn't part of the number.
+            assert_eq!(parser.parse_number(), 0o1);
+            assert_eq!(parser.parse_number(), 8);
+        });
+    }
+
+    #[test]
+    fn it_absorbs_exactly_one_optional_space_after_numbers() {
+        with_parser(
+            &[r"\count0=5 x%", r"\def\sp{ }%", r"\count0=5\sp\sp x%"],
+            |parser| {
+                // The single space after the number is part of the number.
+                parser.parse_assignment();
+                assert_eq!(parser.state.get_count(0), 5);
+                assert_eq!(
+                    parser.lex_expanded_token(),
+                    Some(Token::Char('x', Category::Letter))
+                );
+
+                // But only one space is absorbed, so a second one is left
+                // over.
+                parser.parse_assignment();
+                parser.parse_assignment();
+                assert_eq!(
+                    parser.lex_expanded_token(),
+                    Some(Token::Char(' ', Category::Space))
+                );
+                assert_eq!(
+                    parser.lex_expanded_token(),
+                    Some(Token::Char('x', Category::Letter))
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn it_parses_the_largest_numbers() {
+        with_parser(
+            &[
+                "2147483647 %",
+                "-2147483647 %",
+                "'17777777777 %",
+                r#""7FFFFFFF%"#,
+            ],
+            |parser| {
+                assert_eq!(parser.parse_number(), 2147483647);
+                assert_eq!(parser.parse_number(), -2147483647);
+                assert_eq!(parser.parse_number(), 2147483647);
+                assert_eq!(parser.parse_number(), 2147483647);
+            },
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Number too big")]
+    fn it_fails_parsing_numbers_that_are_too_big() {
+        with_parser(&["2147483648%"], |parser| {
+            parser.parse_number();
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Number too big")]
+    fn it_fails_parsing_hexadecimal_numbers_that_are_too_big() {
+        with_parser(&[r#""80000000%"#], |parser| {
+            parser.parse_number();
+        });
+    }
+
     #[test]
     fn it_parses_character_number_constants() {
         with_parser(&[r"`A%", r"`z%", r"`\a%", r"`\%%", r"`!%"], |parser| {
@@ -0,0 +1,533 @@
+use std::collections::VecDeque;
+
+use crate::boxes::{badness, TeXBox, INF_BAD};
+use crate::category::Category;
+use crate::dimension::{Dimen, SpringDimen};
+use crate::glue::Glue;
+use crate::list::VerticalListElem;
+use crate::parser::boxes::BoxLayout;
+use crate::parser::printing::box_dump_lines;
+use crate::parser::Parser;
+use crate::token::Token;
+use crate::variable::{DimenParameter, GlueParameter, IntegerParameter};
+
+// Penalties at least this large prevent breaks entirely
+const INF_PENALTY: i32 = 10000;
+// Penalties at most this large force a break
+const EJECT_PENALTY: i32 = -10000;
+// The cost of a break that can't be used at all
+const AWFUL_BAD: i32 = (1 << 30) - 1;
+// The cost of a break that leaves the page infinitely bad
+const DEPLORABLE: i32 = 100000;
+
+/// Keeps track of the material on the page that is currently being built,
+/// along with the information needed to decide where to break it.
+pub struct Page {
+    contents: Vec<VerticalListElem>,
+
+    // The height of the material on the page so far, not including the depth
+    // of the last box.
+    height: Glue,
+    // The depth of the last box on the page.
+    depth: Dimen,
+
+    // The height we're trying to fill and the maximum depth of the page.
+    // These are read from \vsize and \maxdepth when the first box is added to
+    // the page.
+    goal: Dimen,
+    max_depth: Dimen,
+
+    // The index into `contents` of the best place to break that we've found
+    // so far, along with the cost of breaking there.
+    best_break: usize,
+    least_cost: i32,
+}
+
+impl Page {
+    pub fn new() -> Self {
+        Page {
+            contents: Vec::new(),
+            height: Glue::zero(),
+            depth: Dimen::zero(),
+            goal: Dimen::zero(),
+            max_depth: Dimen::zero(),
+            best_break: 0,
+            least_cost: AWFUL_BAD,
+        }
+    }
+
+    /// Returns the elements on the page, for when we want to use the
+    /// remaining elements without breaking the page.
+    pub fn into_contents(self) -> Vec<VerticalListElem> {
+        self.contents
+    }
+
+    // Figures out how bad the page would be if we broke it with its current
+    // contents.
+    fn get_badness(&self) -> i32 {
+        let natural_height = self.height.space;
+
+        if natural_height < self.goal {
+            match &self.height.stretch {
+                SpringDimen::Dimen(stretch) => {
+                    badness(&(self.goal - natural_height), stretch)
+                }
+                // Any amount of infinite stretch means we can fill the page
+                // perfectly.
+                SpringDimen::FilDimen(_) => 0,
+            }
+        } else {
+            match &self.height.shrink {
+                SpringDimen::Dimen(shrink) => {
+                    if natural_height - self.goal > *shrink {
+                        AWFUL_BAD
+                    } else {
+                        badness(&(natural_height - self.goal), shrink)
+                    }
+                }
+                SpringDimen::FilDimen(_) => {
+                    panic!("Infinite glue shrinkage found on current page")
+                }
+            }
+        }
+    }
+
+    // Figures out the cost of breaking at the end of the current contents,
+    // where there is a penalty of `penalty`.
+    fn get_break_cost(&self, penalty: i32) -> i32 {
+        let badness = self.get_badness();
+
+        if badness >= AWFUL_BAD {
+            badness
+        } else if penalty <= EJECT_PENALTY {
+            penalty
+        } else if badness < INF_BAD {
+            badness + penalty
+        } else {
+            DEPLORABLE
+        }
+    }
+}
+
+impl<'a> Parser<'a> {
+    pub fn is_shipout_head(&mut self) -> bool {
+        self.is_next_expanded_token_in_set_of_primitives(&["shipout"])
+    }
+
+    /// Parses a \shipout command and stores the box for output.
+    pub fn parse_shipout(&mut self) {
+        let tok = self.lex_expanded_token().unwrap();
+
+        if !self.state.is_token_equal_to_prim(&tok, "shipout") {
+            panic!("Invalid shipout head: {:?}", tok);
+        }
+
+        if let Some(tex_box) = self.parse_box() {
+            self.ship_out(tex_box);
+        }
+    }
+
+    // Stores a box as a finished page.
+    fn ship_out(&mut self, tex_box: TeXBox) {
+        if self
+            .state
+            .get_integer_parameter(&IntegerParameter::TracingOutput)
+            > 0
+        {
+            self.add_message(format!(
+                "Completed box being shipped out [{}]\n{}",
+                self.state.get_count(0),
+                box_dump_lines(&tex_box).join("\n")
+            ));
+        }
+
+        self.shipped_out_boxes.push(tex_box);
+
+        // Since we shipped something out, the output routine isn't stuck.
+        self.state.set_integer_parameter(
+            true,
+            &IntegerParameter::DeadCycles,
+            0,
+        );
+    }
+
+    /// Returns all of the boxes that have been shipped out so far, in the
+    /// order they were shipped.
+    pub fn take_shipped_out_boxes(&mut self) -> Vec<TeXBox> {
+        std::mem::take(&mut self.shipped_out_boxes)
+    }
+
+    /// Moves elements from the main vertical list onto the current page,
+    /// breaking the page and running the output routine whenever a page is
+    /// completed.
+    pub fn build_page(
+        &mut self,
+        page: &mut Page,
+        contributions: Vec<VerticalListElem>,
+    ) {
+        let mut contributions: VecDeque<VerticalListElem> =
+            contributions.into();
+
+        while let Some(elem) = contributions.pop_front() {
+            if let Some(new_contributions) = self.add_to_page(page, elem) {
+                // Whatever was left over after the page broke gets put back
+                // at the front of the contributions to go on the next page.
+                for new_elem in new_contributions.into_iter().rev() {
+                    contributions.push_front(new_elem);
+                }
+            }
+        }
+    }
+
+    // Adds a single element to the page. If this causes the page to break,
+    // returns the list of elements that need to be contributed again.
+    fn add_to_page(
+        &mut self,
+        page: &mut Page,
+        elem: VerticalListElem,
+    ) -> Option<Vec<VerticalListElem>> {
+        let penalty = match elem {
+            VerticalListElem::Box { .. } | VerticalListElem::Rule { .. } => {
+                let (height, depth, _) = elem.get_size();
+                let height = height.space;
+
+                if page.contents.is_empty() {
+                    page.goal =
+                        self.state.get_dimen_parameter(&DimenParameter::VSize);
+                    page.max_depth = self
+                        .state
+                        .get_dimen_parameter(&DimenParameter::MaxDepth);
+
+                    // The \topskip glue is shrunk by the height of the
+                    // first box, but never past 0pt, so the baseline of the
+                    // first box is at least \topskip from the top of the
+                    // page.
+                    let mut top_glue =
+                        self.state.get_glue_parameter(&GlueParameter::TopSkip);
+                    if top_glue.space > height {
+                        top_glue.space = top_glue.space - height;
+                    } else {
+                        top_glue.space = Dimen::zero();
+                    }
+
+                    page.height = page.height.clone() + top_glue.clone();
+                    page.contents.push(VerticalListElem::VSkip(top_glue));
+                }
+
+                page.height =
+                    page.height.clone() + Glue::from_dimen(page.depth + height);
+                page.depth = depth;
+
+                // If the page gets too deep, we move the reference point down
+                // like we do with \boxmaxdepth in vertical boxes.
+                if page.depth > page.max_depth {
+                    page.height = page.height.clone()
+                        + Glue::from_dimen(page.depth - page.max_depth);
+                    page.depth = page.max_depth;
+                }
+
+                page.contents.push(elem);
+                return None;
+            }
+
+            // Glue and penalties at the top of the page are discarded.
+            _ if page.contents.is_empty() => return None,
+
+            // Glue is only a valid place to break if it comes immediately
+            // after a box.
+            VerticalListElem::VSkip(_)
+            | VerticalListElem::RuleLeaders { .. } => {
+                match page.contents.last() {
+                    Some(VerticalListElem::Box { .. })
+                    | Some(VerticalListElem::Rule { .. }) => 0,
+                    _ => INF_PENALTY,
+                }
+            }
+
+            // TODO(xymostech): A kern is a valid place to break if it's
+            // followed by glue, but we don't know what comes next here.
+            VerticalListElem::Kern(_) => INF_PENALTY,
+
+            VerticalListElem::Penalty(penalty) => penalty,
+        };
+
+        if penalty < INF_PENALTY {
+            let cost = page.get_break_cost(penalty);
+
+            if cost <= page.least_cost {
+                page.best_break = page.contents.len();
+                page.least_cost = cost;
+            }
+
+            if cost == AWFUL_BAD || penalty <= EJECT_PENALTY {
+                return Some(self.fire_up(page, elem));
+            }
+        }
+
+        if let VerticalListElem::VSkip(_) | VerticalListElem::Kern(_) = elem {
+            let (glue, _, _) = elem.get_size();
+            page.height =
+                page.height.clone() + Glue::from_dimen(page.depth) + glue;
+            page.depth = Dimen::zero();
+        }
+
+        page.contents.push(elem);
+        None
+    }
+
+    // Breaks the current page at the best break that has been found, puts
+    // the page into \box255, and runs the output routine. Returns the output
+    // routine's vertical list followed by the material that didn't make it
+    // onto the page.
+    fn fire_up(
+        &mut self,
+        page: &mut Page,
+        current_elem: VerticalListElem,
+    ) -> Vec<VerticalListElem> {
+        let mut finished_page = std::mem::replace(page, Page::new());
+
+        let mut held_over =
+            finished_page.contents.split_off(finished_page.best_break);
+        held_over.push(current_elem);
+
+        // The element that we're breaking at is the first one that was held
+        // over. If it's a penalty, we report it in \outputpenalty and then
+        // make sure it can't be used as a break again.
+        let output_penalty = match held_over[0] {
+            VerticalListElem::Penalty(penalty) => {
+                held_over[0] = VerticalListElem::Penalty(INF_PENALTY);
+                penalty
+            }
+            _ => INF_PENALTY,
+        };
+        self.state.set_integer_parameter(
+            true,
+            &IntegerParameter::OutputPenalty,
+            output_penalty,
+        );
+
+        let page_box = self
+            .combine_vertical_list_into_vertical_box_with_layout(
+                finished_page.contents,
+                &BoxLayout::Fixed(finished_page.goal),
+                &finished_page.max_depth,
+            );
+
+        if self.state.with_box(255, |_| ()).is_some() {
+            panic!(r"\box255 is not void");
+        }
+        self.state.set_box(true, 255, TeXBox::VerticalBox(page_box));
+
+        let output_routine = self.state.get_output_routine();
+        let mut result = if output_routine.is_empty() {
+            // With no output routine, we just ship out the page directly.
+            let page_box = self.state.get_box(255).unwrap();
+            self.ship_out(page_box);
+            Vec::new()
+        } else {
+            let dead_cycles = self
+                .state
+                .get_integer_parameter(&IntegerParameter::DeadCycles);
+            let max_dead_cycles = self
+                .state
+                .get_integer_parameter(&IntegerParameter::MaxDeadCycles);
+
+            if dead_cycles >= max_dead_cycles {
+                panic!("Output loop---{} consecutive dead cycles", dead_cycles);
+            }
+
+            self.state.set_integer_parameter(
+                true,
+                &IntegerParameter::DeadCycles,
+                dead_cycles + 1,
+            );
+            self.run_output_routine(output_routine)
+        };
+
+        result.append(&mut held_over);
+        result
+    }
+
+    // Runs the tokens of the output routine inside of a group in internal
+    // vertical mode, returning the vertical list that they produce.
+    fn run_output_routine(
+        &mut self,
+        output_routine: Vec<Token>,
+    ) -> Vec<VerticalListElem> {
+        self.add_upcoming_token(Token::Char('}', Category::EndGroup));
+        self.add_upcoming_tokens(output_routine);
+
+        self.state.push_state();
+        let result = self.parse_vertical_list(true);
+        match self.lex_unexpanded_token() {
+            Some(Token::Char(_, Category::EndGroup)) => (),
+            tok => panic!("Unbalanced output routine, found {:?}", tok),
+        }
+        self.state.pop_state();
+
+        if self.state.with_box(255, |_| ()).is_some() {
+            panic!(r"Output routine didn't use all of \box255");
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::dimension::Unit;
+    use crate::testing::with_parser;
+
+    #[test]
+    fn it_ships_out_pages_at_forced_breaks() {
+        with_parser(
+            &[
+                r"\setbox0=\hbox{}%",
+                r"\ht0=8pt%",
+                r"\copy0%",
+                r"\penalty-10000%",
+                r"\copy0%",
+                r"\end%",
+            ],
+            |parser| {
+                let last_page = parser.parse_outer_vertical_box();
+                let shipped = parser.take_shipped_out_boxes();
+
+                assert_eq!(shipped.len(), 1);
+                // The page is as tall as \vsize.
+                assert_eq!(
+                    *shipped[0].height(),
+                    Dimen::from_unit(8.9, Unit::Inch)
+                );
+
+                // The remaining box ends up on the final page, with \topskip
+                // glue above it.
+                assert_eq!(last_page.list.len(), 2);
+                assert_eq!(
+                    last_page.height,
+                    Dimen::from_unit(10.0, Unit::Point)
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn it_sets_outputpenalty_when_breaking_at_a_penalty() {
+        with_parser(
+            &[
+                r"\vsize=20pt%",
+                r"\output={\global\count1=\outputpenalty\shipout\box255}%",
+                r"\setbox0=\hbox{}%",
+                r"\ht0=8pt%",
+                r"\copy0%",
+                r"\penalty-100%",
+                r"\copy0%",
+                r"\copy0%",
+                r"\end%",
+            ],
+            |parser| {
+                parser.parse_outer_vertical_box();
+
+                assert_eq!(parser.state.get_count(1), -100);
+                assert_eq!(parser.take_shipped_out_boxes().len(), 1);
+                assert_eq!(
+                    parser.state.get_integer_parameter(
+                        &IntegerParameter::OutputPenalty
+                    ),
+                    -100
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn it_sets_outputpenalty_when_breaking_at_glue() {
+        with_parser(
+            &[
+                r"\vsize=20pt%",
+                r"\output={\global\count1=\outputpenalty\shipout\box255}%",
+                r"\setbox0=\hbox{}%",
+                r"\ht0=8pt%",
+                r"\copy0%",
+                r"\copy0%",
+                r"\copy0%",
+                r"\end%",
+            ],
+            |parser| {
+                parser.parse_outer_vertical_box();
+
+                assert_eq!(parser.state.get_count(1), 10000);
+            },
+        );
+    }
+
+    #[test]
+    fn it_resets_deadcycles_when_shipping_out() {
+        with_parser(
+            &[
+                r"\output={\global\count1=\deadcycles\shipout\box255}%",
+                r"\hbox{}%",
+                r"\penalty-10000%",
+                r"\end%",
+            ],
+            |parser| {
+                parser.parse_outer_vertical_box();
+
+                // \deadcycles is incremented before the output routine runs
+                assert_eq!(parser.state.get_count(1), 1);
+                assert_eq!(
+                    parser
+                        .state
+                        .get_integer_parameter(&IntegerParameter::DeadCycles),
+                    0
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn it_shows_shipped_out_boxes_with_tracingoutput() {
+        with_parser(
+            &[r"\shipout\hbox{a}%", r"\tracingoutput=1 \shipout\hbox{a}%"],
+            |parser| {
+                parser.parse_shipout();
+                assert_eq!(parser.take_messages(), Vec::<String>::new());
+
+                parser.parse_assignment();
+                parser.parse_shipout();
+
+                let tex_box = parser.take_shipped_out_boxes().pop().unwrap();
+                assert_eq!(
+                    parser.take_messages(),
+                    vec![format!(
+                        "Completed box being shipped out [0]\n{}",
+                        box_dump_lines(&tex_box).join("\n")
+                    )]
+                );
+                assert_eq!(
+                    box_dump_lines(&tex_box)[1],
+                    r".\cmr10 a".to_string()
+                );
+            },
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Output loop---5 consecutive dead cycles")]
+    fn it_fails_when_the_output_routine_never_ships_out() {
+        with_parser(
+            &[
+                r"\maxdeadcycles=5%",
+                r"\output={\setbox0=\box255 \box0\penalty-10000}%",
+                r"\hbox{}%",
+                r"\penalty-10000%",
+                r"\end%",
+            ],
+            |parser| {
+                parser.parse_outer_vertical_box();
+            },
+        );
+    }
+}
@@ -1,11 +1,248 @@
+use crate::boxes::TeXBox;
 use crate::category::Category;
+use crate::dimension::Dimen;
+use crate::glue::Glue;
+use crate::list::{HorizontalListElem, LeaderRule, VerticalListElem};
 use crate::parser::Parser;
 use crate::token::Token;
 
+// Returns how TeX would print a character in a message, using ^^ notation for
+// unprintable characters.
+pub fn printable_char(chr: char) -> String {
+    match chr as u32 {
+        0..=31 => format!("^^{}", ((chr as u8) + 64) as char),
+        127 => "^^?".to_string(),
+        128..=255 => format!("^^{:02x}", chr as u32),
+        _ => chr.to_string(),
+    }
+}
+
+// Adds the lines that TeX prints for leaders filled with a rule, which are the
+// glue followed by the rule with one more "." in front of it.
+fn add_rule_leaders_dump_lines(
+    glue: &Glue,
+    rule: &LeaderRule,
+    prefix: &str,
+    lines: &mut Vec<String>,
+) {
+    let running_string = |dimen: &Option<Dimen>| match dimen {
+        Some(dimen) => dimen.to_tex_number_string(),
+        None => "*".to_string(),
+    };
+
+    lines.push(format!(
+        "{}\\leaders {}",
+        prefix,
+        glue.to_tex_number_string()
+    ));
+    lines.push(format!(
+        "{}.\\rule({}+{})x{}",
+        prefix,
+        running_string(&rule.height),
+        running_string(&rule.depth),
+        running_string(&rule.width),
+    ));
+}
+
+// Adds the lines for each element of a horizontal list in a \showbox dump to
+// `lines`, with `prefix` in front of each of them.
+fn add_horizontal_list_dump_lines(
+    list: &[HorizontalListElem],
+    prefix: &str,
+    lines: &mut Vec<String>,
+) {
+    for elem in list {
+        match elem {
+            HorizontalListElem::Char { chr, font } => lines.push(format!(
+                "{}\\{} {}",
+                prefix,
+                font.font_name,
+                printable_char(*chr)
+            )),
+            // Runs are shown one character at a time, like TeX does.
+            HorizontalListElem::CharRun { chars, font } => {
+                for chr in chars {
+                    lines.push(format!(
+                        "{}\\{} {}",
+                        prefix,
+                        font.font_name,
+                        printable_char(*chr)
+                    ))
+                }
+            }
+            HorizontalListElem::HSkip(glue) => lines.push(format!(
+                "{}\\glue {}",
+                prefix,
+                glue.to_tex_number_string()
+            )),
+            HorizontalListElem::Kern(width) => lines.push(format!(
+                "{}\\kern {}",
+                prefix,
+                width.to_tex_number_string()
+            )),
+            HorizontalListElem::Penalty(penalty) => {
+                lines.push(format!("{}\\penalty {}", prefix, penalty))
+            }
+            HorizontalListElem::Discretionary {
+                pre_break,
+                post_break,
+                no_break,
+            } => {
+                // Like in TeX, the pre-break material is shown with an extra
+                // "." and the post-break material with an extra "|", and the
+                // no-break material comes after as part of the list.
+                if no_break.is_empty() {
+                    lines.push(format!("{}\\discretionary", prefix));
+                } else {
+                    lines.push(format!(
+                        "{}\\discretionary replacing {}",
+                        prefix,
+                        no_break.len()
+                    ));
+                }
+                add_horizontal_list_dump_lines(
+                    pre_break,
+                    &format!("{}.", prefix),
+                    lines,
+                );
+                add_horizontal_list_dump_lines(
+                    post_break,
+                    &format!("{}|", prefix),
+                    lines,
+                );
+                add_horizontal_list_dump_lines(no_break, prefix, lines);
+            }
+            HorizontalListElem::Rule {
+                height,
+                depth,
+                width,
+            } => lines.push(format!(
+                "{}\\rule({}+{})x{}",
+                prefix,
+                match height {
+                    Some(height) => height.to_tex_number_string(),
+                    None => "*".to_string(),
+                },
+                match depth {
+                    Some(depth) => depth.to_tex_number_string(),
+                    None => "*".to_string(),
+                },
+                width.to_tex_number_string(),
+            )),
+            HorizontalListElem::RuleLeaders { glue, rule } => {
+                add_rule_leaders_dump_lines(glue, rule, prefix, lines)
+            }
+            HorizontalListElem::Box { tex_box, shift } => {
+                add_box_dump_lines(tex_box, shift, prefix, lines)
+            }
+        }
+    }
+}
+
+// Adds the lines that TeX prints for a box in a \showbox dump to `lines`.
+// Each element of the box's list is printed on its own line, with one more
+// "." in front of it than the box itself.
+fn add_box_dump_lines(
+    tex_box: &TeXBox,
+    shift: &Dimen,
+    prefix: &str,
+    lines: &mut Vec<String>,
+) {
+    let (name, glue_set_ratio) = match tex_box {
+        TeXBox::HorizontalBox(hbox) => ("hbox", &hbox.glue_set_ratio),
+        TeXBox::VerticalBox(vbox) => ("vbox", &vbox.glue_set_ratio),
+    };
+
+    let mut header = format!(
+        "{}\\{}({}+{})x{}",
+        prefix,
+        name,
+        tex_box.height().to_tex_number_string(),
+        tex_box.depth().to_tex_number_string(),
+        tex_box.width().to_tex_number_string(),
+    );
+    if let Some(ratio) = glue_set_ratio {
+        let ratio_string = ratio.to_tex_string();
+        if ratio_string != "0.0" {
+            header.push_str(", glue set ");
+            header.push_str(&ratio_string);
+        }
+    }
+    // TeX's shift amounts are positive when boxes are lowered, but ours are
+    // positive when boxes are raised.
+    if *shift != Dimen::zero() {
+        header.push_str(", shifted ");
+        header.push_str(&(Dimen::zero() - *shift).to_tex_number_string());
+    }
+    lines.push(header);
+
+    let inner_prefix = format!("{}.", prefix);
+    match tex_box {
+        TeXBox::HorizontalBox(hbox) => {
+            add_horizontal_list_dump_lines(&hbox.list, &inner_prefix, lines)
+        }
+        TeXBox::VerticalBox(vbox) => {
+            for elem in vbox.list.iter() {
+                match elem {
+                    VerticalListElem::Box { tex_box, shift } => {
+                        add_box_dump_lines(tex_box, shift, &inner_prefix, lines)
+                    }
+                    VerticalListElem::VSkip(glue) => lines.push(format!(
+                        "{}\\glue {}",
+                        inner_prefix,
+                        glue.to_tex_number_string()
+                    )),
+                    VerticalListElem::Kern(height) => lines.push(format!(
+                        "{}\\kern {}",
+                        inner_prefix,
+                        height.to_tex_number_string()
+                    )),
+                    VerticalListElem::Penalty(penalty) => lines
+                        .push(format!("{}\\penalty {}", inner_prefix, penalty)),
+                    VerticalListElem::Rule {
+                        height,
+                        depth,
+                        width,
+                    } => lines.push(format!(
+                        "{}\\rule({}+{})x{}",
+                        inner_prefix,
+                        height.to_tex_number_string(),
+                        depth.to_tex_number_string(),
+                        match width {
+                            Some(width) => width.to_tex_number_string(),
+                            None => "*".to_string(),
+                        }
+                    )),
+                    VerticalListElem::RuleLeaders { glue, rule } => {
+                        add_rule_leaders_dump_lines(
+                            glue,
+                            rule,
+                            &inner_prefix,
+                            lines,
+                        )
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Returns the lines that TeX prints for a box when it is shown with \showbox.
+pub fn box_dump_lines(tex_box: &TeXBox) -> Vec<String> {
+    let mut lines = Vec::new();
+    add_box_dump_lines(tex_box, &Dimen::zero(), "", &mut lines);
+    lines
+}
+
 impl<'a> Parser<'a> {
     pub fn is_print_head(&mut self) -> bool {
         match self.peek_unexpanded_token() {
-            Some(token) => self.state.is_token_equal_to_prim(&token, "number"),
+            Some(token) => {
+                self.state.is_token_equal_to_prim(&token, "number")
+                    || self.state.is_token_equal_to_prim(&token, "the")
+                    || self.state.is_token_equal_to_prim(&token, "string")
+                    || self.state.is_token_equal_to_prim(&token, "meaning")
+            }
             _ => false,
         }
     }
@@ -20,22 +257,202 @@ impl<'a> Parser<'a> {
             .collect()
     }
 
+    fn print_string(&mut self, value: &str) -> Vec<Token> {
+        // Like TeX, spaces are printed as space tokens and everything else is
+        // printed as other tokens.
+        value
+            .chars()
+            .map(|chr| match chr {
+                ' ' => Token::Char(chr, Category::Space),
+                _ => Token::Char(chr, Category::Other),
+            })
+            .collect()
+    }
+
     pub fn expand_print(&mut self) -> Vec<Token> {
         let head = self.lex_unexpanded_token().unwrap();
 
         if self.state.is_token_equal_to_prim(&head, "number") {
             let value = self.parse_number();
             self.print_number(value)
+        } else if self.state.is_token_equal_to_prim(&head, "the") {
+            self.expand_the()
+        } else if self.state.is_token_equal_to_prim(&head, "string") {
+            let token = self.lex_unexpanded_token().unwrap();
+            self.print_string(&token.to_tex_string())
+        } else if self.state.is_token_equal_to_prim(&head, "meaning") {
+            let token = self.lex_unexpanded_token().unwrap();
+            let meaning = self.get_meaning(&token);
+            self.print_string(&meaning)
         } else {
             panic!("unimplemented");
         }
     }
+
+    // Returns what a token means, like it's shown by \meaning.
+    pub fn get_meaning(&self, token: &Token) -> String {
+        if let Some(makro) = self.state.get_macro(token) {
+            return makro.meaning();
+        }
+        if let Some(prim) = self.state.get_primitive(token) {
+            return format!("\\{}", prim);
+        }
+        if let Some(font) = self.state.get_fontdef(token) {
+            let design_size = self
+                .state
+                .get_metrics_for_font(&font)
+                .unwrap()
+                .get_design_size();
+            // Like in \font assignments, the design size is rounded down to
+            // the nearest scaled point.
+            let design_size =
+                Dimen::from_scaled_points((65536.0 * design_size) as i32);
+
+            // TeX only shows the size of fonts that aren't at their design
+            // size.
+            return if font.scale == design_size {
+                format!("select font {}", font.font_name)
+            } else {
+                format!(
+                    "select font {} at {}",
+                    font.font_name,
+                    font.scale.to_tex_string()
+                )
+            };
+        }
+        if let Some(math_code) = self.state.get_math_chardef(token) {
+            return format!(
+                "\\mathchar\"{:X}",
+                (math_code.class as u32) * 0x1000
+                    + (math_code.family as u32) * 0x100
+                    + (math_code.position as u32)
+            );
+        }
+        if let Some(register_index) = self.state.get_muskipdef(token) {
+            return format!("\\muskip{}", register_index);
+        }
+
+        let token = self
+            .state
+            .get_renamed_token(token)
+            .unwrap_or_else(|| token.clone());
+        match token {
+            Token::ControlSequence(_) => "undefined".to_string(),
+            Token::Char(chr, cat) => {
+                let description = match cat {
+                    Category::BeginGroup => "begin-group character",
+                    Category::EndGroup => "end-group character",
+                    Category::MathShift => "math shift character",
+                    Category::AlignmentTab => "alignment tab character",
+                    Category::Parameter => "macro parameter character",
+                    Category::Superscript => "superscript character",
+                    Category::Subscript => "subscript character",
+                    Category::Space => "blank space",
+                    Category::Letter => "the letter",
+                    Category::Other => "the character",
+                    // Active characters that aren't macros are undefined.
+                    _ => return "undefined".to_string(),
+                };
+                format!("{} {}", description, chr)
+            }
+        }
+    }
+
+    // Parses the internal quantity after a \the or \showthe and returns the
+    // tokens that it prints as.
+    fn expand_the(&mut self) -> Vec<Token> {
+        if self.is_internal_integer_head() {
+            let value = self.parse_internal_integer();
+            self.print_number(value)
+        } else if self.is_internal_dimen_head() {
+            let value = self.parse_internal_dimen();
+            self.print_string(&value.to_tex_string())
+        } else if self.is_glue_variable_head() {
+            let value = self.parse_glue_variable().get(self.state);
+            self.print_string(&value.to_tex_string())
+        } else if self.is_mu_glue_variable_head() {
+            let value = self.parse_mu_glue_variable().get(self.state);
+            self.print_string(&value.to_tex_string())
+        } else if self.is_font_identifier_head() {
+            let font = self.parse_font_identifier();
+            // TODO(xymostech): The initial font should be \nullfont, which is
+            // the only font that isn't selected by a \font control sequence.
+            let identifier =
+                self.state.get_font_identifier(&font).unwrap_or_else(|| {
+                    Token::ControlSequence("nullfont".to_string())
+                });
+            vec![identifier]
+        } else {
+            panic!("unimplemented");
+        }
+    }
+
+    pub fn is_show_head(&mut self) -> bool {
+        self.is_next_expanded_token_in_set_of_primitives(&[
+            "show",
+            "showthe",
+            "showhyphens",
+            "showbox",
+        ])
+    }
+
+    pub fn parse_show(&mut self) {
+        let head = self.lex_expanded_token().unwrap();
+
+        if self.state.is_token_equal_to_prim(&head, "showhyphens") {
+            let hyphenated = self.parse_show_hyphens();
+            self.add_message(hyphenated);
+            return;
+        }
+
+        if self.state.is_token_equal_to_prim(&head, "show") {
+            let token = self.lex_unexpanded_token().unwrap();
+            let meaning = self.get_meaning(&token);
+            let message = match token {
+                Token::ControlSequence(_) => {
+                    format!("> {}={}.", token.to_tex_string(), meaning)
+                }
+                Token::Char(_, _) => format!("> {}.", meaning),
+            };
+            self.add_message(message);
+            return;
+        }
+
+        if self.state.is_token_equal_to_prim(&head, "showbox") {
+            let index = self.parse_8bit_number();
+            let contents = match self.state.get_box(index) {
+                Some(tex_box) => {
+                    format!("\n{}", box_dump_lines(&tex_box).join("\n"))
+                }
+                None => "void".to_string(),
+            };
+            self.add_message(format!("> \\box{}={}", index, contents));
+            return;
+        }
+
+        let value: String = self
+            .expand_the()
+            .iter()
+            .map(|token| token.to_token_list_string())
+            .collect();
+        self.add_message(format!("> {}.", value));
+    }
+
+    pub fn add_message(&mut self, message: String) {
+        self.messages.push(message);
+    }
+
+    pub fn take_messages(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.messages)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use crate::boxes::TeXBox;
+    use crate::list::HorizontalListElem;
     use crate::testing::with_parser;
 
     #[test]
@@ -93,4 +510,387 @@ mod tests {
             },
         );
     }
+
+    #[test]
+    fn it_expands_the_for_assigned_box_dimens() {
+        with_parser(
+            &[r"\setbox0=\hbox{a}%", r"\wd0=10pt \the\wd0%"],
+            |parser| {
+                parser.parse_assignment();
+                parser.parse_assignment();
+
+                assert!(parser.is_print_head());
+                assert_eq!(
+                    parser.expand_print(),
+                    vec![
+                        Token::Char('1', Category::Other),
+                        Token::Char('0', Category::Other),
+                        Token::Char('.', Category::Other),
+                        Token::Char('0', Category::Other),
+                        Token::Char('p', Category::Other),
+                        Token::Char('t', Category::Other),
+                    ]
+                );
+
+                // The contents of the box are unchanged
+                match parser.state.get_box(0) {
+                    Some(TeXBox::HorizontalBox(hbox)) => match &hbox.list[..] {
+                        [HorizontalListElem::Char { chr: 'a', .. }] => (),
+                        list => panic!("Unexpected box contents: {:?}", list),
+                    },
+                    other => panic!("Expected hbox, found {:?}", other),
+                }
+            },
+        );
+    }
+
+    #[test]
+    fn it_shows_box_dimens() {
+        with_parser(&[r"\setbox0=\hbox{a} \showthe\wd0%"], |parser| {
+            parser.parse_assignment();
+            parser.parse_optional_spaces_expanded();
+
+            assert!(parser.is_show_head());
+            parser.parse_show();
+
+            let metrics = parser
+                .state
+                .get_metrics_for_font(&parser.state.get_current_font())
+                .unwrap();
+            assert_eq!(
+                parser.take_messages(),
+                vec![format!("> {}.", metrics.get_width('a').to_tex_string())]
+            );
+        });
+    }
+
+    #[test]
+    fn it_shows_kerns_in_box_dumps() {
+        with_parser(&[r"\setbox0=\hbox{AV}\showbox0%"], |parser| {
+            parser.parse_assignment();
+            parser.parse_show();
+
+            let metrics = parser
+                .state
+                .get_metrics_for_font(&parser.state.get_current_font())
+                .unwrap();
+            let kern = metrics.get_kern('A', 'V').unwrap();
+            let width = metrics.get_width('A') + kern + metrics.get_width('V');
+
+            assert_eq!(
+                parser.take_messages(),
+                vec![[
+                    r"> \box0=".to_string(),
+                    format!(
+                        r"\hbox({}+0.0)x{}",
+                        metrics.get_height('A').to_tex_number_string(),
+                        width.to_tex_number_string()
+                    ),
+                    r".\cmr10 A".to_string(),
+                    format!(r".\kern {}", kern.to_tex_number_string()),
+                    r".\cmr10 V".to_string(),
+                ]
+                .join("\n")]
+            );
+        });
+    }
+
+    #[test]
+    fn it_shows_discretionaries_in_box_dumps() {
+        with_parser(
+            &[r"\setbox0=\hbox{\discretionary{b}{c}{d}}\showbox0%"],
+            |parser| {
+                parser.parse_assignment();
+                parser.parse_show();
+
+                let metrics = parser
+                    .state
+                    .get_metrics_for_font(&parser.state.get_current_font())
+                    .unwrap();
+
+                assert_eq!(
+                    parser.take_messages(),
+                    vec![[
+                        r"> \box0=".to_string(),
+                        format!(
+                            r"\hbox({}+0.0)x{}",
+                            metrics.get_height('d').to_tex_number_string(),
+                            metrics.get_width('d').to_tex_number_string()
+                        ),
+                        r".\discretionary replacing 1".to_string(),
+                        r"..\cmr10 b".to_string(),
+                        r".|\cmr10 c".to_string(),
+                        r".\cmr10 d".to_string(),
+                    ]
+                    .join("\n")]
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn it_shows_glue_and_nested_boxes_in_box_dumps() {
+        with_parser(
+            &[
+                r"\setbox0=\vbox{\hbox to 10pt{\hskip 2pt plus 1fil minus 1pt}%",
+                r"\vskip 3pt plus 1.5pt\penalty 100}%",
+                r"\showbox0%",
+                r"\showbox1%",
+            ],
+            |parser| {
+                parser.parse_assignment();
+                parser.parse_show();
+                parser.parse_show();
+
+                assert_eq!(
+                    parser.take_messages(),
+                    vec![
+                        [
+                            r"> \box0=",
+                            r"\vbox(3.0+0.0)x10.0",
+                            r".\hbox(0.0+0.0)x10.0, glue set 8.0fil",
+                            r"..\glue 2.0 plus 1.0fil minus 1.0",
+                            r".\glue 3.0 plus 1.5",
+                            r".\penalty 100",
+                        ]
+                        .join("\n"),
+                        r"> \box1=void".to_string(),
+                    ]
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn it_shows_rule_leaders_in_box_dumps() {
+        with_parser(
+            &[
+                r"\setbox0=\vbox{\hbox to 10pt{%",
+                r"\leaders\vrule height 2pt\hskip 0pt plus 1fil}%",
+                r"\leaders\hrule\vskip 3pt}%",
+                r"\showbox0%",
+            ],
+            |parser| {
+                parser.parse_assignment();
+                parser.parse_show();
+
+                assert_eq!(
+                    parser.take_messages(),
+                    vec![[
+                        r"> \box0=",
+                        r"\vbox(5.0+0.0)x10.0",
+                        r".\hbox(2.0+0.0)x10.0, glue set 10.0fil",
+                        r"..\leaders 0.0 plus 1.0fil",
+                        r"...\rule(2.0+*)x0.4",
+                        r".\leaders 3.0",
+                        r"..\rule(0.4+0.0)x*",
+                    ]
+                    .join("\n")]
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn it_expands_the_for_glue_parameters() {
+        with_parser(
+            &[
+                r"\baselineskip=12pt plus1fil minus0pt \the\baselineskip%",
+                r"\lineskip=-1.5pt minus 2fill%",
+                r"\showthe\lineskip%",
+            ],
+            |parser| {
+                parser.parse_assignment();
+
+                assert!(parser.is_print_head());
+                let printed: String = parser
+                    .expand_print()
+                    .iter()
+                    .map(|token| match token {
+                        Token::Char(chr, _) => *chr,
+                        _ => unreachable!(),
+                    })
+                    .collect();
+                assert_eq!(printed, "12.0pt plus 1.0fil");
+
+                parser.parse_assignment();
+                parser.parse_show();
+                assert_eq!(
+                    parser.take_messages(),
+                    vec!["> -1.5pt minus 2.0fill."]
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn it_shows_font_parameters() {
+        with_parser(&[r"\showthe\fontdimen6\font%"], |parser| {
+            parser.parse_show();
+            assert_eq!(parser.take_messages(), vec!["> 10.00002pt."]);
+        });
+    }
+
+    #[test]
+    fn it_expands_the_current_font() {
+        with_parser(
+            &[
+                r"\font\x=cmr10 \x \the\font%",
+                r"\font\y=cmr7 \y \showthe\font%",
+            ],
+            |parser| {
+                parser.parse_assignment();
+                parser.parse_assignment();
+
+                assert!(parser.is_print_head());
+                assert_eq!(
+                    parser.expand_print(),
+                    vec![Token::ControlSequence("x".to_string())]
+                );
+
+                parser.parse_assignment();
+                parser.parse_assignment();
+                parser.parse_show();
+                assert_eq!(parser.take_messages(), vec![r"> \y ."]);
+            },
+        );
+    }
+
+    #[test]
+    fn it_shows_the_meanings_of_tokens() {
+        with_parser(
+            &[
+                r"\let\y=\relax \def\z#1{a#1}%",
+                r"\show\y \show\z \show a\show\undefined%",
+            ],
+            |parser| {
+                parser.parse_assignment();
+                parser.parse_assignment();
+                for _ in 0..4 {
+                    assert!(parser.is_show_head());
+                    parser.parse_show();
+                }
+                assert_eq!(
+                    parser.take_messages(),
+                    vec![
+                        r"> \y=\relax.",
+                        r"> \z=macro:#1->a#1.",
+                        r"> the letter a.",
+                        r"> \undefined=undefined.",
+                    ]
+                );
+            },
+        );
+    }
+
+    // Expands all of the upcoming tokens and turns them back into a string.
+    fn expand_to_string(parser: &mut Parser) -> String {
+        let mut result = String::new();
+        while let Some(token) = parser.lex_expanded_token() {
+            result.push_str(&token.to_tex_string());
+        }
+        result
+    }
+
+    #[test]
+    fn it_expands_string() {
+        with_parser(&[r"\string\a\string x\string\relax%"], |parser| {
+            assert_eq!(
+                parser.lex_expanded_token(),
+                Some(Token::Char('\\', Category::Other))
+            );
+            assert_eq!(
+                parser.lex_expanded_token(),
+                Some(Token::Char('a', Category::Other))
+            );
+            assert_eq!(expand_to_string(parser), r"x\relax");
+        });
+    }
+
+    #[test]
+    fn it_expands_the_meaning_of_macros() {
+        with_parser(
+            &[
+                r"\long\def\a#1{x#1y}%",
+                r"\def\b#1.#2{\a{#1}##\relax#2}%",
+                r"\outer\def\c{}%",
+                r"\meaning\a%",
+            ],
+            |parser| {
+                parser.parse_assignment();
+                parser.parse_assignment();
+                parser.parse_assignment();
+                assert_eq!(expand_to_string(parser), r"\long macro:#1->x#1y");
+
+                parser.add_upcoming_tokens(vec![
+                    Token::ControlSequence("meaning".to_string()),
+                    Token::ControlSequence("b".to_string()),
+                ]);
+                assert_eq!(
+                    expand_to_string(parser),
+                    r"macro:#1.#2->\a {#1}##\relax #2"
+                );
+
+                parser.add_upcoming_tokens(vec![
+                    Token::ControlSequence("meaning".to_string()),
+                    Token::ControlSequence("c".to_string()),
+                ]);
+                assert_eq!(expand_to_string(parser), r"\outer macro:->");
+            },
+        );
+    }
+
+    #[test]
+    fn it_expands_the_meaning_of_other_tokens() {
+        with_parser(
+            &[
+                r"\let\a=b%",
+                r"\meaning\a,\meaning\relax,\meaning\undefined,%",
+                r"\meaning{,\meaning1%",
+            ],
+            |parser| {
+                parser.parse_assignment();
+                assert_eq!(
+                    expand_to_string(parser),
+                    concat!(
+                        r"the letter b,\relax,undefined,",
+                        r"begin-group character {,the character 1"
+                    )
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn it_expands_the_meaning_of_fonts() {
+        with_parser(
+            &[
+                r"\font\a=cmr10 \font\b=cmr10 at 20pt%",
+                r"\font\c=cmr7 scaled 1000%",
+                r"\meaning\a,\meaning\b,\meaning\c%",
+            ],
+            |parser| {
+                parser.parse_assignment();
+                parser.parse_assignment();
+                parser.parse_assignment();
+                assert_eq!(
+                    expand_to_string(parser),
+                    concat!(
+                        r"select font cmr10,select font cmr10 at 20.0pt,",
+                        r"select font cmr7"
+                    )
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn it_expands_the_input_line_number() {
+        with_parser(
+            &[r"\the\inputlineno,%", r"%", r"\the\inputlineno%"],
+            |parser| {
+                assert_eq!(expand_to_string(parser), "1,3");
+            },
+        );
+    }
 }
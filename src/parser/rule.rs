@@ -0,0 +1,263 @@
+use crate::dimension::Dimen;
+use crate::glue::Glue;
+use crate::list::{HorizontalListElem, LeaderRule, VerticalListElem};
+use crate::parser::Parser;
+
+// The thickness of rules whose thickness isn't specified, which is 0.4pt.
+fn default_rule_thickness() -> Dimen {
+    Dimen::from_scaled_points(26214)
+}
+
+// The dimensions given in a <rule specification>. Each one is None if it
+// wasn't specified.
+struct RuleSpecification {
+    height: Option<Dimen>,
+    depth: Option<Dimen>,
+    width: Option<Dimen>,
+}
+
+impl<'a> Parser<'a> {
+    pub fn is_hrule_head(&mut self) -> bool {
+        self.is_next_expanded_token_in_set_of_primitives(&["hrule"])
+    }
+
+    pub fn is_vrule_head(&mut self) -> bool {
+        self.is_next_expanded_token_in_set_of_primitives(&["vrule"])
+    }
+
+    /// Parses a <rule specification>, which is any number of `width`,
+    /// `height`, and `depth` keywords followed by dimensions. If the same
+    /// keyword shows up multiple times, the last one wins.
+    fn parse_rule_specification(&mut self) -> RuleSpecification {
+        let mut spec = RuleSpecification {
+            height: None,
+            depth: None,
+            width: None,
+        };
+
+        loop {
+            if self.parse_optional_keyword_expanded("width") {
+                spec.width = Some(self.parse_dimen());
+            } else if self.parse_optional_keyword_expanded("height") {
+                spec.height = Some(self.parse_dimen());
+            } else if self.parse_optional_keyword_expanded("depth") {
+                spec.depth = Some(self.parse_dimen());
+            } else {
+                break;
+            }
+        }
+
+        spec
+    }
+
+    /// Parses an \hrule, which is 0.4pt tall by default and as wide as the
+    /// box it ends up in unless a width is given.
+    pub fn parse_hrule(&mut self) -> VerticalListElem {
+        let tok = self.lex_expanded_token().unwrap();
+        if !self.state.is_token_equal_to_prim(&tok, "hrule") {
+            panic!("Invalid hrule head: {:?}", tok);
+        }
+
+        let spec = self.parse_rule_specification();
+
+        VerticalListElem::Rule {
+            height: spec.height.unwrap_or_else(default_rule_thickness),
+            depth: spec.depth.unwrap_or_else(Dimen::zero),
+            width: spec.width,
+        }
+    }
+
+    /// Parses a \vrule, which is 0.4pt wide by default and as tall and deep
+    /// as the box it ends up in unless a height or depth is given.
+    pub fn parse_vrule(&mut self) -> HorizontalListElem {
+        let tok = self.lex_expanded_token().unwrap();
+        if !self.state.is_token_equal_to_prim(&tok, "vrule") {
+            panic!("Invalid vrule head: {:?}", tok);
+        }
+
+        let spec = self.parse_rule_specification();
+
+        HorizontalListElem::Rule {
+            height: spec.height,
+            depth: spec.depth,
+            width: spec.width.unwrap_or_else(default_rule_thickness),
+        }
+    }
+
+    pub fn is_leaders_head(&mut self) -> bool {
+        self.is_next_expanded_token_in_set_of_primitives(&["leaders"])
+    }
+
+    /// Parses \leaders followed by a rule and then glue using the given skip
+    /// primitive. Leaders with boxes aren't supported yet.
+    fn parse_leaders(&mut self, skip_primitive: &str) -> (LeaderRule, Glue) {
+        let tok = self.lex_expanded_token().unwrap();
+        if !self.state.is_token_equal_to_prim(&tok, "leaders") {
+            panic!("Invalid leaders head: {:?}", tok);
+        }
+
+        let rule = if self.is_hrule_head() {
+            self.lex_expanded_token();
+            let spec = self.parse_rule_specification();
+            LeaderRule {
+                height: Some(
+                    spec.height.unwrap_or_else(default_rule_thickness),
+                ),
+                depth: Some(spec.depth.unwrap_or_else(Dimen::zero)),
+                width: spec.width,
+            }
+        } else if self.is_vrule_head() {
+            self.lex_expanded_token();
+            let spec = self.parse_rule_specification();
+            LeaderRule {
+                height: spec.height,
+                depth: spec.depth,
+                width: Some(spec.width.unwrap_or_else(default_rule_thickness)),
+            }
+        } else if self.is_box_head() {
+            panic!("Box leaders are unimplemented");
+        } else {
+            panic!("A <box> was supposed to be here");
+        };
+
+        if !self.is_next_expanded_token_in_set_of_primitives(&[skip_primitive])
+        {
+            panic!("Leaders not followed by proper glue");
+        }
+        self.lex_expanded_token();
+        let glue = self.parse_glue();
+
+        (rule, glue)
+    }
+
+    /// Parses \leaders in a horizontal list, which must be followed by
+    /// \hskip glue.
+    pub fn parse_horizontal_leaders(&mut self) -> HorizontalListElem {
+        let (rule, glue) = self.parse_leaders("hskip");
+        HorizontalListElem::RuleLeaders { glue, rule }
+    }
+
+    /// Parses \leaders in a vertical list, which must be followed by \vskip
+    /// glue.
+    pub fn parse_vertical_leaders(&mut self) -> VerticalListElem {
+        let (rule, glue) = self.parse_leaders("vskip");
+        VerticalListElem::RuleLeaders { glue, rule }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::dimension::{FilDimen, FilKind, SpringDimen, Unit};
+    use crate::testing::with_parser;
+
+    #[test]
+    fn it_parses_rules_with_default_dimensions() {
+        with_parser(&[r"\hrule\vrule%"], |parser| {
+            assert!(parser.is_hrule_head());
+            assert_eq!(
+                parser.parse_hrule(),
+                VerticalListElem::Rule {
+                    height: Dimen::from_unit(0.4, Unit::Point),
+                    depth: Dimen::zero(),
+                    width: None,
+                }
+            );
+
+            assert!(parser.is_vrule_head());
+            assert_eq!(
+                parser.parse_vrule(),
+                HorizontalListElem::Rule {
+                    height: None,
+                    depth: None,
+                    width: Dimen::from_unit(0.4, Unit::Point),
+                }
+            );
+        });
+    }
+
+    #[test]
+    fn it_parses_rule_specifications() {
+        with_parser(
+            &[
+                r"\hrule width 2pt depth 3pt height 1pt%",
+                r"\vrule height 1pt width 5pt height 4pt%",
+            ],
+            |parser| {
+                assert_eq!(
+                    parser.parse_hrule(),
+                    VerticalListElem::Rule {
+                        height: Dimen::from_unit(1.0, Unit::Point),
+                        depth: Dimen::from_unit(3.0, Unit::Point),
+                        width: Some(Dimen::from_unit(2.0, Unit::Point)),
+                    }
+                );
+
+                assert_eq!(
+                    parser.parse_vrule(),
+                    HorizontalListElem::Rule {
+                        height: Some(Dimen::from_unit(4.0, Unit::Point)),
+                        depth: None,
+                        width: Dimen::from_unit(5.0, Unit::Point),
+                    }
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn it_parses_rule_leaders() {
+        with_parser(
+            &[
+                r"\leaders\hrule width 3pt\hskip 1pt plus 1fil%",
+                r"\leaders\vrule depth 2pt\vskip 4pt%",
+            ],
+            |parser| {
+                assert!(parser.is_leaders_head());
+                assert_eq!(
+                    parser.parse_horizontal_leaders(),
+                    HorizontalListElem::RuleLeaders {
+                        glue: Glue {
+                            space: Dimen::from_unit(1.0, Unit::Point),
+                            stretch: SpringDimen::FilDimen(FilDimen::new(
+                                FilKind::Fil,
+                                1.0
+                            )),
+                            shrink: SpringDimen::Dimen(Dimen::zero()),
+                        },
+                        rule: LeaderRule {
+                            height: Some(Dimen::from_unit(0.4, Unit::Point)),
+                            depth: Some(Dimen::zero()),
+                            width: Some(Dimen::from_unit(3.0, Unit::Point)),
+                        },
+                    }
+                );
+
+                assert!(parser.is_leaders_head());
+                assert_eq!(
+                    parser.parse_vertical_leaders(),
+                    VerticalListElem::RuleLeaders {
+                        glue: Glue::from_dimen(Dimen::from_unit(
+                            4.0,
+                            Unit::Point
+                        )),
+                        rule: LeaderRule {
+                            height: None,
+                            depth: Some(Dimen::from_unit(2.0, Unit::Point)),
+                            width: Some(Dimen::from_unit(0.4, Unit::Point)),
+                        },
+                    }
+                );
+            },
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Leaders not followed by proper glue")]
+    fn it_fails_on_leaders_without_matching_glue() {
+        with_parser(&[r"\leaders\hrule\vskip 1pt%"], |parser| {
+            parser.parse_horizontal_leaders();
+        });
+    }
+}
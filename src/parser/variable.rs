@@ -1,9 +1,32 @@
 use crate::parser::Parser;
-use crate::variable::{DimenVariable, IntegerVariable};
+use crate::variable::{
+    DimenParameter, DimenVariable, GlueParameter, GlueVariable,
+    IntegerParameter, IntegerVariable, MuGlueParameter, MuGlueVariable,
+};
 
 impl<'a> Parser<'a> {
     pub fn is_integer_variable_head(&mut self) -> bool {
-        self.is_next_expanded_token_in_set_of_primitives(&["count"])
+        self.is_next_expanded_token_in_set_of_primitives(&[
+            "count",
+            "outputpenalty",
+            "deadcycles",
+            "maxdeadcycles",
+            "interlinepenalty",
+            "interdisplaylinepenalty",
+            "predisplaypenalty",
+            "postdisplaypenalty",
+            "relpenalty",
+            "binoppenalty",
+            "tracingonline",
+            "tracingoutput",
+            "tolerance",
+            "linepenalty",
+            "hyphenpenalty",
+            "adjdemerits",
+            "delimiterfactor",
+            "skewchar",
+            "hyphenchar",
+        ])
     }
 
     pub fn parse_integer_variable(&mut self) -> IntegerVariable {
@@ -12,19 +35,98 @@ impl<'a> Parser<'a> {
         if self.state.is_token_equal_to_prim(&token, "count") {
             let index = self.parse_8bit_number();
             IntegerVariable::CountRegister(index)
+        } else if self.state.is_token_equal_to_prim(&token, "outputpenalty") {
+            IntegerVariable::IntegerParameter(IntegerParameter::OutputPenalty)
+        } else if self.state.is_token_equal_to_prim(&token, "deadcycles") {
+            IntegerVariable::IntegerParameter(IntegerParameter::DeadCycles)
+        } else if self.state.is_token_equal_to_prim(&token, "maxdeadcycles") {
+            IntegerVariable::IntegerParameter(IntegerParameter::MaxDeadCycles)
+        } else if self
+            .state
+            .is_token_equal_to_prim(&token, "interlinepenalty")
+        {
+            IntegerVariable::IntegerParameter(
+                IntegerParameter::InterLinePenalty,
+            )
+        } else if self
+            .state
+            .is_token_equal_to_prim(&token, "interdisplaylinepenalty")
+        {
+            IntegerVariable::IntegerParameter(
+                IntegerParameter::InterDisplayLinePenalty,
+            )
+        } else if self
+            .state
+            .is_token_equal_to_prim(&token, "predisplaypenalty")
+        {
+            IntegerVariable::IntegerParameter(
+                IntegerParameter::PreDisplayPenalty,
+            )
+        } else if self
+            .state
+            .is_token_equal_to_prim(&token, "postdisplaypenalty")
+        {
+            IntegerVariable::IntegerParameter(
+                IntegerParameter::PostDisplayPenalty,
+            )
+        } else if self.state.is_token_equal_to_prim(&token, "relpenalty") {
+            IntegerVariable::IntegerParameter(IntegerParameter::RelPenalty)
+        } else if self.state.is_token_equal_to_prim(&token, "binoppenalty") {
+            IntegerVariable::IntegerParameter(IntegerParameter::BinOpPenalty)
+        } else if self.state.is_token_equal_to_prim(&token, "tracingonline") {
+            IntegerVariable::IntegerParameter(IntegerParameter::TracingOnline)
+        } else if self.state.is_token_equal_to_prim(&token, "tracingoutput") {
+            IntegerVariable::IntegerParameter(IntegerParameter::TracingOutput)
+        } else if self.state.is_token_equal_to_prim(&token, "tolerance") {
+            IntegerVariable::IntegerParameter(IntegerParameter::Tolerance)
+        } else if self.state.is_token_equal_to_prim(&token, "linepenalty") {
+            IntegerVariable::IntegerParameter(IntegerParameter::LinePenalty)
+        } else if self.state.is_token_equal_to_prim(&token, "hyphenpenalty") {
+            IntegerVariable::IntegerParameter(IntegerParameter::HyphenPenalty)
+        } else if self.state.is_token_equal_to_prim(&token, "adjdemerits") {
+            IntegerVariable::IntegerParameter(IntegerParameter::AdjDemerits)
+        } else if self.state.is_token_equal_to_prim(&token, "delimiterfactor") {
+            IntegerVariable::IntegerParameter(IntegerParameter::DelimiterFactor)
+        } else if self.state.is_token_equal_to_prim(&token, "skewchar") {
+            let font = self.parse_font_identifier();
+            IntegerVariable::SkewChar(font)
+        } else if self.state.is_token_equal_to_prim(&token, "hyphenchar") {
+            let font = self.parse_font_identifier();
+            IntegerVariable::HyphenChar(font)
         } else {
             panic!("unimplemented");
         }
     }
 
     pub fn is_dimen_variable_head(&mut self) -> bool {
-        self.is_next_expanded_token_in_set_of_primitives(&["wd", "ht", "dp"])
+        self.is_next_expanded_token_in_set_of_primitives(&[
+            "dimen",
+            "wd",
+            "ht",
+            "dp",
+            "maxdepth",
+            "boxmaxdepth",
+            "vsize",
+            "hsize",
+            "predisplaysize",
+            "displaywidth",
+            "displayindent",
+            "scriptspace",
+            "prevdepth",
+            "lineskiplimit",
+            "delimitershortfall",
+            "nulldelimiterspace",
+            "fontdimen",
+        ])
     }
 
     pub fn parse_dimen_variable(&mut self) -> DimenVariable {
         let token = self.lex_expanded_token().unwrap();
 
-        if self.state.is_token_equal_to_prim(&token, "wd") {
+        if self.state.is_token_equal_to_prim(&token, "dimen") {
+            let index = self.parse_8bit_number();
+            DimenVariable::DimenRegister(index)
+        } else if self.state.is_token_equal_to_prim(&token, "wd") {
             let index = self.parse_8bit_number();
             DimenVariable::BoxWidth(index)
         } else if self.state.is_token_equal_to_prim(&token, "ht") {
@@ -33,6 +135,136 @@ impl<'a> Parser<'a> {
         } else if self.state.is_token_equal_to_prim(&token, "dp") {
             let index = self.parse_8bit_number();
             DimenVariable::BoxDepth(index)
+        } else if self.state.is_token_equal_to_prim(&token, "maxdepth") {
+            DimenVariable::DimenParameter(DimenParameter::MaxDepth)
+        } else if self.state.is_token_equal_to_prim(&token, "boxmaxdepth") {
+            DimenVariable::DimenParameter(DimenParameter::BoxMaxDepth)
+        } else if self.state.is_token_equal_to_prim(&token, "vsize") {
+            DimenVariable::DimenParameter(DimenParameter::VSize)
+        } else if self.state.is_token_equal_to_prim(&token, "hsize") {
+            DimenVariable::DimenParameter(DimenParameter::HSize)
+        } else if self.state.is_token_equal_to_prim(&token, "predisplaysize") {
+            DimenVariable::DimenParameter(DimenParameter::PreDisplaySize)
+        } else if self.state.is_token_equal_to_prim(&token, "displaywidth") {
+            DimenVariable::DimenParameter(DimenParameter::DisplayWidth)
+        } else if self.state.is_token_equal_to_prim(&token, "displayindent") {
+            DimenVariable::DimenParameter(DimenParameter::DisplayIndent)
+        } else if self.state.is_token_equal_to_prim(&token, "scriptspace") {
+            DimenVariable::DimenParameter(DimenParameter::ScriptSpace)
+        } else if self.state.is_token_equal_to_prim(&token, "prevdepth") {
+            if let Some(None) = self.current_vertical_lists.last() {
+                panic!("Improper \\prevdepth in horizontal mode");
+            }
+            DimenVariable::DimenParameter(DimenParameter::PrevDepth)
+        } else if self.state.is_token_equal_to_prim(&token, "lineskiplimit") {
+            DimenVariable::DimenParameter(DimenParameter::LineSkipLimit)
+        } else if self
+            .state
+            .is_token_equal_to_prim(&token, "delimitershortfall")
+        {
+            DimenVariable::DimenParameter(DimenParameter::DelimiterShortfall)
+        } else if self
+            .state
+            .is_token_equal_to_prim(&token, "nulldelimiterspace")
+        {
+            DimenVariable::DimenParameter(DimenParameter::NullDelimiterSpace)
+        } else if self.state.is_token_equal_to_prim(&token, "fontdimen") {
+            let index = self.parse_number();
+            let font = self.parse_font_identifier();
+
+            if index <= 0 {
+                panic!("Invalid \\fontdimen index: {}", index);
+            }
+
+            DimenVariable::FontDimen(index as usize, font)
+        } else {
+            panic!("unimplemented");
+        }
+    }
+
+    pub fn is_glue_variable_head(&mut self) -> bool {
+        self.is_next_expanded_token_in_set_of_primitives(&[
+            "skip",
+            "abovedisplayskip",
+            "belowdisplayskip",
+            "abovedisplayshortskip",
+            "belowdisplayshortskip",
+            "baselineskip",
+            "lineskip",
+            "parfillskip",
+            "topskip",
+        ])
+    }
+
+    pub fn parse_glue_variable(&mut self) -> GlueVariable {
+        let token = self.lex_expanded_token().unwrap();
+
+        if self.state.is_token_equal_to_prim(&token, "skip") {
+            let index = self.parse_8bit_number();
+            GlueVariable::SkipRegister(index)
+        } else if self
+            .state
+            .is_token_equal_to_prim(&token, "abovedisplayskip")
+        {
+            GlueVariable::GlueParameter(GlueParameter::AboveDisplaySkip)
+        } else if self
+            .state
+            .is_token_equal_to_prim(&token, "belowdisplayskip")
+        {
+            GlueVariable::GlueParameter(GlueParameter::BelowDisplaySkip)
+        } else if self
+            .state
+            .is_token_equal_to_prim(&token, "abovedisplayshortskip")
+        {
+            GlueVariable::GlueParameter(GlueParameter::AboveDisplayShortSkip)
+        } else if self
+            .state
+            .is_token_equal_to_prim(&token, "belowdisplayshortskip")
+        {
+            GlueVariable::GlueParameter(GlueParameter::BelowDisplayShortSkip)
+        } else if self.state.is_token_equal_to_prim(&token, "baselineskip") {
+            GlueVariable::GlueParameter(GlueParameter::BaselineSkip)
+        } else if self.state.is_token_equal_to_prim(&token, "lineskip") {
+            GlueVariable::GlueParameter(GlueParameter::LineSkip)
+        } else if self.state.is_token_equal_to_prim(&token, "parfillskip") {
+            GlueVariable::GlueParameter(GlueParameter::ParFillSkip)
+        } else if self.state.is_token_equal_to_prim(&token, "topskip") {
+            GlueVariable::GlueParameter(GlueParameter::TopSkip)
+        } else {
+            panic!("unimplemented");
+        }
+    }
+
+    pub fn is_mu_glue_variable_head(&mut self) -> bool {
+        if self.is_next_expanded_token_in_set_of_primitives(&[
+            "muskip",
+            "thinmuskip",
+            "medmuskip",
+            "thickmuskip",
+        ]) {
+            return true;
+        }
+
+        match self.peek_expanded_token() {
+            Some(token) => self.state.get_muskipdef(&token).is_some(),
+            None => false,
+        }
+    }
+
+    pub fn parse_mu_glue_variable(&mut self) -> MuGlueVariable {
+        let token = self.lex_expanded_token().unwrap();
+
+        if self.state.is_token_equal_to_prim(&token, "muskip") {
+            let index = self.parse_8bit_number();
+            MuGlueVariable::MuSkipRegister(index)
+        } else if self.state.is_token_equal_to_prim(&token, "thinmuskip") {
+            MuGlueVariable::MuGlueParameter(MuGlueParameter::ThinMuSkip)
+        } else if self.state.is_token_equal_to_prim(&token, "medmuskip") {
+            MuGlueVariable::MuGlueParameter(MuGlueParameter::MedMuSkip)
+        } else if self.state.is_token_equal_to_prim(&token, "thickmuskip") {
+            MuGlueVariable::MuGlueParameter(MuGlueParameter::ThickMuSkip)
+        } else if let Some(index) = self.state.get_muskipdef(&token) {
+            MuGlueVariable::MuSkipRegister(index)
         } else {
             panic!("unimplemented");
         }
@@ -43,6 +275,8 @@ impl<'a> Parser<'a> {
 mod tests {
     use super::*;
 
+    use crate::dimension::{Dimen, SpringDimen, Unit};
+    use crate::glue::Glue;
     use crate::testing::with_parser;
 
     #[test]
@@ -93,4 +327,51 @@ mod tests {
             );
         });
     }
+
+    #[test]
+    fn it_parses_glue_variables() {
+        with_parser(&[r"\abovedisplayskip=1pt plus 2pt%"], |parser| {
+            parser.parse_assignment();
+            assert_eq!(
+                parser
+                    .state
+                    .get_glue_parameter(&GlueParameter::AboveDisplaySkip),
+                Glue {
+                    space: Dimen::from_unit(1.0, Unit::Point),
+                    stretch: SpringDimen::Dimen(Dimen::from_unit(
+                        2.0,
+                        Unit::Point
+                    )),
+                    shrink: SpringDimen::Dimen(Dimen::zero()),
+                }
+            );
+        });
+    }
+
+    #[test]
+    fn it_sets_skewchars_globally() {
+        with_parser(
+            &[
+                r"\font\a=cmr10%",
+                r"\font\b=cmr10 scaled 2000%",
+                r"\skewchar\a=127%",
+                r"\count1=\skewchar\a%",
+                r"\count2=\skewchar\b%",
+            ],
+            |parser| {
+                parser.parse_assignment();
+                parser.parse_assignment();
+
+                parser.state.push_state();
+                parser.parse_assignment();
+                parser.state.pop_state();
+
+                parser.parse_assignment();
+                parser.parse_assignment();
+
+                assert_eq!(parser.state.get_count(1), 127);
+                assert_eq!(parser.state.get_count(2), -1);
+            },
+        );
+    }
 }
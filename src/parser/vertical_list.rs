@@ -1,23 +1,93 @@
+use crate::boxes::{HorizontalBox, TeXBox};
 use crate::category::Category;
 use crate::dimension::{Dimen, Unit};
 use crate::glue::Glue;
 use crate::list::VerticalListElem;
+use crate::parser::page_builder::Page;
 use crate::parser::Parser;
 use crate::token::Token;
+use crate::variable::{DimenParameter, GlueParameter, IntegerParameter};
+
+// A vertical list that is being built. The depth of its most recent box is
+// kept in \prevdepth.
+pub struct CurrentVerticalList {
+    elems: Vec<VerticalListElem>,
+}
+
+// The \prevdepth value that means that no interline glue should be added
+// before the next box.
+fn ignore_depth() -> Dimen {
+    Dimen::from_unit(-1000.0, Unit::Point)
+}
 
 impl<'a> Parser<'a> {
-    /// Handle generating an optionally indented horizontal mode box by
-    /// entering horizontal mode and parsing the box there.
+    // Adds the lines of part of a paragraph to `result`, with
+    // \interlinepenalty between the lines.
+    fn add_paragraph_lines(
+        &mut self,
+        lines: Vec<HorizontalBox>,
+        result: &mut Vec<VerticalListElem>,
+    ) {
+        let inter_line_penalty = self
+            .state
+            .get_integer_parameter(&IntegerParameter::InterLinePenalty);
+
+        for (i, line) in lines.into_iter().enumerate() {
+            if i > 0 && inter_line_penalty != 0 {
+                result.push(VerticalListElem::Penalty(inter_line_penalty));
+            }
+            result.push(VerticalListElem::Box {
+                tex_box: TeXBox::HorizontalBox(line),
+                shift: Dimen::zero(),
+            });
+        }
+    }
+
+    /// Handle generating an optionally indented paragraph by entering
+    /// horizontal mode and parsing the paragraph there, and then breaking it
+    /// into lines. If the paragraph is interrupted by displays, this returns
+    /// the lines of each part of the paragraph along with the displays and
+    /// the penalties around them.
     fn handle_enter_horizontal_mode(
         &mut self,
         indent: bool,
-    ) -> VerticalListElem {
-        // TODO(xymostech): This will eventually potentially
-        // produce a series of boxes instead of just one, if there
-        // are line breaks. Handle that.
-        let tex_box = self.parse_unrestricted_horizontal_box(indent);
-        // TODO(xymostech): Add \parskip glue before the box.
-        VerticalListElem::Box(tex_box)
+    ) -> Vec<VerticalListElem> {
+        let mut result = Vec::new();
+        let mut indent = indent;
+
+        loop {
+            let list = self.parse_horizontal_list(false, indent);
+
+            let (lines, display) = match self.interrupting_display.take() {
+                Some(interrupted) => interrupted,
+                None => {
+                    // The part of the paragraph after a display is dropped
+                    // if it's empty.
+                    if !list.is_empty() {
+                        // TODO(xymostech): Add \parskip glue before the box.
+                        let lines = self.break_paragraph_into_lines(list);
+                        self.add_paragraph_lines(lines, &mut result);
+                    } else if result.is_empty() {
+                        result.push(VerticalListElem::Box {
+                            tex_box: TeXBox::HorizontalBox(
+                                HorizontalBox::empty(),
+                            ),
+                            shift: Dimen::zero(),
+                        });
+                    }
+                    return result;
+                }
+            };
+
+            // The part of the paragraph before a display has no lines if it's
+            // empty, like when the display comes right after \noindent.
+            self.add_paragraph_lines(lines, &mut result);
+
+            result.extend(display);
+
+            // The paragraph continues after the display without indentation.
+            indent = false;
+        }
     }
 
     /// Checks if a token is the start of something that only is valid in
@@ -30,7 +100,20 @@ impl<'a> Parser<'a> {
             _ => {}
         }
 
-        if self.state.is_token_equal_to_prim(tok, "hskip") {
+        if self.state.is_token_equal_to_prim(tok, "hskip")
+            || self.state.is_token_equal_to_prim(tok, "accent")
+            || self.state.is_token_equal_to_prim(tok, "char")
+            || self.state.is_token_equal_to_prim(tok, "leavevmode")
+            || self.state.is_token_equal_to_prim(tok, "vrule")
+            || self.state.is_token_equal_to_prim(tok, "discretionary")
+            || self.state.is_token_equal_to_prim(tok, "-")
+            || self.state.is_token_equal_to_prim(tok, "hfil")
+            || self.state.is_token_equal_to_prim(tok, "hfill")
+            || self.state.is_token_equal_to_prim(tok, "hss")
+            || self.state.is_token_equal_to_prim(tok, "hfilneg")
+            || self.state.is_token_equal_to_prim(tok, "unhbox")
+            || self.state.is_token_equal_to_prim(tok, "unhcopy")
+        {
             return true;
         }
 
@@ -41,7 +124,7 @@ impl<'a> Parser<'a> {
         &mut self,
         group_level: &mut usize,
         internal: bool,
-    ) -> Option<VerticalListElem> {
+    ) -> Option<Vec<VerticalListElem>> {
         let expanded_token = self.peek_expanded_token();
         let expanded_renamed_token = self.replace_renamed_token(expanded_token);
         match expanded_renamed_token {
@@ -55,7 +138,9 @@ impl<'a> Parser<'a> {
             Some(ref tok) if self.is_horizontal_mode_head(tok) => {
                 Some(self.handle_enter_horizontal_mode(true))
             }
-            Some(Token::Char(_, cat)) => match cat {
+            // Active characters which weren't expanded as macros have been
+            // \let to something else, so they're handled below.
+            Some(Token::Char(_, cat)) if cat != Category::Active => match cat {
                 Category::Space => {
                     self.lex_expanded_token();
                     self.parse_vertical_list_elem(group_level, internal)
@@ -99,7 +184,44 @@ impl<'a> Parser<'a> {
             {
                 self.lex_expanded_token();
                 let glue = self.parse_glue();
-                Some(VerticalListElem::VSkip(glue))
+                Some(vec![VerticalListElem::VSkip(glue)])
+            }
+            Some(ref tok) if self.state.is_token_equal_to_prim(tok, "kern") => {
+                self.lex_expanded_token();
+                let kern = self.parse_dimen();
+                Some(vec![VerticalListElem::Kern(kern)])
+            }
+            Some(ref tok)
+                if self.state.is_token_equal_to_prim(tok, "penalty") =>
+            {
+                self.lex_expanded_token();
+                let penalty = self.parse_number();
+                Some(vec![VerticalListElem::Penalty(penalty)])
+            }
+            Some(ref tok)
+                if self.state.is_token_equal_to_prim(tok, "moveright") =>
+            {
+                self.lex_expanded_token();
+                let shift = self.parse_dimen();
+                if let Some(tex_box) = self.parse_box() {
+                    Some(vec![VerticalListElem::Box { tex_box, shift }])
+                } else {
+                    self.parse_vertical_list_elem(group_level, internal)
+                }
+            }
+            Some(ref tok)
+                if self.state.is_token_equal_to_prim(tok, "moveleft") =>
+            {
+                self.lex_expanded_token();
+                let shift = self.parse_dimen();
+                if let Some(tex_box) = self.parse_box() {
+                    Some(vec![VerticalListElem::Box {
+                        tex_box,
+                        shift: shift * -1,
+                    }])
+                } else {
+                    self.parse_vertical_list_elem(group_level, internal)
+                }
             }
             _ => {
                 if self.is_assignment_head() {
@@ -112,11 +234,39 @@ impl<'a> Parser<'a> {
                     let indent =
                         self.state.is_token_equal_to_prim(&tok, "indent");
                     Some(self.handle_enter_horizontal_mode(indent))
+                } else if self.is_shipout_head() {
+                    self.parse_shipout();
+                    self.parse_vertical_list_elem(group_level, internal)
+                } else if self.is_remove_last_item_head() {
+                    self.parse_remove_last_item();
+                    self.parse_vertical_list_elem(group_level, internal)
+                } else if self.is_show_head() {
+                    self.parse_show();
+                    self.parse_vertical_list_elem(group_level, internal)
+                } else if self.is_hrule_head() {
+                    Some(vec![self.parse_hrule()])
+                } else if self.is_halign_head() {
+                    Some(self.parse_halign())
+                } else if self.is_leaders_head() {
+                    Some(vec![self.parse_vertical_leaders()])
+                } else if self.is_vertical_fil_glue_head() {
+                    Some(vec![VerticalListElem::VSkip(self.parse_fil_glue())])
+                } else if self.is_unvbox_head() {
+                    // The unpacked list already has its own interline glue,
+                    // so its elements are added directly instead of going
+                    // through the usual handling of new boxes, and \prevdepth
+                    // is unchanged.
+                    let mut elems = self.parse_unvbox();
+                    self.current_vertical_list().elems.append(&mut elems);
+                    Some(Vec::new())
                 } else if self.is_box_head() {
                     let maybe_tex_box = self.parse_box();
                     if let Some(tex_box) = maybe_tex_box {
                         // TODO(xymostech): Insert interline glue here.
-                        Some(VerticalListElem::Box(tex_box))
+                        Some(vec![VerticalListElem::Box {
+                            tex_box,
+                            shift: Dimen::zero(),
+                        }])
                     } else {
                         self.parse_vertical_list_elem(group_level, internal)
                     }
@@ -127,81 +277,191 @@ impl<'a> Parser<'a> {
         }
     }
 
+    fn current_vertical_list(&mut self) -> &mut CurrentVerticalList {
+        self.current_vertical_lists
+            .last_mut()
+            .and_then(|list| list.as_mut())
+            .expect("Not currently building a vertical list")
+    }
+
+    /// Removes the last element of the current list for \lastbox if it is a
+    /// box. In vertical lists, the depth of the box before it is restored as
+    /// \prevdepth, so interline glue for the next box is computed from that
+    /// box.
+    pub fn remove_last_box_from_current_list(&mut self) -> Option<TeXBox> {
+        let current_list = match self.current_vertical_lists.last_mut() {
+            Some(Some(current_list)) => current_list,
+            Some(None) => return self.remove_last_box_from_horizontal_list(),
+            None => return None,
+        };
+
+        let tex_box = match current_list.elems.pop() {
+            Some(VerticalListElem::Box { tex_box, .. }) => tex_box,
+            Some(elem) => {
+                current_list.elems.push(elem);
+                return None;
+            }
+            None => return None,
+        };
+
+        let prev_depth = current_list
+            .elems
+            .iter()
+            .rev()
+            .find_map(|elem| match elem {
+                VerticalListElem::Box { tex_box, .. } => Some(*tex_box.depth()),
+                VerticalListElem::Rule { .. }
+                | VerticalListElem::RuleLeaders { .. } => Some(ignore_depth()),
+                _ => None,
+            })
+            .unwrap_or_else(ignore_depth);
+        self.state.set_dimen_parameter(
+            true,
+            &DimenParameter::PrevDepth,
+            &prev_depth,
+        );
+
+        Some(tex_box)
+    }
+
+    fn is_remove_last_item_head(&mut self) -> bool {
+        self.is_next_expanded_token_in_set_of_primitives(&[
+            "unskip",
+            "unkern",
+            "unpenalty",
+        ])
+    }
+
+    // Parses \unskip, \unpenalty, or \unkern, which remove the last element
+    // of the current vertical list if it is glue, a penalty, or a kern,
+    // respectively. Elements that have already been moved to the current page
+    // can't be removed.
+    fn parse_remove_last_item(&mut self) {
+        let tok = self.lex_expanded_token().unwrap();
+        let is_unskip = self.state.is_token_equal_to_prim(&tok, "unskip");
+        let is_unpenalty = self.state.is_token_equal_to_prim(&tok, "unpenalty");
+        let is_unkern = self.state.is_token_equal_to_prim(&tok, "unkern");
+        let current_list = self.current_vertical_list();
+
+        let should_remove = match current_list.elems.last() {
+            Some(VerticalListElem::VSkip(_))
+            | Some(VerticalListElem::RuleLeaders { .. }) => is_unskip,
+            Some(VerticalListElem::Penalty(_)) => is_unpenalty,
+            Some(VerticalListElem::Kern(_)) => is_unkern,
+            _ => false,
+        };
+
+        if should_remove {
+            current_list.elems.pop();
+        }
+    }
+
     pub fn parse_vertical_list(
         &mut self,
         internal: bool,
     ) -> Vec<VerticalListElem> {
-        let mut result = Vec::new();
+        self.current_vertical_lists
+            .push(Some(CurrentVerticalList { elems: Vec::new() }));
 
-        // The depth of the most recent box.
-        // TODO(xymostech): Store this in the \prevdepth parameter
-        let mut prev_depth = Dimen::from_unit(-1000.0, Unit::Point);
+        // Each vertical list has its own \prevdepth, so we save the one for
+        // the enclosing list and restore it when this list is finished.
+        let enclosing_prev_depth =
+            self.state.get_dimen_parameter(&DimenParameter::PrevDepth);
+        self.state.set_dimen_parameter(
+            true,
+            &DimenParameter::PrevDepth,
+            &ignore_depth(),
+        );
 
-        // TODO(xymostech): Store these as \baselineskip, \lineskiplimit,
-        // \lineskip, and \topskip parameters
-        let baselineskip =
-            Glue::from_dimen(Dimen::from_unit(12.0, Unit::Point));
-        let lineskiplimit = Dimen::from_unit(0.0, Unit::Point);
-        let lineskip = Glue::from_dimen(Dimen::from_unit(1.0, Unit::Point));
-        let topskip = Glue::from_dimen(Dimen::from_unit(10.0, Unit::Point));
+        // When we're parsing the main vertical list, elements are moved onto
+        // the current page as we go instead of being collected in `result`.
+        let mut page = Page::new();
 
         let mut group_level = 0;
-        while let Some(elem) =
+        while let Some(elems) =
             self.parse_vertical_list_elem(&mut group_level, internal)
         {
-            // Handle box elements specially so we can add interline glue
-            if let VerticalListElem::Box(ref tex_box) = elem {
-                // HACK(xymostech): \topskip should be handled in the outer
-                // place where we build pages, but we're doing it here since
-                // that doesn't exist yet.
-                if !internal && result.is_empty() {
-                    let box_height = tex_box.height();
-                    let total_skip =
-                        topskip.clone() - Glue::from_dimen(*box_height);
-
-                    if total_skip.space > Dimen::zero() {
-                        result.push(VerticalListElem::VSkip(total_skip));
+            for elem in elems {
+                // Handle box elements specially so we can add interline glue
+                if let VerticalListElem::Box { ref tex_box, .. } = elem {
+                    let prev_depth = self
+                        .state
+                        .get_dimen_parameter(&DimenParameter::PrevDepth);
+
+                    // If prev_depth is -1000pt, don't add interline glue
+                    if prev_depth != ignore_depth() {
+                        let baselineskip = self
+                            .state
+                            .get_glue_parameter(&GlueParameter::BaselineSkip);
+                        let lineskiplimit = self.state.get_dimen_parameter(
+                            &DimenParameter::LineSkipLimit,
+                        );
+                        let lineskip = self
+                            .state
+                            .get_glue_parameter(&GlueParameter::LineSkip);
+
+                        // Calculate how much interline glue we'd add if we
+                        // just take into account
+                        // baselineskip - prev_depth - box.height
+                        let box_height = tex_box.height();
+                        let total_skip = baselineskip
+                            - Glue::from_dimen(*box_height + prev_depth);
+
+                        // If the interline glue would be less than
+                        // lineskiplimit, use lineskip instead.
+                        let interline_glue = if total_skip.space < lineskiplimit
+                        {
+                            lineskip
+                        } else {
+                            total_skip
+                        };
+
+                        self.current_vertical_list()
+                            .elems
+                            .push(VerticalListElem::VSkip(interline_glue));
                     }
-                }
 
-                // If prev_depth is -1000pt, don't add interline glue
-                if prev_depth != Dimen::from_unit(-1000.0, Unit::Point) {
-                    // Calculate how much interline glue we'd add if we just
-                    // take into account baselineskip - prev_depth - box.height
-                    let box_height = tex_box.height();
-                    let total_skip = baselineskip.clone()
-                        - Glue::from_dimen(*box_height + prev_depth);
-
-                    // If the interline glue would be less than lineskiplimit,
-                    // use lineskip instead.
-                    let interline_glue = if total_skip.space < lineskiplimit {
-                        lineskip.clone()
-                    } else {
-                        total_skip
-                    };
+                    // Keep track of the depth of the most recent box
+                    self.state.set_dimen_parameter(
+                        true,
+                        &DimenParameter::PrevDepth,
+                        tex_box.depth(),
+                    );
+                }
 
-                    result.push(VerticalListElem::VSkip(interline_glue));
+                // Rules don't get interline glue after them
+                if let VerticalListElem::Rule { .. }
+                | VerticalListElem::RuleLeaders { .. } = elem
+                {
+                    self.state.set_dimen_parameter(
+                        true,
+                        &DimenParameter::PrevDepth,
+                        &ignore_depth(),
+                    );
                 }
 
-                // Keep track of the depth of the most recent box
-                prev_depth = *tex_box.depth();
+                self.current_vertical_list().elems.push(elem);
             }
 
             if !internal {
-                if let VerticalListElem::VSkip(_) = elem {
-                    // Glue disappears at a page break.
-                    if !result.is_empty() {
-                        result.push(elem);
-                    }
-                } else {
-                    result.push(elem);
-                }
-            } else {
-                result.push(elem);
+                let elems =
+                    std::mem::take(&mut self.current_vertical_list().elems);
+                self.build_page(&mut page, elems);
             }
         }
 
-        result
+        self.state.set_dimen_parameter(
+            true,
+            &DimenParameter::PrevDepth,
+            &enclosing_prev_depth,
+        );
+        let result = self.current_vertical_lists.pop().unwrap().unwrap().elems;
+
+        if internal {
+            result
+        } else {
+            page.into_contents()
+        }
     }
 }
 
@@ -209,10 +469,35 @@ impl<'a> Parser<'a> {
 mod tests {
     use super::*;
 
-    use crate::boxes::{GlueSetRatio, GlueSetRatioKind, TeXBox, VerticalBox};
+    use std::rc::Rc;
+
+    use crate::boxes::{GlueSetRatio, GlueSetRatioKind, VerticalBox};
     use crate::dimension::{FilDimen, FilKind, SpringDimen};
+    use crate::list::HorizontalListElem;
+    use crate::parser::boxes::BoxLayout;
     use crate::testing::with_parser;
 
+    // Returns the line that a paragraph containing just the contents of
+    // `tex_box` is set into, which is \hsize wide and ends with \parfillskip.
+    fn make_paragraph_line(parser: &mut Parser, tex_box: TeXBox) -> TeXBox {
+        let mut list = match tex_box {
+            TeXBox::HorizontalBox(hbox) => (*hbox.list).clone(),
+            TeXBox::VerticalBox(_) => panic!("Expected an hbox"),
+        };
+        list.push(HorizontalListElem::Penalty(10000));
+        list.push(HorizontalListElem::HSkip(
+            parser.state.get_glue_parameter(&GlueParameter::ParFillSkip),
+        ));
+
+        let hsize = parser.state.get_dimen_parameter(&DimenParameter::HSize);
+        TeXBox::HorizontalBox(
+            parser.combine_horizontal_list_into_horizontal_box_with_layout(
+                list,
+                &BoxLayout::Fixed(hsize),
+            ),
+        )
+    }
+
     fn assert_parses_to(lines: &[&str], expected_list: &[VerticalListElem]) {
         with_parser(lines, |parser| {
             assert_eq!(parser.parse_vertical_list(true), expected_list);
@@ -250,6 +535,76 @@ mod tests {
         );
     }
 
+    #[test]
+    fn it_parses_vertical_kerns() {
+        assert_parses_to(
+            &[r"\kern 1pt\kern-2pt%"],
+            &[
+                VerticalListElem::Kern(Dimen::from_unit(1.0, Unit::Point)),
+                VerticalListElem::Kern(Dimen::from_unit(-2.0, Unit::Point)),
+            ],
+        );
+    }
+
+    #[test]
+    fn it_parses_moveleft_and_moveright_commands() {
+        with_parser(
+            &[
+                r"\hbox{a}%",
+                r"\dimen0=3pt%",
+                r"\moveright 5pt\hbox{a}\moveleft\dimen0\hbox{a}%",
+                r"\moveright 2pt\box10%",
+            ],
+            |parser| {
+                let abox = parser.parse_box().unwrap();
+
+                let list = parser.parse_vertical_list(true);
+                // The shifted boxes are separated by interline glue.
+                assert_eq!(list.len(), 3);
+                assert_eq!(
+                    list[0],
+                    VerticalListElem::Box {
+                        tex_box: abox.clone(),
+                        shift: Dimen::from_unit(5.0, Unit::Point),
+                    }
+                );
+                assert_eq!(
+                    list[2],
+                    VerticalListElem::Box {
+                        tex_box: abox,
+                        shift: Dimen::from_unit(-3.0, Unit::Point),
+                    }
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn it_unpacks_vertical_boxes() {
+        with_parser(
+            &[
+                r"\setbox0=\vbox{\hbox{a}\hbox{b}}%",
+                r"\hbox{c}\unvcopy0\unvbox0%",
+            ],
+            |parser| {
+                parser.parse_assignment();
+                let vbox_list = match parser.state.get_box_copy(0) {
+                    Some(TeXBox::VerticalBox(vbox)) => (*vbox.list).clone(),
+                    other => panic!("Expected a vbox: {:?}", other),
+                };
+
+                let list = parser.parse_vertical_list(true);
+
+                // No interline glue is added before or inside the unpacked
+                // lists.
+                assert_eq!(list.len(), 1 + 2 * vbox_list.len());
+                assert_eq!(&list[1..1 + vbox_list.len()], &vbox_list[..]);
+                assert_eq!(&list[1 + vbox_list.len()..], &vbox_list[..]);
+                assert_eq!(parser.state.get_box(0), None);
+            },
+        );
+    }
+
     #[test]
     fn it_ignores_spaces() {
         assert_parses_to(
@@ -374,13 +729,16 @@ mod tests {
                     10.0,
                     Unit::Point,
                 ))),
-                VerticalListElem::Box(TeXBox::VerticalBox(VerticalBox {
-                    height: Dimen::zero(),
-                    depth: Dimen::zero(),
-                    width: Dimen::zero(),
-                    list: vec![],
-                    glue_set_ratio: None,
-                })),
+                VerticalListElem::Box {
+                    tex_box: TeXBox::VerticalBox(VerticalBox {
+                        height: Dimen::zero(),
+                        depth: Dimen::zero(),
+                        width: Dimen::zero(),
+                        list: Rc::new(vec![]),
+                        glue_set_ratio: None,
+                    }),
+                    shift: Dimen::zero(),
+                },
             ],
         );
 
@@ -391,45 +749,83 @@ mod tests {
                     5.0,
                     Unit::Point,
                 ))),
-                VerticalListElem::Box(TeXBox::VerticalBox(VerticalBox {
-                    height: Dimen::from_unit(5.0, Unit::Point),
-                    depth: Dimen::zero(),
-                    width: Dimen::zero(),
-                    list: vec![VerticalListElem::VSkip(Glue {
-                        space: Dimen::zero(),
-                        stretch: SpringDimen::Dimen(Dimen::from_unit(
-                            1.0,
-                            Unit::Point,
+                VerticalListElem::Box {
+                    tex_box: TeXBox::VerticalBox(VerticalBox {
+                        height: Dimen::from_unit(5.0, Unit::Point),
+                        depth: Dimen::zero(),
+                        width: Dimen::zero(),
+                        list: Rc::new(vec![VerticalListElem::VSkip(Glue {
+                            space: Dimen::zero(),
+                            stretch: SpringDimen::Dimen(Dimen::from_unit(
+                                1.0,
+                                Unit::Point,
+                            )),
+                            shrink: SpringDimen::Dimen(Dimen::zero()),
+                        })]),
+                        glue_set_ratio: Some(GlueSetRatio::from(
+                            GlueSetRatioKind::Finite,
+                            5.0,
                         )),
-                        shrink: SpringDimen::Dimen(Dimen::zero()),
-                    })],
-                    glue_set_ratio: Some(GlueSetRatio::from(
-                        GlueSetRatioKind::Finite,
-                        5.0,
-                    )),
-                })),
+                    }),
+                    shift: Dimen::zero(),
+                },
             ],
         );
 
+        // Boxes taller than \topskip still get \topskip glue, but it's
+        // clamped to 0pt.
         assert_parses_to_non_internal(
             &[r"\vbox to15pt{\vskip 0pt plus1pt}\end%"],
-            &[VerticalListElem::Box(TeXBox::VerticalBox(VerticalBox {
-                height: Dimen::from_unit(15.0, Unit::Point),
-                depth: Dimen::zero(),
-                width: Dimen::zero(),
-                list: vec![VerticalListElem::VSkip(Glue {
-                    space: Dimen::zero(),
+            &[
+                VerticalListElem::VSkip(Glue::zero()),
+                VerticalListElem::Box {
+                    tex_box: TeXBox::VerticalBox(VerticalBox {
+                        height: Dimen::from_unit(15.0, Unit::Point),
+                        depth: Dimen::zero(),
+                        width: Dimen::zero(),
+                        list: Rc::new(vec![VerticalListElem::VSkip(Glue {
+                            space: Dimen::zero(),
+                            stretch: SpringDimen::Dimen(Dimen::from_unit(
+                                1.0,
+                                Unit::Point,
+                            )),
+                            shrink: SpringDimen::Dimen(Dimen::zero()),
+                        })]),
+                        glue_set_ratio: Some(GlueSetRatio::from(
+                            GlueSetRatioKind::Finite,
+                            15.0,
+                        )),
+                    }),
+                    shift: Dimen::zero(),
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn it_uses_the_topskip_parameter() {
+        assert_parses_to_non_internal(
+            &[r"\topskip=3pt plus2pt\vbox to1pt{}\end%"],
+            &[
+                VerticalListElem::VSkip(Glue {
+                    space: Dimen::from_unit(2.0, Unit::Point),
                     stretch: SpringDimen::Dimen(Dimen::from_unit(
-                        1.0,
+                        2.0,
                         Unit::Point,
                     )),
                     shrink: SpringDimen::Dimen(Dimen::zero()),
-                })],
-                glue_set_ratio: Some(GlueSetRatio::from(
-                    GlueSetRatioKind::Finite,
-                    15.0,
-                )),
-            }))],
+                }),
+                VerticalListElem::Box {
+                    tex_box: TeXBox::VerticalBox(VerticalBox {
+                        height: Dimen::from_unit(1.0, Unit::Point),
+                        depth: Dimen::zero(),
+                        width: Dimen::zero(),
+                        list: Rc::new(vec![]),
+                        glue_set_ratio: None,
+                    }),
+                    shift: Dimen::zero(),
+                },
+            ],
         );
     }
 
@@ -478,14 +874,20 @@ mod tests {
                         VerticalListElem::VSkip(Glue::from_dimen(
                             Dimen::from_unit(1.0, Unit::Point)
                         )),
-                        VerticalListElem::Box(box0),
+                        VerticalListElem::Box {
+                            tex_box: box0,
+                            shift: Dimen::zero()
+                        },
                         VerticalListElem::VSkip(Glue::from_dimen(
                             Dimen::from_unit(2.0, Unit::Point)
                         )),
                         VerticalListElem::VSkip(Glue::from_dimen(
                             interline_glue
                         )),
-                        VerticalListElem::Box(box1),
+                        VerticalListElem::Box {
+                            tex_box: box1,
+                            shift: Dimen::zero()
+                        },
                     ]
                 );
             },
@@ -508,7 +910,9 @@ mod tests {
                 parser.parse_assignment();
 
                 let box0 = parser.state.get_box(0).unwrap();
+                let box0 = make_paragraph_line(parser, box0);
                 let box1 = parser.state.get_box(1).unwrap();
+                let box1 = make_paragraph_line(parser, box1);
 
                 let interline_glue = Dimen::from_unit(12.0, Unit::Point)
                     - *box0.depth()
@@ -520,14 +924,20 @@ mod tests {
                         VerticalListElem::VSkip(Glue::from_dimen(
                             Dimen::from_unit(1.0, Unit::Point)
                         )),
-                        VerticalListElem::Box(box0),
+                        VerticalListElem::Box {
+                            tex_box: box0,
+                            shift: Dimen::zero()
+                        },
                         VerticalListElem::VSkip(Glue::from_dimen(
                             Dimen::from_unit(2.0, Unit::Point)
                         )),
                         VerticalListElem::VSkip(Glue::from_dimen(
                             interline_glue
                         )),
-                        VerticalListElem::Box(box1),
+                        VerticalListElem::Box {
+                            tex_box: box1,
+                            shift: Dimen::zero()
+                        },
                     ]
                 );
             },
@@ -554,7 +964,9 @@ mod tests {
                 parser.parse_assignment();
 
                 let box0 = parser.state.get_box(0).unwrap();
+                let box0 = make_paragraph_line(parser, box0);
                 let box1 = parser.state.get_box(1).unwrap();
+                let box1 = make_paragraph_line(parser, box1);
 
                 let interline_glue = Dimen::from_unit(12.0, Unit::Point)
                     - *box0.depth()
@@ -566,14 +978,20 @@ mod tests {
                         VerticalListElem::VSkip(Glue::from_dimen(
                             Dimen::from_unit(1.0, Unit::Point)
                         )),
-                        VerticalListElem::Box(box0),
+                        VerticalListElem::Box {
+                            tex_box: box0,
+                            shift: Dimen::zero()
+                        },
                         VerticalListElem::VSkip(Glue::from_dimen(
                             Dimen::from_unit(2.0, Unit::Point)
                         )),
                         VerticalListElem::VSkip(Glue::from_dimen(
                             interline_glue
                         )),
-                        VerticalListElem::Box(box1),
+                        VerticalListElem::Box {
+                            tex_box: box1,
+                            shift: Dimen::zero()
+                        },
                     ]
                 );
             },
@@ -589,7 +1007,8 @@ mod tests {
                 r"\setbox1=\hbox{\copy0 a}%",
                 r"\setbox2=\hbox{\copy0 @}%",
                 r"\setbox3=\hbox{\copy0 $a$}%",
-                r"\setbox4=\hbox{\copy0 \hskip1pt}%",
+                // The glue at the end of a paragraph is removed.
+                r"\setbox4=\hbox{\copy0}%",
                 r"a\par%",
                 r"@\par%",
                 r"$a$\par%",
@@ -603,10 +1022,17 @@ mod tests {
                 parser.parse_assignment();
                 parser.parse_assignment();
 
-                let box1 = parser.state.get_box(1).unwrap();
-                let box2 = parser.state.get_box(2).unwrap();
-                let box3 = parser.state.get_box(3).unwrap();
-                let box4 = parser.state.get_box(4).unwrap();
+                let mut boxes = Vec::new();
+                for index in 1..=4 {
+                    let tex_box = parser.state.get_box(index).unwrap();
+                    boxes.push(make_paragraph_line(parser, tex_box));
+                }
+                let (box1, box2, box3, box4) = (
+                    boxes[0].clone(),
+                    boxes[1].clone(),
+                    boxes[2].clone(),
+                    boxes[3].clone(),
+                );
 
                 let interline_glue1 = Dimen::from_unit(12.0, Unit::Point)
                     - *box1.depth()
@@ -621,25 +1047,181 @@ mod tests {
                 assert_eq!(
                     parser.parse_vertical_list(true),
                     &[
-                        VerticalListElem::Box(box1),
+                        VerticalListElem::Box {
+                            tex_box: box1,
+                            shift: Dimen::zero()
+                        },
                         VerticalListElem::VSkip(Glue::from_dimen(
                             interline_glue1
                         )),
-                        VerticalListElem::Box(box2),
+                        VerticalListElem::Box {
+                            tex_box: box2,
+                            shift: Dimen::zero()
+                        },
                         VerticalListElem::VSkip(Glue::from_dimen(
                             interline_glue2
                         )),
-                        VerticalListElem::Box(box3),
+                        VerticalListElem::Box {
+                            tex_box: box3,
+                            shift: Dimen::zero()
+                        },
                         VerticalListElem::VSkip(Glue::from_dimen(
                             interline_glue3
                         )),
-                        VerticalListElem::Box(box4),
+                        VerticalListElem::Box {
+                            tex_box: box4,
+                            shift: Dimen::zero()
+                        },
                     ]
                 );
             },
         );
     }
 
+    #[test]
+    fn it_starts_paragraphs_with_leavevmode() {
+        with_parser(
+            &[
+                r"\setbox0=\hbox{}%",
+                r"\wd0=20pt%",
+                // The glue at the end of a paragraph is removed.
+                r"\setbox1=\hbox{\copy0}%",
+                r"\setbox2=\hbox{\hskip0pt}%",
+                r"\leavevmode\hskip0pt\par%",
+                r"\hbox{\leavevmode\hskip0pt}%",
+            ],
+            |parser| {
+                parser.parse_assignment();
+                parser.parse_assignment();
+                parser.parse_assignment();
+                parser.parse_assignment();
+
+                let box1 = parser.state.get_box(1).unwrap();
+                let box1 = make_paragraph_line(parser, box1);
+                let box2 = parser.state.get_box(2).unwrap();
+
+                let interline_glue = Dimen::from_unit(12.0, Unit::Point)
+                    - *box1.depth()
+                    - *box2.height();
+
+                // In vertical mode, \leavevmode starts an indented paragraph,
+                // but in horizontal mode it does nothing.
+                assert_eq!(
+                    parser.parse_vertical_list(true),
+                    &[
+                        VerticalListElem::Box {
+                            tex_box: box1,
+                            shift: Dimen::zero()
+                        },
+                        VerticalListElem::VSkip(Glue::from_dimen(
+                            interline_glue
+                        )),
+                        VerticalListElem::Box {
+                            tex_box: box2,
+                            shift: Dimen::zero()
+                        },
+                    ]
+                );
+            },
+        );
+    }
+
+    fn count_paragraph_lines(list: &[VerticalListElem]) -> usize {
+        list.iter()
+            .filter(|elem| matches!(elem, VerticalListElem::Box { .. }))
+            .count()
+    }
+
+    #[test]
+    fn it_breaks_paragraphs_into_lines_with_interlinepenalty() {
+        with_parser(
+            &[
+                r"\hsize=110pt \interlinepenalty=100%",
+                r"\def\w{\hbox to20pt{}}%",
+                r"\def\s{\hskip10pt plus5pt minus3pt}%",
+                r"\noindent\w\s\w\s\w\s\w\s\w\s\w\s\w\s\w\s\w\s\w\par%",
+            ],
+            |parser| {
+                let list = parser.parse_vertical_list(true);
+                assert_eq!(count_paragraph_lines(&list), 3);
+                assert_eq!(
+                    list.iter()
+                        .filter(|elem| **elem == VerticalListElem::Penalty(100))
+                        .count(),
+                    2
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn it_ends_paragraphs_with_endgraf() {
+        with_parser(
+            &[r"a\endgraf b\endgraf%", r"\def\par{}c\endgraf%"],
+            |parser| {
+                // \endgraf still ends paragraphs after \par is redefined.
+                assert_eq!(
+                    count_paragraph_lines(&parser.parse_vertical_list(true)),
+                    3
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn it_ends_paragraphs_with_active_end_of_line_characters() {
+        with_parser(&["a", "b", "c"], |parser| {
+            // Normally, lines are joined into a single paragraph.
+            assert_eq!(
+                count_paragraph_lines(&parser.parse_vertical_list(true)),
+                1
+            );
+        });
+
+        with_parser(&["\\let\n=\\par%", "a", "b", "c"], |parser| {
+            parser.state.set_category(false, '\n', Category::Active);
+
+            // When the end of line character is active and \let to \par,
+            // like with \obeylines, each line becomes its own paragraph.
+            assert_eq!(
+                count_paragraph_lines(&parser.parse_vertical_list(true)),
+                3
+            );
+        });
+    }
+
+    #[test]
+    fn it_switches_modes_for_rules() {
+        with_parser(&[r"\vrule\hrule%"], |parser| {
+            // \vrule starts a paragraph, and \hrule ends it.
+            let list = parser.parse_vertical_list(true);
+            assert_eq!(list.len(), 2);
+
+            match &list[0] {
+                VerticalListElem::Box {
+                    tex_box: TeXBox::HorizontalBox(hbox),
+                    ..
+                } => {
+                    assert!(hbox.list.contains(&HorizontalListElem::Rule {
+                        height: None,
+                        depth: None,
+                        width: Dimen::from_unit(0.4, Unit::Point),
+                    }));
+                }
+                other => panic!("Expected a paragraph line, got {:?}", other),
+            }
+
+            assert_eq!(
+                list[1],
+                VerticalListElem::Rule {
+                    height: Dimen::from_unit(0.4, Unit::Point),
+                    depth: Dimen::zero(),
+                    width: None,
+                }
+            );
+        });
+    }
+
     #[test]
     fn it_adds_interline_glue() {
         with_parser(
@@ -667,21 +1249,201 @@ mod tests {
                 assert_eq!(
                     parser.parse_vertical_list(true),
                     &[
-                        VerticalListElem::Box(parser.state.get_box(0).unwrap()),
+                        VerticalListElem::Box {
+                            tex_box: parser.state.get_box(0).unwrap(),
+                            shift: Dimen::zero()
+                        },
                         // 12pt - 5pt - 5pt = 2pt of interline glue
                         VerticalListElem::VSkip(Glue::from_dimen(
                             Dimen::from_unit(2.0, Unit::Point)
                         )),
-                        VerticalListElem::Box(parser.state.get_box(1).unwrap()),
+                        VerticalListElem::Box {
+                            tex_box: parser.state.get_box(1).unwrap(),
+                            shift: Dimen::zero()
+                        },
                         // 12pt - 8pt - 5pt = -1pt
                         // -1pt < 0pt (lineskiplimit), so we end up with
                         // lineskip (1pt) interline glue
                         VerticalListElem::VSkip(Glue::from_dimen(
                             Dimen::from_unit(1.0, Unit::Point)
                         )),
-                        VerticalListElem::Box(parser.state.get_box(2).unwrap()),
+                        VerticalListElem::Box {
+                            tex_box: parser.state.get_box(2).unwrap(),
+                            shift: Dimen::zero()
+                        },
+                    ]
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn it_removes_the_last_box_with_lastbox() {
+        with_parser(
+            &[
+                r"\setbox0=\hbox{}%",
+                r"\dp0=5pt%",
+                r"\setbox1=\hbox{}%",
+                r"\ht1=1pt%",
+                r"\dp1=3pt%",
+                r"\setbox2=\hbox{}%",
+                r"\ht2=4pt%",
+                r"\copy0%",
+                r"\copy1%",
+                r"\setbox3=\lastbox%",
+                r"\copy2%",
+            ],
+            |parser| {
+                for _ in 0..7 {
+                    parser.parse_assignment();
+                }
+
+                assert_eq!(
+                    parser.parse_vertical_list(true),
+                    &[
+                        VerticalListElem::Box {
+                            tex_box: parser.state.get_box(0).unwrap(),
+                            shift: Dimen::zero()
+                        },
+                        // 12pt - 5pt - 1pt = 6pt of interline glue before the
+                        // removed box
+                        VerticalListElem::VSkip(Glue::from_dimen(
+                            Dimen::from_unit(6.0, Unit::Point)
+                        )),
+                        // 12pt - 5pt - 4pt = 3pt, using the depth of the box
+                        // before the removed box
+                        VerticalListElem::VSkip(Glue::from_dimen(
+                            Dimen::from_unit(3.0, Unit::Point)
+                        )),
+                        VerticalListElem::Box {
+                            tex_box: parser.state.get_box(2).unwrap(),
+                            shift: Dimen::zero()
+                        },
                     ]
                 );
+                assert_eq!(parser.state.get_box(3), parser.state.get_box(1));
+            },
+        );
+    }
+
+    #[test]
+    fn it_does_not_remove_non_boxes_with_lastbox() {
+        with_parser(
+            &[r"\hbox{}\vskip 1pt%", r"\setbox0=\lastbox%"],
+            |parser| {
+                assert_eq!(parser.parse_vertical_list(true).len(), 2);
+                assert_eq!(parser.state.get_box(0), None);
+            },
+        );
+    }
+
+    #[test]
+    fn it_removes_the_last_glue_with_unskip() {
+        assert_parses_to(
+            &[r"\hbox{}\vskip3pt\unskip%"],
+            &[VerticalListElem::Box {
+                tex_box: TeXBox::HorizontalBox(HorizontalBox::empty()),
+                shift: Dimen::zero(),
+            }],
+        );
+    }
+
+    #[test]
+    fn it_only_removes_matching_elements_with_unskip_and_unpenalty() {
+        assert_parses_to(
+            &[
+                r"\penalty5\unskip\unkern%",
+                r"\vskip1pt\unpenalty\unkern%",
+                r"\penalty10\unpenalty%",
+            ],
+            &[
+                VerticalListElem::Penalty(5),
+                VerticalListElem::VSkip(Glue::from_dimen(Dimen::from_unit(
+                    1.0,
+                    Unit::Point,
+                ))),
+            ],
+        );
+    }
+
+    #[test]
+    fn it_removes_the_last_kern_with_unkern() {
+        assert_parses_to(
+            &[r"\kern1pt\kern2pt\unkern%", r"\kern3pt\unskip\unpenalty%"],
+            &[
+                VerticalListElem::Kern(Dimen::from_unit(1.0, Unit::Point)),
+                VerticalListElem::Kern(Dimen::from_unit(3.0, Unit::Point)),
+            ],
+        );
+    }
+
+    #[test]
+    fn it_suppresses_interline_glue_with_prevdepth() {
+        assert_parses_to(
+            &[r"\hbox{}\prevdepth=-1000pt\hbox{}%"],
+            &[
+                VerticalListElem::Box {
+                    tex_box: TeXBox::HorizontalBox(HorizontalBox::empty()),
+                    shift: Dimen::zero(),
+                },
+                VerticalListElem::Box {
+                    tex_box: TeXBox::HorizontalBox(HorizontalBox::empty()),
+                    shift: Dimen::zero(),
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn it_does_not_add_interline_glue_after_rules() {
+        assert_parses_to(
+            &[r"\hbox{}\hrule\hbox{}%"],
+            &[
+                VerticalListElem::Box {
+                    tex_box: TeXBox::HorizontalBox(HorizontalBox::empty()),
+                    shift: Dimen::zero(),
+                },
+                VerticalListElem::Rule {
+                    height: Dimen::from_unit(0.4, Unit::Point),
+                    depth: Dimen::zero(),
+                    width: None,
+                },
+                VerticalListElem::Box {
+                    tex_box: TeXBox::HorizontalBox(HorizontalBox::empty()),
+                    shift: Dimen::zero(),
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn it_keeps_track_of_prevdepth_for_each_vertical_list() {
+        with_parser(
+            &[
+                r"\setbox0=\hbox{}%",
+                r"\dp0=5pt%",
+                r"\copy0%",
+                r"\dimen0=\prevdepth%",
+                r"\setbox1=\vbox{\global\dimen1=\prevdepth}%",
+                r"\dimen2=\prevdepth%",
+            ],
+            |parser| {
+                parser.parse_assignment();
+                parser.parse_assignment();
+                parser.parse_vertical_list(true);
+
+                assert_eq!(
+                    parser.state.get_dimen_register(0),
+                    Dimen::from_unit(5.0, Unit::Point)
+                );
+                assert_eq!(
+                    parser.state.get_dimen_register(1),
+                    Dimen::from_unit(-1000.0, Unit::Point)
+                );
+                assert_eq!(
+                    parser.state.get_dimen_register(2),
+                    Dimen::from_unit(5.0, Unit::Point)
+                );
             },
         );
     }
@@ -702,4 +1464,62 @@ mod tests {
             );
         });
     }
+
+    #[test]
+    fn it_adds_penalties_around_displays() {
+        with_parser(
+            &[
+                r"\predisplaypenalty=100%",
+                r"\postdisplaypenalty=200%",
+                r"\noindent a$$$$c\par%",
+            ],
+            |parser| {
+                parser.parse_assignment();
+                parser.parse_assignment();
+
+                let list: Vec<VerticalListElem> = parser
+                    .parse_vertical_list(true)
+                    .into_iter()
+                    .filter(|elem| !matches!(elem, VerticalListElem::VSkip(_)))
+                    .collect();
+
+                let chars: Vec<Option<Vec<char>>> = list
+                    .iter()
+                    .map(|elem| match elem {
+                        VerticalListElem::Box {
+                            tex_box: TeXBox::HorizontalBox(hbox),
+                            ..
+                        } => Some(hbox.to_chars()),
+                        _ => None,
+                    })
+                    .collect();
+                assert_eq!(
+                    chars,
+                    // The \parfillskip at the end of each line prints as a
+                    // space.
+                    vec![
+                        Some(vec!['a', ' ']),
+                        None,
+                        Some(vec![]),
+                        None,
+                        Some(vec!['c', ' ']),
+                    ]
+                );
+
+                assert_eq!(list[1], VerticalListElem::Penalty(100));
+                assert_eq!(list[3], VerticalListElem::Penalty(200));
+            },
+        );
+    }
+
+    #[test]
+    fn it_drops_empty_paragraph_parts_around_displays() {
+        with_parser(&[r"\noindent$$$$\par%"], |parser| {
+            let list = parser.parse_vertical_list(true);
+
+            assert_eq!(list.len(), 5);
+            assert_eq!(list[0], VerticalListElem::Penalty(0));
+            assert_eq!(list[3], VerticalListElem::Penalty(0));
+        });
+    }
 }
@@ -4,12 +4,19 @@ use std::rc::Rc;
 
 use crate::boxes::TeXBox;
 use crate::category::Category;
-use crate::dimension::{Dimen, Unit};
+use crate::dimension::{Dimen, FilDimen, FilKind, SpringDimen, Unit};
 use crate::font::Font;
 use crate::font_metrics::FontMetrics;
+use crate::glue::Glue;
+use crate::hyphenation::HyphenationPatterns;
 use crate::makro::Macro;
 use crate::math_code::MathCode;
+use crate::math_list::MathStyle;
+use crate::mu_glue::MuGlue;
 use crate::token::Token;
+use crate::variable::{
+    DimenParameter, GlueParameter, IntegerParameter, MuGlueParameter,
+};
 
 // A list of all primitive control sequences, used so that we can \let other
 // control sequences equal to them.
@@ -19,14 +26,32 @@ const ALL_PRIMITIVES: &[&str] = &[
     "fi",
     "else",
     "def",
+    "edef",
     "let",
     "global",
+    "outer",
+    "long",
+    "string",
+    "meaning",
+    "unexpanded",
+    "expanded",
+    "expandafter",
+    "csname",
+    "endcsname",
+    "/",
     "count",
+    "dimen",
     "ifnum",
     "advance",
     "multiply",
     "divide",
     "number",
+    "the",
+    "show",
+    "showthe",
+    "showhyphens",
+    "showbox",
+    "patterns",
     "par",
     "hskip",
     "hbox",
@@ -37,11 +62,31 @@ const ALL_PRIMITIVES: &[&str] = &[
     "dp",
     "box",
     "vskip",
+    "kern",
+    "textfont",
+    "scriptfont",
+    "scriptscriptfont",
     "end",
     "indent",
     "noindent",
     "copy",
     "vbox",
+    "vtop",
+    "mathstrut",
+    "hfil",
+    "hfill",
+    "hss",
+    "hfilneg",
+    "vfil",
+    "vfill",
+    "vss",
+    "vfilneg",
+    "mskip",
+    "nonscript",
+    "unhbox",
+    "unhcopy",
+    "unvbox",
+    "unvcopy",
     "mathchardef",
     "mathcode",
     "displaystyle",
@@ -49,8 +94,110 @@ const ALL_PRIMITIVES: &[&str] = &[
     "scriptstyle",
     "scriptscriptstyle",
     "font",
+    "fontdimen",
     "raise",
     "lower",
+    "moveleft",
+    "moveright",
+    "accent",
+    "char",
+    "maxdepth",
+    "boxmaxdepth",
+    "vsize",
+    "outputpenalty",
+    "penalty",
+    "output",
+    "shipout",
+    "deadcycles",
+    "maxdeadcycles",
+    "interlinepenalty",
+    "interdisplaylinepenalty",
+    "predisplaypenalty",
+    "postdisplaypenalty",
+    "hsize",
+    "predisplaysize",
+    "displaywidth",
+    "displayindent",
+    "abovedisplayskip",
+    "belowdisplayskip",
+    "abovedisplayshortskip",
+    "belowdisplayshortskip",
+    "vcenter",
+    "halign",
+    "cr",
+    "relpenalty",
+    "binoppenalty",
+    "tracingonline",
+    "tracingoutput",
+    "tolerance",
+    "linepenalty",
+    "hyphenpenalty",
+    "adjdemerits",
+    "delimiterfactor",
+    "delimitershortfall",
+    "nulldelimiterspace",
+    "parfillskip",
+    "limits",
+    "nolimits",
+    "displaylimits",
+    "scriptspace",
+    "skewchar",
+    "hyphenchar",
+    "inputlineno",
+    "numexpr",
+    "dimexpr",
+    "fontcharwd",
+    "fontcharht",
+    "fontchardp",
+    "fontcharic",
+    "mathaccent",
+    "delcode",
+    "delimiter",
+    "left",
+    "right",
+    "lastbox",
+    "unskip",
+    "baselineskip",
+    "lineskip",
+    "lineskiplimit",
+    "topskip",
+    "ifdim",
+    "ifx",
+    "iffontchar",
+    "ifcase",
+    "or",
+    "maxdimen",
+    "skip",
+    "muskip",
+    "muskipdef",
+    "thinmuskip",
+    "medmuskip",
+    "thickmuskip",
+    "over",
+    "atop",
+    "above",
+    "overwithdelims",
+    "atopwithdelims",
+    "abovewithdelims",
+    "mathord",
+    "mathop",
+    "mathbin",
+    "mathrel",
+    "mathopen",
+    "mathclose",
+    "mathpunct",
+    "mathinner",
+    "leavevmode",
+    "unkern",
+    "unpenalty",
+    "prevdepth",
+    "hrule",
+    "vrule",
+    "leaders",
+    "discretionary",
+    "-",
+    "radical",
+    "sqrt",
 ];
 
 fn is_primitive(maybe_prim: &str) -> bool {
@@ -67,6 +214,9 @@ enum TokenDefinition {
     Macro(Rc<Macro>),
     Token(Token),
     MathCode(MathCode),
+    // A control sequence defined with \muskipdef that refers to a \muskip
+    // register.
+    MuSkipRegister(u8),
     Primitive(&'static str),
     Font(Font),
 }
@@ -78,10 +228,21 @@ pub struct TeXStateInner {
     // with. Set and retrieved with \catcode, used in the lexer.
     category_map: HashMap<char, Category>,
 
+    // Maps input characters to positions in the font. Fonts only have 256
+    // positions, so characters outside of that range (like non-ASCII unicode
+    // characters) need to be mapped to a position to be typeset. Characters
+    // below 256 that aren't in this map use their own code as the position.
+    font_encoding_map: HashMap<char, u8>,
+
     // A map of individual characters to the math code that it is associated
     // with. Set and retrieved with \mathcode, only used in math mode.
     math_code_map: HashMap<char, MathCode>,
 
+    // A map of characters to their delimiter codes, set and retrieved with
+    // \delcode. Characters that aren't in the map have a delcode of -1,
+    // meaning that they can't be used as delimiters.
+    delimiter_code_map: HashMap<char, i32>,
+
     // There are several ways to redefine what a given token means, with \def,
     // \let, \chardef, etc. This map contains the definition of each redefined
     // token.
@@ -93,6 +254,15 @@ pub struct TeXStateInner {
     // close track of that).
     count_registers: [i32; 256],
 
+    // TeX's 256 dimen registers.
+    dimen_registers: [Dimen; 256],
+
+    // TeX's 256 skip (glue) registers.
+    skip_registers: Vec<Glue>,
+
+    // TeX's 256 muskip (math glue) registers.
+    muskip_registers: Vec<MuGlue>,
+
     // TeX's 256 box registers. The values are designed such that:
     //  * When entering a new group, we don't make a copy of a box by making
     //    the values Rc.
@@ -106,9 +276,34 @@ pub struct TeXStateInner {
     // most of the time.
     box_registers: HashMap<u8, Rc<RefCell<Option<TeXBox>>>>,
 
+    // The values of TeX's integer parameters, like \outputpenalty. Parameters
+    // that aren't in this map have a value of 0.
+    integer_parameters: HashMap<IntegerParameter, i32>,
+
+    // The values of TeX's dimen parameters, like \maxdepth. Parameters that
+    // aren't in this map have a value of 0pt.
+    dimen_parameters: HashMap<DimenParameter, Dimen>,
+
+    // The values of TeX's glue parameters, like \abovedisplayskip. Parameters
+    // that aren't in this map have a value of 0pt.
+    glue_parameters: HashMap<GlueParameter, Glue>,
+
+    // The values of TeX's math glue parameters, like \thinmuskip. Parameters
+    // that aren't in this map have a value of 0mu.
+    mu_glue_parameters: HashMap<MuGlueParameter, MuGlue>,
+
+    // The token list that is run as the output routine, set with \output.
+    // The surrounding braces aren't included.
+    output_routine: Vec<Token>,
+
     // We keep track of the name of the current font. Metrics and other
     // information about the font are stored elsewhere.
     current_font: Font,
+
+    // The fonts in each of the 16 math families, set with \textfont,
+    // \scriptfont, and \scriptscriptfont. These are keyed by the uncramped
+    // text, script, and scriptscript styles.
+    family_fonts: HashMap<(MathStyle, u8), Font>,
 }
 
 impl TeXStateInner {
@@ -138,6 +333,7 @@ impl TeXStateInner {
         initial_categories.insert('}', Category::EndGroup);
         initial_categories.insert('#', Category::Parameter);
         initial_categories.insert('$', Category::MathShift);
+        initial_categories.insert('&', Category::AlignmentTab);
 
         let mut initial_math_codes = HashMap::new();
         for i in 0..255 {
@@ -151,6 +347,128 @@ impl TeXStateInner {
             }
         }
 
+        let mut initial_delimiter_codes = HashMap::new();
+        initial_delimiter_codes.insert('.', 0);
+        // TODO(xymostech): Like the categories above, these are set in
+        // plain.tex and not by default.
+        initial_delimiter_codes.insert('(', 0x028300);
+        initial_delimiter_codes.insert(')', 0x029301);
+        initial_delimiter_codes.insert('[', 0x05B302);
+        initial_delimiter_codes.insert(']', 0x05D303);
+        initial_delimiter_codes.insert('<', 0x26830A);
+        initial_delimiter_codes.insert('>', 0x26930B);
+        initial_delimiter_codes.insert('/', 0x02F30E);
+        initial_delimiter_codes.insert('|', 0x26A30C);
+        initial_delimiter_codes.insert('\\', 0x26E30F);
+
+        // TODO(xymostech): Like the categories above, these are set in
+        // plain.tex and not by default.
+        let mut initial_dimen_parameters = HashMap::new();
+        initial_dimen_parameters.insert(
+            DimenParameter::MaxDepth,
+            Dimen::from_unit(4.0, Unit::Point),
+        );
+        initial_dimen_parameters
+            .insert(DimenParameter::BoxMaxDepth, Dimen::max_value());
+        initial_dimen_parameters
+            .insert(DimenParameter::VSize, Dimen::from_unit(8.9, Unit::Inch));
+        initial_dimen_parameters
+            .insert(DimenParameter::HSize, Dimen::from_unit(6.5, Unit::Inch));
+        initial_dimen_parameters.insert(
+            DimenParameter::PrevDepth,
+            Dimen::from_unit(-1000.0, Unit::Point),
+        );
+        initial_dimen_parameters
+            .insert(DimenParameter::LineSkipLimit, Dimen::zero());
+        initial_dimen_parameters.insert(
+            DimenParameter::DelimiterShortfall,
+            Dimen::from_unit(5.0, Unit::Point),
+        );
+        initial_dimen_parameters.insert(
+            DimenParameter::NullDelimiterSpace,
+            Dimen::from_unit(1.2, Unit::Point),
+        );
+
+        let mut initial_glue_parameters = HashMap::new();
+        initial_glue_parameters.insert(
+            GlueParameter::BaselineSkip,
+            Glue::from_dimen(Dimen::from_unit(12.0, Unit::Point)),
+        );
+        initial_glue_parameters.insert(
+            GlueParameter::LineSkip,
+            Glue::from_dimen(Dimen::from_unit(1.0, Unit::Point)),
+        );
+        initial_glue_parameters.insert(
+            GlueParameter::TopSkip,
+            Glue::from_dimen(Dimen::from_unit(10.0, Unit::Point)),
+        );
+        initial_glue_parameters.insert(
+            GlueParameter::ParFillSkip,
+            Glue {
+                space: Dimen::zero(),
+                stretch: SpringDimen::FilDimen(FilDimen::new(
+                    FilKind::Fil,
+                    1.0,
+                )),
+                shrink: SpringDimen::Dimen(Dimen::zero()),
+            },
+        );
+
+        // These are the values of the math glue parameters from plain TeX.
+        let mut initial_mu_glue_parameters = HashMap::new();
+        initial_mu_glue_parameters.insert(
+            MuGlueParameter::ThinMuSkip,
+            MuGlue::from_dimen(Dimen::from_unit(3.0, Unit::Point)),
+        );
+        initial_mu_glue_parameters.insert(
+            MuGlueParameter::MedMuSkip,
+            MuGlue {
+                space: Dimen::from_unit(4.0, Unit::Point),
+                stretch: SpringDimen::Dimen(Dimen::from_unit(2.0, Unit::Point)),
+                shrink: SpringDimen::Dimen(Dimen::from_unit(4.0, Unit::Point)),
+            },
+        );
+        initial_mu_glue_parameters.insert(
+            MuGlueParameter::ThickMuSkip,
+            MuGlue {
+                space: Dimen::from_unit(5.0, Unit::Point),
+                stretch: SpringDimen::Dimen(Dimen::from_unit(5.0, Unit::Point)),
+                shrink: SpringDimen::Dimen(Dimen::zero()),
+            },
+        );
+
+        let mut initial_integer_parameters = HashMap::new();
+        initial_integer_parameters.insert(IntegerParameter::MaxDeadCycles, 25);
+        initial_integer_parameters.insert(IntegerParameter::Tolerance, 200);
+        initial_integer_parameters.insert(IntegerParameter::LinePenalty, 10);
+        initial_integer_parameters.insert(IntegerParameter::HyphenPenalty, 50);
+        initial_integer_parameters.insert(IntegerParameter::AdjDemerits, 10000);
+        initial_integer_parameters
+            .insert(IntegerParameter::DelimiterFactor, 901);
+
+        // TODO(xymostech): Like the categories above, these are set in
+        // plain.tex and not by default.
+        let mut initial_family_fonts = HashMap::new();
+        for (family, font_name) in
+            [(0, "cmr"), (1, "cmmi"), (2, "cmsy"), (3, "cmex")].iter()
+        {
+            for (style, size) in [
+                (MathStyle::TextStyle, 10),
+                (MathStyle::ScriptStyle, 7),
+                (MathStyle::ScriptScriptStyle, 5),
+            ]
+            .iter()
+            {
+                initial_family_fonts.insert(
+                    (style.clone(), *family),
+                    Font {
+                        font_name: format!("{}{}", font_name, size),
+                        scale: Dimen::from_unit(*size as f64, Unit::Point),
+                    },
+                );
+            }
+        }
+
         let mut token_definitions = HashMap::new();
 
         for primitive in ALL_PRIMITIVES {
@@ -160,17 +478,35 @@ impl TeXStateInner {
             );
         }
 
+        // \endgraf is a synonym for \par which keeps its meaning even when
+        // \par is redefined.
+        token_definitions.insert(
+            Token::ControlSequence("endgraf".to_string()),
+            TokenDefinition::Primitive("par"),
+        );
+
         TeXStateInner {
             category_map: initial_categories,
+            font_encoding_map: HashMap::new(),
             math_code_map: initial_math_codes,
+            delimiter_code_map: initial_delimiter_codes,
             token_definition_map: token_definitions,
             count_registers: [0; 256],
+            dimen_registers: [Dimen::zero(); 256],
+            skip_registers: vec![Glue::zero(); 256],
+            muskip_registers: vec![MuGlue::zero(); 256],
             box_registers: HashMap::new(),
+            integer_parameters: initial_integer_parameters,
+            dimen_parameters: initial_dimen_parameters,
+            glue_parameters: initial_glue_parameters,
+            mu_glue_parameters: initial_mu_glue_parameters,
+            output_routine: Vec::new(),
             current_font: Font {
                 // TODO(xymostech): This should initially be "nullfont"
                 font_name: "cmr10".to_string(),
                 scale: Dimen::from_unit(10.0, Unit::Point),
             },
+            family_fonts: initial_family_fonts,
         }
     }
 
@@ -185,6 +521,18 @@ impl TeXStateInner {
         self.category_map.insert(ch, cat);
     }
 
+    fn get_font_position(&self, ch: char) -> Option<u8> {
+        match self.font_encoding_map.get(&ch) {
+            Some(&position) => Some(position),
+            None if (ch as u32) < 256 => Some(ch as u8),
+            None => None,
+        }
+    }
+
+    fn set_font_position(&mut self, ch: char, position: u8) {
+        self.font_encoding_map.insert(ch, position);
+    }
+
     fn get_math_code(&self, ch: char) -> MathCode {
         match self.math_code_map.get(&ch) {
             Some(mathcode) => mathcode.clone(),
@@ -196,6 +544,14 @@ impl TeXStateInner {
         self.math_code_map.insert(ch, mathcode.clone());
     }
 
+    fn get_delimiter_code(&self, ch: char) -> i32 {
+        *self.delimiter_code_map.get(&ch).unwrap_or(&-1)
+    }
+
+    fn set_delimiter_code(&mut self, ch: char, delimiter_code: i32) {
+        self.delimiter_code_map.insert(ch, delimiter_code);
+    }
+
     fn get_math_chardef(&self, token: &Token) -> Option<MathCode> {
         if let Some(TokenDefinition::MathCode(math_code)) =
             self.token_definition_map.get(token)
@@ -213,6 +569,23 @@ impl TeXStateInner {
         );
     }
 
+    fn get_muskipdef(&self, token: &Token) -> Option<u8> {
+        if let Some(TokenDefinition::MuSkipRegister(register_index)) =
+            self.token_definition_map.get(token)
+        {
+            Some(*register_index)
+        } else {
+            None
+        }
+    }
+
+    fn set_muskipdef(&mut self, token: &Token, register_index: u8) {
+        self.token_definition_map.insert(
+            token.clone(),
+            TokenDefinition::MuSkipRegister(register_index),
+        );
+    }
+
     fn get_macro(&self, token: &Token) -> Option<Rc<Macro>> {
         if let Some(TokenDefinition::Macro(makro)) =
             self.token_definition_map.get(token)
@@ -238,6 +611,33 @@ impl TeXStateInner {
         }
     }
 
+    // Returns the name of the primitive that the token means, if it means a
+    // primitive.
+    fn get_primitive(&self, token: &Token) -> Option<&'static str> {
+        match self.token_definition_map.get(token) {
+            Some(TokenDefinition::Primitive(prim)) => Some(prim),
+            Some(_) => None,
+            None => match token {
+                Token::ControlSequence(name) => {
+                    ALL_PRIMITIVES.iter().find(|prim| *prim == name).copied()
+                }
+                _ => None,
+            },
+        }
+    }
+
+    // Returns whether the token means anything, which is true for
+    // primitives, redefined tokens, and character tokens that aren't active.
+    fn is_defined(&self, token: &Token) -> bool {
+        if self.token_definition_map.contains_key(token) {
+            return true;
+        }
+        match token {
+            Token::ControlSequence(name) => is_primitive(name),
+            Token::Char(_, cat) => cat != &Category::Active,
+        }
+    }
+
     fn set_let(&mut self, set_token: &Token, to_token: &Token) {
         if let Some(token_definition) = self.token_definition_map.get(to_token)
         {
@@ -268,21 +668,16 @@ impl TeXStateInner {
             panic!("Testing invalid primitive: {}", prim);
         }
 
-        if let Token::ControlSequence(real_cs) = token {
-            if real_cs == prim {
-                return true;
-            }
-        }
-
-        if let Some(TokenDefinition::Primitive(prim_cs)) =
-            self.token_definition_map.get(token)
-        {
-            if prim_cs == &prim {
-                return true;
-            }
+        // If the token has been redefined (like with \let or \muskipdef),
+        // then its definition wins over its name.
+        match self.token_definition_map.get(token) {
+            Some(TokenDefinition::Primitive(prim_cs)) => prim_cs == &prim,
+            Some(_) => false,
+            None => match token {
+                Token::ControlSequence(real_cs) => real_cs == prim,
+                _ => false,
+            },
         }
-
-        false
     }
 
     fn get_count(&self, register_index: u8) -> i32 {
@@ -297,6 +692,86 @@ impl TeXStateInner {
         self.count_registers[register_index as usize] = value;
     }
 
+    fn get_dimen_register(&self, register_index: u8) -> Dimen {
+        self.dimen_registers[register_index as usize]
+    }
+
+    fn set_dimen_register(&mut self, register_index: u8, value: &Dimen) {
+        self.dimen_registers[register_index as usize] = *value;
+    }
+
+    fn get_skip_register(&self, register_index: u8) -> Glue {
+        self.skip_registers[register_index as usize].clone()
+    }
+
+    fn set_skip_register(&mut self, register_index: u8, value: &Glue) {
+        self.skip_registers[register_index as usize] = value.clone();
+    }
+
+    fn get_muskip_register(&self, register_index: u8) -> MuGlue {
+        self.muskip_registers[register_index as usize].clone()
+    }
+
+    fn set_muskip_register(&mut self, register_index: u8, value: &MuGlue) {
+        self.muskip_registers[register_index as usize] = value.clone();
+    }
+
+    fn get_integer_parameter(&self, param: &IntegerParameter) -> i32 {
+        match self.integer_parameters.get(param) {
+            Some(value) => *value,
+            None => 0,
+        }
+    }
+
+    fn set_integer_parameter(&mut self, param: &IntegerParameter, value: i32) {
+        self.integer_parameters.insert(*param, value);
+    }
+
+    fn get_dimen_parameter(&self, param: &DimenParameter) -> Dimen {
+        match self.dimen_parameters.get(param) {
+            Some(dimen) => *dimen,
+            None => Dimen::zero(),
+        }
+    }
+
+    fn set_dimen_parameter(&mut self, param: &DimenParameter, value: &Dimen) {
+        self.dimen_parameters.insert(*param, *value);
+    }
+
+    fn get_glue_parameter(&self, param: &GlueParameter) -> Glue {
+        match self.glue_parameters.get(param) {
+            Some(glue) => glue.clone(),
+            None => Glue::zero(),
+        }
+    }
+
+    fn set_glue_parameter(&mut self, param: &GlueParameter, value: &Glue) {
+        self.glue_parameters.insert(*param, value.clone());
+    }
+
+    fn get_mu_glue_parameter(&self, param: &MuGlueParameter) -> MuGlue {
+        match self.mu_glue_parameters.get(param) {
+            Some(glue) => glue.clone(),
+            None => MuGlue::zero(),
+        }
+    }
+
+    fn set_mu_glue_parameter(
+        &mut self,
+        param: &MuGlueParameter,
+        value: &MuGlue,
+    ) {
+        self.mu_glue_parameters.insert(*param, value.clone());
+    }
+
+    fn get_output_routine(&self) -> Vec<Token> {
+        self.output_routine.clone()
+    }
+
+    fn set_output_routine(&mut self, tokens: &[Token]) {
+        self.output_routine = tokens.to_vec();
+    }
+
     fn get_current_font(&self) -> Font {
         self.current_font.clone()
     }
@@ -305,6 +780,15 @@ impl TeXStateInner {
         self.current_font = font.clone();
     }
 
+    fn get_family_font(&self, style: &MathStyle, family: u8) -> Option<Font> {
+        self.family_fonts.get(&(style.clone(), family)).cloned()
+    }
+
+    fn set_family_font(&mut self, style: &MathStyle, family: u8, font: &Font) {
+        self.family_fonts
+            .insert((style.clone(), family), font.clone());
+    }
+
     fn set_fontdef(&mut self, token: &Token, font: &Font) {
         self.token_definition_map
             .insert(token.clone(), TokenDefinition::Font(font.clone()));
@@ -412,19 +896,45 @@ impl TeXStateStack {
 
     generate_inner_func!(fn get_category(ch: char) -> Category);
     generate_inner_global_func!(fn set_category(global: bool, ch: char, cat: Category));
+    generate_inner_func!(fn get_font_position(ch: char) -> Option<u8>);
+    generate_inner_global_func!(fn set_font_position(global: bool, ch: char, position: u8));
     generate_inner_func!(fn get_math_code(ch: char) -> MathCode);
     generate_inner_global_func!(fn set_math_code(global: bool, ch: char, mathcode: &MathCode));
+    generate_inner_func!(fn get_delimiter_code(ch: char) -> i32);
+    generate_inner_global_func!(fn set_delimiter_code(global: bool, ch: char, delimiter_code: i32));
     generate_inner_func!(fn get_math_chardef(token: &Token) -> Option<MathCode>);
     generate_inner_global_func!(fn set_math_chardef(global: bool, token: &Token, mathcode: &MathCode));
+    generate_inner_func!(fn get_muskipdef(token: &Token) -> Option<u8>);
+    generate_inner_global_func!(fn set_muskipdef(global: bool, token: &Token, register_index: u8));
     generate_inner_func!(fn get_macro(token: &Token) -> Option<Rc<Macro>>);
     generate_inner_global_func!(fn set_macro(global: bool, token: &Token, makro: &Rc<Macro>));
     generate_inner_func!(fn get_renamed_token(token: &Token) -> Option<Token>);
+    generate_inner_func!(fn get_primitive(token: &Token) -> Option<&'static str>);
+    generate_inner_func!(fn is_defined(token: &Token) -> bool);
     generate_inner_global_func!(fn set_let(global: bool, set_token: &Token, to_token: &Token));
     generate_inner_func!(fn is_token_equal_to_prim(token: &Token, cs: &str) -> bool);
     generate_inner_func!(fn get_count(register_index: u8) -> i32);
     generate_inner_global_func!(fn set_count(global: bool, register_index: u8, value: i32));
+    generate_inner_func!(fn get_dimen_register(register_index: u8) -> Dimen);
+    generate_inner_global_func!(fn set_dimen_register(global: bool, register_index: u8, value: &Dimen));
+    generate_inner_func!(fn get_skip_register(register_index: u8) -> Glue);
+    generate_inner_global_func!(fn set_skip_register(global: bool, register_index: u8, value: &Glue));
+    generate_inner_func!(fn get_muskip_register(register_index: u8) -> MuGlue);
+    generate_inner_global_func!(fn set_muskip_register(global: bool, register_index: u8, value: &MuGlue));
+    generate_inner_func!(fn get_integer_parameter(param: &IntegerParameter) -> i32);
+    generate_inner_global_func!(fn set_integer_parameter(global: bool, param: &IntegerParameter, value: i32));
+    generate_inner_func!(fn get_dimen_parameter(param: &DimenParameter) -> Dimen);
+    generate_inner_global_func!(fn set_dimen_parameter(global: bool, param: &DimenParameter, value: &Dimen));
+    generate_inner_func!(fn get_glue_parameter(param: &GlueParameter) -> Glue);
+    generate_inner_global_func!(fn set_glue_parameter(global: bool, param: &GlueParameter, value: &Glue));
+    generate_inner_func!(fn get_mu_glue_parameter(param: &MuGlueParameter) -> MuGlue);
+    generate_inner_global_func!(fn set_mu_glue_parameter(global: bool, param: &MuGlueParameter, value: &MuGlue));
+    generate_inner_func!(fn get_output_routine() -> Vec<Token>);
+    generate_inner_global_func!(fn set_output_routine(global: bool, tokens: &[Token]));
     generate_inner_func!(fn get_current_font() -> Font);
     generate_inner_global_func!(fn set_current_font(global: bool, font: &Font));
+    generate_inner_func!(fn get_family_font(style: &MathStyle, family: u8) -> Option<Font>);
+    generate_inner_global_func!(fn set_family_font(global: bool, style: &MathStyle, family: u8, font: &Font));
     generate_inner_global_func!(fn set_fontdef(global: bool, token: &Token, font: &Font));
     generate_inner_func!(fn get_fontdef(token: &Token) -> Option<Font>);
     generate_inner_func!(fn get_box(box_index: u8) -> Option<TeXBox>);
@@ -466,6 +976,21 @@ pub struct TeXState {
     // in the `TeXStateInner` because loading the font metrics is global and
     // isn't affected by grouping.
     font_metrics: RefCell<HashMap<Font, FontMetrics>>,
+
+    // The patterns loaded with \patterns. Like the font metrics, these are
+    // global and aren't affected by grouping.
+    hyphenation_patterns: RefCell<HyphenationPatterns>,
+
+    // The \skewchar of each font. Like other font information, these are
+    // global and aren't affected by grouping.
+    skew_chars: RefCell<HashMap<Font, i32>>,
+
+    // The \hyphenchar of each font, which is also global.
+    hyphen_chars: RefCell<HashMap<Font, i32>>,
+
+    // The control sequence most recently defined with \font to select each
+    // font, which is what \the\font produces. This is also global.
+    font_identifiers: RefCell<HashMap<Font, Token>>,
 }
 
 // Since we're mostly want to just be calling the same-named functions from
@@ -487,6 +1012,10 @@ impl TeXState {
         TeXState {
             state_stack: RefCell::new(TeXStateStack::new()),
             font_metrics: RefCell::new(HashMap::new()),
+            hyphenation_patterns: RefCell::new(HyphenationPatterns::new()),
+            skew_chars: RefCell::new(HashMap::new()),
+            hyphen_chars: RefCell::new(HashMap::new()),
+            font_identifiers: RefCell::new(HashMap::new()),
         }
     }
 
@@ -505,19 +1034,45 @@ impl TeXState {
 
     generate_stack_func!(fn get_category(ch: char) -> Category);
     generate_stack_func!(fn set_category(global: bool, ch: char, cat: Category));
+    generate_stack_func!(fn get_font_position(ch: char) -> Option<u8>);
+    generate_stack_func!(fn set_font_position(global: bool, ch: char, position: u8));
     generate_stack_func!(fn get_math_code(ch: char) -> MathCode);
     generate_stack_func!(fn set_math_code(global: bool, ch: char, mathcode: &MathCode));
+    generate_stack_func!(fn get_delimiter_code(ch: char) -> i32);
+    generate_stack_func!(fn set_delimiter_code(global: bool, ch: char, delimiter_code: i32));
     generate_stack_func!(fn get_math_chardef(token: &Token) -> Option<MathCode>);
     generate_stack_func!(fn set_math_chardef(global: bool, token: &Token, mathcode: &MathCode));
+    generate_stack_func!(fn get_muskipdef(token: &Token) -> Option<u8>);
+    generate_stack_func!(fn set_muskipdef(global: bool, token: &Token, register_index: u8));
     generate_stack_func!(fn get_macro(token: &Token) -> Option<Rc<Macro>>);
     generate_stack_func!(fn set_macro(global: bool, token: &Token, makro: &Rc<Macro>));
     generate_stack_func!(fn get_renamed_token(token: &Token) -> Option<Token>);
+    generate_stack_func!(fn get_primitive(token: &Token) -> Option<&'static str>);
+    generate_stack_func!(fn is_defined(token: &Token) -> bool);
     generate_stack_func!(fn set_let(global: bool, set_token: &Token, to_token: &Token));
     generate_stack_func!(fn is_token_equal_to_prim(token: &Token, cs: &str) -> bool);
     generate_stack_func!(fn get_count(register_index: u8) -> i32);
     generate_stack_func!(fn set_count(global: bool, register_index: u8, value: i32));
+    generate_stack_func!(fn get_dimen_register(register_index: u8) -> Dimen);
+    generate_stack_func!(fn set_dimen_register(global: bool, register_index: u8, value: &Dimen));
+    generate_stack_func!(fn get_skip_register(register_index: u8) -> Glue);
+    generate_stack_func!(fn set_skip_register(global: bool, register_index: u8, value: &Glue));
+    generate_stack_func!(fn get_muskip_register(register_index: u8) -> MuGlue);
+    generate_stack_func!(fn set_muskip_register(global: bool, register_index: u8, value: &MuGlue));
+    generate_stack_func!(fn get_integer_parameter(param: &IntegerParameter) -> i32);
+    generate_stack_func!(fn set_integer_parameter(global: bool, param: &IntegerParameter, value: i32));
+    generate_stack_func!(fn get_dimen_parameter(param: &DimenParameter) -> Dimen);
+    generate_stack_func!(fn set_dimen_parameter(global: bool, param: &DimenParameter, value: &Dimen));
+    generate_stack_func!(fn get_glue_parameter(param: &GlueParameter) -> Glue);
+    generate_stack_func!(fn set_glue_parameter(global: bool, param: &GlueParameter, value: &Glue));
+    generate_stack_func!(fn get_mu_glue_parameter(param: &MuGlueParameter) -> MuGlue);
+    generate_stack_func!(fn set_mu_glue_parameter(global: bool, param: &MuGlueParameter, value: &MuGlue));
+    generate_stack_func!(fn get_output_routine() -> Vec<Token>);
+    generate_stack_func!(fn set_output_routine(global: bool, tokens: &[Token]));
     generate_stack_func!(fn get_current_font() -> Font);
     generate_stack_func!(fn set_current_font(global: bool, font: &Font));
+    generate_stack_func!(fn get_family_font(style: &MathStyle, family: u8) -> Option<Font>);
+    generate_stack_func!(fn set_family_font(global: bool, style: &MathStyle, family: u8, font: &Font));
     generate_stack_func!(fn set_fontdef(global: bool, token: &Token, font: &Font));
     generate_stack_func!(fn get_fontdef(token: &Token) -> Option<Font>);
     generate_stack_func!(fn get_box(box_index: u8) -> Option<TeXBox>);
@@ -554,6 +1109,62 @@ impl TeXState {
             x.get(font).unwrap()
         }))
     }
+
+    // Font parameters are part of the font information, so like the
+    // \skewchar, setting them is always global.
+    pub fn set_font_parameter(&self, font: &Font, index: usize, value: Dimen) {
+        if self.get_metrics_for_font(font).is_none() {
+            panic!("Invalid font: {:?}", font);
+        }
+
+        self.font_metrics
+            .borrow_mut()
+            .get_mut(font)
+            .unwrap()
+            .set_font_parameter(index, value);
+    }
+
+    // Fonts don't have a skew char until one is set, which is represented by
+    // -1.
+    pub fn get_skew_char(&self, font: &Font) -> i32 {
+        *self.skew_chars.borrow().get(font).unwrap_or(&-1)
+    }
+
+    pub fn set_skew_char(&self, font: &Font, value: i32) {
+        self.skew_chars.borrow_mut().insert(font.clone(), value);
+    }
+
+    // Fonts use `-` as their hyphen char until another one is set, like in
+    // plain TeX where \defaulthyphenchar is `\-.
+    pub fn get_hyphen_char(&self, font: &Font) -> i32 {
+        *self
+            .hyphen_chars
+            .borrow()
+            .get(font)
+            .unwrap_or(&('-' as i32))
+    }
+
+    pub fn set_hyphen_char(&self, font: &Font, value: i32) {
+        self.hyphen_chars.borrow_mut().insert(font.clone(), value);
+    }
+
+    pub fn get_font_identifier(&self, font: &Font) -> Option<Token> {
+        self.font_identifiers.borrow().get(font).cloned()
+    }
+
+    pub fn set_font_identifier(&self, font: &Font, token: &Token) {
+        self.font_identifiers
+            .borrow_mut()
+            .insert(font.clone(), token.clone());
+    }
+
+    pub fn add_hyphenation_pattern(&self, pattern: &str) {
+        self.hyphenation_patterns.borrow_mut().add_pattern(pattern);
+    }
+
+    pub fn hyphenate(&self, word: &[char]) -> Vec<usize> {
+        self.hyphenation_patterns.borrow().hyphenate(word)
+    }
 }
 
 #[cfg(test)]
@@ -640,6 +1251,20 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn it_checks_if_tokens_are_defined() {
+        let state = TeXState::new();
+        let foo = Token::ControlSequence("foo".to_string());
+
+        assert!(state.is_defined(&Token::ControlSequence("let".to_string())));
+        assert!(state.is_defined(&Token::Char('a', Category::Letter)));
+        assert!(!state.is_defined(&Token::Char('~', Category::Active)));
+        assert!(!state.is_defined(&foo));
+
+        state.set_let(false, &foo, &Token::Char('a', Category::Letter));
+        assert!(state.is_defined(&foo));
+    }
+
     #[test]
     fn it_retrieves_boxes_once() {
         let state = TeXState::new();
@@ -648,7 +1273,7 @@ mod tests {
             height: Dimen::from_unit(0.0, Unit::Point),
             depth: Dimen::from_unit(0.0, Unit::Point),
             width: Dimen::from_unit(0.0, Unit::Point),
-            list: Vec::new(),
+            list: Rc::new(Vec::new()),
             glue_set_ratio: None,
         });
 
@@ -668,7 +1293,7 @@ mod tests {
             height: Dimen::from_unit(0.0, Unit::Point),
             depth: Dimen::from_unit(0.0, Unit::Point),
             width: Dimen::from_unit(0.0, Unit::Point),
-            list: Vec::new(),
+            list: Rc::new(Vec::new()),
             glue_set_ratio: None,
         });
 
@@ -688,7 +1313,7 @@ mod tests {
             height: Dimen::from_unit(0.0, Unit::Point),
             depth: Dimen::from_unit(0.0, Unit::Point),
             width: Dimen::from_unit(0.0, Unit::Point),
-            list: Vec::new(),
+            list: Rc::new(Vec::new()),
             glue_set_ratio: None,
         });
 
@@ -710,7 +1335,7 @@ mod tests {
             height: Dimen::from_unit(0.0, Unit::Point),
             depth: Dimen::from_unit(0.0, Unit::Point),
             width: Dimen::from_unit(0.0, Unit::Point),
-            list: Vec::new(),
+            list: Rc::new(Vec::new()),
             glue_set_ratio: None,
         });
 
@@ -718,7 +1343,7 @@ mod tests {
             height: Dimen::from_unit(0.0, Unit::Point),
             depth: Dimen::from_unit(0.0, Unit::Point),
             width: Dimen::from_unit(1.0, Unit::Point),
-            list: Vec::new(),
+            list: Rc::new(Vec::new()),
             glue_set_ratio: None,
         });
 
@@ -746,7 +1371,7 @@ mod tests {
             height: Dimen::from_unit(0.0, Unit::Point),
             depth: Dimen::from_unit(0.0, Unit::Point),
             width: Dimen::from_unit(0.0, Unit::Point),
-            list: Vec::new(),
+            list: Rc::new(Vec::new()),
             glue_set_ratio: None,
         });
 
@@ -770,7 +1395,7 @@ mod tests {
             height: Dimen::from_unit(1.0, Unit::Point),
             depth: Dimen::from_unit(2.0, Unit::Point),
             width: Dimen::from_unit(3.0, Unit::Point),
-            list: Vec::new(),
+            list: Rc::new(Vec::new()),
             glue_set_ratio: None,
         });
 
@@ -803,6 +1428,15 @@ mod tests {
         assert_eq!(state.get_math_code('('), MathCode::from_number(0x0028));
     }
 
+    #[test]
+    fn it_sets_delimiter_codes_initially() {
+        let state = TeXState::new();
+
+        assert_eq!(state.get_delimiter_code('.'), 0);
+        assert_eq!(state.get_delimiter_code('['), 0x05B302);
+        assert_eq!(state.get_delimiter_code('a'), -1);
+    }
+
     #[test]
     fn it_gets_and_sets_math_codes_correctly() {
         let state = TeXState::new();
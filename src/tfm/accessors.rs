@@ -1,11 +1,23 @@
 use crate::dimension::{Dimen, Unit};
-use crate::tfm::{CharInfoEntry, TFMFile};
+use crate::tfm::{
+    CharInfoEntry, CharKind, ExtensibleRecipe, LigKernKind, LigKernStep,
+    TFMFile,
+};
 
 impl TFMFile {
     pub fn get_design_size(&self) -> f64 {
         self.header.design_size
     }
 
+    // Returns whether the font has a character at the given position. Unused
+    // positions in the font have a width index of 0.
+    pub fn exists(&self, chr: char) -> bool {
+        let char_index = chr as usize;
+        self.first_char <= char_index
+            && char_index <= self.last_char
+            && self.char_infos[char_index - self.first_char].width_index != 0
+    }
+
     fn get_char_info(&self, chr: char) -> &CharInfoEntry {
         let char_index = chr as usize;
         assert!(
@@ -45,9 +57,128 @@ impl TFMFile {
         )
     }
 
+    pub fn get_italic_correction(&self, chr: char) -> Dimen {
+        let char_info = self.get_char_info(chr);
+
+        Dimen::from_unit(
+            self.header.design_size
+                * self.italic_corrections[char_info.italic_correction_index],
+            Unit::Point,
+        )
+    }
+
+    // Returns the step in the first character's lig/kern program that applies
+    // when it's followed by the second character, if there is one.
+    fn get_lig_kern_step(
+        &self,
+        left: char,
+        right: char,
+    ) -> Option<&LigKernStep> {
+        let mut index = match self.get_char_info(left).kind {
+            CharKind::LigKern { ligkern_index } => ligkern_index,
+            _ => return None,
+        };
+
+        loop {
+            let step = &self.lig_kern_steps[index];
+
+            if step.next_char == right as usize {
+                return Some(step);
+            }
+
+            if step.stop {
+                return None;
+            }
+            index += 1;
+        }
+    }
+
+    // Returns the kern between two characters from the first character's
+    // lig/kern program, if there is one.
+    pub fn get_kern(&self, left: char, right: char) -> Option<Dimen> {
+        match self.get_lig_kern_step(left, right)?.kind {
+            LigKernKind::Kern { kern_index } => Some(Dimen::from_unit(
+                self.header.design_size * self.kerns[kern_index],
+                Unit::Point,
+            )),
+            LigKernKind::Ligature { .. } => None,
+        }
+    }
+
+    // Returns the character that two characters are replaced with from the
+    // first character's lig/kern program, if they form a ligature. Only the
+    // simple =: ligatures, which replace both characters, are supported.
+    pub fn get_ligature(&self, left: char, right: char) -> Option<char> {
+        match self.get_lig_kern_step(left, right)?.kind {
+            LigKernKind::Ligature { substitution } => {
+                Some(substitution as u8 as char)
+            }
+            LigKernKind::Kern { .. } => None,
+        }
+    }
+
+    // Returns the next larger variant of a character, for characters which are
+    // part of a chain of successively larger characters like delimiters.
+    pub fn get_next_larger_char(&self, chr: char) -> Option<char> {
+        match self.get_char_info(chr).kind {
+            CharKind::CharList { next_char } => Some(next_char as u8 as char),
+            _ => None,
+        }
+    }
+
+    // Returns the recipe for building an extensible character out of pieces,
+    // if the character is extensible.
+    pub fn get_extensible_recipe(&self, chr: char) -> Option<ExtensibleRecipe> {
+        let recipe = match self.get_char_info(chr).kind {
+            CharKind::Extensible { ext_recipe_index } => {
+                &self.ext_recipes[ext_recipe_index]
+            }
+            _ => return None,
+        };
+
+        // A piece at position 0 means that the piece is missing, except for
+        // the repeated piece which is always there.
+        let piece = |position: usize| {
+            if position == 0 {
+                None
+            } else {
+                Some(position as u8 as char)
+            }
+        };
+
+        Some(ExtensibleRecipe {
+            top: piece(recipe.top),
+            middle: piece(recipe.mid),
+            bottom: piece(recipe.bot),
+            repeat: recipe.ext as u8 as char,
+        })
+    }
+
     pub const fn get_checksum(&self) -> u32 {
         self.header.checksum
     }
+
+    pub fn get_num_font_parameters(&self) -> usize {
+        self.font_parameters.len()
+    }
+
+    // Returns the value of the font parameter with the given 1-based index,
+    // like \fontdimen does. The first parameter (the slant) is a pure number
+    // and so isn't scaled by the design size.
+    pub fn get_font_parameter(&self, index: usize) -> Dimen {
+        assert!(
+            1 <= index && index <= self.font_parameters.len(),
+            "Font has only {} fontdimen parameters",
+            self.font_parameters.len()
+        );
+
+        let value = self.font_parameters[index - 1];
+        if index == 1 {
+            Dimen::from_unit(value, Unit::Point)
+        } else {
+            Dimen::from_unit(self.header.design_size * value, Unit::Point)
+        }
+    }
 }
 
 #[cfg(test)]
@@ -74,6 +205,15 @@ mod tests {
         );
     }
 
+    #[test]
+    fn checks_if_chars_exist() {
+        let font_metrics = TFMFile::new(CMR10_TFM).unwrap();
+
+        assert!(font_metrics.exists('a'));
+        assert!(font_metrics.exists(0 as char));
+        assert!(!font_metrics.exists(200 as char));
+    }
+
     #[test]
     fn get_cmr10_dimensions() {
         let font_metrics = TFMFile::new(CMR10_TFM).unwrap();
@@ -90,5 +230,40 @@ mod tests {
         for ch in (0 as u8)..128 {
             assert!(font_metrics.get_width(ch as char) > Dimen::zero());
         }
+
+        assert!(font_metrics.get_italic_correction('f') > Dimen::zero());
+        assert_eq!(font_metrics.get_italic_correction('a'), Dimen::zero());
+    }
+
+    #[test]
+    fn get_basic_font_parameters() {
+        let font_metrics = TFMFile::new(&BASIC_TFM[..]).unwrap();
+
+        assert_eq!(font_metrics.get_font_parameter(1), Dimen::zero());
+        assert_eq!(
+            font_metrics.get_font_parameter(2),
+            Dimen::from_unit(20.0, Unit::Point)
+        );
+    }
+
+    #[test]
+    fn get_cmr10_kerns() {
+        let font_metrics = TFMFile::new(CMR10_TFM).unwrap();
+
+        assert!(font_metrics.get_kern('A', 'V').unwrap() < Dimen::zero());
+        assert_eq!(font_metrics.get_kern('A', 'a'), None);
+        assert_eq!(font_metrics.get_kern('f', 'i'), None);
+    }
+
+    #[test]
+    fn get_cmr10_ligatures() {
+        let font_metrics = TFMFile::new(CMR10_TFM).unwrap();
+
+        // cmr10 has ff at '013, fi at '014, and ffi at '016.
+        assert_eq!(font_metrics.get_ligature('f', 'f'), Some('\x0b'));
+        assert_eq!(font_metrics.get_ligature('f', 'i'), Some('\x0c'));
+        assert_eq!(font_metrics.get_ligature('\x0b', 'i'), Some('\x0e'));
+        assert_eq!(font_metrics.get_ligature('A', 'V'), None);
+        assert_eq!(font_metrics.get_ligature('a', 'b'), None);
     }
 }
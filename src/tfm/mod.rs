@@ -46,6 +46,17 @@ struct ExtRecipe {
     ext: usize,
 }
 
+// The pieces that an extensible character is built from, stacked with the top
+// piece at the top, the repeated piece filling the space between the other
+// pieces, and so on. Only the repeated piece is required.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ExtensibleRecipe {
+    pub top: Option<char>,
+    pub middle: Option<char>,
+    pub bottom: Option<char>,
+    pub repeat: char,
+}
+
 #[derive(Debug, PartialEq)]
 pub struct TFMFile {
     first_char: usize,
@@ -5,3 +5,28 @@ pub enum Token {
     ControlSequence(String),
     Char(char, Category),
 }
+
+impl Token {
+    // Returns the characters that \string turns the token into.
+    pub fn to_tex_string(&self) -> String {
+        match self {
+            Token::ControlSequence(name) => format!("\\{}", name),
+            Token::Char(chr, _) => chr.to_string(),
+        }
+    }
+
+    // Returns how the token is printed in a list of tokens, like in the
+    // meaning of a macro. Control sequences made of letters are followed by
+    // a space so they don't run into the tokens after them.
+    pub fn to_token_list_string(&self) -> String {
+        match self {
+            Token::ControlSequence(name)
+                if name.chars().count() != 1
+                    || name.chars().all(|chr| chr.is_ascii_alphabetic()) =>
+            {
+                format!("\\{} ", name)
+            }
+            _ => self.to_tex_string(),
+        }
+    }
+}
@@ -1,9 +1,57 @@
 use crate::dimension::Dimen;
+use crate::font::Font;
+use crate::glue::Glue;
+use crate::mu_glue::MuGlue;
 use crate::state::TeXState;
 
+/// The integer parameters that TeX keeps track of, like \outputpenalty.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub enum IntegerParameter {
+    // The value of the penalty at the most recent page break, set by the page
+    // builder before the output routine runs.
+    OutputPenalty,
+    // The number of times the output routine has run since the last
+    // \shipout. This is always set globally.
+    DeadCycles,
+    // The number of times the output routine can run without shipping
+    // anything out before we decide it's stuck in a loop.
+    MaxDeadCycles,
+    // The penalty added between the lines of a paragraph.
+    InterLinePenalty,
+    // The penalty added between the lines of a multi-line display.
+    InterDisplayLinePenalty,
+    // The penalties added just before and just after a display.
+    PreDisplayPenalty,
+    PostDisplayPenalty,
+    // The penalties for breaking inline math after a relation or a binary
+    // operation.
+    RelPenalty,
+    BinOpPenalty,
+    // Whether diagnostics are shown on the terminal as well as in the log.
+    // We don't write a log file, so diagnostics are always shown.
+    TracingOnline,
+    // If positive, the contents of every box that is shipped out are shown.
+    TracingOutput,
+    // The largest badness of a line that the line breaker will accept.
+    Tolerance,
+    // The demerits added to every line of a paragraph.
+    LinePenalty,
+    // The penalty for breaking a line at a discretionary.
+    HyphenPenalty,
+    // The demerits added when a very loose line is next to a decent one, or
+    // a loose line is next to a tight one.
+    AdjDemerits,
+    // The fraction, in thousandths, of a \left...\right formula that the
+    // delimiters have to cover.
+    DelimiterFactor,
+}
+
 #[derive(PartialEq, Eq, Debug)]
 pub enum IntegerVariable {
     CountRegister(u8),
+    IntegerParameter(IntegerParameter),
+    SkewChar(Font),
+    HyphenChar(Font),
 }
 
 impl IntegerVariable {
@@ -12,26 +60,79 @@ impl IntegerVariable {
             Self::CountRegister(index) => {
                 state.set_count(global, *index, value)
             }
+            Self::IntegerParameter(IntegerParameter::DeadCycles) => state
+                .set_integer_parameter(
+                    true,
+                    &IntegerParameter::DeadCycles,
+                    value,
+                ),
+            Self::IntegerParameter(param) => {
+                state.set_integer_parameter(global, param, value)
+            }
+            // Font information is always set globally.
+            Self::SkewChar(font) => state.set_skew_char(font, value),
+            Self::HyphenChar(font) => state.set_hyphen_char(font, value),
         }
     }
 
     pub fn get(&self, state: &TeXState) -> i32 {
         match self {
             Self::CountRegister(index) => state.get_count(*index),
+            Self::IntegerParameter(param) => state.get_integer_parameter(param),
+            Self::SkewChar(font) => state.get_skew_char(font),
+            Self::HyphenChar(font) => state.get_hyphen_char(font),
         }
     }
 }
 
+/// The dimen parameters that TeX keeps track of, like \maxdepth. These are
+/// stored in the state and can be read and assigned like dimen registers.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub enum DimenParameter {
+    // The maximum depth of the box that a page is built into.
+    MaxDepth,
+    // The maximum depth of boxes built with \vbox.
+    BoxMaxDepth,
+    // The height that the page builder tries to fill on each page.
+    VSize,
+    // The width of lines in paragraphs.
+    HSize,
+    // The width of the line before a display, set when a display starts.
+    PreDisplaySize,
+    // The width and indentation of the line that a display is centered in,
+    // set when a display starts.
+    DisplayWidth,
+    DisplayIndent,
+    // Extra space added after superscripts and subscripts.
+    ScriptSpace,
+    // The depth of the most recent box in the current vertical list, used
+    // for interline glue. This is always set globally, since it belongs to
+    // the list being built and not to the current group.
+    PrevDepth,
+    // If the interline glue between two boxes would be less than this, then
+    // \lineskip is used instead of \baselineskip.
+    LineSkipLimit,
+    // The most that \left...\right delimiters can fall short of covering
+    // the formula between them.
+    DelimiterShortfall,
+    // The width of null delimiters.
+    NullDelimiterSpace,
+}
+
 #[derive(PartialEq, Eq, Debug)]
 pub enum DimenVariable {
+    DimenRegister(u8),
     BoxWidth(u8),
     BoxHeight(u8),
     BoxDepth(u8),
+    DimenParameter(DimenParameter),
+    FontDimen(usize, Font),
 }
 
 impl DimenVariable {
     pub fn get(&self, state: &TeXState) -> Dimen {
         match self {
+            Self::DimenRegister(index) => state.get_dimen_register(*index),
             Self::BoxWidth(index) => state
                 .with_box(*index, |tex_box| *tex_box.width())
                 .unwrap_or_else(Dimen::zero),
@@ -41,11 +142,19 @@ impl DimenVariable {
             Self::BoxDepth(index) => state
                 .with_box(*index, |tex_box| *tex_box.depth())
                 .unwrap_or_else(Dimen::zero),
+            Self::DimenParameter(param) => state.get_dimen_parameter(param),
+            Self::FontDimen(index, font) => state
+                .get_metrics_for_font(font)
+                .unwrap_or_else(|| panic!("Invalid font: {:?}", font))
+                .get_font_parameter(*index),
         }
     }
 
-    pub fn set(&self, state: &TeXState, _global: bool, new_dimen: Dimen) {
+    pub fn set(&self, state: &TeXState, global: bool, new_dimen: Dimen) {
         match self {
+            Self::DimenRegister(index) => {
+                state.set_dimen_register(global, *index, &new_dimen);
+            }
             Self::BoxWidth(index) => {
                 state.with_box(*index, |tex_box| {
                     *tex_box.mut_width() = new_dimen
@@ -61,6 +170,103 @@ impl DimenVariable {
                     *tex_box.mut_depth() = new_dimen
                 });
             }
+            Self::DimenParameter(DimenParameter::PrevDepth) => state
+                .set_dimen_parameter(
+                    true,
+                    &DimenParameter::PrevDepth,
+                    &new_dimen,
+                ),
+            Self::DimenParameter(param) => {
+                state.set_dimen_parameter(global, param, &new_dimen);
+            }
+            // Font information is always set globally.
+            Self::FontDimen(index, font) => {
+                state.set_font_parameter(font, *index, new_dimen);
+            }
+        }
+    }
+}
+
+/// The glue parameters that TeX keeps track of, like \abovedisplayskip.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+#[allow(clippy::enum_variant_names)]
+pub enum GlueParameter {
+    // The glue added above and below a display.
+    AboveDisplaySkip,
+    BelowDisplaySkip,
+    // The glue added above and below a display when the line before the
+    // display is short enough that it ends before the display starts.
+    AboveDisplayShortSkip,
+    BelowDisplayShortSkip,
+    // The distance between baselines of adjacent boxes in a vertical list.
+    BaselineSkip,
+    // The glue added between boxes in a vertical list when they are too close
+    // together for \baselineskip to be used.
+    LineSkip,
+    // The glue added at the end of the last line of a paragraph.
+    ParFillSkip,
+    // The distance from the top of a page to the baseline of its first box.
+    TopSkip,
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum GlueVariable {
+    SkipRegister(u8),
+    GlueParameter(GlueParameter),
+}
+
+impl GlueVariable {
+    pub fn get(&self, state: &TeXState) -> Glue {
+        match self {
+            Self::SkipRegister(index) => state.get_skip_register(*index),
+            Self::GlueParameter(param) => state.get_glue_parameter(param),
+        }
+    }
+
+    pub fn set(&self, state: &TeXState, global: bool, new_glue: Glue) {
+        match self {
+            Self::SkipRegister(index) => {
+                state.set_skip_register(global, *index, &new_glue);
+            }
+            Self::GlueParameter(param) => {
+                state.set_glue_parameter(global, param, &new_glue);
+            }
+        }
+    }
+}
+
+/// The math glue parameters that TeX keeps track of, which are used for the
+/// spacing between atoms in math lists.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+#[allow(clippy::enum_variant_names)]
+pub enum MuGlueParameter {
+    ThinMuSkip,
+    MedMuSkip,
+    ThickMuSkip,
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum MuGlueVariable {
+    MuSkipRegister(u8),
+    MuGlueParameter(MuGlueParameter),
+}
+
+impl MuGlueVariable {
+    pub fn get(&self, state: &TeXState) -> MuGlue {
+        match self {
+            Self::MuSkipRegister(index) => state.get_muskip_register(*index),
+            Self::MuGlueParameter(param) => state.get_mu_glue_parameter(param),
+        }
+    }
+
+    pub fn set(&self, state: &TeXState, global: bool, new_glue: MuGlue) {
+        match self {
+            Self::MuSkipRegister(index) => {
+                state.set_muskip_register(global, *index, &new_glue);
+            }
+            Self::MuGlueParameter(param) => {
+                state.set_mu_glue_parameter(global, param, &new_glue);
+            }
         }
     }
 }
@@ -69,6 +275,8 @@ impl DimenVariable {
 mod tests {
     use super::*;
 
+    use std::rc::Rc;
+
     use crate::boxes::{HorizontalBox, TeXBox};
     use crate::dimension::Unit;
 
@@ -85,7 +293,7 @@ mod tests {
             height: Dimen::from_unit(1.0, Unit::Point),
             depth: Dimen::from_unit(2.0, Unit::Point),
             width: Dimen::from_unit(3.0, Unit::Point),
-            list: Vec::new(),
+            list: Rc::new(Vec::new()),
             glue_set_ratio: None,
         });
 
@@ -117,7 +325,7 @@ mod tests {
             height: Dimen::from_unit(1.0, Unit::Point),
             depth: Dimen::from_unit(2.0, Unit::Point),
             width: Dimen::from_unit(3.0, Unit::Point),
-            list: Vec::new(),
+            list: Rc::new(Vec::new()),
             glue_set_ratio: None,
         });
 